@@ -0,0 +1,29 @@
+// Explicit returns, like the crate.
+#![allow(clippy::needless_return)]
+
+use std::{env, fs, path::Path};
+
+// Also compiled into the crate's tests, see src/schema.rs.
+mod codegen;
+
+const SCHEMA_PATH: &str = "schema/messages.json";
+
+fn main() {
+    // The whole directory, so the schema appearing or going away counts too.
+    println!("cargo:rerun-if-changed=schema");
+
+    // Without a schema there are no messages, but MessageSchema still exists so scripts load.
+    let messages = match fs::read_to_string(SCHEMA_PATH) {
+        Ok(schema) => {
+            codegen::parse_schema(&schema).unwrap_or_else(|error| panic!("{SCHEMA_PATH}: {error}"))
+        }
+        Err(_) => Vec::new(),
+    };
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("messages.rs"),
+        codegen::generate(&messages),
+    )
+    .unwrap();
+}
@@ -0,0 +1,447 @@
+use std::fmt::Write;
+
+use serde_json::Value;
+
+// Start - Generates message types from schema/messages.json
+// The schema is shared with the server, so both sides encode the same bytes without anyone packing them by
+// hand. It looks like this:
+//
+//     { "messages": [
+//         { "name": "PlayerMove", "id": 1, "fields": [
+//             { "name": "x", "type": "f32" },
+//             { "name": "y", "type": "f32" }
+//         ] }
+//     ] }
+//
+// Every message becomes a Rust struct with `encode`/`decode`, and MessageSchema gets an `encode_player_move`
+// function for GDScript. See src/schema.rs for the wire format.
+
+pub struct Message {
+    name: String,
+    id: u16,
+    fields: Vec<(String, FieldType)>,
+}
+
+#[derive(Clone, Copy)]
+enum FieldType {
+    U8,
+    U16,
+    U32,
+    U64,
+    I32,
+    I64,
+    F32,
+    F64,
+    Bool,
+    String,
+    Bytes,
+}
+
+impl FieldType {
+    fn parse(name: &str) -> Option<Self> {
+        return Some(match name {
+            "u8" => FieldType::U8,
+            "u16" => FieldType::U16,
+            "u32" => FieldType::U32,
+            "u64" => FieldType::U64,
+            "i32" => FieldType::I32,
+            "i64" => FieldType::I64,
+            "f32" => FieldType::F32,
+            "f64" => FieldType::F64,
+            "bool" => FieldType::Bool,
+            "string" => FieldType::String,
+            "bytes" => FieldType::Bytes,
+            _ => return None,
+        });
+    }
+
+    fn rust_type(self) -> &'static str {
+        return match self {
+            FieldType::U8 => "u8",
+            FieldType::U16 => "u16",
+            FieldType::U32 => "u32",
+            FieldType::U64 => "u64",
+            FieldType::I32 => "i32",
+            FieldType::I64 => "i64",
+            FieldType::F32 => "f32",
+            FieldType::F64 => "f64",
+            FieldType::Bool => "bool",
+            FieldType::String => "String",
+            FieldType::Bytes => "Vec<u8>",
+        };
+    }
+
+    // What GDScript passes in.
+    fn godot_type(self) -> &'static str {
+        return match self {
+            FieldType::F32 | FieldType::F64 => "f64",
+            FieldType::Bool => "bool",
+            FieldType::String => "GString",
+            FieldType::Bytes => "PackedByteArray",
+            _ => "i64",
+        };
+    }
+
+    fn godot_into_rust(self, value: &str) -> String {
+        return match self {
+            FieldType::Bool => value.to_string(),
+            FieldType::String => format!("{value}.to_string()"),
+            FieldType::Bytes => format!("{value}.to_vec()"),
+            _ => format!("{value} as {}", self.rust_type()),
+        };
+    }
+
+    fn to_godot(self, value: &str) -> String {
+        return match self {
+            FieldType::Bool => value.to_string(),
+            FieldType::F32 | FieldType::F64 => format!("{value} as f64"),
+            FieldType::String => format!("GString::from({value}.as_str())"),
+            FieldType::Bytes => format!("PackedByteArray::from({value}.as_slice())"),
+            _ => format!("{value} as i64"),
+        };
+    }
+
+    fn encode(self, field: &str, value: &str) -> String {
+        return match self {
+            FieldType::Bool => format!("buffer.extend_from_slice(&[{value} as u8]);"),
+            FieldType::String => format!("write_bytes(\"{field}\", {value}.as_bytes(), buffer)?;"),
+            FieldType::Bytes => format!("write_bytes(\"{field}\", &{value}, buffer)?;"),
+            _ => format!("buffer.extend_from_slice(&{value}.to_le_bytes());"),
+        };
+    }
+
+    fn decode(self) -> &'static str {
+        return match self {
+            FieldType::U8 => "reader.read_u8()?",
+            FieldType::U16 => "reader.read_u16()?",
+            FieldType::U32 => "reader.read_u32()?",
+            FieldType::U64 => "reader.read_u64()?",
+            FieldType::I32 => "reader.read_i32()?",
+            FieldType::I64 => "reader.read_u64()? as i64",
+            FieldType::F32 => "f32::from_bits(reader.read_u32()?)",
+            FieldType::F64 => "f64::from_bits(reader.read_u64()?)",
+            FieldType::Bool => "reader.read_u8()? != 0",
+            FieldType::String => "String::from_utf8(read_bytes(&mut reader)?.to_vec()).ok()?",
+            FieldType::Bytes => "read_bytes(&mut reader)?.to_vec()",
+        };
+    }
+}
+
+pub fn parse_schema(schema: &str) -> Result<Vec<Message>, String> {
+    let schema: Value = serde_json::from_str(schema).map_err(|error| error.to_string())?;
+    let Some(entries) = schema["messages"].as_array() else {
+        return Err(String::from("expected a \"messages\" array"));
+    };
+
+    let mut messages: Vec<Message> = Vec::new();
+    for entry in entries {
+        let name = entry["name"]
+            .as_str()
+            .ok_or("every message needs a \"name\"")?;
+        let id = entry["id"]
+            .as_u64()
+            .filter(|id| *id <= u16::MAX as u64)
+            .ok_or(format!("{name}: \"id\" must be 0 to 65535"))? as u16;
+        if messages.iter().any(|message| message.id == id) {
+            return Err(format!("{name}: id {id} is used twice"));
+        }
+
+        let mut fields = Vec::new();
+        for field in entry["fields"]
+            .as_array()
+            .map_or(&[][..], |fields| fields.as_slice())
+        {
+            let field_name = field["name"]
+                .as_str()
+                .ok_or(format!("{name}: every field needs a \"name\""))?;
+            let field_type = field["type"]
+                .as_str()
+                .and_then(FieldType::parse)
+                .ok_or(format!("{name}.{field_name}: unknown type"))?;
+            fields.push((field_name.to_string(), field_type));
+        }
+
+        messages.push(Message {
+            name: name.to_string(),
+            id,
+            fields,
+        });
+    }
+
+    return Ok(messages);
+}
+
+pub fn generate(messages: &[Message]) -> String {
+    let mut code = String::new();
+
+    for message in messages {
+        let name = &message.name;
+        writeln!(code, "#[derive(Debug, Clone, Default, PartialEq)]").unwrap();
+        writeln!(code, "pub struct {name} {{").unwrap();
+        for (field, field_type) in &message.fields {
+            writeln!(code, "    pub {field}: {},", field_type.rust_type()).unwrap();
+        }
+        writeln!(code, "}}\n").unwrap();
+
+        writeln!(code, "impl {name} {{").unwrap();
+        writeln!(code, "    pub const ID: u16 = {};\n", message.id).unwrap();
+        writeln!(
+            code,
+            "    pub fn encode(&self, buffer: &mut BytesMut) -> Result<(), String> {{"
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "        buffer.extend_from_slice(&Self::ID.to_le_bytes());"
+        )
+        .unwrap();
+        for (field, field_type) in &message.fields {
+            writeln!(
+                code,
+                "        {}",
+                field_type.encode(field, &format!("self.{field}"))
+            )
+            .unwrap();
+        }
+        writeln!(code, "        return Ok(());").unwrap();
+        writeln!(code, "    }}\n").unwrap();
+        writeln!(
+            code,
+            "    fn decode_body(mut reader: Reader) -> Option<Self> {{"
+        )
+        .unwrap();
+        writeln!(code, "        let message = Self {{").unwrap();
+        for (field, field_type) in &message.fields {
+            writeln!(code, "            {field}: {},", field_type.decode()).unwrap();
+        }
+        writeln!(code, "        }};").unwrap();
+        writeln!(code, "        return reader.is_empty().then_some(message);").unwrap();
+        writeln!(code, "    }}").unwrap();
+        writeln!(code, "}}\n").unwrap();
+    }
+
+    writeln!(code, "pub enum SchemaMessage {{").unwrap();
+    for message in messages {
+        writeln!(code, "    {0}({0}),", message.name).unwrap();
+    }
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(code, "impl SchemaMessage {{").unwrap();
+    writeln!(code, "    pub fn decode(bytes: &[u8]) -> Option<Self> {{").unwrap();
+    writeln!(code, "        let mut reader = Reader::new(bytes);").unwrap();
+    writeln!(code, "        return match reader.read_u16()? {{").unwrap();
+    for message in messages {
+        writeln!(
+            code,
+            "            {0}::ID => {0}::decode_body(reader).map(SchemaMessage::{0}),",
+            message.name
+        )
+        .unwrap();
+    }
+    writeln!(code, "            _ => None,").unwrap();
+    writeln!(code, "        }};").unwrap();
+    writeln!(code, "    }}\n").unwrap();
+    writeln!(code, "    fn to_dictionary(&self) -> Dictionary {{").unwrap();
+    writeln!(code, "        let mut dictionary = Dictionary::new();").unwrap();
+    // `*self` because a match on a reference to an enum without variants doesn't compile.
+    writeln!(code, "        match *self {{").unwrap();
+    for message in messages {
+        writeln!(
+            code,
+            "            SchemaMessage::{}(ref message) => {{",
+            message.name
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "                dictionary.set(\"type\", \"{}\");",
+            message.name
+        )
+        .unwrap();
+        for (field, field_type) in &message.fields {
+            writeln!(
+                code,
+                "                dictionary.set(\"{field}\", {});",
+                field_type.to_godot(&format!("message.{field}"))
+            )
+            .unwrap();
+        }
+        writeln!(code, "            }}").unwrap();
+    }
+    writeln!(code, "        }}").unwrap();
+    writeln!(code, "        return dictionary;").unwrap();
+    writeln!(code, "    }}").unwrap();
+    writeln!(code, "}}\n").unwrap();
+
+    writeln!(code, "#[godot_api]").unwrap();
+    writeln!(code, "impl MessageSchema {{").unwrap();
+    for message in messages {
+        writeln!(code, "    #[constant]").unwrap();
+        writeln!(
+            code,
+            "    const {}: i64 = {};",
+            snake_case(&message.name).to_uppercase(),
+            message.id
+        )
+        .unwrap();
+    }
+    for message in messages {
+        let parameters: Vec<String> = message
+            .fields
+            .iter()
+            .map(|(field, field_type)| format!("{field}: {}", field_type.godot_type()))
+            .collect();
+        writeln!(code, "\n    #[func]").unwrap();
+        writeln!(
+            code,
+            "    fn encode_{}({}) -> PackedByteArray {{",
+            snake_case(&message.name),
+            parameters.join(", ")
+        )
+        .unwrap();
+        writeln!(code, "        let message = {} {{", message.name).unwrap();
+        for (field, field_type) in &message.fields {
+            writeln!(
+                code,
+                "            {field}: {},",
+                field_type.godot_into_rust(field)
+            )
+            .unwrap();
+        }
+        writeln!(code, "        }};").unwrap();
+        writeln!(code, "        let mut buffer = BytesMut::new();").unwrap();
+        writeln!(
+            code,
+            "        if let Err(error) = message.encode(&mut buffer) {{"
+        )
+        .unwrap();
+        writeln!(
+            code,
+            "            godot_error!(\"MessageSchema: can't encode {}: {{error}}\");",
+            message.name
+        )
+        .unwrap();
+        writeln!(code, "            return PackedByteArray::new();").unwrap();
+        writeln!(code, "        }}").unwrap();
+        writeln!(code, "        return PackedByteArray::from(&buffer[..]);").unwrap();
+        writeln!(code, "    }}").unwrap();
+    }
+    writeln!(code, "\n    /// Returns the message as a Dictionary with its fields and a `type` key holding the message").unwrap();
+    writeln!(
+        code,
+        "    /// name, or an empty Dictionary if the payload isn't a message from the schema."
+    )
+    .unwrap();
+    writeln!(code, "    #[func]").unwrap();
+    writeln!(
+        code,
+        "    fn decode(payload: PackedByteArray) -> Dictionary {{"
+    )
+    .unwrap();
+    writeln!(
+        code,
+        "        return match SchemaMessage::decode(payload.as_slice()) {{"
+    )
+    .unwrap();
+    writeln!(
+        code,
+        "            Some(message) => message.to_dictionary(),"
+    )
+    .unwrap();
+    writeln!(code, "            None => Dictionary::new(),").unwrap();
+    writeln!(code, "        }};").unwrap();
+    writeln!(code, "    }}").unwrap();
+    writeln!(code, "}}").unwrap();
+
+    return code;
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (index, character) in name.chars().enumerate() {
+        if character.is_uppercase() && index > 0 {
+            snake.push('_');
+        }
+        snake.push(character.to_ascii_lowercase());
+    }
+    return snake;
+}
+// End - Generates message types from schema/messages.json
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("schema/messages.example.json");
+
+    #[test]
+    fn the_example_schema_generates() {
+        let messages = parse_schema(EXAMPLE).unwrap();
+        assert_eq!(messages.len(), 2);
+        let code = generate(&messages);
+
+        assert!(code.contains("pub struct PlayerMove {"));
+        assert!(code.contains("    pub x: f32,"));
+        assert!(code.contains("    pub sprinting: bool,"));
+        assert!(code.contains("pub struct ChatLine {"));
+        assert!(code.contains("    pub text: String,"));
+        assert!(code.contains("    const PLAYER_MOVE: i64 = 1;"));
+        assert!(code.contains("    const CHAT_LINE: i64 = 2;"));
+        assert!(code.contains(
+            "    fn encode_player_move(x: f64, y: f64, sprinting: bool) -> PackedByteArray {"
+        ));
+        assert!(code.contains(
+            "    fn encode_chat_line(sender_id: i64, text: GString) -> PackedByteArray {"
+        ));
+        assert!(code.contains(
+            "ChatLine::ID => ChatLine::decode_body(reader).map(SchemaMessage::ChatLine),"
+        ));
+        // Fields go on the wire in schema order.
+        let x = code.find("self.x.to_le_bytes()").unwrap();
+        let y = code.find("self.y.to_le_bytes()").unwrap();
+        assert!(x < y);
+    }
+
+    #[test]
+    fn an_empty_schema_still_generates() {
+        let code = generate(&parse_schema(r#"{ "messages": [] }"#).unwrap());
+        assert!(code.contains("pub enum SchemaMessage {\n}"));
+        assert!(code.contains("impl MessageSchema {"));
+    }
+
+    #[test]
+    fn bad_schemas_are_rejected() {
+        let cases = [
+            ("{}", "expected a \"messages\" array"),
+            (
+                r#"{ "messages": [{ "id": 1 }] }"#,
+                "every message needs a \"name\"",
+            ),
+            (
+                r#"{ "messages": [{ "name": "A", "id": 70000 }] }"#,
+                "A: \"id\" must be 0 to 65535",
+            ),
+            (
+                r#"{ "messages": [{ "name": "A", "id": 1 }, { "name": "B", "id": 1 }] }"#,
+                "B: id 1 is used twice",
+            ),
+            (
+                r#"{ "messages": [{ "name": "A", "id": 1, "fields": [{ "name": "f", "type": "f16" }] }] }"#,
+                "A.f: unknown type",
+            ),
+        ];
+        for (schema, error) in cases {
+            assert_eq!(
+                parse_schema(schema).err().as_deref(),
+                Some(error),
+                "{schema}"
+            );
+        }
+    }
+
+    #[test]
+    fn names_become_snake_case() {
+        assert_eq!(snake_case("PlayerMove"), "player_move");
+        assert_eq!(snake_case("Chat"), "chat");
+    }
+}
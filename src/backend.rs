@@ -0,0 +1,453 @@
+use godot::{
+    engine::{
+        file_access::ModeFlags, global::Error, http_client::Method, DirAccess, Engine, FileAccess,
+        HttpRequest, Json, Marshalls,
+    },
+    prelude::*,
+};
+
+use crate::{clock, credentials};
+
+// Start - Talking to the game's backend
+// The backend hands out identities and connect tokens over HTTPS with JSON bodies. Connect tokens are binary, so
+// they travel base64 encoded in a `connect_token` field.
+
+/// Parses a JSON response body into a Dictionary. Returns None for anything but a JSON object.
+pub fn parse_json_object(body: &PackedByteArray) -> Option<Dictionary> {
+    let text = String::from_utf8_lossy(body.as_slice());
+    return Json::parse_string(GString::from(text.as_ref()))
+        .try_to::<Dictionary>()
+        .ok();
+}
+
+pub fn get_string(response: &Dictionary, key: &str) -> Option<GString> {
+    return response.get(key)?.try_to::<GString>().ok();
+}
+
+/// The decoded `connect_token` field, None if it's missing or not base64.
+pub fn get_connect_token(response: &Dictionary) -> Option<PackedByteArray> {
+    let encoded = get_string(response, "connect_token")?;
+    let connect_token = Marshalls::singleton().base64_to_raw(encoded);
+    if connect_token.is_empty() {
+        return None;
+    }
+    return Some(connect_token);
+}
+
+/// Starts a request as a child of `parent`. `callback` gets its `request_completed`. The access token is sent as
+/// a bearer token and the body as JSON, when given. Returns None if it couldn't start.
+pub fn start_request(
+    parent: &mut Node,
+    callback: Callable,
+    url: &str,
+    method: Method,
+    access_token: &GString,
+    body: Option<Dictionary>,
+) -> Option<Gd<HttpRequest>> {
+    let mut request = HttpRequest::new_alloc();
+    parent.add_child(request.clone().upcast());
+    request.connect("request_completed".into(), callback);
+    let mut headers = PackedStringArray::new();
+    if !access_token.is_empty() {
+        headers.push(format!("Authorization: Bearer {access_token}").into());
+    }
+    let mut data = GString::new();
+    if let Some(body) = body {
+        headers.push("Content-Type: application/json".into());
+        data = Json::stringify(body.to_variant());
+    }
+    let error = request
+        .request_ex(url.into())
+        .custom_headers(headers)
+        .method(method)
+        .request_data(data)
+        .done();
+    if error != Error::OK {
+        godot_error!("Couldn't request {url}: {error:?}");
+        request.queue_free();
+        return None;
+    }
+    return Some(request);
+}
+
+/// Whether an HttpRequest finished with a 2xx response.
+pub fn is_success(result: i64, response_code: i64) -> bool {
+    return result == 0 && (200..300).contains(&response_code);
+}
+// End - Talking to the game's backend
+
+// Start - Account sessions
+// BackendClient logs the player in and keeps them logged in, so games don't have to mix their own HTTPRequest
+// scripts with the netcode flow. It talks to four endpoints under `api_url`, all POST with JSON bodies:
+//   login          {username, password}  -> {account_id, access_token, refresh_token, expires_in}
+//   refresh        {refresh_token}       -> the same as login, refresh_token may be left out to keep the old one
+//   logout         {refresh_token}       -> anything
+//   connect_token  {...game specific}    -> {connect_token}, base64
+// Everything but login sends the access token as a bearer token. The access token is refreshed shortly before
+// it expires. Only the refresh token is kept on disk, encrypted with the device key like the connect token
+// cache, so the next launch logs in without asking for the password again.
+
+// Seconds before the access token expires that it is refreshed, and between tries when that fails.
+const REFRESH_MARGIN: f64 = 60.0;
+const REFRESH_RETRY: f64 = 10.0;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Endpoint {
+    Login,
+    Refresh,
+    Logout,
+    ConnectToken,
+}
+
+impl Endpoint {
+    fn path(self) -> &'static str {
+        return match self {
+            Endpoint::Login => "login",
+            Endpoint::Refresh => "refresh",
+            Endpoint::Logout => "logout",
+            Endpoint::ConnectToken => "connect_token",
+        };
+    }
+}
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+pub struct BackendClient {
+    base: Base<Node>,
+
+    // The API's base URL, like "https://api.example.com/v1". Endpoints are appended with a slash.
+    #[export]
+    api_url: GString,
+    // When set, connect tokens from `request_connect_token` are passed to this GameplaySessionManager's
+    // `join_session_with_token`.
+    #[export]
+    session_manager: NodePath,
+    // Where the refresh token is kept between launches. Give every local player their own in split-screen
+    // games. Empty to not keep it.
+    #[export]
+    #[init(default = GString::from("user://backend_session.bin"))]
+    session_path: GString,
+
+    account_id: GString,
+    access_token: GString,
+    refresh_token: GString,
+    // Unix time in seconds the access token expires at, and when refreshing it may be tried again.
+    expires_at: f64,
+    refresh_retry_at: f64,
+
+    // One request at a time, the endpoint says how to read its response.
+    pending: Option<(Gd<HttpRequest>, Endpoint)>,
+}
+
+#[godot_api]
+impl INode for BackendClient {
+    fn ready(&mut self) {
+        if Engine::singleton().is_editor_hint() {
+            return;
+        }
+
+        // Picks up where the last launch left off.
+        if let Some(refresh_token) = self.load_refresh_token() {
+            self.refresh_token = refresh_token;
+            self.refresh();
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        if self.pending.is_some() || self.access_token.is_empty() {
+            return;
+        }
+        let now = unix_time();
+        if self.expires_at - now < REFRESH_MARGIN && now >= self.refresh_retry_at {
+            self.refresh();
+        }
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        let api_url = self.api_url.to_string();
+        if api_url.is_empty() {
+            warnings.push("Set api_url to the backend's base URL.".into());
+        } else if !api_url.starts_with("https://") {
+            warnings.push("api_url isn't HTTPS, passwords and tokens would be readable.".into());
+        }
+        return warnings;
+    }
+}
+
+#[godot_api]
+impl BackendClient {
+    // Emitted after `login`, and when a session kept from the last launch was restored.
+    #[signal]
+    fn logged_in(account_id: GString);
+    #[signal]
+    fn login_failed(error: GString);
+    // Emitted after `logout`, and when the backend no longer accepts the refresh token.
+    #[signal]
+    fn logged_out();
+    #[signal]
+    fn session_refreshed();
+    #[signal]
+    fn connect_token_received(connect_token: PackedByteArray);
+    // Emitted when a refresh, logout or connect token request failed. `endpoint` is its name, like "refresh".
+    #[signal]
+    fn request_failed(endpoint: GString, error: GString);
+
+    /// Logs in with the player's credentials. Returns false if another request is still running.
+    #[func]
+    fn login(&mut self, username: GString, password: GString) -> bool {
+        let mut body = Dictionary::new();
+        body.set("username", username);
+        body.set("password", password);
+        return self.send(Endpoint::Login, body);
+    }
+
+    /// Gets a new access token with the refresh token. Happens on its own before the access token expires.
+    #[func]
+    fn refresh(&mut self) -> bool {
+        if self.refresh_token.is_empty() {
+            return false;
+        }
+        let mut body = Dictionary::new();
+        body.set("refresh_token", self.refresh_token.clone());
+        return self.send(Endpoint::Refresh, body);
+    }
+
+    /// Logs out on the backend and forgets the session here, even if the backend can't be reached.
+    #[func]
+    fn logout(&mut self) {
+        if !self.refresh_token.is_empty() {
+            let mut body = Dictionary::new();
+            body.set("refresh_token", self.refresh_token.clone());
+            // Anything still running is about the session that is going away.
+            if let Some((mut request, _)) = self.pending.take() {
+                request.queue_free();
+            }
+            self.send(Endpoint::Logout, body);
+        }
+        self.clear_session();
+        self.base_mut().emit_signal("logged_out".into(), &[]);
+    }
+
+    /// Asks the backend for a connect token. `request` is game specific, like the match or region to join.
+    /// The token arrives through `connect_token_received`, and is used to join right away if
+    /// `session_manager` is set. Returns false when not logged in or another request is still running.
+    #[func]
+    fn request_connect_token(&mut self, request: Dictionary) -> bool {
+        if self.access_token.is_empty() {
+            return false;
+        }
+        return self.send(Endpoint::ConnectToken, request);
+    }
+
+    #[func]
+    fn is_logged_in(&self) -> bool {
+        return !self.access_token.is_empty();
+    }
+
+    #[func]
+    fn get_account_id(&self) -> GString {
+        return self.account_id.clone();
+    }
+
+    /// For the game's own calls to the backend.
+    #[func]
+    fn get_access_token(&self) -> GString {
+        return self.access_token.clone();
+    }
+
+    #[func]
+    fn on_request_completed(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        body: PackedByteArray,
+    ) {
+        let Some((mut request, endpoint)) = self.pending.take() else {
+            return;
+        };
+        request.queue_free();
+
+        let response = match is_success(result, response_code) {
+            true => parse_json_object(&body),
+            false => None,
+        };
+        let error = GString::from(format!("result {result}, HTTP {response_code}"));
+        match endpoint {
+            Endpoint::Login | Endpoint::Refresh => {
+                let restored = endpoint == Endpoint::Refresh && self.access_token.is_empty();
+                if response.is_some_and(|response| self.set_session(&response)) {
+                    if endpoint == Endpoint::Login || restored {
+                        let account_id = self.account_id.clone();
+                        self.base_mut()
+                            .emit_signal("logged_in".into(), &[account_id.to_variant()]);
+                    } else {
+                        self.base_mut().emit_signal("session_refreshed".into(), &[]);
+                    }
+                } else if endpoint == Endpoint::Login {
+                    self.base_mut()
+                        .emit_signal("login_failed".into(), &[error.to_variant()]);
+                } else if response_code == 401 || response_code == 403 {
+                    // The refresh token was revoked or expired, only logging in again helps.
+                    self.clear_session();
+                    self.base_mut().emit_signal("logged_out".into(), &[]);
+                } else {
+                    self.refresh_retry_at = unix_time() + REFRESH_RETRY;
+                    self.emit_request_failed(endpoint, error);
+                }
+            }
+            Endpoint::Logout => {
+                if response.is_none() {
+                    self.emit_request_failed(endpoint, error);
+                }
+            }
+            Endpoint::ConnectToken => {
+                let Some(connect_token) = response.as_ref().and_then(get_connect_token) else {
+                    self.emit_request_failed(endpoint, error);
+                    return;
+                };
+                self.base_mut().emit_signal(
+                    "connect_token_received".into(),
+                    &[connect_token.to_variant()],
+                );
+                if !self.session_manager.is_empty() {
+                    let path = self.session_manager.clone();
+                    let Some(mut manager) = self.base().get_node_or_null(path.clone()) else {
+                        godot_error!("BackendClient: no session manager found at '{path}'");
+                        return;
+                    };
+                    manager.call(
+                        "join_session_with_token".into(),
+                        &[connect_token.to_variant()],
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl BackendClient {
+    pub(crate) fn api_url(&self) -> String {
+        return self.api_url.to_string().trim_end_matches('/').to_owned();
+    }
+
+    pub(crate) fn access_token(&self) -> &GString {
+        return &self.access_token;
+    }
+
+    pub(crate) fn account_id(&self) -> &GString {
+        return &self.account_id;
+    }
+
+    fn send(&mut self, endpoint: Endpoint, body: Dictionary) -> bool {
+        if self.pending.is_some() {
+            godot_warn!(
+                "BackendClient: can't call {} while another request is running",
+                endpoint.path()
+            );
+            return false;
+        }
+
+        let access_token = match endpoint {
+            Endpoint::Login => GString::new(),
+            _ => self.access_token.clone(),
+        };
+        let url = format!("{}/{}", self.api_url(), endpoint.path());
+        let callback = Callable::from_object_method(&self.to_gd(), "on_request_completed");
+        let Some(request) = start_request(
+            &mut self.base_mut(),
+            callback,
+            &url,
+            Method::POST,
+            &access_token,
+            Some(body),
+        ) else {
+            return false;
+        };
+        self.pending = Some((request, endpoint));
+        return true;
+    }
+
+    // Returns false if the response is missing anything.
+    fn set_session(&mut self, response: &Dictionary) -> bool {
+        let Some(access_token) = get_string(response, "access_token") else {
+            return false;
+        };
+        let Some(expires_in) = response
+            .get("expires_in")
+            .and_then(|value| value.try_to::<f64>().ok())
+        else {
+            return false;
+        };
+        if let Some(account_id) = get_string(response, "account_id") {
+            self.account_id = account_id;
+        }
+        if let Some(refresh_token) = get_string(response, "refresh_token") {
+            self.refresh_token = refresh_token;
+            self.save_refresh_token();
+        }
+        self.access_token = access_token;
+        self.expires_at = unix_time() + expires_in;
+        return true;
+    }
+
+    fn clear_session(&mut self) {
+        self.account_id = GString::new();
+        self.access_token = GString::new();
+        self.refresh_token = GString::new();
+        self.expires_at = 0.0;
+        if !self.session_path.is_empty() && FileAccess::file_exists(self.session_path.clone()) {
+            DirAccess::remove_absolute(self.session_path.clone());
+        }
+    }
+
+    fn emit_request_failed(&mut self, endpoint: Endpoint, error: GString) {
+        godot_warn!("BackendClient: {} failed, {error}", endpoint.path());
+        self.base_mut().emit_signal(
+            "request_failed".into(),
+            &[endpoint.path().to_variant(), error.to_variant()],
+        );
+    }
+
+    fn save_refresh_token(&self) {
+        if self.session_path.is_empty() {
+            return;
+        }
+        let Some(key) = credentials::device_key() else {
+            return;
+        };
+        let Some(mut file) =
+            FileAccess::open_encrypted_with_pass(self.session_path.clone(), ModeFlags::WRITE, key)
+        else {
+            godot_warn!(
+                "BackendClient: couldn't save the session to {}",
+                self.session_path
+            );
+            return;
+        };
+        file.store_pascal_string(self.refresh_token.clone());
+        file.close();
+    }
+
+    fn load_refresh_token(&self) -> Option<GString> {
+        if self.session_path.is_empty() || !FileAccess::file_exists(self.session_path.clone()) {
+            return None;
+        }
+        let key = credentials::device_key()?;
+        let mut file =
+            FileAccess::open_encrypted_with_pass(self.session_path.clone(), ModeFlags::READ, key)?;
+        let refresh_token = file.get_pascal_string();
+        file.close();
+        if refresh_token.is_empty() {
+            return None;
+        }
+        return Some(refresh_token);
+    }
+}
+
+fn unix_time() -> f64 {
+    return clock::unix_seconds();
+}
+// End - Account sessions
@@ -0,0 +1,246 @@
+use std::{
+    net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use godot::prelude::*;
+use renet::{
+    transport::{ClientAuthentication, NetcodeClientTransport},
+    ConnectionConfig, RenetClient,
+};
+
+use crate::{channels, clock, protocol::ClientMessage};
+
+// Renet's default, which is also what GameplaySessionManager uses unless it is changed in the inspector.
+const MEMORY_BUDGET: usize = 5 * 1024 * 1024;
+
+// Start - Load testing with lots of fake clients
+// Only built with the `bots` feature. Add a BotClientSwarm to an otherwise empty scene, run the project
+// headless, and call `start` to connect `count` bots to a server. Each bot has its own socket and renet client,
+// and sends `message_size` bytes of application data every `send_interval` seconds once connected.
+// `get_stats` reports how many bots got in, how long it took and what their connections look like.
+#[derive(GodotClass)]
+#[class(init, base=Node)]
+struct BotClientSwarm {
+    base: Base<Node>,
+
+    // Seconds between messages from each bot. 0 sends every tick.
+    #[export]
+    #[init(default = 0.1)]
+    send_interval: f64,
+    #[export]
+    #[init(default = 64)]
+    message_size: i64,
+    #[export]
+    #[init(default = channels::UNRELIABLE as i64)]
+    channel: i64,
+    // Bots that haven't connected after this many seconds count as failed.
+    #[export]
+    #[init(default = 10.0)]
+    connect_timeout: f64,
+
+    bots: Vec<Bot>,
+    payload: Bytes,
+    elapsed: f64,
+    settled: bool,
+}
+
+struct Bot {
+    client: RenetClient,
+    transport: NetcodeClientTransport,
+    // Seconds after `start` the bot connected.
+    connected_after: Option<f64>,
+    failed: bool,
+    since_last_send: f64,
+    messages_sent: u64,
+}
+
+#[godot_api]
+impl INode for BotClientSwarm {
+    fn physics_process(&mut self, delta: f64) {
+        self.elapsed += delta;
+        let deltadur = Duration::from_secs_f64(delta);
+        let channel = self.channel.clamp(0, channels::CHANNEL_COUNT as i64 - 1) as u8;
+
+        for bot in &mut self.bots {
+            if bot.failed {
+                continue;
+            }
+
+            bot.client.update(deltadur);
+            if bot.transport.update(deltadur, &mut bot.client).is_err() {
+                bot.failed = true;
+                continue;
+            }
+
+            if bot.client.is_connected() {
+                bot.connected_after.get_or_insert(self.elapsed);
+                bot.since_last_send += delta;
+                if bot.since_last_send >= self.send_interval {
+                    bot.since_last_send = 0.0;
+                    bot.client.send_message(channel, self.payload.clone());
+                    bot.messages_sent += 1;
+                }
+            } else if bot.connected_after.is_none() && self.elapsed > self.connect_timeout {
+                bot.failed = true;
+                continue;
+            }
+
+            if bot.transport.send_packets(&mut bot.client).is_err() {
+                bot.failed = true;
+            }
+        }
+
+        if self.settled || self.bots.is_empty() {
+            return;
+        }
+        let connected = self.count(|bot| !bot.failed && bot.connected_after.is_some());
+        let failed = self.count(|bot| bot.failed);
+        if connected + failed == self.bots.len() as i64 {
+            self.settled = true;
+            self.base_mut().emit_signal(
+                "settled".into(),
+                &[connected.to_variant(), failed.to_variant()],
+            );
+        }
+    }
+}
+
+#[godot_api]
+impl BotClientSwarm {
+    // Emitted once every bot has either connected or failed.
+    #[signal]
+    fn settled(connected: i64, failed: i64);
+
+    /// Connects `count` bots to the server, with client ids counting up from `first_client_id`.
+    /// Stops any bots that are already running. Returns false if the address can't be parsed.
+    #[func]
+    fn start(&mut self, address: GString, count: i64, first_client_id: i64) -> bool {
+        self.stop();
+
+        let Ok(server_addr) = address.to_string().parse::<SocketAddr>() else {
+            godot_error!("BotClientSwarm: invalid server address '{address}'");
+            return false;
+        };
+
+        let mut payload = BytesMut::new();
+        ClientMessage::Application(&vec![0; self.message_size.max(0) as usize])
+            .encode(&mut payload);
+        self.payload = payload.freeze();
+
+        for index in 0..count.max(0) {
+            let client_id = (first_client_id + index) as u64;
+            match create_bot(server_addr, client_id) {
+                Ok(bot) => self.bots.push(bot),
+                Err(error) => {
+                    godot_error!("BotClientSwarm: bot {client_id} failed to start: {error}")
+                }
+            }
+        }
+
+        return true;
+    }
+
+    /// Disconnects and removes every bot. The stats are reset too.
+    #[func]
+    fn stop(&mut self) {
+        for bot in &mut self.bots {
+            bot.transport.disconnect();
+        }
+        self.bots.clear();
+        self.elapsed = 0.0;
+        self.settled = false;
+    }
+
+    /// Returns a Dictionary with `bots`, `connected`, `connecting`, `failed`, `messages_sent`, the average and
+    /// max connect time as `avg_connect_ms`/`max_connect_ms`, the average and max round trip time of connected
+    /// bots as `avg_rtt_ms`/`max_rtt_ms`, and their average `packet_loss`.
+    #[func]
+    fn get_stats(&self) -> Dictionary {
+        let connected: Vec<&Bot> = self
+            .bots
+            .iter()
+            .filter(|bot| !bot.failed && bot.client.is_connected())
+            .collect();
+        let connect_times: Vec<f64> = self
+            .bots
+            .iter()
+            .filter_map(|bot| bot.connected_after)
+            .collect();
+        let rtts: Vec<f64> = connected.iter().map(|bot| bot.client.rtt()).collect();
+        let packet_loss: Vec<f64> = connected
+            .iter()
+            .map(|bot| bot.client.packet_loss())
+            .collect();
+
+        let mut dictionary = Dictionary::new();
+        dictionary.set("bots", self.bots.len() as i64);
+        dictionary.set("connected", connected.len() as i64);
+        dictionary.set(
+            "connecting",
+            self.count(|bot| !bot.failed && bot.connected_after.is_none()),
+        );
+        dictionary.set("failed", self.count(|bot| bot.failed));
+        dictionary.set(
+            "messages_sent",
+            self.bots
+                .iter()
+                .map(|bot| bot.messages_sent as i64)
+                .sum::<i64>(),
+        );
+        dictionary.set("avg_connect_ms", average(&connect_times) * 1000.0);
+        dictionary.set("max_connect_ms", maximum(&connect_times) * 1000.0);
+        dictionary.set("avg_rtt_ms", average(&rtts) * 1000.0);
+        dictionary.set("max_rtt_ms", maximum(&rtts) * 1000.0);
+        dictionary.set("packet_loss", average(&packet_loss));
+        return dictionary;
+    }
+
+    fn count(&self, predicate: impl Fn(&Bot) -> bool) -> i64 {
+        return self.bots.iter().filter(|bot| predicate(bot)).count() as i64;
+    }
+}
+// End - Load testing with lots of fake clients
+
+fn create_bot(server_addr: SocketAddr, client_id: u64) -> Result<Bot, String> {
+    let channels = channels::channels_config([MEMORY_BUDGET; channels::CHANNEL_COUNT]);
+    let client = RenetClient::new(ConnectionConfig {
+        server_channels_config: channels.clone(),
+        client_channels_config: channels,
+        ..Default::default()
+    });
+
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0))
+        .map_err(|error| error.to_string())?;
+    let current_time = clock::unix_time();
+    let authentication = ClientAuthentication::Unsecure {
+        server_addr,
+        client_id,
+        user_data: None,
+        protocol_id: 0,
+    };
+    let transport = NetcodeClientTransport::new(current_time, authentication, socket)
+        .map_err(|error| error.to_string())?;
+
+    return Ok(Bot {
+        client,
+        transport,
+        connected_after: None,
+        failed: false,
+        since_last_send: 0.0,
+        messages_sent: 0,
+    });
+}
+
+fn average(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+
+    return values.iter().sum::<f64>() / values.len() as f64;
+}
+
+fn maximum(values: &[f64]) -> f64 {
+    return values.iter().copied().fold(0.0, f64::max);
+}
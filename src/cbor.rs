@@ -0,0 +1,417 @@
+use godot::{builtin::VariantType, prelude::*};
+
+use crate::protocol::Reader;
+
+// Start - CBOR encoding of Variants
+// CBOR (RFC 8949) is a compact, self-describing format with good libraries in most languages, so servers not
+// written in Godot can read and write it without a schema. Variants map onto it like this:
+//
+//     null, bool, int, float      null, bool, integer, float64
+//     String, StringName          text string
+//     PackedByteArray             byte string
+//     Array, other packed arrays  array
+//     Dictionary                  map
+//     Vector2/3/4(i)              array of numbers
+//
+// Decoding accepts anything a CBOR encoder produces: tags are ignored, half and single precision floats are
+// widened, and indefinite lengths are supported.
+
+// Deeply nested data from the server shouldn't be able to overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xFF;
+
+pub fn encode(value: &Variant, buffer: &mut Vec<u8>) -> Result<(), String> {
+    match value.get_type() {
+        VariantType::NIL => buffer.push(0xF6),
+        VariantType::BOOL => buffer.push(if value.to::<bool>() { 0xF5 } else { 0xF4 }),
+        VariantType::INT => encode_integer(value.to::<i64>(), buffer),
+        VariantType::FLOAT => {
+            buffer.push(0xFB);
+            buffer.extend_from_slice(&value.to::<f64>().to_be_bytes());
+        }
+        VariantType::STRING | VariantType::STRING_NAME => {
+            encode_text(&value.to::<GString>().to_string(), buffer);
+        }
+        VariantType::PACKED_BYTE_ARRAY => {
+            let bytes = value.to::<PackedByteArray>();
+            write_head(MAJOR_BYTES, bytes.len() as u64, buffer);
+            buffer.extend_from_slice(bytes.as_slice());
+        }
+        VariantType::DICTIONARY => {
+            let dictionary = value.to::<Dictionary>();
+            write_head(MAJOR_MAP, dictionary.len() as u64, buffer);
+            for (key, value) in dictionary.iter_shared() {
+                encode(&key, buffer)?;
+                encode(&value, buffer)?;
+            }
+        }
+        VariantType::ARRAY => {
+            let array = value.to::<VariantArray>();
+            encode_array(array.len(), array.iter_shared(), buffer)?;
+        }
+        // Vectors and packed arrays become arrays of their elements.
+        VariantType::PACKED_INT32_ARRAY => {
+            encode_numbers(value.to::<PackedInt32Array>().as_slice(), buffer)?;
+        }
+        VariantType::PACKED_INT64_ARRAY => {
+            encode_numbers(value.to::<PackedInt64Array>().as_slice(), buffer)?;
+        }
+        VariantType::PACKED_FLOAT32_ARRAY => {
+            encode_numbers(value.to::<PackedFloat32Array>().as_slice(), buffer)?;
+        }
+        VariantType::PACKED_FLOAT64_ARRAY => {
+            encode_numbers(value.to::<PackedFloat64Array>().as_slice(), buffer)?;
+        }
+        VariantType::PACKED_STRING_ARRAY => {
+            let strings = value.to::<PackedStringArray>();
+            write_head(MAJOR_ARRAY, strings.len() as u64, buffer);
+            for string in strings.as_slice() {
+                encode_text(&string.to_string(), buffer);
+            }
+        }
+        VariantType::VECTOR2 => {
+            let vector = value.to::<Vector2>();
+            encode_numbers(&[vector.x, vector.y], buffer)?;
+        }
+        VariantType::VECTOR3 => {
+            let vector = value.to::<Vector3>();
+            encode_numbers(&[vector.x, vector.y, vector.z], buffer)?;
+        }
+        VariantType::VECTOR4 => {
+            let vector = value.to::<Vector4>();
+            encode_numbers(&[vector.x, vector.y, vector.z, vector.w], buffer)?;
+        }
+        VariantType::VECTOR2I => {
+            let vector = value.to::<Vector2i>();
+            encode_numbers(&[vector.x, vector.y], buffer)?;
+        }
+        VariantType::VECTOR3I => {
+            let vector = value.to::<Vector3i>();
+            encode_numbers(&[vector.x, vector.y, vector.z], buffer)?;
+        }
+        VariantType::VECTOR4I => {
+            let vector = value.to::<Vector4i>();
+            encode_numbers(&[vector.x, vector.y, vector.z, vector.w], buffer)?;
+        }
+        other => return Err(format!("{other:?} can't be encoded as CBOR")),
+    }
+
+    return Ok(());
+}
+
+fn encode_numbers<T: ToGodot>(numbers: &[T], buffer: &mut Vec<u8>) -> Result<(), String> {
+    return encode_array(
+        numbers.len(),
+        numbers.iter().map(|number| number.to_variant()),
+        buffer,
+    );
+}
+
+fn encode_array(
+    length: usize,
+    values: impl Iterator<Item = Variant>,
+    buffer: &mut Vec<u8>,
+) -> Result<(), String> {
+    write_head(MAJOR_ARRAY, length as u64, buffer);
+    for value in values {
+        encode(&value, buffer)?;
+    }
+    return Ok(());
+}
+
+fn encode_integer(value: i64, buffer: &mut Vec<u8>) {
+    if value >= 0 {
+        write_head(MAJOR_UNSIGNED, value as u64, buffer);
+    } else {
+        // Negative integers are stored as -1 - n.
+        write_head(MAJOR_NEGATIVE, !(value as u64), buffer);
+    }
+}
+
+fn encode_text(text: &str, buffer: &mut Vec<u8>) {
+    write_head(MAJOR_TEXT, text.len() as u64, buffer);
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+// The first byte is the major type in the top 3 bits, and either the value or how many bytes follow with it.
+fn write_head(major: u8, value: u64, buffer: &mut Vec<u8>) {
+    let major = major << 5;
+    if value < 24 {
+        buffer.push(major | value as u8);
+    } else if value <= u8::MAX as u64 {
+        buffer.extend_from_slice(&[major | 24, value as u8]);
+    } else if value <= u16::MAX as u64 {
+        buffer.push(major | 25);
+        buffer.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX as u64 {
+        buffer.push(major | 26);
+        buffer.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buffer.push(major | 27);
+        buffer.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Returns `None` if the bytes aren't exactly one well formed CBOR value.
+pub fn decode(bytes: &[u8]) -> Option<Variant> {
+    let mut reader = Reader::new(bytes);
+    let value = decode_value(&mut reader, 0)?;
+    return reader.is_empty().then_some(value);
+}
+
+fn decode_value(reader: &mut Reader, depth: usize) -> Option<Variant> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let initial = reader.read_u8()?;
+    let (major, additional) = (initial >> 5, initial & 0x1F);
+
+    if major == MAJOR_SIMPLE {
+        return match additional {
+            20 => Some(false.to_variant()),
+            21 => Some(true.to_variant()),
+            // Null and undefined.
+            22 | 23 => Some(Variant::nil()),
+            25 => Some(half_to_f64(u16::from_be_bytes(read_array(reader)?)).to_variant()),
+            26 => Some((f32::from_be_bytes(read_array(reader)?) as f64).to_variant()),
+            27 => Some(f64::from_be_bytes(read_array(reader)?).to_variant()),
+            _ => None,
+        };
+    }
+
+    if additional == INDEFINITE {
+        return decode_indefinite(reader, major, depth);
+    }
+
+    let argument = read_argument(reader, additional)?;
+    // Every item takes at least a byte, so a count larger than what's left is a lie, and we don't want to
+    // start building a huge array for it.
+    let items = match major {
+        MAJOR_ARRAY => argument,
+        MAJOR_MAP => argument.saturating_mul(2),
+        _ => 0,
+    };
+    if items > reader.len() as u64 {
+        return None;
+    }
+
+    return match major {
+        // Values over i64::MAX wrap around, GDScript has no unsigned integers.
+        MAJOR_UNSIGNED => Some((argument as i64).to_variant()),
+        MAJOR_NEGATIVE => Some((!argument as i64).to_variant()),
+        MAJOR_BYTES => {
+            let bytes = reader.read_bytes(usize::try_from(argument).ok()?)?;
+            Some(PackedByteArray::from(bytes).to_variant())
+        }
+        MAJOR_TEXT => {
+            let bytes = reader.read_bytes(usize::try_from(argument).ok()?)?;
+            Some(GString::from(std::str::from_utf8(bytes).ok()?).to_variant())
+        }
+        MAJOR_ARRAY => {
+            let mut array = VariantArray::new();
+            for _ in 0..argument {
+                array.push(decode_value(reader, depth + 1)?);
+            }
+            Some(array.to_variant())
+        }
+        MAJOR_MAP => {
+            let mut dictionary = Dictionary::new();
+            for _ in 0..argument {
+                let key = decode_value(reader, depth + 1)?;
+                dictionary.set(key, decode_value(reader, depth + 1)?);
+            }
+            Some(dictionary.to_variant())
+        }
+        // Tags only add meaning to the value that follows, which we pass on as is.
+        MAJOR_TAG => decode_value(reader, depth + 1),
+        _ => None,
+    };
+}
+
+// Indefinite length items are a list of items ended by a break byte. Strings are split into chunks.
+fn decode_indefinite(reader: &mut Reader, major: u8, depth: usize) -> Option<Variant> {
+    let mut array = VariantArray::new();
+    let mut dictionary = Dictionary::new();
+    let mut chunks = Vec::new();
+
+    loop {
+        if reader.peek_u8()? == BREAK {
+            reader.read_u8()?;
+            break;
+        }
+
+        match major {
+            MAJOR_ARRAY => array.push(decode_value(reader, depth + 1)?),
+            MAJOR_MAP => {
+                let key = decode_value(reader, depth + 1)?;
+                dictionary.set(key, decode_value(reader, depth + 1)?);
+            }
+            MAJOR_BYTES | MAJOR_TEXT => {
+                // Chunks must be definite length strings of the same type.
+                let initial = reader.read_u8()?;
+                if initial >> 5 != major || initial & 0x1F == INDEFINITE {
+                    return None;
+                }
+                let length = read_argument(reader, initial & 0x1F)?;
+                chunks.extend_from_slice(reader.read_bytes(usize::try_from(length).ok()?)?);
+            }
+            _ => return None,
+        }
+    }
+
+    return match major {
+        MAJOR_ARRAY => Some(array.to_variant()),
+        MAJOR_MAP => Some(dictionary.to_variant()),
+        MAJOR_BYTES => Some(PackedByteArray::from(chunks.as_slice()).to_variant()),
+        _ => Some(GString::from(std::str::from_utf8(&chunks).ok()?).to_variant()),
+    };
+}
+
+fn read_argument(reader: &mut Reader, additional: u8) -> Option<u64> {
+    return match additional {
+        0..=23 => Some(additional as u64),
+        24 => Some(reader.read_u8()? as u64),
+        25 => Some(u16::from_be_bytes(read_array(reader)?) as u64),
+        26 => Some(u32::from_be_bytes(read_array(reader)?) as u64),
+        27 => Some(u64::from_be_bytes(read_array(reader)?)),
+        _ => None,
+    };
+}
+
+fn read_array<const N: usize>(reader: &mut Reader) -> Option<[u8; N]> {
+    return reader.read_bytes(N)?.try_into().ok();
+}
+
+fn half_to_f64(half: u16) -> f64 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f64;
+
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    };
+    return sign * magnitude;
+}
+// End - CBOR encoding of Variants
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Variants need the engine, so these stick to the parts that don't make any.
+
+    fn head(major: u8, value: u64) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        write_head(major, value, &mut buffer);
+        return buffer;
+    }
+
+    #[test]
+    fn heads_round_trip() {
+        let cases = [
+            (0, 1),
+            (23, 1),
+            (24, 2),
+            (255, 2),
+            (256, 3),
+            (65535, 3),
+            (65536, 5),
+            (u32::MAX as u64, 5),
+            (u32::MAX as u64 + 1, 9),
+            (u64::MAX, 9),
+        ];
+        for (value, size) in cases {
+            let bytes = head(MAJOR_ARRAY, value);
+            assert_eq!(bytes.len(), size, "{value}");
+            assert_eq!(bytes[0] >> 5, MAJOR_ARRAY);
+
+            let mut reader = Reader::new(&bytes[1..]);
+            assert_eq!(read_argument(&mut reader, bytes[0] & 0x1F), Some(value));
+            assert!(reader.is_empty());
+        }
+    }
+
+    #[test]
+    fn integers_use_the_shortest_head() {
+        let encoded = |value: i64| {
+            let mut buffer = Vec::new();
+            encode_integer(value, &mut buffer);
+            return buffer;
+        };
+        assert_eq!(encoded(0), [0x00]);
+        assert_eq!(encoded(23), [0x17]);
+        assert_eq!(encoded(100), [0x18, 100]);
+        assert_eq!(encoded(-1), [0x20]);
+        assert_eq!(encoded(-100), [0x38, 99]);
+        assert_eq!(encoded(1000), [0x19, 0x03, 0xE8]);
+        assert_eq!(encoded(i64::MIN)[0], 0x3B);
+    }
+
+    #[test]
+    fn text_is_length_prefixed() {
+        let mut buffer = Vec::new();
+        encode_text("IETF", &mut buffer);
+        assert_eq!(buffer, [0x64, b'I', b'E', b'T', b'F']);
+    }
+
+    #[test]
+    fn half_floats_widen() {
+        assert_eq!(half_to_f64(0x0000), 0.0);
+        assert_eq!(half_to_f64(0x3C00), 1.0);
+        assert_eq!(half_to_f64(0xC000), -2.0);
+        assert_eq!(half_to_f64(0x7BFF), 65504.0);
+        assert_eq!(half_to_f64(0x0001), 2f64.powi(-24));
+        assert_eq!(half_to_f64(0x7C00), f64::INFINITY);
+        assert!(half_to_f64(0x7E00).is_nan());
+    }
+
+    #[test]
+    fn hostile_lengths_are_rejected() {
+        for major in [MAJOR_BYTES, MAJOR_TEXT, MAJOR_ARRAY, MAJOR_MAP] {
+            for length in [u64::MAX, u32::MAX as u64 + 1, 1 << 20, 4] {
+                let mut bytes = head(major, length);
+                // Some data, but not as much as the length says.
+                bytes.extend_from_slice(&[0; 3]);
+                assert!(decode(&bytes).is_none(), "major {major}, length {length}");
+            }
+        }
+        // A map needs two items per entry.
+        let mut bytes = head(MAJOR_MAP, 2);
+        bytes.extend_from_slice(&[0; 3]);
+        assert!(decode(&bytes).is_none());
+    }
+
+    #[test]
+    fn truncated_heads_are_rejected() {
+        // Heads that promise 1, 2, 4 and 8 bytes of argument, one short.
+        for (additional, size) in [(24, 1), (25, 2), (26, 4), (27, 8)] {
+            for major in [MAJOR_UNSIGNED, MAJOR_BYTES, MAJOR_ARRAY, MAJOR_SIMPLE] {
+                let mut bytes = vec![(major << 5) | additional];
+                bytes.resize(size, 0);
+                assert!(
+                    decode(&bytes).is_none(),
+                    "major {major}, additional {additional}"
+                );
+            }
+        }
+        // Reserved additional values and a lone break.
+        assert!(decode(&[0x1C]).is_none());
+        assert!(decode(&[BREAK]).is_none());
+        assert!(decode(&[]).is_none());
+    }
+}
@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use bytes::BytesMut;
+use renet::{ChannelConfig, SendType};
+
+// Start - Channel setup shared with the server
+// The server's ConnectionConfig must use the same channel ids and send types.
+//
+// Reliable ordered: always arrives, in the order it was sent. For events and anything that must not be lost.
+// Reliable unordered: always arrives, in any order. For independent events that shouldn't wait on each other.
+// Unreliable: may be lost or arrive out of order. For data that is sent again constantly anyway.
+// Unreliable sequenced: may be lost, but never arrives after a newer message. For state updates where only
+// the latest one matters. Renet doesn't have this, so it is an unreliable channel plus a sequence number.
+pub const RELIABLE_ORDERED: u8 = 0;
+pub const RELIABLE_UNORDERED: u8 = 1;
+pub const UNRELIABLE: u8 = 2;
+pub const UNRELIABLE_SEQUENCED: u8 = 3;
+pub const CHANNEL_COUNT: usize = 4;
+
+// Names the channels can be given by instead of their ids, indexed by channel id.
+pub const CHANNEL_NAMES: [&str; CHANNEL_COUNT] = [
+    "reliable_ordered",
+    "reliable_unordered",
+    "unreliable",
+    "unreliable_sequenced",
+];
+
+pub fn channel_by_name(name: &str) -> Option<u8> {
+    return CHANNEL_NAMES
+        .iter()
+        .position(|channel| *channel == name)
+        .map(|channel_id| channel_id as u8);
+}
+
+// Renet acknowledges reliable messages when they arrive, so the server won't send one again if we drop it.
+pub fn is_reliable(channel_id: u8) -> bool {
+    return channel_id == RELIABLE_ORDERED || channel_id == RELIABLE_UNORDERED;
+}
+
+// Same as renet's default channels.
+const RESEND_TIME: Duration = Duration::from_millis(300);
+
+pub fn channels_config(memory_budgets: [usize; CHANNEL_COUNT]) -> Vec<ChannelConfig> {
+    return (0..CHANNEL_COUNT)
+        .map(|channel_id| ChannelConfig {
+            channel_id: channel_id as u8,
+            max_memory_usage_bytes: memory_budgets[channel_id],
+            send_type: send_type(channel_id as u8),
+        })
+        .collect();
+}
+
+fn send_type(channel_id: u8) -> SendType {
+    return match channel_id {
+        RELIABLE_ORDERED => SendType::ReliableOrdered {
+            resend_time: RESEND_TIME,
+        },
+        RELIABLE_UNORDERED => SendType::ReliableUnordered {
+            resend_time: RESEND_TIME,
+        },
+        _ => SendType::Unreliable,
+    };
+}
+
+// Messages on the unreliable sequenced channel start with a 16 bit sequence number, so the receiver can drop
+// messages that arrive after a newer one. Skipped and late sequence numbers are counted, which tells loss on
+// the way apart from a server that sent nothing.
+#[derive(Default)]
+pub struct Sequencer {
+    outbound: u16,
+    newest_inbound: Option<u16>,
+    pub stats: SequenceStats,
+    // Missing messages since `take_missing` was last called.
+    missing_since_taken: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct SequenceStats {
+    // Times one or more sequence numbers were skipped, and how many were skipped in total. Messages that
+    // show up late were counted as missing first.
+    pub gaps: u64,
+    pub missing: u64,
+    // Arrived after a newer message, so they were dropped.
+    pub reordered: u64,
+}
+
+impl Sequencer {
+    /// Appends the next sequence number followed by the message to `buffer`.
+    pub fn wrap(&mut self, message: &[u8], buffer: &mut BytesMut) {
+        self.outbound = self.outbound.wrapping_add(1);
+        buffer.extend_from_slice(&self.outbound.to_le_bytes());
+        buffer.extend_from_slice(message);
+    }
+
+    /// Returns the message without its sequence number, or `None` if it is older than one we already got.
+    pub fn unwrap<'a>(&mut self, message: &'a [u8]) -> Option<&'a [u8]> {
+        if message.len() < 2 {
+            return None;
+        }
+
+        let (sequence, body) = message.split_at(2);
+        let sequence = u16::from_le_bytes([sequence[0], sequence[1]]);
+        if let Some(newest) = self.newest_inbound {
+            // Wrapping comparison, so the sequence can roll over.
+            let ahead = sequence.wrapping_sub(newest) as i16;
+            if ahead <= 0 {
+                self.stats.reordered += 1;
+                return None;
+            }
+            if ahead > 1 {
+                self.stats.gaps += 1;
+                self.stats.missing += (ahead - 1) as u64;
+                self.missing_since_taken += (ahead - 1) as u64;
+            }
+        }
+
+        self.newest_inbound = Some(sequence);
+        return Some(body);
+    }
+
+    /// Returns how many messages were skipped since the last call.
+    pub fn take_missing(&mut self) -> u64 {
+        return std::mem::take(&mut self.missing_since_taken);
+    }
+}
+// End - Channel setup shared with the server
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(sender: &mut Sequencer, message: &[u8]) -> Vec<u8> {
+        let mut buffer = BytesMut::new();
+        sender.wrap(message, &mut buffer);
+        return buffer.to_vec();
+    }
+
+    fn with_sequence(sequence: u16) -> Vec<u8> {
+        let mut message = sequence.to_le_bytes().to_vec();
+        message.push(sequence as u8);
+        return message;
+    }
+
+    #[test]
+    fn in_order_messages_pass() {
+        let (mut sender, mut receiver) = (Sequencer::default(), Sequencer::default());
+        for body in [b"a", b"b", b"c"] {
+            let message = wrapped(&mut sender, body);
+            assert_eq!(receiver.unwrap(&message), Some(&body[..]));
+        }
+        assert_eq!(receiver.stats.gaps, 0);
+        assert_eq!(receiver.stats.reordered, 0);
+    }
+
+    #[test]
+    fn older_messages_are_dropped() {
+        let mut receiver = Sequencer::default();
+        assert!(receiver.unwrap(&with_sequence(5)).is_some());
+        assert!(receiver.unwrap(&with_sequence(4)).is_none());
+        // Copies of the newest one too.
+        assert!(receiver.unwrap(&with_sequence(5)).is_none());
+        assert_eq!(receiver.stats.reordered, 2);
+    }
+
+    #[test]
+    fn gaps_are_counted() {
+        let mut receiver = Sequencer::default();
+        for sequence in [1, 2, 5, 6, 8] {
+            assert!(receiver.unwrap(&with_sequence(sequence)).is_some());
+        }
+        assert_eq!(receiver.stats.gaps, 2);
+        assert_eq!(receiver.stats.missing, 3);
+        assert_eq!(receiver.take_missing(), 3);
+        assert_eq!(receiver.take_missing(), 0);
+    }
+
+    #[test]
+    fn sequences_wrap_around() {
+        let mut receiver = Sequencer::default();
+        for sequence in [u16::MAX - 1, u16::MAX, 0, 1] {
+            assert!(receiver.unwrap(&with_sequence(sequence)).is_some());
+        }
+        assert_eq!(receiver.stats.gaps, 0);
+
+        // A gap across the wrap, and a message from before it arriving late.
+        assert!(receiver.unwrap(&with_sequence(3)).is_some());
+        assert!(receiver.unwrap(&with_sequence(u16::MAX)).is_none());
+        assert_eq!(receiver.stats.missing, 1);
+        assert_eq!(receiver.stats.reordered, 1);
+
+        let mut sender = Sequencer {
+            outbound: u16::MAX - 1,
+            ..Default::default()
+        };
+        assert_eq!(wrapped(&mut sender, b"")[..2], u16::MAX.to_le_bytes());
+        assert_eq!(wrapped(&mut sender, b"")[..2], 0u16.to_le_bytes());
+    }
+
+    #[test]
+    fn messages_without_a_sequence_are_rejected() {
+        let mut receiver = Sequencer::default();
+        assert!(receiver.unwrap(&[]).is_none());
+        assert!(receiver.unwrap(&[1]).is_none());
+        assert_eq!(receiver.unwrap(&[1, 0]), Some(&[][..]));
+    }
+}
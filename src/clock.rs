@@ -0,0 +1,37 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime},
+};
+
+// Start - Monotonic unix time
+// SystemTime jumps when the OS corrects its clock or the player changes it, which mid-session throws off
+// anything timed with it: netcode's packet timestamps, token expiry, clock sync with the server. Unix time
+// here is read once, when the extension loads, and moved forward by a monotonic clock from then on, so it
+// never jumps. It drifts from the OS clock only by the corrections made since, which is what we want ignored.
+// Times meant for people or files, like a save's modified_at or a report's created_at, still use SystemTime.
+
+static ANCHOR: OnceLock<(Instant, Duration)> = OnceLock::new();
+
+/// Called when the extension loads, so the anchor is taken before anything is timed.
+pub fn init() {
+    unix_time();
+}
+
+pub fn unix_time() -> Duration {
+    let (instant, unix_time) = ANCHOR.get_or_init(|| {
+        let unix_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        return (Instant::now(), unix_time);
+    });
+    return *unix_time + instant.elapsed();
+}
+
+pub fn unix_seconds() -> f64 {
+    return unix_time().as_secs_f64();
+}
+
+pub fn unix_micros() -> u64 {
+    return unix_time().as_micros() as u64;
+}
+// End - Monotonic unix time
@@ -0,0 +1,104 @@
+use std::collections::VecDeque;
+
+use crate::clock;
+
+// Start - Clock sync
+// Netcode's keepalives tell us the round trip, but not how our clock lines up with the server's or whether
+// the way there is slower than the way back. For that we send our own pings with the time we sent them, and
+// the server answers with that time, when the ping arrived and when it sent the answer (all unix time in
+// microseconds). Like NTP, the offset is worked out assuming both ways take as long:
+//   offset = ((arrived - sent) + (answered - received)) / 2
+// Samples with a long round trip are the most likely to have been held up on one side, so the offset is taken
+// from the quickest recent sample. Against that offset, the other samples show how long each way took.
+
+// How many recent samples are kept.
+const MAX_SAMPLES: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    // All in seconds.
+    rtt: f64,
+    offset: f64,
+    sent: f64,
+    arrived: f64,
+    answered: f64,
+    received: f64,
+}
+
+#[derive(Default)]
+pub struct ClockSync {
+    next_ping_id: u32,
+    // Seconds since the last ping was sent.
+    since_ping: f64,
+    samples: VecDeque<Sample>,
+}
+
+pub fn now_micros() -> u64 {
+    return clock::unix_micros();
+}
+
+impl ClockSync {
+    /// Returns the id of the ping to send now, if one is due.
+    pub fn due(&mut self, delta: f64, interval: f64) -> Option<u32> {
+        self.since_ping += delta;
+        if interval <= 0.0 || self.since_ping < interval {
+            return None;
+        }
+        self.since_ping = 0.0;
+        self.next_ping_id = self.next_ping_id.wrapping_add(1);
+        return Some(self.next_ping_id);
+    }
+
+    /// Adds the server's answer, received at `received`. Answers to pings we didn't send are ignored.
+    pub fn pong(&mut self, ping_id: u32, sent: u64, arrived: u64, answered: u64, received: u64) {
+        // Answers come back on an unreliable channel, anything from before the last few pings is stale.
+        if self.next_ping_id.wrapping_sub(ping_id) >= MAX_SAMPLES as u32 || answered < arrived {
+            return;
+        }
+        let seconds = |micros: u64| micros as f64 / 1_000_000.0;
+        let (sent, arrived, answered, received) = (
+            seconds(sent),
+            seconds(arrived),
+            seconds(answered),
+            seconds(received),
+        );
+        let rtt = (received - sent) - (answered - arrived);
+        if rtt < 0.0 {
+            return;
+        }
+
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            rtt,
+            offset: ((arrived - sent) + (answered - received)) / 2.0,
+            sent,
+            arrived,
+            answered,
+            received,
+        });
+    }
+
+    /// Seconds to add to our clock to get the server's, None before the first answer.
+    pub fn offset(&self) -> Option<f64> {
+        return self
+            .samples
+            .iter()
+            .min_by(|a, b| a.rtt.total_cmp(&b.rtt))
+            .map(|sample| sample.offset);
+    }
+
+    /// Average seconds the way to the server and back took, measured against `offset`.
+    pub fn one_way_delays(&self) -> Option<(f64, f64)> {
+        let offset = self.offset()?;
+        let count = self.samples.len() as f64;
+        let (mut up, mut down) = (0.0, 0.0);
+        for sample in &self.samples {
+            up += sample.arrived - offset - sample.sent;
+            down += sample.received - (sample.answered - offset);
+        }
+        return Some(((up / count).max(0.0), (down / count).max(0.0)));
+    }
+}
+// End - Clock sync
@@ -0,0 +1,107 @@
+use std::{io, net::SocketAddr};
+
+use godot::{
+    engine::{file_access::ModeFlags, DirAccess, FileAccess, Os},
+    prelude::*,
+};
+
+use renet::transport::{ConnectToken, NetcodeTransportError};
+
+use crate::clock;
+
+// Start - Moving a session onto a new connect token
+// Connect tokens from the backend expire, and the keys netcode encrypts with come from the token. Shortly
+// before that `credentials_expiring` is emitted so the game can fetch a new token, and `rotate_credentials`
+// connects with it. Netcode servers only take one connection per client id, so the current connection is
+// disconnected first, and the new one replaces it inside the same GameSession: entities, subscriptions,
+// pending requests and stats carry over, and the server is asked to move our session onto it with a reclaim.
+// The outbox is sent again, since reliable messages the old connection hadn't delivered are lost with it.
+
+pub fn read_connect_token(bytes: &[u8]) -> Result<ConnectToken, NetcodeTransportError> {
+    let mut reader = bytes;
+    return Ok(ConnectToken::read(&mut reader)?);
+}
+
+// Tokens can list several servers. Netcode tries them in order, so the first one decides the socket family.
+pub fn token_server_addr(token: &ConnectToken) -> Result<SocketAddr, NetcodeTransportError> {
+    return token
+        .server_addresses
+        .iter()
+        .flatten()
+        .next()
+        .copied()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the connect token has no server address",
+            )
+            .into()
+        });
+}
+
+// Netcode's own timeout for unsecure connections, which have no token to say otherwise.
+pub const UNSECURE_TIMEOUT_SECONDS: f64 = 15.0;
+
+/// How long netcode waits without a packet before it disconnects. Infinite for tokens that turn it off.
+pub fn timeout_seconds(token: &ConnectToken) -> f64 {
+    if token.timeout_seconds < 0 {
+        return f64::INFINITY;
+    }
+    return token.timeout_seconds as f64;
+}
+// End - Moving a session onto a new connect token
+
+// Start - Connect token cache
+// The newest connect token is kept on disk, so a client that crashed mid match can rejoin right away instead of
+// going through the backend again. The file is encrypted with a key only this device has, so copying it
+// elsewhere doesn't hand out the session.
+
+pub const TOKEN_CACHE_PATH: &str = "user://connect_token.bin";
+
+// Platforms without a unique id (the web) get no cache rather than a key everyone knows.
+pub fn device_key() -> Option<GString> {
+    let id = Os::singleton().get_unique_id();
+    if id.is_empty() {
+        return None;
+    }
+    return Some(id);
+}
+
+pub fn save_cached_token(path: GString, connect_token: &[u8]) -> bool {
+    let Some(key) = device_key() else {
+        return false;
+    };
+    let Some(mut file) = FileAccess::open_encrypted_with_pass(path, ModeFlags::WRITE, key) else {
+        return false;
+    };
+
+    file.store_buffer(PackedByteArray::from(connect_token));
+    file.close();
+    return true;
+}
+
+/// Returns the cached token if it's still valid for at least `min_seconds_left`.
+pub fn load_cached_token(path: GString, min_seconds_left: f64) -> Option<Vec<u8>> {
+    let key = device_key()?;
+    if !FileAccess::file_exists(path.clone()) {
+        return None;
+    }
+    let mut file = FileAccess::open_encrypted_with_pass(path.clone(), ModeFlags::READ, key)?;
+    let length = file.get_length() as i64;
+    let connect_token = file.get_buffer(length).to_vec();
+    file.close();
+
+    let token = read_connect_token(&connect_token).ok()?;
+    if (token.expire_timestamp as f64) - clock::unix_seconds() < min_seconds_left {
+        clear_cached_token(path);
+        return None;
+    }
+    return Some(connect_token);
+}
+
+pub fn clear_cached_token(path: GString) {
+    if FileAccess::file_exists(path.clone()) {
+        DirAccess::remove_absolute(path);
+    }
+}
+// End - Connect token cache
@@ -0,0 +1,90 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::clock;
+
+// Start - Suppresses messages that were delivered twice
+// Messages that may be resent after a reconnect carry an idempotency key. We remember the newest keys we've
+// handled, and a message with a key we still remember was handled already. The window is kept across
+// sessions, since a reconnect is exactly when the copies show up.
+
+// How many keys are remembered. Older keys are forgotten first.
+pub const DEDUP_WINDOW_SIZE: usize = 1024;
+
+pub struct DedupWindow {
+    keys: HashSet<u64>,
+    // Oldest first.
+    order: VecDeque<u64>,
+}
+
+impl Default for DedupWindow {
+    fn default() -> Self {
+        return Self {
+            keys: HashSet::with_capacity(DEDUP_WINDOW_SIZE),
+            order: VecDeque::with_capacity(DEDUP_WINDOW_SIZE),
+        };
+    }
+}
+
+impl DedupWindow {
+    /// Returns false if the key was seen before, so the message is a copy.
+    pub fn insert(&mut self, key: u64) -> bool {
+        if !self.keys.insert(key) {
+            return false;
+        }
+
+        self.order.push_back(key);
+        if self.order.len() > DEDUP_WINDOW_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        return true;
+    }
+}
+
+/// Returns an id larger than `last`. Ids start at the current time in microseconds, so they stay unique
+/// across restarts without storing anything. See clock.rs for why the time can't jump back.
+pub fn next_unique_id(last: u64) -> u64 {
+    return clock::unix_micros().max(last + 1);
+}
+// End - Suppresses messages that were delivered twice
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_are_caught() {
+        let mut window = DedupWindow::default();
+        assert!(window.insert(1));
+        assert!(window.insert(2));
+        assert!(!window.insert(1));
+        assert!(!window.insert(2));
+    }
+
+    #[test]
+    fn the_oldest_keys_are_forgotten_first() {
+        let mut window = DedupWindow::default();
+        for key in 0..DEDUP_WINDOW_SIZE as u64 {
+            assert!(window.insert(key));
+        }
+        // Still remembered, and a copy doesn't push anything out.
+        assert!(!window.insert(0));
+
+        assert!(window.insert(DEDUP_WINDOW_SIZE as u64));
+        assert!(
+            window.insert(0),
+            "the oldest key should have been forgotten"
+        );
+        assert!(!window.insert(2));
+        assert_eq!(window.keys.len(), DEDUP_WINDOW_SIZE);
+        assert_eq!(window.order.len(), DEDUP_WINDOW_SIZE);
+    }
+
+    #[test]
+    fn unique_ids_increase() {
+        let first = next_unique_id(0);
+        assert!(next_unique_id(first) > first);
+        assert_eq!(next_unique_id(u64::MAX - 1), u64::MAX);
+    }
+}
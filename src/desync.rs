@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use godot::prelude::*;
+
+// Start - Desync detection for deterministic games
+// Every `interval` server ticks GDScript hashes the state it simulates, and the server sends its own hash
+// for the same ticks. Whichever arrives second does the comparison, so it doesn't matter if the server is
+// ahead of us or behind. Only the last few ticks are kept, a hash that never finds its partner is dropped.
+
+// How many ticks of hashes are kept waiting for their partner.
+const MAX_PENDING: usize = 64;
+
+pub struct DesyncChecker {
+    interval: u32,
+    checksum_callback: Callable,
+    // Optional, asked for a description of the state when a desync is found.
+    dump_callback: Callable,
+    local: BTreeMap<u32, i64>,
+    server: BTreeMap<u32, i64>,
+    // The newest tick we hashed.
+    last_checked: Option<u32>,
+}
+
+pub struct Desync {
+    pub tick: u32,
+    pub local_checksum: i64,
+    pub server_checksum: i64,
+}
+
+impl DesyncChecker {
+    pub fn new(interval: u32, checksum_callback: Callable, dump_callback: Callable) -> Self {
+        return Self {
+            interval: interval.max(1),
+            checksum_callback,
+            dump_callback,
+            local: BTreeMap::new(),
+            server: BTreeMap::new(),
+            last_checked: None,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        self.local.clear();
+        self.server.clear();
+        self.last_checked = None;
+    }
+
+    /// Returns the tick to hash, if `server_tick` reached a checked tick we haven't hashed yet. Ticks skipped
+    /// by a slow frame aren't made up, since the state for them is already gone.
+    pub fn due_tick(&mut self, server_tick: u32) -> Option<u32> {
+        let tick = server_tick - server_tick % self.interval;
+        if let Some(last) = self.last_checked {
+            if (tick.wrapping_sub(last) as i32) <= 0 {
+                return None;
+            }
+        }
+        self.last_checked = Some(tick);
+        return Some(tick);
+    }
+
+    // The callbacks are handed out rather than called here, so GDScript runs while the manager isn't
+    // borrowed and can call back into it.
+    pub fn checksum_callback(&self) -> Callable {
+        return self.checksum_callback.clone();
+    }
+
+    pub fn dump_callback(&self) -> Option<Callable> {
+        return self
+            .dump_callback
+            .is_valid()
+            .then(|| self.dump_callback.clone());
+    }
+
+    pub fn add_local_checksum(&mut self, tick: u32, checksum: i64) -> Option<Desync> {
+        return self.add(tick, checksum, true);
+    }
+
+    pub fn add_server_checksum(&mut self, tick: u32, checksum: i64) -> Option<Desync> {
+        return self.add(tick, checksum, false);
+    }
+
+    fn add(&mut self, tick: u32, checksum: i64, local: bool) -> Option<Desync> {
+        let (own, other) = match local {
+            true => (&mut self.local, &mut self.server),
+            false => (&mut self.server, &mut self.local),
+        };
+
+        if let Some(other_checksum) = other.remove(&tick) {
+            if other_checksum == checksum {
+                return None;
+            }
+            let (local_checksum, server_checksum) = match local {
+                true => (checksum, other_checksum),
+                false => (other_checksum, checksum),
+            };
+            return Some(Desync {
+                tick,
+                local_checksum,
+                server_checksum,
+            });
+        }
+
+        own.insert(tick, checksum);
+        while own.len() > MAX_PENDING {
+            own.pop_first();
+        }
+        return None;
+    }
+}
+// End - Desync detection for deterministic games
@@ -0,0 +1,211 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::Instant,
+};
+
+use godot::prelude::*;
+
+use crate::{
+    probe::{Prober, PROBE_MAGIC, PROBE_TIMEOUT},
+    stun::{self, StunQuery},
+    transport::SocketOptions,
+};
+
+#[cfg(not(target_family = "wasm"))]
+use crate::transport;
+
+// Start - Connection self-test
+// What a "test connection" button in the settings runs. Every check is independent, so one failing still
+// leaves the others to narrow the problem down:
+//   sockets    can we open UDP sockets at all, for IPv4 and IPv6
+//   echo       does an echo endpoint answer probes (see probe.rs), and how quickly
+//   datagrams  the largest probe that still makes it there and back, see `DATAGRAM_SIZES`
+//   nat        what a STUN server says our public endpoint is, and what that says about our NAT
+// The checks run at the same time and are done within a few seconds.
+
+// UDP payload sizes tried, in bytes. 1472 fills a 1500 byte Ethernet frame, 1280 is the IPv6 minimum MTU and
+// 576 the smallest datagram every IPv4 host has to take. Renet keeps its packets under 1200, so anything that
+// fails below that is a problem. Bigger datagrams get fragmented rather than dropped where the path is
+// smaller, which is fine as long as the fragments make it, so this finds what gets through, not the MTU.
+const DATAGRAM_SIZES: [usize; 5] = [576, 1200, 1280, 1400, 1472];
+const DATAGRAM_ROUNDS: u32 = 3;
+// Seconds between rounds of datagram probes.
+const DATAGRAM_INTERVAL: f64 = 0.2;
+
+// Sends a probe of each size in `DATAGRAM_SIZES`, padded with zeros after the probe header. The nonce is the
+// size, since that's all a reply has to tell us.
+struct DatagramProbe {
+    socket: UdpSocket,
+    target: SocketAddr,
+    started_at: Instant,
+    rounds_sent: u32,
+    largest: Option<usize>,
+    receive_buffer: [u8; 2048],
+}
+
+impl DatagramProbe {
+    #[cfg(target_family = "wasm")]
+    fn new(_target: SocketAddr) -> io::Result<Self> {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "UDP sockets aren't available in web builds",
+        ));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    fn new(target: SocketAddr) -> io::Result<Self> {
+        let socket = transport::bind_socket(target, &SocketOptions::default())?;
+        socket.set_nonblocking(true)?;
+        return Ok(Self {
+            socket,
+            target,
+            started_at: Instant::now(),
+            rounds_sent: 0,
+            largest: None,
+            receive_buffer: [0; 2048],
+        });
+    }
+
+    /// Returns true once the largest size answered, or the timeout has passed.
+    fn update(&mut self) -> bool {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        if self.rounds_sent < DATAGRAM_ROUNDS
+            && elapsed >= self.rounds_sent as f64 * DATAGRAM_INTERVAL
+        {
+            self.rounds_sent += 1;
+            for size in DATAGRAM_SIZES {
+                let mut packet = vec![0; size];
+                packet[..4].copy_from_slice(&PROBE_MAGIC);
+                packet[4..12].copy_from_slice(&(size as u64).to_le_bytes());
+                // Too big for the local interface is the same as lost on the way.
+                let _ = self.socket.send_to(&packet, self.target);
+            }
+        }
+
+        while let Ok((size, from)) = self.socket.recv_from(&mut self.receive_buffer) {
+            let packet = &self.receive_buffer[..size];
+            if from != self.target || size < 12 || packet[..4] != PROBE_MAGIC {
+                continue;
+            }
+            let nonce = u64::from_le_bytes(packet[4..12].try_into().unwrap());
+            if nonce == size as u64 {
+                self.largest = Some(self.largest.map_or(size, |largest| largest.max(size)));
+            }
+        }
+
+        return self.largest == DATAGRAM_SIZES.last().copied() || elapsed >= PROBE_TIMEOUT;
+    }
+}
+
+pub struct Diagnostics {
+    results: Dictionary,
+    echo: Option<Prober>,
+    datagrams: Option<DatagramProbe>,
+    stun: Option<StunQuery>,
+}
+
+impl Diagnostics {
+    /// Starts every check. `echo` and `stun` are None when not configured or the name didn't resolve, in
+    /// which case their results say so.
+    pub fn start(echo: Option<SocketAddr>, stun: Option<SocketAddr>) -> Self {
+        let mut diagnostics = Self {
+            results: Dictionary::new(),
+            echo: None,
+            datagrams: None,
+            stun: None,
+        };
+        let mut errors = PackedStringArray::new();
+
+        // Binding without a target, like the OS would for any game.
+        for (key, address) in [("ipv4", "0.0.0.0:0"), ("ipv6", "[::]:0")] {
+            let bound = UdpSocket::bind(address);
+            if let Err(error) = &bound {
+                errors.push(format!("{key} socket: {error}").into());
+            }
+            diagnostics.results.set(key, bound.is_ok());
+        }
+
+        diagnostics.results.set("echo_reachable", false);
+        diagnostics.results.set("echo_rtt_ms", -1.0);
+        diagnostics.results.set("max_datagram_size", -1);
+        if let Some(echo) = echo {
+            match Prober::new(&[echo]) {
+                Ok(prober) => diagnostics.echo = Some(prober),
+                Err(error) => errors.push(format!("echo: {error}").into()),
+            }
+            match DatagramProbe::new(echo) {
+                Ok(probe) => diagnostics.datagrams = Some(probe),
+                Err(error) => errors.push(format!("datagrams: {error}").into()),
+            }
+        }
+
+        diagnostics.results.set("public_endpoint", GString::new());
+        diagnostics.results.set("nat", "unknown");
+        if let Some(stun) = stun {
+            match StunQuery::new(stun, &SocketOptions::default()) {
+                Ok(query) => diagnostics.stun = Some(query),
+                Err(error) => errors.push(format!("stun: {error}").into()),
+            }
+        }
+
+        diagnostics.results.set("errors", errors);
+        return diagnostics;
+    }
+
+    /// Returns true once every check is done.
+    pub fn update(&mut self) -> bool {
+        if let Some(echo) = &mut self.echo {
+            if echo.update() {
+                let rtt = echo.results().next().and_then(|(_, rtt)| rtt);
+                self.results.set("echo_reachable", rtt.is_some());
+                self.results
+                    .set("echo_rtt_ms", rtt.map_or(-1.0, |rtt| rtt * 1000.0));
+                self.echo = None;
+            }
+        }
+
+        if let Some(datagrams) = &mut self.datagrams {
+            if datagrams.update() {
+                let largest = datagrams.largest.map_or(-1, |size| size as i64);
+                self.results.set("max_datagram_size", largest);
+                self.datagrams = None;
+            }
+        }
+
+        if let Some(stun) = &mut self.stun {
+            if let Some(result) = stun.update() {
+                match result {
+                    Ok(endpoint) => {
+                        let nat = match stun::is_own_address(endpoint.ip()) {
+                            true => "open",
+                            false => "behind_nat",
+                        };
+                        self.results
+                            .set("public_endpoint", GString::from(endpoint.to_string()));
+                        self.results.set("nat", nat);
+                    }
+                    Err(error) => self.add_error(format!("stun: {error}")),
+                }
+                self.stun = None;
+            }
+        }
+
+        return self.echo.is_none() && self.datagrams.is_none() && self.stun.is_none();
+    }
+
+    pub fn results(&self) -> Dictionary {
+        return self.results.clone();
+    }
+
+    fn add_error(&mut self, error: String) {
+        let mut errors = self
+            .results
+            .get("errors")
+            .and_then(|errors| errors.try_to::<PackedStringArray>().ok())
+            .unwrap_or_default();
+        errors.push(error.into());
+        self.results.set("errors", errors);
+    }
+}
+// End - Connection self-test
@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+
+use godot::prelude::*;
+
+// Start - Trying other routes to the server
+// Some networks block the game's UDP port, or the direct route to the server is broken. `join_session_failover`
+// takes the ways to reach the server in order, like the server itself and then a relay that forwards to it,
+// and moves on to the next one whenever the handshake fails in a way another route could fix. A server that
+// denies us would deny us through a relay too.
+//
+// Every route is UDP. Renet's netcode transport only runs over a UdpSocket, so a WebSocket route has to wait
+// for a transport that isn't tied to one. Connect tokens already list several server addresses, which
+// netcode tries in order by itself, so this is only for joining without a token.
+
+pub struct Failover {
+    pub client_id: u64,
+    // The name and address of each route, in the order they are tried.
+    routes: Vec<(GString, SocketAddr)>,
+    current: usize,
+}
+
+impl Failover {
+    pub fn new(client_id: u64, routes: Vec<(GString, SocketAddr)>) -> Self {
+        return Self {
+            client_id,
+            routes,
+            current: 0,
+        };
+    }
+
+    /// The route being tried, None once every route failed.
+    pub fn current(&self) -> Option<(GString, SocketAddr)> {
+        return self.routes.get(self.current).cloned();
+    }
+
+    pub fn advance(&mut self) {
+        self.current += 1;
+    }
+}
+// End - Trying other routes to the server
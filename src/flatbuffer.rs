@@ -0,0 +1,154 @@
+#[cfg(feature = "flatbuffers")]
+use flatbuffers::InvalidFlatbuffer;
+#[cfg(feature = "flatbuffers")]
+use godot::prelude::*;
+
+#[cfg(feature = "flatbuffers")]
+use crate::{channels::CHANNEL_COUNT, GameplaySessionManager};
+
+// Start - FlatBuffers payloads handled in Rust
+// Only does anything with the `flatbuffers` feature, for studios whose server already speaks FlatBuffers.
+// Generate Rust code from the schema with `flatc --rust`, add it to the crate as a module, and register a
+// handler for the channel the server sends those payloads on with the GameplaySessionManager node:
+//
+//     flatbuffer::register_handler(
+//         &manager,
+//         channels::RELIABLE_ORDERED,
+//         flatbuffer_handler!(Monster, |monster: Monster| { ... }),
+//     );
+//
+// Every application payload on that channel is verified before the handler sees it, and the handler reads
+// it in place through the generated accessors. Payloads that fail verification are rejected through
+// `message_rejected`, and handled payloads are not emitted through `message_received`.
+
+/// Verifies a payload and hands its root table to the handler. Returns the verification error otherwise.
+#[cfg(feature = "flatbuffers")]
+pub type FlatBufferHandler = Box<dyn FnMut(&[u8]) -> Result<(), InvalidFlatbuffer>>;
+
+/// Makes a `FlatBufferHandler` for payloads whose root table is `$root`.
+#[cfg(feature = "flatbuffers")]
+#[macro_export]
+macro_rules! flatbuffer_handler {
+    ($root:ty, $handler:expr) => {{
+        // Handlers that only read what they capture don't need the mut.
+        #[allow(unused_mut)]
+        let mut handler = $handler;
+        Box::new(move |payload: &[u8]| {
+            let root = flatbuffers::root::<$root>(payload)?;
+            handler(root);
+            return Ok(());
+        }) as $crate::flatbuffer::FlatBufferHandler
+    }};
+}
+
+// Exists without the feature too, so GameplaySessionManager doesn't need a field that comes and goes.
+#[derive(Default)]
+pub struct FlatBufferHandlers {
+    // Indexed by channel id.
+    #[cfg(feature = "flatbuffers")]
+    handlers: [Option<FlatBufferHandler>; CHANNEL_COUNT],
+}
+
+/// Sends the channel's application payloads to the handler, replacing the one it had. Returns false if
+/// `manager` isn't a GameplaySessionManager or the channel doesn't exist.
+#[cfg(feature = "flatbuffers")]
+pub fn register_handler(manager: &Gd<Node>, channel_id: u8, handler: FlatBufferHandler) -> bool {
+    let Ok(mut manager) = manager.clone().try_cast::<GameplaySessionManager>() else {
+        godot_error!("register_handler: {manager} isn't a GameplaySessionManager");
+        return false;
+    };
+    if channel_id as usize >= CHANNEL_COUNT {
+        godot_error!("register_handler: unknown channel {channel_id}");
+        return false;
+    }
+
+    manager
+        .bind_mut()
+        .register_flatbuffer_handler(channel_id, handler);
+    return true;
+}
+
+impl FlatBufferHandlers {
+    #[cfg(feature = "flatbuffers")]
+    pub fn register(&mut self, channel_id: u8, handler: FlatBufferHandler) {
+        self.handlers[channel_id as usize] = Some(handler);
+    }
+
+    /// Returns `None` if the channel has no handler, otherwise whether the payload passed verification.
+    pub fn handle(&mut self, channel_id: u8, payload: &[u8]) -> Option<Result<(), String>> {
+        #[cfg(feature = "flatbuffers")]
+        if let Some(handler) = &mut self.handlers[channel_id as usize] {
+            return Some(handler(payload).map_err(|error| error.to_string()));
+        }
+
+        #[cfg(not(feature = "flatbuffers"))]
+        let _ = (channel_id, payload);
+        return None;
+    }
+}
+// End - FlatBuffers payloads handled in Rust
+
+#[cfg(all(test, feature = "flatbuffers"))]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use flatbuffers::FlatBufferBuilder;
+
+    use super::*;
+    use crate::channels::{RELIABLE_ORDERED, UNRELIABLE};
+
+    // A buffer whose root is a string, which needs no generated code to read.
+    fn text_buffer(text: &str) -> Vec<u8> {
+        let mut builder = FlatBufferBuilder::new();
+        let root = builder.create_string(text);
+        builder.finish(root, None);
+        return builder.finished_data().to_vec();
+    }
+
+    #[test]
+    fn verified_buffers_reach_the_handler() {
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let mut handlers = FlatBufferHandlers::default();
+        let sink = received.clone();
+        handlers.register(
+            RELIABLE_ORDERED,
+            crate::flatbuffer_handler!(&str, move |text: &str| sink
+                .borrow_mut()
+                .push(text.to_string())),
+        );
+
+        assert_eq!(
+            handlers.handle(RELIABLE_ORDERED, &text_buffer("hello")),
+            Some(Ok(()))
+        );
+        assert_eq!(*received.borrow(), ["hello"]);
+
+        // Other channels aren't handled.
+        assert_eq!(handlers.handle(UNRELIABLE, &text_buffer("other")), None);
+        assert_eq!(received.borrow().len(), 1);
+    }
+
+    #[test]
+    fn buffers_that_fail_verification_are_rejected() {
+        let received = Rc::new(RefCell::new(0));
+        let mut handlers = FlatBufferHandlers::default();
+        let sink = received.clone();
+        handlers.register(
+            RELIABLE_ORDERED,
+            crate::flatbuffer_handler!(&str, move |_: &str| *sink.borrow_mut() += 1),
+        );
+
+        // The root offset points past the end.
+        let mut buffer = text_buffer("hello");
+        buffer[0] = 0xFF;
+        assert!(matches!(
+            handlers.handle(RELIABLE_ORDERED, &buffer),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            handlers.handle(RELIABLE_ORDERED, &[1, 2]),
+            Some(Err(_))
+        ));
+        assert_eq!(*received.borrow(), 0);
+    }
+}
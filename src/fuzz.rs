@@ -0,0 +1,112 @@
+use std::{fs, io, path::PathBuf};
+
+use bytes::Bytes;
+
+// Start - Tools for hardening message handlers against bad data
+// A compromised or buggy server can send anything, so the game's message handlers should survive garbage.
+// The fuzzer mangles a fraction of the application payloads we receive before the game sees them, and the
+// corpus recorder saves payloads as they arrived so they can be fed to an offline fuzzer or replayed later.
+// Both are for development only, GameplaySessionManager never enables them in release builds.
+
+// Mutated payloads never grow past this, so a fuzzed message can't become absurdly large.
+const MAX_MUTATED_SIZE: usize = 64 * 1024;
+
+pub struct PayloadFuzzer {
+    // Xorshift state. Never 0.
+    state: u64,
+}
+
+impl PayloadFuzzer {
+    pub fn new(seed: u64) -> Self {
+        return Self { state: seed.max(1) };
+    }
+
+    /// Returns the payload with a few random mutations `fraction` of the time, otherwise unchanged.
+    pub fn maybe_mutate(&mut self, payload: Bytes, fraction: f64) -> Bytes {
+        if self.next_f64() >= fraction {
+            return payload;
+        }
+
+        let mut payload = payload.to_vec();
+        let mutations = 1 + self.next_below(4);
+        for _ in 0..mutations {
+            self.mutate(&mut payload);
+        }
+        return Bytes::from(payload);
+    }
+
+    fn mutate(&mut self, payload: &mut Vec<u8>) {
+        let index = self.next_below(payload.len().max(1));
+        match self.next_below(6) {
+            // Flip a bit.
+            0 if !payload.is_empty() => payload[index] ^= 1 << self.next_below(8),
+            // Replace a byte, favouring values that tend to break length and count fields.
+            1 if !payload.is_empty() => {
+                const INTERESTING: [u8; 5] = [0x00, 0x01, 0x7F, 0x80, 0xFF];
+                payload[index] = match self.next_below(2) {
+                    0 => INTERESTING[self.next_below(INTERESTING.len())],
+                    _ => self.next_u64() as u8,
+                };
+            }
+            // Cut it short.
+            2 => payload.truncate(index),
+            // Remove a byte.
+            3 if !payload.is_empty() => {
+                payload.remove(index);
+            }
+            // Repeat a chunk.
+            4 if !payload.is_empty() && payload.len() < MAX_MUTATED_SIZE => {
+                let end = (index + 1 + self.next_below(16)).min(payload.len());
+                let chunk = payload[index..end].to_vec();
+                payload.splice(index..index, chunk);
+            }
+            // Insert a random byte.
+            _ if payload.len() < MAX_MUTATED_SIZE => {
+                let byte = self.next_u64() as u8;
+                payload.insert(index.min(payload.len()), byte);
+            }
+            _ => {}
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    fn next_below(&mut self, bound: usize) -> usize {
+        return (self.next_u64() % bound.max(1) as u64) as usize;
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+}
+
+// Saves every payload as its own file, named `<number>_ch<channel>.bin`, which is the layout most fuzzers
+// expect for a seed corpus.
+pub struct CorpusRecorder {
+    directory: PathBuf,
+    next_index: u64,
+}
+
+impl CorpusRecorder {
+    pub fn new(directory: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        return Ok(Self {
+            directory,
+            next_index: 0,
+        });
+    }
+
+    pub fn record(&mut self, channel_id: u8, payload: &[u8]) -> io::Result<()> {
+        let path = self
+            .directory
+            .join(format!("{:08}_ch{channel_id}.bin", self.next_index));
+        self.next_index += 1;
+        return fs::write(path, payload);
+    }
+}
+// End - Tools for hardening message handlers against bad data
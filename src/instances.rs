@@ -0,0 +1,43 @@
+use std::sync::Mutex;
+
+// Start - One manager per local player
+// Each GameplaySessionManager binds its own socket and runs its own session, which is how split-screen games
+// give every local player a connection. Two managers for the same `player_index` would also share files in
+// user://, like the cached token and the outbox, and end up fighting over them, which is never what was meant.
+// Usually it's an autoload plus a copy left in a scene. Managers claim their index when they enter the tree,
+// and a second claim for the same index is refused.
+
+struct Claim {
+    player_index: i64,
+    instance_id: i64,
+    path: String,
+}
+
+static CLAIMS: Mutex<Vec<Claim>> = Mutex::new(Vec::new());
+
+/// Returns the node path of the manager that already has `player_index`, if another one does.
+pub fn claim(player_index: i64, instance_id: i64, path: String) -> Result<(), String> {
+    let mut claims = CLAIMS.lock().unwrap();
+    if let Some(claim) = claims
+        .iter()
+        .find(|claim| claim.player_index == player_index && claim.instance_id != instance_id)
+    {
+        return Err(claim.path.clone());
+    }
+
+    claims.retain(|claim| claim.instance_id != instance_id);
+    claims.push(Claim {
+        player_index,
+        instance_id,
+        path,
+    });
+    return Ok(());
+}
+
+pub fn release(instance_id: i64) {
+    CLAIMS
+        .lock()
+        .unwrap()
+        .retain(|claim| claim.instance_id != instance_id);
+}
+// End - One manager per local player
@@ -0,0 +1,69 @@
+// Start - Works out how far behind the server we should render
+// Snapshots never arrive exactly one tick apart, so the game renders a little in the past and interpolates
+// between the two snapshots around that time. The delay has to cover the jitter, otherwise the game runs out
+// of snapshots and has to extrapolate. In adaptive mode the delay follows the measured jitter.
+
+// How many snapshot intervals we always keep buffered, the two snapshots we interpolate between.
+const BUFFERED_SNAPSHOTS: f64 = 2.0;
+// How many standard-ish deviations of jitter to cover on top of that.
+const JITTER_MARGIN: f64 = 3.0;
+// Same smoothing as the RFC 3550 jitter estimate.
+const JITTER_SMOOTHING: f64 = 1.0 / 16.0;
+// The delay grows quickly so we stop running dry, but shrinks slowly so it doesn't oscillate.
+const GROW_SMOOTHING: f64 = 0.5;
+const SHRINK_SMOOTHING: f64 = 0.02;
+
+#[derive(Default)]
+pub struct InterpolationDelay {
+    // Tick and arrival time (seconds) of the newest snapshot.
+    last_snapshot: Option<(u32, f64)>,
+    // Seconds.
+    jitter: f64,
+    // Seconds. Only used in adaptive mode.
+    adaptive_delay: Option<f64>,
+}
+
+impl InterpolationDelay {
+    /// Call for every snapshot. `tick_interval` is the server's seconds per tick.
+    pub fn on_snapshot(&mut self, tick: u32, arrival_time: f64, tick_interval: f64) {
+        if let Some((last_tick, last_arrival)) = self.last_snapshot {
+            // Snapshots that arrive out of order say nothing useful about the jitter.
+            let ticks = tick.wrapping_sub(last_tick) as i32;
+            if ticks <= 0 {
+                return;
+            }
+
+            let expected = ticks as f64 * tick_interval;
+            let actual = arrival_time - last_arrival;
+            self.jitter += ((actual - expected).abs() - self.jitter) * JITTER_SMOOTHING;
+        }
+        self.last_snapshot = Some((tick, arrival_time));
+
+        let target = BUFFERED_SNAPSHOTS * tick_interval + JITTER_MARGIN * self.jitter;
+        let delay = self.adaptive_delay.unwrap_or(target);
+        let smoothing = if target > delay {
+            GROW_SMOOTHING
+        } else {
+            SHRINK_SMOOTHING
+        };
+        self.adaptive_delay = Some(delay + (target - delay) * smoothing);
+    }
+
+    pub fn jitter(&self) -> f64 {
+        return self.jitter;
+    }
+
+    /// Returns the delay in seconds. `configured` is used until snapshots arrive, and always when
+    /// `adaptive` is off. In adaptive mode the result is kept within `min..=max`.
+    pub fn delay(&self, configured: f64, adaptive: bool, min: f64, max: f64) -> f64 {
+        if !adaptive {
+            return configured;
+        }
+
+        return self
+            .adaptive_delay
+            .unwrap_or(configured)
+            .clamp(min, max.max(min));
+    }
+}
+// End - Works out how far behind the server we should render
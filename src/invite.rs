@@ -0,0 +1,92 @@
+use godot::{engine::Marshalls, prelude::*};
+
+// Start - Joining from invite links
+// Invites are links like `arcade://join?address=203.0.113.7:7000&client_id=42` or
+// `arcade://join?token=<connect token>`, which the OS hands to the game when the player clicks one. Registering
+// the scheme with the OS is part of the game's installer or export settings, the OS then starts the game with
+// the link as a command line argument. See `join_from_uri`.
+//
+// The query keys we understand:
+//   address    server address, as for join_session, together with client_id
+//   client_id  u64
+//   token      a connect token, base64 or base64url, which is preferred over an address
+//   ticket     a reference the backend swaps for a connect token, left to the game
+//   lobby      a lobby or party id, left to the game
+// Anything else is passed on to the game too.
+
+#[derive(Default)]
+pub struct JoinUri {
+    pub address: Option<String>,
+    pub client_id: Option<u64>,
+    pub connect_token: Option<PackedByteArray>,
+    // Every key and value of the query, decoded, for the game.
+    pub params: Dictionary,
+}
+
+impl JoinUri {
+    pub fn parse(uri: &str, scheme: &str) -> Result<Self, String> {
+        let Some(rest) = uri
+            .trim()
+            .strip_prefix(scheme)
+            .and_then(|rest| rest.strip_prefix("://"))
+        else {
+            return Err(format!("not an {scheme}:// link"));
+        };
+        let (action, query) = rest.split_once('?').unwrap_or((rest, ""));
+        if action.trim_end_matches('/') != "join" {
+            return Err(format!("unknown action '{action}'"));
+        }
+
+        let mut join = Self::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let (Some(key), Some(value)) = (percent_decode(key), percent_decode(value)) else {
+                return Err(format!("'{pair}' isn't valid percent encoding"));
+            };
+            match key.as_str() {
+                "address" => join.address = Some(value.clone()),
+                "client_id" => match value.parse() {
+                    Ok(client_id) => join.client_id = Some(client_id),
+                    Err(_) => return Err(format!("invalid client_id '{value}'")),
+                },
+                "token" => match decode_token(&value) {
+                    Some(connect_token) => join.connect_token = Some(connect_token),
+                    None => return Err("the token isn't base64".into()),
+                },
+                _ => {}
+            }
+            join.params.set(key, value);
+        }
+        return Ok(join);
+    }
+}
+
+// Links can't hold '+' and '/' without escaping, so tokens are often base64url without padding.
+fn decode_token(value: &str) -> Option<PackedByteArray> {
+    let mut encoded = value.replace('-', "+").replace('_', "/");
+    let padding = (4 - encoded.len() % 4) % 4;
+    encoded.push_str(&"=".repeat(padding));
+    let connect_token = Marshalls::singleton().base64_to_raw(encoded.into());
+    if connect_token.is_empty() {
+        return None;
+    }
+    return Some(connect_token);
+}
+
+// '+' is kept as it is, a space would break tokens that weren't escaped.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(byte) = input.next() {
+        match byte {
+            b'%' => {
+                let high = (input.next()? as char).to_digit(16)?;
+                let low = (input.next()? as char).to_digit(16)?;
+                bytes.push((high * 16 + low) as u8);
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    return String::from_utf8(bytes).ok();
+}
+// End - Joining from invite links
@@ -0,0 +1,261 @@
+use std::collections::VecDeque;
+
+use godot::prelude::*;
+
+// Start - Smooths out unreliable streams
+// Unreliable data like voice or transforms arrives out of order, late, or not at all. The jitter buffer holds a
+// few packets back so it can put them in order, and drops packets that arrive after their turn has passed.
+// Senders number their packets with a 16 bit sequence that wraps around.
+//
+//     buffer.push(sequence, payload)   # whenever a packet arrives
+//     var payload = buffer.pop()       # once per playback step, empty if nothing is ready
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+struct JitterBuffer {
+    base: Base<RefCounted>,
+
+    // The most packets held at once. When full, the oldest packet is played even if earlier ones are missing.
+    #[var]
+    #[init(default = 8)]
+    window: i64,
+
+    // How many packets to collect before playback starts, and how long to wait for a missing packet before
+    // skipping it. Higher is smoother, lower has less latency.
+    #[var]
+    #[init(default = 2)]
+    depth: i64,
+
+    queue: JitterQueue<PackedByteArray>,
+}
+
+#[derive(Default)]
+struct JitterBufferStats {
+    received: u64,
+    // Arrived after newer packets, but still in time to be played.
+    reordered: u64,
+    // Arrived after their turn, so they were dropped.
+    late: u64,
+    duplicates: u64,
+    // Never arrived, or arrived when the buffer was full.
+    lost: u64,
+}
+
+// The ordering itself, apart from the class so it can be tested without the engine.
+#[derive(Default)]
+struct JitterQueue<T> {
+    // Sorted by sequence, oldest first.
+    packets: VecDeque<(u16, T)>,
+    // The sequence pop() wants next. None until playback starts.
+    next_sequence: Option<u16>,
+    stats: JitterBufferStats,
+}
+
+impl<T> JitterQueue<T> {
+    fn push(&mut self, sequence: u16, payload: T, window: usize) {
+        self.stats.received += 1;
+
+        if let Some(next) = self.next_sequence {
+            if is_before(sequence, next) {
+                self.stats.late += 1;
+                return;
+            }
+        }
+
+        // Find where it goes, searching from the back since packets mostly arrive in order.
+        let mut index = self.packets.len();
+        while index > 0 {
+            let (existing, _) = &self.packets[index - 1];
+            if *existing == sequence {
+                self.stats.duplicates += 1;
+                return;
+            }
+            if is_before(*existing, sequence) {
+                break;
+            }
+            index -= 1;
+        }
+        if index < self.packets.len() {
+            self.stats.reordered += 1;
+        }
+        self.packets.insert(index, (sequence, payload));
+
+        while self.packets.len() > window.max(1) {
+            // Everything in the buffer is at or after `next_sequence`, since late packets are rejected.
+            let (sequence, _) = self.packets.pop_front().unwrap();
+            if let Some(next) = self.next_sequence {
+                self.stats.lost += sequence.wrapping_sub(next) as u64;
+            }
+            self.stats.lost += 1;
+            self.next_sequence = Some(sequence.wrapping_add(1));
+        }
+    }
+
+    fn pop(&mut self, depth: usize) -> Option<T> {
+        let &(oldest, _) = self.packets.front()?;
+
+        let next = match self.next_sequence {
+            Some(next) => next,
+            None if self.packets.len() >= depth => oldest,
+            None => return None,
+        };
+
+        // A packet is missing. Give it until the buffer fills up to `depth` to show up.
+        if oldest != next {
+            if self.packets.len() < depth {
+                return None;
+            }
+            self.stats.lost += oldest.wrapping_sub(next) as u64;
+        }
+
+        let (sequence, payload) = self.packets.pop_front().unwrap();
+        self.next_sequence = Some(sequence.wrapping_add(1));
+        return Some(payload);
+    }
+
+    fn clear(&mut self) {
+        self.packets.clear();
+        self.next_sequence = None;
+        self.stats = JitterBufferStats::default();
+    }
+}
+
+#[godot_api]
+impl JitterBuffer {
+    #[func]
+    fn push(&mut self, sequence: i64, payload: PackedByteArray) {
+        let window = self.window.max(1) as usize;
+        self.queue.push(sequence as u16, payload, window);
+    }
+
+    /// Returns the next payload in order, or an empty array if it isn't ready yet.
+    #[func]
+    fn pop(&mut self) -> PackedByteArray {
+        let depth = self.depth.max(0) as usize;
+        return self.queue.pop(depth).unwrap_or_default();
+    }
+
+    /// Returns how many packets are waiting.
+    #[func]
+    fn get_buffered_count(&self) -> i64 {
+        return self.queue.packets.len() as i64;
+    }
+
+    /// Returns a Dictionary with `received`, `reordered`, `late`, `duplicates` and `lost` packet counts.
+    #[func]
+    fn get_stats(&self) -> Dictionary {
+        let stats = &self.queue.stats;
+        let mut dictionary = Dictionary::new();
+        dictionary.set("received", stats.received as i64);
+        dictionary.set("reordered", stats.reordered as i64);
+        dictionary.set("late", stats.late as i64);
+        dictionary.set("duplicates", stats.duplicates as i64);
+        dictionary.set("lost", stats.lost as i64);
+        return dictionary;
+    }
+
+    /// Drops everything, including the stats. Use when the stream restarts.
+    #[func]
+    fn clear(&mut self) {
+        self.queue.clear();
+    }
+}
+// End - Smooths out unreliable streams
+
+// Wrapping comparison, so the sequence can roll over.
+fn is_before(a: u16, b: u16) -> bool {
+    return (a.wrapping_sub(b) as i16) < 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queue: &mut JitterQueue<u16>, depth: usize) -> Vec<u16> {
+        let mut popped = Vec::new();
+        while let Some(payload) = queue.pop(depth) {
+            popped.push(payload);
+        }
+        return popped;
+    }
+
+    #[test]
+    fn reordered_packets_come_out_in_order() {
+        let mut queue = JitterQueue::default();
+        for sequence in [1, 3, 2, 5, 4] {
+            queue.push(sequence, sequence, 8);
+        }
+        assert_eq!(pop_all(&mut queue, 2), vec![1, 2, 3, 4, 5]);
+        assert_eq!(queue.stats.reordered, 2);
+        assert_eq!(queue.stats.lost, 0);
+    }
+
+    #[test]
+    fn playback_waits_for_depth() {
+        let mut queue = JitterQueue::default();
+        queue.push(1, 1, 8);
+        assert_eq!(queue.pop(2), None);
+        queue.push(2, 2, 8);
+        assert_eq!(queue.pop(2), Some(1));
+    }
+
+    #[test]
+    fn late_packets_are_dropped() {
+        let mut queue = JitterQueue::default();
+        for sequence in [1, 2, 3] {
+            queue.push(sequence, sequence, 8);
+        }
+        assert_eq!(pop_all(&mut queue, 0), vec![1, 2, 3]);
+
+        queue.push(2, 2, 8);
+        assert_eq!(queue.stats.late, 1);
+        assert_eq!(queue.pop(0), None);
+    }
+
+    #[test]
+    fn duplicates_are_dropped() {
+        let mut queue = JitterQueue::default();
+        queue.push(1, 1, 8);
+        queue.push(1, 1, 8);
+        assert_eq!(queue.stats.duplicates, 1);
+        assert_eq!(queue.packets.len(), 1);
+    }
+
+    #[test]
+    fn missing_packets_are_skipped_once_depth_is_reached() {
+        let mut queue = JitterQueue::default();
+        queue.push(1, 1, 8);
+        queue.push(2, 2, 8);
+        assert_eq!(pop_all(&mut queue, 2), vec![1, 2]);
+
+        // 3 is missing, 4 waits for it until the buffer holds `depth` packets.
+        queue.push(4, 4, 8);
+        assert_eq!(queue.pop(2), None);
+        queue.push(5, 5, 8);
+        assert_eq!(queue.pop(2), Some(4));
+        assert_eq!(queue.stats.lost, 1);
+    }
+
+    #[test]
+    fn a_full_window_plays_the_oldest_packet() {
+        let mut queue = JitterQueue::default();
+        for sequence in 1..=4 {
+            queue.push(sequence, sequence, 3);
+        }
+        assert_eq!(queue.packets.len(), 3);
+        assert_eq!(queue.stats.lost, 1);
+        assert_eq!(pop_all(&mut queue, 0), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn sequences_wrap_around() {
+        let mut queue = JitterQueue::default();
+        for sequence in [u16::MAX - 1, 0, u16::MAX, 1] {
+            queue.push(sequence, sequence, 8);
+        }
+        assert_eq!(pop_all(&mut queue, 2), vec![u16::MAX - 1, u16::MAX, 0, 1]);
+
+        // After the wrap, a packet from before it is late rather than far ahead.
+        queue.push(u16::MAX, u16::MAX, 8);
+        assert_eq!(queue.stats.late, 1);
+    }
+}
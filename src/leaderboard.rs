@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+
+use godot::{
+    engine::{http_client::Method, HttpRequest, Time},
+    prelude::*,
+};
+
+use crate::backend::{self, BackendClient};
+
+// Start - Leaderboards
+// LeaderboardClient fetches pages of a leaderboard from the backend with the BackendClient's login. There are
+// three views of a board: everyone, only the player's friends, and the page the player is on. Pages are kept
+// for `cache_seconds`, so flipping back and forth in a menu doesn't ask the backend again every time.
+//
+// The endpoint is GET leaderboards/<board>?scope=<global|friends|around_self>&page=<n>&page_size=<n>, which
+// answers {total, entries: [{rank, account_id, name, score, metadata}], self: entry or null}. `page` is
+// ignored for around_self, `metadata` is optional and game specific.
+
+const SCOPE_NAMES: [&str; 3] = ["global", "friends", "around_self"];
+
+// A page is its board, scope and page number.
+type PageKey = (String, i64, i64);
+
+// One row of a leaderboard.
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct LeaderboardEntry {
+    base: Base<RefCounted>,
+
+    // 1 for the top, within the page's scope.
+    #[var]
+    rank: i64,
+    #[var]
+    account_id: GString,
+    #[var]
+    name: GString,
+    #[var]
+    score: i64,
+    #[var]
+    metadata: Dictionary,
+}
+
+impl LeaderboardEntry {
+    fn from_dictionary(entry: &Dictionary) -> Option<Gd<Self>> {
+        let account_id = backend::get_string(entry, "account_id")?;
+        // JSON numbers arrive as floats.
+        let get_int = |key: &str| {
+            return entry
+                .get(key)
+                .and_then(|value| value.try_to::<f64>().ok())
+                .map(|value| value as i64);
+        };
+        let (rank, score) = (get_int("rank")?, get_int("score")?);
+        let name = backend::get_string(entry, "name").unwrap_or_default();
+        let metadata = entry
+            .get("metadata")
+            .and_then(|metadata| metadata.try_to::<Dictionary>().ok())
+            .unwrap_or_default();
+        return Some(Gd::from_init_fn(|base| Self {
+            base,
+            rank,
+            account_id,
+            name,
+            score,
+            metadata,
+        }));
+    }
+}
+
+// A page of a leaderboard, as given to `page_received`.
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct LeaderboardPage {
+    base: Base<RefCounted>,
+
+    #[var]
+    board: GString,
+    // One of LeaderboardClient's SCOPE_ constants.
+    #[var]
+    scope: i64,
+    #[var]
+    page: i64,
+    // How many entries the whole scope has, so menus can show the page count.
+    #[var]
+    total: i64,
+    #[var]
+    entries: Array<Gd<LeaderboardEntry>>,
+    // The player's own entry, null if they aren't on the board.
+    #[var]
+    own_entry: Option<Gd<LeaderboardEntry>>,
+    // Engine ticks in msec when it was fetched.
+    fetched_at: u64,
+}
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+pub struct LeaderboardClient {
+    base: Base<Node>,
+
+    // The BackendClient whose login is used.
+    #[export]
+    backend_client: NodePath,
+    // How many entries to ask for per page.
+    #[export]
+    #[init(default = 25)]
+    page_size: i64,
+    // How long fetched pages are reused, 0 to always ask the backend.
+    #[export]
+    #[init(default = 30.0)]
+    cache_seconds: f64,
+
+    // Fetched pages by board, scope and page.
+    cache: HashMap<PageKey, Gd<LeaderboardPage>>,
+    // Running requests by the id bound to their callback.
+    requests: HashMap<i64, (Gd<HttpRequest>, PageKey)>,
+    next_request_id: i64,
+}
+
+#[godot_api]
+impl INode for LeaderboardClient {
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        if self.backend_client.is_empty() {
+            warnings.push("Set backend_client to the BackendClient to log in with.".into());
+        } else if let Some(node) = self.base().get_node_or_null(self.backend_client.clone()) {
+            // Autoloads don't resolve in the editor, so only a node that is there and wrong is flagged.
+            if !node.is_class("BackendClient".into()) {
+                warnings.push("backend_client doesn't point at a BackendClient.".into());
+            }
+        }
+        return warnings;
+    }
+}
+
+#[godot_api]
+impl LeaderboardClient {
+    #[constant]
+    const SCOPE_GLOBAL: i64 = 0;
+    #[constant]
+    const SCOPE_FRIENDS: i64 = 1;
+    // The page with the player on it.
+    #[constant]
+    const SCOPE_AROUND_SELF: i64 = 2;
+
+    // Emitted for every `fetch_page`, also when the page came from the cache.
+    #[signal]
+    fn page_received(page: Gd<LeaderboardPage>);
+    #[signal]
+    fn leaderboard_request_failed(board: GString, scope: i64, error: GString);
+
+    /// Fetches a page of `board`, counting from 0, and emits `page_received` with it. Pages from the cache
+    /// are emitted deferred, so awaiting the signal right after works either way. Returns false when not
+    /// logged in, or if the request couldn't start.
+    #[func]
+    fn fetch_page(&mut self, board: GString, scope: i64, page: i64) -> bool {
+        let Some(scope_name) = usize::try_from(scope).ok().and_then(|s| SCOPE_NAMES.get(s)) else {
+            godot_error!("LeaderboardClient: unknown scope {scope}");
+            return false;
+        };
+        // The backend picks the page for around_self.
+        let page = match scope {
+            Self::SCOPE_AROUND_SELF => 0,
+            _ => page.max(0),
+        };
+        let key = (board.to_string(), scope, page);
+        if let Some(cached) = self.cached(&key) {
+            self.base_mut().call_deferred(
+                "emit_signal".into(),
+                &[
+                    StringName::from("page_received").to_variant(),
+                    cached.to_variant(),
+                ],
+            );
+            return true;
+        }
+
+        let Some(backend) = self.backend() else {
+            return false;
+        };
+        let (url, access_token) = {
+            let backend = backend.bind();
+            (
+                format!(
+                    "{}/leaderboards/{}?scope={scope_name}&page={page}&page_size={}",
+                    backend.api_url(),
+                    percent_encode(&key.0),
+                    self.page_size
+                ),
+                backend.access_token().clone(),
+            )
+        };
+        if access_token.is_empty() {
+            return false;
+        }
+
+        let request_id = self.next_request_id;
+        self.next_request_id += 1;
+        let mut bound = VariantArray::new();
+        bound.push(request_id.to_variant());
+        let callback =
+            Callable::from_object_method(&self.to_gd(), "on_request_completed").bindv(bound);
+        let Some(request) = backend::start_request(
+            &mut self.base_mut(),
+            callback,
+            &url,
+            Method::GET,
+            &access_token,
+            None,
+        ) else {
+            return false;
+        };
+        self.requests.insert(request_id, (request, key));
+        return true;
+    }
+
+    /// Returns a page fetched before if it's still within `cache_seconds`, or null.
+    #[func]
+    fn get_cached_page(
+        &self,
+        board: GString,
+        scope: i64,
+        page: i64,
+    ) -> Option<Gd<LeaderboardPage>> {
+        return self.cached(&(board.to_string(), scope, page));
+    }
+
+    /// Forgets every fetched page, like after the player's score changed.
+    #[func]
+    fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    #[func]
+    fn on_request_completed(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        body: PackedByteArray,
+        request_id: i64,
+    ) {
+        let Some((mut request, key)) = self.requests.remove(&request_id) else {
+            return;
+        };
+        request.queue_free();
+
+        let response = match backend::is_success(result, response_code) {
+            true => backend::parse_json_object(&body),
+            false => None,
+        };
+        let (board, scope, page) = key.clone();
+        let Some(response) = response else {
+            let error = GString::from(format!("result {result}, HTTP {response_code}"));
+            godot_warn!("LeaderboardClient: fetching {board} failed, {error}");
+            self.base_mut().emit_signal(
+                "leaderboard_request_failed".into(),
+                &[
+                    GString::from(board).to_variant(),
+                    scope.to_variant(),
+                    error.to_variant(),
+                ],
+            );
+            return;
+        };
+
+        let mut entries = Array::new();
+        let listed = response
+            .get("entries")
+            .and_then(|entries| entries.try_to::<VariantArray>().ok())
+            .unwrap_or_default();
+        for entry in listed.iter_shared() {
+            let Ok(entry) = entry.try_to::<Dictionary>() else {
+                continue;
+            };
+            if let Some(entry) = LeaderboardEntry::from_dictionary(&entry) {
+                entries.push(entry);
+            }
+        }
+        let own_entry = response
+            .get("self")
+            .and_then(|entry| entry.try_to::<Dictionary>().ok())
+            .and_then(|entry| LeaderboardEntry::from_dictionary(&entry));
+        let total = response
+            .get("total")
+            .and_then(|total| total.try_to::<f64>().ok())
+            .map_or(entries.len() as i64, |total| total as i64);
+
+        let fetched = Gd::from_init_fn(|base| LeaderboardPage {
+            base,
+            board: GString::from(board),
+            scope,
+            page,
+            total,
+            entries,
+            own_entry,
+            fetched_at: Time::singleton().get_ticks_msec(),
+        });
+        self.cache.insert(key, fetched.clone());
+        self.base_mut()
+            .emit_signal("page_received".into(), &[fetched.to_variant()]);
+    }
+}
+
+impl LeaderboardClient {
+    fn backend(&self) -> Option<Gd<BackendClient>> {
+        let node = self.base().get_node_or_null(self.backend_client.clone())?;
+        return node.try_cast::<BackendClient>().ok();
+    }
+
+    fn cached(&self, key: &PageKey) -> Option<Gd<LeaderboardPage>> {
+        let page = self.cache.get(key)?;
+        let age = Time::singleton().get_ticks_msec() - page.bind().fetched_at;
+        if age as f64 / 1000.0 >= self.cache_seconds {
+            return None;
+        }
+        return Some(page.clone());
+    }
+}
+
+// Board names go into the URL path.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    return encoded;
+}
+// End - Leaderboards
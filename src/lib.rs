@@ -1,33 +1,678 @@
+// Explicit returns are how this crate is written.
+#![allow(clippy::needless_return)]
+
 use std::{
-    net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket},
+    collections::{BTreeSet, HashMap, VecDeque},
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
     str::FromStr,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 
+use bytes::{Bytes, BytesMut};
 use godot::{
-    engine::{global::Key, node::ProcessMode},
+    builtin::VariantType,
+    engine::{
+        global::Error, http_client::Method, node::ProcessMode, notify::NodeNotification,
+        AudioServer, Engine, FileAccess, HttpRequest, Input, InputEvent, InputEventJoypadMotion,
+        Ip, Json, Os, ProjectSettings, Time, Timer,
+    },
     prelude::*,
 };
 use renet::{
-    transport::{ClientAuthentication, NetcodeClientTransport, NetcodeTransportError},
-    ConnectionConfig, DefaultChannel, RenetClient,
+    transport::{
+        ClientAuthentication, NetcodeClientTransport, NetcodeDisconnectReason, NetcodeError,
+        NetcodeTransportError,
+    },
+    ConnectionConfig, DisconnectReason, RenetClient,
 };
 
+use channels::{Sequencer, CHANNEL_COUNT, CHANNEL_NAMES};
+use clock_sync::ClockSync;
+use dedup::DedupWindow;
+use desync::{Desync, DesyncChecker};
+use diagnostics::Diagnostics;
+use failover::Failover;
+use flatbuffer::FlatBufferHandlers;
+use fuzz::{CorpusRecorder, PayloadFuzzer};
+use interpolation::InterpolationDelay;
+use invite::JoinUri;
+use local_server::LocalServer;
+use mute_list::MuteList;
+use negotiation::Negotiation;
+use notifications::{Mailbox, Notification};
+use outbox::Outbox;
+use performance::FrameStats;
+use presence::{Presence, RichPresence, MAX_RICH_PRESENCE_SIZE};
+use probe::Prober;
+use protobuf::ProtobufCodec;
+use protocol::{ClientMessage, RpcPacket, ServerMessage};
+use quality::{QualityRating, QualitySummary};
+use rate_limit::{InboundLimit, InboundLimiter};
+use replay::SessionRecorder;
+use report::{MessageTrace, StatsHistory, SESSION_REPORT_MESSAGES};
+use requests::PendingRequests;
+use save_sync::{SaveSync, VersionOrder};
+use send_rate::SendRateController;
+use speed_test::SpeedTest;
+use stun::{NatDetector, StunQuery};
+use transport::{SocketErrorKind, SocketOptions};
+use validation::{MessageLimits, Rejection};
+use voice::VoiceGate;
+
+mod backend;
+#[cfg(all(feature = "bots", not(target_family = "wasm")))]
+mod bots;
+mod cbor;
+mod channels;
+mod clock;
+mod clock_sync;
+mod credentials;
+mod dedup;
+mod desync;
+mod diagnostics;
+mod failover;
+// Public for `register_handler` and the `flatbuffer_handler!` macro, which game code calls.
+pub mod flatbuffer;
+mod fuzz;
+mod instances;
+mod interpolation;
+mod invite;
+mod jitter_buffer;
+mod leaderboard;
+mod local_server;
+mod mock_server;
+mod mute_list;
+mod negotiation;
+mod notifications;
+mod outbox;
+mod performance;
+mod port_mapping;
+mod presence;
+mod probe;
+mod protobuf;
+mod protocol;
+mod quality;
+mod rate_limit;
+mod reload;
+mod replay;
+mod replica;
+mod report;
+mod requests;
+mod rpc;
+mod save_sync;
+mod schema;
+mod send_rate;
+mod social;
+mod spawner;
+mod speed_test;
+mod stun;
+mod transport;
+mod validation;
+mod voice;
+
 // Start - Register Plugin
 struct ArcadeClient;
 
 #[gdextension]
-unsafe impl ExtensionLibrary for ArcadeClient {}
+unsafe impl ExtensionLibrary for ArcadeClient {
+    fn on_level_init(level: InitLevel) {
+        if level == InitLevel::Scene {
+            clock::init();
+            transport::register_project_settings();
+        }
+    }
+}
 // End - Register Plugin
 
 // Start - System that manages connection with the server
+// A tool class so the editor shows its configuration warnings. It does nothing there until a session is joined.
 #[derive(GodotClass)]
-#[class(init, base=Node)]
+#[class(init, tool, base=Node)]
 struct GameplaySessionManager {
     base: Base<Node>,
     game_session: Option<GameSession>,
+
+    // Which local player this manager is for, for split-screen games that run one manager per player. Every
+    // manager has its own socket, session and signals, so there's nothing else to set up. To tell the players
+    // apart, turn on `player_signals` and connect `player_signal`, which carries the index, or bind the
+    // index when connecting, like `session_manager.message_received.connect(_on_message.bind(index))`. The
+    // index also keeps each player's files in user:// apart. Two managers with the same index are a mistake,
+    // the second one reports an error and refuses to join.
+    #[export]
+    player_index: i64,
+    // Emits `player_signal` along with every other signal, so one handler can serve all local players. Off
+    // unless a game needs it, since it doubles the signals emitted.
+    #[export]
+    player_signals: bool,
+    // Set when another manager already has our `player_index`, see instances.rs. Such a manager won't join.
+    duplicate_of: Option<String>,
+    // Set by NOTIFICATION_PREDELETE. Being dropped without it means a hot reload, see reload.rs.
+    predeleted: bool,
+
+    // What drives the network tick, one of the TICK_ constants: the physics tick, every frame, or a timer running
+    // at `network_tick_rate` ticks per second. Everything per tick, like `available_bytes_per_tick` and the
+    // send rate, follows it.
+    #[export]
+    tick_mode: i64,
+    #[export]
+    #[init(default = 60.0)]
+    network_tick_rate: f64,
+    tick_timer: f64,
+    // When the last network tick ran. Godot's deltas shrink with `Engine.time_scale`, which would slow
+    // heartbeats, timeouts and the server tick estimate in slow motion, so the network goes by this instead.
+    last_network_tick: Option<Instant>,
+
+    // What happens to the network while the scene tree is paused, one of the PAUSE_ constants. Read when the
+    // node enters the tree.
+    #[export]
+    pause_policy: i64,
+    // Drives `keepalive_tick` for PAUSE_KEEPALIVE.
+    keepalive_timer: Option<Gd<Timer>>,
+    // When the tree was paused, for PAUSE_FULL.
+    paused_at: Option<Instant>,
+
+    // How many bytes renet may put on the wire each tick, over all channels.
+    #[export]
+    #[init(default = 60_000)]
+    available_bytes_per_tick: i64,
+
+    // How many bytes each channel may hold in its send and receive queues. Renet disconnects when a
+    // channel runs out, so the reliable channels should have plenty.
+    #[export]
+    #[init(default = DEFAULT_CHANNEL_MEMORY)]
+    reliable_ordered_memory_budget: i64,
+    #[export]
+    #[init(default = DEFAULT_CHANNEL_MEMORY)]
+    reliable_unordered_memory_budget: i64,
+    #[export]
+    #[init(default = DEFAULT_CHANNEL_MEMORY)]
+    unreliable_memory_budget: i64,
+    #[export]
+    #[init(default = DEFAULT_CHANNEL_MEMORY)]
+    unreliable_sequenced_memory_budget: i64,
+
+    // Names for the channels, like {"chat": 1, "state": 3}, so scripts don't scatter channel ids around. Every
+    // function that takes a channel takes a name too, as do the built in names like "reliable_ordered".
+    // Signals still carry ids, `get_channel_name` turns them back into names.
+    #[export]
+    channel_names: Dictionary,
+
+    // Fraction of a channel's memory budget that can be queued before `channel_congested` is emitted.
+    #[export(range = (0.0, 1.0))]
+    #[init(default = 0.5)]
+    congestion_threshold: f64,
+
+    // When enabled, packets are sent less often while the connection is losing packets or channels are
+    // congested, down to `min_send_rate` sends per second. Keeps connections alive on poor links.
+    #[export]
+    adaptive_send_rate: bool,
+    #[export]
+    #[init(default = 10.0)]
+    min_send_rate: f64,
+    #[export(range = (0.0, 1.0))]
+    #[init(default = 0.05)]
+    adaptive_packet_loss_threshold: f64,
+
+    // How far in the past snapshots are rendered. Also settable in ticks with `set_interpolation_delay_ticks`.
+    // In adaptive mode the delay follows the measured snapshot jitter, within the min and max.
+    #[export]
+    #[init(default = 100.0)]
+    interpolation_delay_ms: f64,
+    #[export]
+    adaptive_interpolation_delay: bool,
+    #[export]
+    #[init(default = 30.0)]
+    min_interpolation_delay_ms: f64,
+    #[export]
+    #[init(default = 500.0)]
+    max_interpolation_delay_ms: f64,
+    // When enabled, `get_render_tick` follows `Engine.time_scale`, so slow motion also slows the snapshots
+    // being shown. It falls behind the server by the time that was slowed away, and only catches up again
+    // while the time scale is over 1. The connection itself always runs in real time.
+    #[export]
+    scale_render_time: bool,
+
+    // When enabled, small messages sent during a tick are combined into one message per channel, which is
+    // sent right before the packets go out. Cuts per-message overhead for code that sends lots of tiny
+    // messages. The server has to understand batches, so this is off by default.
+    #[export]
+    coalesce_messages: bool,
+
+    // Development only, ignored in release builds. Mangles this fraction of the application payloads we
+    // receive before `message_received` is emitted, so message handlers can be tested against malformed data.
+    // The seed makes runs repeatable, 0 picks a new one every session.
+    #[export(range = (0.0, 1.0))]
+    fuzz_inbound_fraction: f64,
+    #[export]
+    fuzz_seed: i64,
+    // Development only, ignored in release builds. When set, every application payload is saved to this
+    // directory as it arrived, before any fuzzing. Accepts res:// and user:// paths.
+    #[export(global_dir)]
+    corpus_directory: GString,
+    // Development only, ignored in release builds. When set, everything the server sends in a session and
+    // everything we send are saved to a recording in this directory, so the inputs can be played back. See
+    // replay.rs.
+    #[export(global_dir)]
+    recording_directory: GString,
+    // Development only, ignored in release builds. Every join goes to a small server running inside the game
+    // instead of the address it was given, so multiplayer scenes can be played solo in the editor. See
+    // local_server.rs for what it answers.
+    #[export]
+    simulate_server: bool,
+    #[export]
+    #[init(default = 30)]
+    simulated_tick_rate: i64,
+
+    // The most messages and bytes per second each unreliable channel accepts from the server, 0 for no
+    // limit. Anything over is dropped and counted, and `inbound_flood_detected` is emitted. Use
+    // `set_inbound_limit` to give a channel its own limits. Reliable channels aren't limited, a message
+    // dropped there would be lost.
+    #[export]
+    inbound_messages_per_second: i64,
+    #[export]
+    inbound_bytes_per_second: i64,
+    // Indexed by channel id. Set by `set_inbound_limit`, otherwise the exported limits apply.
+    inbound_limit_overrides: [Option<InboundLimit>; CHANNEL_COUNT],
+
+    // The largest application and snapshot payloads accepted from the server, 0 for no limit. Larger ones
+    // are rejected through `message_rejected` instead of being handed to GDScript.
+    #[export]
+    max_application_payload_size: i64,
+    #[export]
+    max_snapshot_payload_size: i64,
+
+    // The most messages from the server handled per tick, and the most milliseconds spent handling them, 0
+    // for no limit. The rest waits for the next tick, so a burst after a hitch is spread over a few frames
+    // instead of making one long one. Messages on channels from `set_channel_priority` are always handled,
+    // and so are the oldest once too many are waiting.
+    #[export]
+    max_messages_per_tick: i64,
+    #[export]
+    message_time_budget_ms: f64,
+    // Indexed by channel id.
+    priority_channels: [bool; CHANNEL_COUNT],
+
+    // Payload formats to negotiate with the server when connecting, most preferred first, from the FORMAT_*
+    // constants. Formats that weren't built in are skipped and raw is always the last resort. Leave empty for
+    // servers that don't know about negotiation, and payloads stay raw.
+    #[export]
+    preferred_formats: PackedInt32Array,
+
+    // How many seconds before the connect token expires `credentials_expiring` is emitted, to leave time for
+    // fetching a new one. Only applies to sessions joined with a connect token.
+    #[export]
+    #[init(default = 60.0)]
+    credential_refresh_margin: f64,
+    // Keeps the newest connect token on disk, encrypted with a key tied to this device, so the game can
+    // rejoin with `rejoin_with_cached_token` after a crash. See credentials.rs.
+    #[export]
+    cache_connect_token: bool,
+
+    // How many times a join tries the handshake before it fails, and the seconds between tries. Netcode
+    // resends its connection request every 0.25 seconds and gives up after the connect token's timeout (15
+    // seconds without a token), which is fixed per token, so on far away or lossy links it's worth starting
+    // the handshake over. Only timeouts are retried, and only while the connect token is still valid.
+    #[export]
+    #[init(default = 1)]
+    handshake_attempts: i64,
+    #[export]
+    #[init(default = 1.0)]
+    handshake_retry_delay: f64,
+    // The URI scheme of invite links, see invite.rs. When the game is started with such a link on the command
+    // line, it's joined once the scene is ready. Empty to ignore the command line.
+    #[export]
+    #[init(default = GString::from("arcade"))]
+    join_uri_scheme: GString,
+
+    // Limits for players on metered connections, applied while `set_bandwidth_limited(true)`: packets go out
+    // at most `limited_send_rate` times per second and at most `bandwidth_cap_bytes_per_second` (0 for no
+    // cap), and optional channels stop sending, see `set_channel_optional`.
+    #[export]
+    #[init(default = 20.0)]
+    limited_send_rate: f64,
+    #[export]
+    bandwidth_cap_bytes_per_second: i64,
+    bandwidth_limited: bool,
+    // Indexed by channel id.
+    optional_channels: [bool; CHANNEL_COUNT],
+
+    // How the UDP socket is bound, see transport.rs. An empty address binds every local address of the server's
+    // family, port 0 lets the OS pick one. A fixed port is for firewall allow-lists, but the connection
+    // made by `rotate_credentials` binds its socket while the old one still holds the port, so it always
+    // gets one from the OS. Buffer sizes of 0 keep the OS defaults.
+    #[export]
+    bind_address: GString,
+    // Binds to this network interface's address instead, for machines with VPNs or several active networks.
+    // The name is one from `get_network_interfaces`. Takes precedence over `bind_address`.
+    #[export]
+    bind_interface: GString,
+    #[export(range = (0.0, 65535.0))]
+    bind_port: i64,
+    #[export]
+    socket_send_buffer_size: i64,
+    #[export]
+    socket_receive_buffer_size: i64,
+
+    // Fraction of netcode's timeout without a packet from the server before `connection_unstable` is emitted.
+    #[export(range = (0.0, 1.0))]
+    #[init(default = 0.25)]
+    unstable_timeout_fraction: f64,
+
+    // How many past sessions `get_session_history` keeps, 0 to stop recording them.
+    #[export]
+    #[init(default = 10)]
+    session_history_limit: i64,
+
+    // Writes a bug report with `create_report` when a desync is detected, or when the connection is lost for
+    // any reason but us disconnecting. When an upload URL is set, every report is also POSTed there as JSON.
+    #[export]
+    auto_report_on_desync: bool,
+    #[export]
+    auto_report_on_disconnect: bool,
+    #[export]
+    report_upload_url: GString,
+    // The request of a running `join_as_guest`, and the guest id the backend gave us.
+    guest_login: Option<Gd<HttpRequest>>,
+    guest_id: GString,
+    // The running `measure_download_speed`.
+    speed_test: Option<SpeedTest>,
+
+    // Where `run_network_diagnostics` sends its probes, a server that echoes them like for `probe_regions`,
+    // and the STUN server it asks for our public endpoint. Checks without an address are skipped.
+    #[export]
+    diagnostics_echo_address: GString,
+    #[export]
+    diagnostics_stun_server: GString,
+    diagnostics: Option<Diagnostics>,
+
+    // The upload in progress and the path of its report. One at a time, reports made meanwhile stay on disk.
+    report_upload: Option<(Gd<HttpRequest>, GString)>,
+
+    // 0 to 4 bars for a connection icon, from RTT, jitter and packet loss. 0 without a connection. Read only,
+    // `quality_changed` is emitted when it changes.
+    #[var(get)]
+    connection_quality: i64,
+
+    // RPC packets waiting for the RenetMultiplayerPeer to pick them up. Only filled once a peer is attached,
+    // otherwise nothing would ever drain it.
+    rpc_bridge_attached: bool,
+    rpc_inbox: VecDeque<RpcPacket>,
+
+    // The running `probe_regions`, with the region name for each address it pings. Regions whose address
+    // didn't parse are reported as unreachable with the rest.
+    region_probe: Option<(Prober, Vec<GString>)>,
+    unparsed_regions: Vec<(GString, GString)>,
+    // Running `ping_address` calls, with the address as it was given.
+    address_pings: Vec<(Prober, GString)>,
+
+    // The running `discover_public_endpoint`, and what it found. The socket it asked on is kept for the next
+    // session, because the public endpoint is only valid for that socket. See stun.rs.
+    stun_query: Option<StunQuery>,
+    stun_socket: Option<UdpSocket>,
+    // Started by the first join with `simulate_server` on.
+    local_server: Option<LocalServer>,
+    public_endpoint: Option<SocketAddr>,
+    // The running `detect_nat_type`, and one of the NAT_ constants from the last one.
+    nat_detector: Option<NatDetector>,
+    nat_type: u8,
+
+    // Counts the sessions started, so handling a message can tell whether a handler ended its session or
+    // joined another one.
+    sessions_started: u64,
+    // While a message from the server is being handled: when its packet was read off the socket, in
+    // `Time.get_ticks_usec()` microseconds, and the server tick it's stamped with.
+    delivering: Option<(u64, Option<u32>)>,
+
+    signal_names: SignalNames,
+
+    // Why the last join_session failed before a session could be made. Cleared by the next one.
+    join_error: Option<NetcodeTransportError>,
+    // What `get_last_session_report` returns, filled in when a session ends.
+    last_session_report: Dictionary,
+    // Set while `join_session_failover` is going through its routes.
+    failover: Option<Failover>,
+    // Set while waiting to try a timed out handshake again, see `handshake_attempts`, or to join again after
+    // a pause the server didn't wait out, see PAUSE_FULL.
+    handshake_retry: Option<HandshakeRetry>,
+
+    // Messages from `send_durable` the server hasn't acknowledged yet. Loaded from disk the first time it's
+    // needed, see outbox.rs.
+    outbox: Option<Outbox>,
+
+    // Keys of idempotent messages from the server that were already handled, kept across sessions. And the
+    // last key `send_idempotent` made.
+    received_keys: DedupWindow,
+    last_idempotency_key: u64,
+
+    // Set by `set_desync_check`.
+    desync: Option<DesyncChecker>,
+
+    // When voice is transmitted, one of the VOICE_ constants, see voice.rs. Push-to-talk uses the input action,
+    // voice activation opens at the threshold loudness, from 0 to 1, and stays open for the hangover in seconds.
+    #[export]
+    voice_mode: i64,
+    #[export]
+    #[init(default = StringName::from("push_to_talk"))]
+    push_to_talk_action: StringName,
+    #[export(range = (0.0, 1.0))]
+    #[init(default = 0.02)]
+    voice_activation_threshold: f64,
+    #[export]
+    #[init(default = 0.3)]
+    voice_activation_hangover: f64,
+    voice_gate: VoiceGate,
+
+    // Players whose voice and chat we drop, loaded from disk the first time it's needed, see mute_list.rs.
+    // When sharing is on the server is told too, so it can stop relaying them to us at all.
+    mute_list: Option<MuteList>,
+    // Loaded the first time saves are synced.
+    save_sync: Option<SaveSync>,
+    // Kept across sessions, so notifications sent again after a reconnect are still deduped.
+    mailbox: Mailbox,
+    #[export]
+    share_mute_list: bool,
+    // From `add_chat_filter`, run in the order they were added.
+    chat_filters: Vec<Callable>,
+
+    // Seconds without input before the player counts as AFK, 0 to never. The server is told whenever that
+    // changes, see `afk_state_changed`.
+    #[export]
+    afk_timeout: f64,
+    idle_seconds: f64,
+    afk: bool,
+
+    // Seconds between performance reports to the server, 0 for none, and which PERFORMANCE_ flags they
+    // include. See performance.rs.
+    #[export]
+    performance_report_interval: f64,
+    #[export]
+    #[init(default = performance::PERFORMANCE_ALL as i64)]
+    performance_report_fields: i64,
+
+    // Seconds between our pings for measuring the clock offset to the server, 0 for none. See clock_sync.rs.
+    #[export]
+    #[init(default = 2.0)]
+    heartbeat_interval: f64,
+
+    // Emits `packet_event` every `packet_event_interval` seconds, for debugging and bandwidth graphs made in
+    // GDScript. Off unless a tool needs it.
+    #[export]
+    packet_events: bool,
+    #[export]
+    #[init(default = 0.1)]
+    packet_event_interval: f64,
+
+    // From `set_presence`, None until it's called so servers that don't know presence get no pings.
+    presence_state: Option<u8>,
+    // From `set_rich_presence`, CBOR. None until it's called.
+    rich_presence: Option<Vec<u8>>,
+
+    // Topics from `subscribe`. Kept across sessions and sent to the server every time we connect.
+    subscriptions: BTreeSet<String>,
+    // Replicated stores watched by NetworkDictionary nodes, with their updates until the node takes them.
+    // See replica.rs.
+    replicas: HashMap<String, VecDeque<(u8, Bytes)>>,
+
+    // Rust handlers for FlatBuffers payloads, see flatbuffer.rs.
+    flatbuffer_handlers: FlatBufferHandlers,
+    // Message types for channels that carry protobuf, see protobuf.rs.
+    protobuf: ProtobufCodec,
+    // Channels whose application payloads are CBOR, see cbor.rs. Indexed by channel id.
+    cbor_channels: [bool; CHANNEL_COUNT],
+    // From `add_channel_handler`, indexed by channel id.
+    channel_handlers: [Vec<Callable>; CHANNEL_COUNT],
+}
+
+// Signal names are made once, so emitting from the network tick doesn't build a new StringName every time.
+struct SignalNames {
+    lost_connection: StringName,
+    message_received: StringName,
+    entity_spawned: StringName,
+    entity_despawned: StringName,
+    authority_changed: StringName,
+    channel_congested: StringName,
+    channel_drained: StringName,
+    snapshot_received: StringName,
+    resynced: StringName,
+    server_tick_rate_changed: StringName,
+    match_seed_received: StringName,
+    server_config_received: StringName,
+    send_rate_changed: StringName,
+    inbound_flood_detected: StringName,
+    message_rejected: StringName,
+    connection_denied: StringName,
+    connect_token_expired: StringName,
+    session_taken_over: StringName,
+    join_completed: StringName,
+    join_uri_received: StringName,
+    save_downloaded: StringName,
+    save_uploaded: StringName,
+    save_conflict: StringName,
+    notification_received: StringName,
+    guest_login_failed: StringName,
+    topic_message: StringName,
+    protobuf_message_received: StringName,
+    cbor_message_received: StringName,
+    wire_format_negotiated: StringName,
+    regions_probed: StringName,
+    address_pinged: StringName,
+    network_diagnostics_completed: StringName,
+    download_speed_measured: StringName,
+    public_endpoint_discovered: StringName,
+    nat_type_detected: StringName,
+    bandwidth_limited: StringName,
+    durable_message_acknowledged: StringName,
+    sequence_gap: StringName,
+    packet_event: StringName,
+    connection_unstable: StringName,
+    desync_detected: StringName,
+    voice_frame_received: StringName,
+    voice_transmission_changed: StringName,
+    chat_received: StringName,
+    quick_chat_received: StringName,
+    peer_presence_changed: StringName,
+    peer_rich_presence_changed: StringName,
+    afk_state_changed: StringName,
+    failover_route_failed: StringName,
+    join_retrying: StringName,
+    failover_route_selected: StringName,
+    report_created: StringName,
+    report_uploaded: StringName,
+    connection_recovered: StringName,
+    quality_changed: StringName,
+    credentials_expiring: StringName,
+    credentials_rotated: StringName,
+    credentials_rotation_failed: StringName,
+    response_received: StringName,
+    request_failed: StringName,
+    player_signal: StringName,
+}
+
+impl Default for SignalNames {
+    fn default() -> Self {
+        Self {
+            lost_connection: StringName::from("lost_connection"),
+            message_received: StringName::from("message_received"),
+            entity_spawned: StringName::from("entity_spawned"),
+            entity_despawned: StringName::from("entity_despawned"),
+            authority_changed: StringName::from("authority_changed"),
+            channel_congested: StringName::from("channel_congested"),
+            channel_drained: StringName::from("channel_drained"),
+            snapshot_received: StringName::from("snapshot_received"),
+            resynced: StringName::from("resynced"),
+            server_tick_rate_changed: StringName::from("server_tick_rate_changed"),
+            match_seed_received: StringName::from("match_seed_received"),
+            server_config_received: StringName::from("server_config_received"),
+            send_rate_changed: StringName::from("send_rate_changed"),
+            inbound_flood_detected: StringName::from("inbound_flood_detected"),
+            message_rejected: StringName::from("message_rejected"),
+            connection_denied: StringName::from("connection_denied"),
+            connect_token_expired: StringName::from("connect_token_expired"),
+            session_taken_over: StringName::from("session_taken_over"),
+            join_completed: StringName::from("join_completed"),
+            join_uri_received: StringName::from("join_uri_received"),
+            save_downloaded: StringName::from("save_downloaded"),
+            save_uploaded: StringName::from("save_uploaded"),
+            save_conflict: StringName::from("save_conflict"),
+            notification_received: StringName::from("notification_received"),
+            guest_login_failed: StringName::from("guest_login_failed"),
+            topic_message: StringName::from("topic_message"),
+            protobuf_message_received: StringName::from("protobuf_message_received"),
+            cbor_message_received: StringName::from("cbor_message_received"),
+            wire_format_negotiated: StringName::from("wire_format_negotiated"),
+            quality_changed: StringName::from("quality_changed"),
+            regions_probed: StringName::from("regions_probed"),
+            address_pinged: StringName::from("address_pinged"),
+            network_diagnostics_completed: StringName::from("network_diagnostics_completed"),
+            download_speed_measured: StringName::from("download_speed_measured"),
+            public_endpoint_discovered: StringName::from("public_endpoint_discovered"),
+            nat_type_detected: StringName::from("nat_type_detected"),
+            bandwidth_limited: StringName::from("bandwidth_limited"),
+            durable_message_acknowledged: StringName::from("durable_message_acknowledged"),
+            sequence_gap: StringName::from("sequence_gap"),
+            packet_event: StringName::from("packet_event"),
+            connection_unstable: StringName::from("connection_unstable"),
+            desync_detected: StringName::from("desync_detected"),
+            voice_frame_received: StringName::from("voice_frame_received"),
+            voice_transmission_changed: StringName::from("voice_transmission_changed"),
+            chat_received: StringName::from("chat_received"),
+            quick_chat_received: StringName::from("quick_chat_received"),
+            peer_presence_changed: StringName::from("peer_presence_changed"),
+            peer_rich_presence_changed: StringName::from("peer_rich_presence_changed"),
+            afk_state_changed: StringName::from("afk_state_changed"),
+            failover_route_failed: StringName::from("failover_route_failed"),
+            join_retrying: StringName::from("join_retrying"),
+            failover_route_selected: StringName::from("failover_route_selected"),
+            report_created: StringName::from("report_created"),
+            report_uploaded: StringName::from("report_uploaded"),
+            connection_recovered: StringName::from("connection_recovered"),
+            credentials_expiring: StringName::from("credentials_expiring"),
+            credentials_rotated: StringName::from("credentials_rotated"),
+            credentials_rotation_failed: StringName::from("credentials_rotation_failed"),
+            response_received: StringName::from("response_received"),
+            request_failed: StringName::from("request_failed"),
+            player_signal: StringName::from("player_signal"),
+        }
+    }
 }
 
+// Same as renet's default channel config.
+const DEFAULT_CHANNEL_MEMORY: i64 = 5 * 1024 * 1024;
+
+// The kind and tick in front of a full snapshot's payload.
+const FULL_SNAPSHOT_HEADER_SIZE: i64 = 5;
+
+// A cached token has to stay valid long enough to finish connecting with it.
+const CACHED_TOKEN_MIN_LIFETIME: f64 = 10.0;
+
+// The most messages from the server held back for later ticks, see `max_messages_per_tick`.
+const MAX_DEFERRED_MESSAGES: usize = 4096;
+
+// Outgoing messages are written into one buffer and split off it. Once renet drops the messages it has sent,
+// the buffer's allocation is reused, so sending doesn't allocate in the steady state.
+const SEND_BUFFER_CAPACITY: usize = 64 * 1024;
+
 struct GameSession {
     // The client and transport are treated as the same thing because it doesn't make an different in this game.
     // Also setting up a singleton transport in Godot is annoying because you must make a GDScript that inherits
@@ -38,130 +683,5496 @@ struct GameSession {
 
     // If there is an error, you will need to call join_session to (re)connect.
     transport_error: Result<(), NetcodeTransportError>,
+
+    client_id: u64,
+    server_addr: SocketAddr,
+    // Set when the server tells us our client id connected from somewhere else.
+    taken_over: bool,
+    // Set by `reclaim_session` until the reclaim message is sent.
+    reclaim_pending: bool,
+    // Set until `join_completed` is emitted.
+    join_pending: bool,
+    // Which try of the handshake this is, from 1.
+    handshake_attempt: u32,
+    // Set once the session's `last_session_report` was taken, so a session that is ended twice keeps the
+    // first report.
+    report_captured: bool,
+
+    // Unix time in seconds the connect token expires at, None for unsecure sessions. See credentials.rs.
+    credentials_expire_at: Option<u64>,
+    // The token the current connection was made with, kept for joining again after a hot reload.
+    connect_token: Option<Vec<u8>>,
+    // How long netcode waits without a packet from the server before it disconnects.
+    timeout_seconds: f64,
+    // Set while `connection_unstable` has been emitted and no packet has arrived since.
+    unstable: bool,
+    // Set once `credentials_expiring` has been emitted for the current token.
+    credentials_expiry_warned: bool,
+    // Set from `rotate_credentials` until the connection made with the new token is up or has failed.
+    rotating_credentials: bool,
+
+    requests: PendingRequests,
+    // Set from connecting until the server picks a payload format, see negotiation.rs.
+    negotiation: Option<Negotiation>,
+    wire_format: u8,
+    compression: u8,
+
+    // Which client id has authority over each spawned entity, as told to us by the server.
+    owners: HashMap<u64, u64>,
+
+    // Indexed by channel id. Set while a channel's backlog is over the congestion threshold, so
+    // `channel_congested` is only emitted when it crosses the threshold.
+    congested: [bool; CHANNEL_COUNT],
+    // Indexed by channel id. Set when `try_send_message` returned SEND_QUEUED or SEND_REJECTED_FULL, until
+    // `channel_drained` is emitted.
+    awaiting_drain: [bool; CHANNEL_COUNT],
+
+    // Indexed by channel id.
+    channel_stats: [ChannelStats; CHANNEL_COUNT],
+    // Seconds since the last `packet_event`, and the channel stats at that time.
+    packet_event_elapsed: f64,
+    packet_event_baseline: [ChannelStats; CHANNEL_COUNT],
+
+    send_rate: SendRateController,
+
+    // Indexed by channel id.
+    inbound_limiters: [InboundLimiter; CHANNEL_COUNT],
+
+    // Seconds since the session started, advanced by the network tick.
+    session_time: f64,
+    // Seconds `get_render_tick` is behind because of `scale_render_time`.
+    render_lag: f64,
+    // Recorded to the session history when the session ends.
+    quality: QualitySummary,
+    quality_rating: QualityRating,
+    // What goes into bug reports, see report.rs.
+    trace: MessageTrace,
+    stats_history: StatsHistory,
+    interpolation: InterpolationDelay,
+    presence: Presence,
+    rich_presence: RichPresence,
+    frame_stats: FrameStats,
+    clock_sync: ClockSync,
+
+    // None until the server tells us.
+    server_tick_rate: Option<f64>,
+    // The newest server tick we know of, and the session time we learned it at.
+    server_tick_reference: Option<(u32, f64)>,
+    // The newest snapshot tick that arrived, for stamping commands.
+    snapshot_tick: Option<u32>,
+    // None until the server sends one.
+    match_seed: Option<u64>,
+    // When the transport last read packets off the socket, in `Time.get_ticks_usec()` microseconds.
+    received_at_usec: u64,
+    // Messages from the server waiting to be handled, with the channel and when they were read off the
+    // socket. Empty between ticks unless `max_messages_per_tick` or `message_time_budget_ms` held some back.
+    // Reused every tick so receiving doesn't allocate a new list each time.
+    received_queue: VecDeque<(u8, u64, Result<ServerMessage, Rejection>)>,
+    // Which of the manager's sessions this is, see `sessions_started`.
+    generation: u64,
+    // Everything the server's config messages had, newest keys winning.
+    server_config: Dictionary,
+
+    // Set between `request_full_snapshot` and the server's reply.
+    resync_pending: bool,
+
+    sequencer: Sequencer,
+
+    coalesce_messages: bool,
+    // Indexed by channel id. Messages waiting to be sent as a batch, and their total size with length prefixes.
+    coalesced: [Vec<Bytes>; CHANNEL_COUNT],
+    coalesced_size: [usize; CHANNEL_COUNT],
+
+    send_buffer: BytesMut,
+
+    // Only set in debug builds, see `fuzz_inbound_fraction` and `corpus_directory`.
+    fuzzer: Option<PayloadFuzzer>,
+    corpus: Option<CorpusRecorder>,
+    // Only set in debug builds, see `recording_directory`.
+    recorder: Option<SessionRecorder>,
+}
+
+impl GameSession {
+    // Ticks only move forward, so old snapshots don't pull the estimate back.
+    fn update_server_tick(&mut self, tick: u32) {
+        if let Some((newest, _)) = self.server_tick_reference {
+            if (tick.wrapping_sub(newest) as i32) < 0 {
+                return;
+            }
+        }
+
+        self.server_tick_reference = Some((tick, self.session_time));
+    }
+
+    fn update_snapshot_tick(&mut self, tick: u32) {
+        if let Some(newest) = self.snapshot_tick {
+            if (tick.wrapping_sub(newest) as i32) < 0 {
+                return;
+            }
+        }
+
+        self.snapshot_tick = Some(tick);
+    }
+
+    fn send_client_message(&mut self, channel_id: u8, message: &ClientMessage) {
+        message.encode(&mut self.send_buffer);
+        let message = self.take_send_buffer();
+        self.send(channel_id, message);
+    }
+
+    // Splits whatever was written to the send buffer off as one message.
+    fn take_send_buffer(&mut self) -> Bytes {
+        let message = self.send_buffer.split().freeze();
+        // Only topping up when it runs low, because reserving takes the allocation back if renet is done
+        // with everything split off it, and otherwise allocates a new one.
+        if self.send_buffer.capacity() < SEND_BUFFER_CAPACITY / 4 {
+            self.send_buffer.reserve(SEND_BUFFER_CAPACITY);
+        }
+        return message;
+    }
+
+    // Sends the message only if the channel has room for it, with what is still waiting to be coalesced.
+    // Returns false if it didn't.
+    fn try_send_client_message(&mut self, channel_id: u8, message: &ClientMessage) -> bool {
+        message.encode(&mut self.send_buffer);
+        let message = self.take_send_buffer();
+        let size = self.queued_size(channel_id, message.len());
+        if !self.client.can_send_message(channel_id, size) {
+            return false;
+        }
+        self.send(channel_id, message);
+        return true;
+    }
+
+    // How many bytes renet gets once a message of `message_len` is sent and the channel is flushed. Follows
+    // `send`, `flush_channel` and `send_now`: batches add their kind and a length per message, and the
+    // unreliable sequenced channel adds a sequence number to everything.
+    fn queued_size(&self, channel_id: u8, message_len: usize) -> usize {
+        let channel = channel_id as usize;
+        let sequence_header = match channel_id {
+            channels::UNRELIABLE_SEQUENCED => 2,
+            _ => 0,
+        };
+        let (waiting, waiting_size) = (self.coalesced[channel].len(), self.coalesced_size[channel]);
+        let batch_size = |coalesced_size: usize| 1 + coalesced_size + sequence_header;
+        let size = 2 + message_len;
+        if !self.coalesce_messages
+            || size > protocol::MAX_BATCH_SIZE
+            || waiting_size + size > protocol::MAX_BATCH_SIZE
+        {
+            let flushed = match waiting {
+                0 => 0,
+                1 => waiting_size - 2 + sequence_header,
+                _ => batch_size(waiting_size),
+            };
+            return flushed + message_len + sequence_header;
+        }
+        return match waiting {
+            0 => message_len + sequence_header,
+            _ => batch_size(waiting_size + size),
+        };
+    }
+
+    // All sends go through here so the channel stats stay accurate.
+    fn send(&mut self, channel_id: u8, message: Bytes) {
+        let stats = &mut self.channel_stats[channel_id as usize];
+        stats.messages_sent += 1;
+        stats.bytes_sent += message.len() as u64;
+        self.trace
+            .record(self.session_time, true, channel_id, &message);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.record(self.session_time, true, channel_id, &message) {
+                godot_warn!("Stopped recording the session: {error}");
+                self.recorder = None;
+            }
+        }
+
+        let channel = channel_id as usize;
+        let size = 2 + message.len();
+        if !self.coalesce_messages || size > protocol::MAX_BATCH_SIZE {
+            // Anything waiting has to go first, or messages on ordered channels would be reordered.
+            self.flush_channel(channel_id);
+            self.send_now(channel_id, message);
+            return;
+        }
+
+        if self.coalesced_size[channel] + size > protocol::MAX_BATCH_SIZE {
+            self.flush_channel(channel_id);
+        }
+        self.coalesced[channel].push(message);
+        self.coalesced_size[channel] += size;
+    }
+
+    // Sends everything that is waiting to be coalesced.
+    fn flush_coalesced(&mut self) {
+        for channel_id in 0..CHANNEL_COUNT as u8 {
+            self.flush_channel(channel_id);
+        }
+    }
+
+    fn flush_channel(&mut self, channel_id: u8) {
+        let channel = channel_id as usize;
+        self.coalesced_size[channel] = 0;
+
+        match self.coalesced[channel].len() {
+            0 => {}
+            1 => {
+                let message = self.coalesced[channel].pop().unwrap();
+                self.send_now(channel_id, message);
+            }
+            _ => {
+                protocol::encode_batch(&self.coalesced[channel], &mut self.send_buffer);
+                // Clearing keeps the list's capacity for the next batch.
+                self.coalesced[channel].clear();
+                let batch = self.take_send_buffer();
+                self.send_now(channel_id, batch);
+            }
+        }
+    }
+
+    fn send_now(&mut self, channel_id: u8, message: Bytes) {
+        let message = match channel_id {
+            channels::UNRELIABLE_SEQUENCED => {
+                self.sequencer.wrap(&message, &mut self.send_buffer);
+                self.take_send_buffer()
+            }
+            _ => message,
+        };
+        self.client.send_message(channel_id, message);
+    }
+}
+
+// What it takes to start a timed out handshake over.
+struct HandshakeRetry {
+    // Seconds until the next try.
+    delay: f64,
+    attempt: u32,
+    client_id: u64,
+    server_addr: SocketAddr,
+    // None for unsecure sessions.
+    connect_token: Option<Vec<u8>>,
+    credentials_expire_at: Option<u64>,
+}
+
+// Counted by us rather than renet, so these are application messages, not packets.
+#[derive(Default, Clone, Copy)]
+struct ChannelStats {
+    messages_sent: u64,
+    messages_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    // Dropped for going over the inbound limits.
+    messages_dropped: u64,
+    // Malformed or failed validation.
+    messages_rejected: u64,
+    // Idempotent messages that were copies of ones already handled.
+    duplicates_suppressed: u64,
 }
 
+// Seconds between `keepalive_tick`s while paused, well within any timeout.
+const KEEPALIVE_INTERVAL: f64 = 0.25;
+
+// Object's NOTIFICATION_PREDELETE, sent before an object is freed.
+const NOTIFICATION_PREDELETE: i32 = 1;
+// Object's NOTIFICATION_EXTENSION_RELOADED, sent after a hot reload of the extension.
+const NOTIFICATION_EXTENSION_RELOADED: i32 = 2;
+
+// How far a stick has to move to count as input for AFK detection, from 0 to 1.
+const AFK_JOYPAD_DEADZONE: f32 = 0.2;
+
 #[godot_api]
 impl INode for GameplaySessionManager {
-    // This node is not allowed to be paused, so this is set as soon as it enters the tree/exists.
+    // By default this node is not allowed to be paused, so this is set as soon as it enters the tree/exists.
     // If it could be paused, then you could get undesirable stuff like disconnecting when opening a menu.
+    // `pause_policy` is for games that do want it paused.
     fn enter_tree(&mut self) {
-        self.base_mut().set_process_mode(ProcessMode::ALWAYS);
-    }
+        // In the editor this would be saved into the scene.
+        if Engine::singleton().is_editor_hint() {
+            return;
+        }
+        let instance_id = self.base().instance_id().to_i64();
+        let path = self.base().get_path().to_string();
+        self.duplicate_of = instances::claim(self.player_index, instance_id, path.clone()).err();
+        if let Some(other) = &self.duplicate_of {
+            godot_error!(
+                "The GameplaySessionManager at {path} has player_index {}, which the one at {other} already \
+                 has. Only one manager per local player can run, so this one won't join sessions. Remove one \
+                 of them, or give each local player its own player_index.",
+                self.player_index
+            );
+        }
 
-    // Using a physics process because it runs 60 times a second, which is the same tickrate that we want to use for networking.
-    // If a higher tickrate is desired, then change it in the project settings under Physics>Common.
-    fn physics_process(&mut self, delta: f64) {
-        // If the transport has an error we don't want to do anything.
-        // When the transport has error, it will emit a signal on `lost_connection`. You can see where it
-        // emits the signal below inside this function.
-        if self.transport_has_error() {
+        if self.pause_policy == Self::PAUSE_ALWAYS {
+            self.base_mut().set_process_mode(ProcessMode::ALWAYS);
             return;
         }
 
-        // Update client and transport.
-        let deltadur = Duration::from_secs_f64(delta);
-        if let Some(session) = &mut self.game_session {
-            session.client.update(deltadur);
-            // Capturing any errors the transport might throw.
-            session.transport_error = session.transport.update(deltadur, &mut session.client);
+        self.base_mut().set_process_mode(ProcessMode::PAUSABLE);
+        if self.pause_policy == Self::PAUSE_KEEPALIVE && self.keepalive_timer.is_none() {
+            let mut timer = Timer::new_alloc();
+            timer.set_wait_time(KEEPALIVE_INTERVAL);
+            timer.set_process_mode(ProcessMode::ALWAYS);
+            timer.set_autostart(true);
+            timer.connect(
+                "timeout".into(),
+                Callable::from_object_method(&self.to_gd(), "keepalive_tick"),
+            );
+            self.base_mut().add_child(timer.clone().upcast());
+            self.keepalive_timer = Some(timer);
+        }
+    }
+
+    fn exit_tree(&mut self) {
+        if Engine::singleton().is_editor_hint() || self.duplicate_of.is_some() {
+            return;
         }
+        instances::release(self.base().instance_id().to_i64());
+    }
 
-        if self.transport_has_error() {
-            let message = self.transport_error_message().to_variant();
-            self.base_mut()
-                .emit_signal("lost_connection".into(), &[message]);
+    fn ready(&mut self) {
+        if Engine::singleton().is_editor_hint() || self.join_uri_scheme.is_empty() {
             return;
         }
+        // The link is usually the only argument, but some launchers put theirs in front of it.
+        let prefix = format!("{}://", self.join_uri_scheme);
+        let args = Os::singleton().get_cmdline_args();
+        let user_args = Os::singleton().get_cmdline_user_args();
+        let Some(uri) = args
+            .as_slice()
+            .iter()
+            .chain(user_args.as_slice())
+            .find(|arg| arg.to_string().starts_with(&prefix))
+            .cloned()
+        else {
+            return;
+        };
+        // Deferred, so the rest of the scene is ready and connected to our signals first.
+        self.base_mut()
+            .call_deferred("join_from_uri".into(), &[uri.to_variant()]);
+    }
 
-        if let Some(session) = &mut self.game_session {
-            if session.client.is_connected() {
-                // Get messages from the server.
-                while let Some(message) = session
-                    .client
-                    .receive_message(DefaultChannel::ReliableOrdered)
-                {
-                    // Handle received message
+    // Any input counts as activity, including what the UI handles. Sticks resting slightly off center don't.
+    fn input(&mut self, event: Gd<InputEvent>) {
+        if let Ok(motion) = event.try_cast::<InputEventJoypadMotion>() {
+            if motion.get_axis_value().abs() < AFK_JOYPAD_DEADZONE {
+                return;
+            }
+        }
+        self.mark_active();
+    }
+
+    fn process(&mut self, delta: f64) {
+        // Frame times for performance reports. The physics tick runs at a fixed rate, so it can't see them.
+        if self.performance_report_interval > 0.0 {
+            if let Some(session) = &mut self.game_session {
+                if session.client.is_connected() {
+                    session.frame_stats.record_frame(delta);
                 }
+            }
+        }
 
-                // Send messages to the server.
-                if Input::singleton().is_key_pressed(Key::W) {
-                    session
-                        .client
-                        .send_message(DefaultChannel::ReliableOrdered, vec![8]);
+        match self.tick_mode {
+            Self::TICK_PROCESS => self.network_tick(delta),
+            Self::TICK_TIMER => {
+                // Frames don't line up with the timer, so each tick gets all the time since the last one.
+                // The timer runs in real time, see `last_network_tick`.
+                self.tick_timer += delta;
+                let interval = 1.0 / self.network_tick_rate.max(1.0);
+                let due = self
+                    .last_network_tick
+                    .is_none_or(|last| last.elapsed().as_secs_f64() >= interval);
+                if due {
+                    let elapsed = std::mem::take(&mut self.tick_timer);
+                    self.network_tick(elapsed);
                 }
             }
+            _ => {}
+        }
+    }
 
-            // Sends all packets to the server based on the client settings.
-            session.transport_error = session.transport.send_packets(&mut session.client);
+    // Shown in the scene dock, so settings that can't work are caught before running the game.
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        if self.available_bytes_per_tick <= 0 {
+            warnings
+                .push("available_bytes_per_tick has to be over 0, or nothing is ever sent.".into());
+        }
+        for (channel_id, name) in CHANNEL_NAMES.into_iter().enumerate() {
+            if self.channel_memory_budget(channel_id as u8) <= 0 {
+                warnings.push(
+                    format!("{name}_memory_budget has to be over 0, or renet disconnects.").into(),
+                );
+            }
+        }
+        if self.tick_mode == Self::TICK_TIMER && self.network_tick_rate <= 0.0 {
+            warnings.push("network_tick_rate has to be over 0 when tick_mode is the timer.".into());
+        }
+        if self.adaptive_send_rate && self.min_send_rate <= 0.0 {
+            warnings.push("min_send_rate has to be over 0 when adaptive_send_rate is on.".into());
+        }
+        if self.limited_send_rate <= 0.0 {
+            warnings.push(
+                "limited_send_rate has to be over 0, or bandwidth limited players never send."
+                    .into(),
+            );
+        }
+        if self.adaptive_interpolation_delay
+            && self.min_interpolation_delay_ms > self.max_interpolation_delay_ms
+        {
+            warnings.push("min_interpolation_delay_ms is over max_interpolation_delay_ms.".into());
+        }
+        for &format in self.preferred_formats.as_slice() {
+            let built = u8::try_from(format).is_ok_and(negotiation::is_format_built);
+            if !built {
+                warnings
+                    .push(format!("preferred_formats has {format}, which isn't built in.").into());
+            }
         }
+        let action_setting = format!("input/{}", self.push_to_talk_action);
+        if self.voice_mode == voice::VOICE_PUSH_TO_TALK
+            && !ProjectSettings::singleton().has_setting(action_setting.into())
+        {
+            warnings.push(
+                format!(
+                    "push_to_talk_action is {}, which isn't in the Input Map.",
+                    self.push_to_talk_action
+                )
+                .into(),
+            );
+        }
+        return warnings;
+    }
 
-        if self.transport_has_error() {
-            let message = self.transport_error_message().to_variant();
-            self.base_mut()
-                .emit_signal("lost_connection".into(), &[message]);
+    fn on_notification(&mut self, what: NodeNotification) {
+        match what {
+            NodeNotification::PAUSED => {
+                self.paused_at = Some(Instant::now());
+                // The first tick after the pause shouldn't count the pause, `resume_after_pause` handles it.
+                self.last_network_tick = None;
+                return;
+            }
+            NodeNotification::UNPAUSED => {
+                self.resume_after_pause();
+                return;
+            }
+            // Not every API version names these, so we match on the number.
+            _ if i32::from(what) == NOTIFICATION_PREDELETE => {
+                self.predeleted = true;
+                return;
+            }
+            _ if i32::from(what) == NOTIFICATION_EXTENSION_RELOADED => {}
+            _ => return,
+        }
+
+        // See reload.rs.
+        let Some(handoff) = reload::take_handoff(self.base().instance_id()) else {
             return;
+        };
+        for topic in handoff.subscriptions.as_slice() {
+            self.subscriptions.insert(topic.to_string());
+        }
+        if handoff.connect_token.is_empty() {
+            self.join_session(handoff.address, handoff.client_id);
+        } else {
+            self.join_session_with_token(handoff.connect_token);
+        }
+    }
+
+    // Godot's physics tick is the default because it runs at a fixed rate, 60 times a second unless changed in
+    // the project settings under Physics>Common. Set `tick_mode` when the network should run at another rate.
+    fn physics_process(&mut self, delta: f64) {
+        // Also the fallback for modes that don't exist, so a typo doesn't stop the network.
+        if self.tick_mode != Self::TICK_PROCESS && self.tick_mode != Self::TICK_TIMER {
+            self.network_tick(delta);
         }
     }
 }
 
 #[godot_api]
 impl GameplaySessionManager {
+    // For `tick_mode`.
+    #[constant]
+    const TICK_PHYSICS: i64 = 0;
+    #[constant]
+    const TICK_PROCESS: i64 = 1;
+    #[constant]
+    const TICK_TIMER: i64 = 2;
+
+    // For `pause_policy`. The network keeps running while the tree is paused, which is what online games
+    // want: opening the pause menu shouldn't stop the match.
+    #[constant]
+    const PAUSE_ALWAYS: i64 = 0;
+    // The manager pauses with the tree, nothing is sent or handled, but a timer keeps the connection alive.
+    // Messages from the server wait until the tree is unpaused, so a long pause can run a reliable channel
+    // out of memory.
+    #[constant]
+    const PAUSE_KEEPALIVE: i64 = 1;
+    // The manager pauses with the tree, the connection too. If the pause lasted longer than the timeout, the
+    // server has dropped us and the session is joined again once unpaused, which ends with `join_completed`.
+    #[constant]
+    const PAUSE_FULL: i64 = 2;
+
+    // Why the session ended, from `get_disconnect_code`.
+    #[constant]
+    const DISCONNECT_NONE: i64 = 0;
+    // The server refused the connection. Netcode doesn't say why, so a full server looks the same.
+    #[constant]
+    const DISCONNECT_DENIED: i64 = 1;
+    // The connect token ran out before the connection was made. Retrying needs a new token.
+    #[constant]
+    const DISCONNECT_TOKEN_EXPIRED: i64 = 2;
+    #[constant]
+    const DISCONNECT_TIMED_OUT: i64 = 3;
+    #[constant]
+    const DISCONNECT_BY_SERVER: i64 = 4;
+    #[constant]
+    const DISCONNECT_BY_CLIENT: i64 = 5;
+    // Renet dropped the connection, usually because a channel ran out of memory.
+    #[constant]
+    const DISCONNECT_PROTOCOL_ERROR: i64 = 6;
+    #[constant]
+    const DISCONNECT_SOCKET_ERROR: i64 = 7;
+    #[constant]
+    const DISCONNECT_OTHER: i64 = 8;
+    // Our client id connected from somewhere else. See `reclaim_session`.
+    #[constant]
+    const DISCONNECT_TAKEN_OVER: i64 = 9;
+    // The OS won't let the app use the network. Ask the player to check the app's network permissions.
+    #[constant]
+    const DISCONNECT_PERMISSION_DENIED: i64 = 10;
+    // The device has no route to the server, usually because it is offline. Worth retrying once the
+    // network is back.
+    #[constant]
+    const DISCONNECT_NETWORK_UNREACHABLE: i64 = 11;
+    // The device's address changed, like when moving from wifi to cellular. Joining again fixes it.
+    #[constant]
+    const DISCONNECT_ADDRESS_CHANGED: i64 = 12;
+
+    // What `try_send_message` did with the message.
+    #[constant]
+    const SEND_SENT: i64 = 0;
+    // Sent, but it's waiting behind more than `congestion_threshold` of the channel's budget.
+    #[constant]
+    const SEND_QUEUED: i64 = 1;
+    // Not sent, it doesn't fit in what is left of the channel's budget.
+    #[constant]
+    const SEND_REJECTED_FULL: i64 = 2;
+    // Not sent because there is no connection, or the channel is unknown or suppressed.
+    #[constant]
+    const SEND_FAILED: i64 = 3;
+
+    // Channel ids, see `send_message` for what each one is for.
+    #[constant]
+    const CHANNEL_RELIABLE_ORDERED: i64 = channels::RELIABLE_ORDERED as i64;
+    #[constant]
+    const CHANNEL_RELIABLE_UNORDERED: i64 = channels::RELIABLE_UNORDERED as i64;
+    #[constant]
+    const CHANNEL_UNRELIABLE: i64 = channels::UNRELIABLE as i64;
+    #[constant]
+    const CHANNEL_UNRELIABLE_SEQUENCED: i64 = channels::UNRELIABLE_SEQUENCED as i64;
+
+    // From `get_nat_type`, see stun.rs.
+    #[constant]
+    const NAT_UNKNOWN: i64 = stun::NAT_UNKNOWN as i64;
+    // Nothing between us and the internet rewrites our packets, anyone can reach us.
+    #[constant]
+    const NAT_OPEN: i64 = stun::NAT_OPEN as i64;
+    // Peers can usually reach us with hole punching.
+    #[constant]
+    const NAT_MODERATE: i64 = stun::NAT_MODERATE as i64;
+    // Symmetric NAT, peer to peer only works with a peer that isn't strict too, or through a relay.
+    #[constant]
+    const NAT_STRICT: i64 = stun::NAT_STRICT as i64;
+
+    // Emitted whenever the session ends. `get_disconnect_code` says why.
     #[signal]
     fn lost_connection(reason: GString);
 
-    // Input server address should be ipv6.
+    // Emitted along with `lost_connection` when the server refused the connection, which includes the
+    // server being full.
+    #[signal]
+    fn connection_denied();
+
+    // Emitted along with `lost_connection` when the connect token expired before we got in.
+    #[signal]
+    fn connect_token_expired();
+
+    /// Returns one of the DISCONNECT_ constants for why the session ended or couldn't start, or
+    /// DISCONNECT_NONE while it hasn't.
     #[func]
-    fn join_session(&mut self, address: GString, client_id: i64) {
-        // Creating a client settings profile. This profile controls how the client communicates with the server.
-        let client = RenetClient::new(ConnectionConfig::default());
+    fn get_disconnect_code(&self) -> i64 {
+        let Some(session) = &self.game_session else {
+            return match &self.join_error {
+                Some(error) => Self::disconnect_code(error),
+                None => Self::DISCONNECT_NONE,
+            };
+        };
+        let Err(error) = &session.transport_error else {
+            return Self::DISCONNECT_NONE;
+        };
+        if session.taken_over {
+            return Self::DISCONNECT_TAKEN_OVER;
+        }
 
-        // Setup transport layer
-        let server_addr: SocketAddr = address.to_string().parse().unwrap();
-        let socket =
-            UdpSocket::bind(SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0)).unwrap();
-        let current_time = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .unwrap();
+        return Self::disconnect_code(error);
+    }
 
-        // This struct is a connection profile. It defines which server to connect to along with other info like
-        // encryption, some basic user data, protocol id, etc...
-        let authentication = ClientAuthentication::Unsecure {
-            server_addr,
-            // The client must get its id from another server/service/api that it will use to connect with this server.
-            // Current id is temporary for testing purposes.
-            client_id: client_id as u64,
-            user_data: None,
-            protocol_id: 0,
+    fn disconnect_code(error: &NetcodeTransportError) -> i64 {
+        return match error {
+            NetcodeTransportError::Netcode(NetcodeError::Disconnected(reason)) => match reason {
+                NetcodeDisconnectReason::ConnectionDenied => Self::DISCONNECT_DENIED,
+                NetcodeDisconnectReason::ConnectTokenExpired => Self::DISCONNECT_TOKEN_EXPIRED,
+                NetcodeDisconnectReason::ConnectionTimedOut
+                | NetcodeDisconnectReason::ConnectionResponseTimedOut
+                | NetcodeDisconnectReason::ConnectionRequestTimedOut => Self::DISCONNECT_TIMED_OUT,
+                NetcodeDisconnectReason::DisconnectedByServer => Self::DISCONNECT_BY_SERVER,
+                NetcodeDisconnectReason::DisconnectedByClient => Self::DISCONNECT_BY_CLIENT,
+            },
+            NetcodeTransportError::Renet(DisconnectReason::DisconnectedByServer) => {
+                Self::DISCONNECT_BY_SERVER
+            }
+            NetcodeTransportError::Renet(DisconnectReason::DisconnectedByClient) => {
+                Self::DISCONNECT_BY_CLIENT
+            }
+            NetcodeTransportError::Renet(_) => Self::DISCONNECT_PROTOCOL_ERROR,
+            NetcodeTransportError::IO(error) => match transport::classify_socket_error(error) {
+                SocketErrorKind::PermissionDenied => Self::DISCONNECT_PERMISSION_DENIED,
+                SocketErrorKind::NetworkUnreachable => Self::DISCONNECT_NETWORK_UNREACHABLE,
+                SocketErrorKind::AddressUnavailable => Self::DISCONNECT_ADDRESS_CHANGED,
+                SocketErrorKind::Other => Self::DISCONNECT_SOCKET_ERROR,
+            },
+            _ => Self::DISCONNECT_OTHER,
         };
+    }
 
-        let transport = NetcodeClientTransport::new(current_time, authentication, socket).unwrap();
+    // Emitted when the server says our client id connected from somewhere else, like another device. The
+    // server drops this connection right after, and `lost_connection` follows.
+    #[signal]
+    fn session_taken_over();
 
-        self.game_session = Some(GameSession {
-            client,
-            transport,
-            transport_error: Result::Ok(()),
-        });
+    /// Joins the last session again with the same address and client id, and once connected tells the server
+    /// to drop whichever other connection has our client id. For the "play here instead" button after
+    /// `session_taken_over`. Returns false if there was no session to reclaim.
+    #[func]
+    fn reclaim_session(&mut self) -> bool {
+        let Some(session) = &self.game_session else {
+            return false;
+        };
+
+        let address = GString::from(session.server_addr.to_string());
+        let client_id = session.client_id as i64;
+        self.join_session(address, client_id);
+        if let Some(session) = &mut self.game_session {
+            session.reclaim_pending = true;
+        }
+        return true;
     }
 
-    #[inline]
-    fn transport_has_error(&self) -> bool {
-        if let Some(session) = &self.game_session {
-            return session.transport_error.is_err();
+    /// Returns true if joining again with the same details might work, like after a timeout. Denied and
+    /// expired connections need a new token, or for the server to have room, so this is false for them.
+    #[func]
+    fn is_disconnect_retryable(&self) -> bool {
+        return matches!(
+            self.get_disconnect_code(),
+            Self::DISCONNECT_TIMED_OUT
+                | Self::DISCONNECT_SOCKET_ERROR
+                | Self::DISCONNECT_NETWORK_UNREACHABLE
+                | Self::DISCONNECT_ADDRESS_CHANGED
+        );
+    }
+
+    // Emitted instead of `message_received` on channels with a protobuf message type. The message is a
+    // Dictionary keyed by field name, with nested messages as Dictionaries and repeated fields as Arrays.
+    #[signal]
+    fn protobuf_message_received(channel: i64, message: Dictionary);
+
+    /// Loads protobuf message types from a descriptor set made with
+    /// `protoc --include_imports --descriptor_set_out=<path> <files>`. Needs the `protobuf` build feature.
+    /// Clears every channel's message type.
+    #[func]
+    fn load_protobuf_descriptors(&mut self, path: GString) -> bool {
+        let descriptor_set = FileAccess::get_file_as_bytes(path.clone());
+        if descriptor_set.is_empty() {
+            godot_error!("load_protobuf_descriptors: couldn't read '{path}'");
+            return false;
         }
 
-        return false;
+        if let Err(error) = self.protobuf.load_descriptors(descriptor_set.as_slice()) {
+            godot_error!("load_protobuf_descriptors: {error}");
+            return false;
+        }
+        return true;
     }
 
-    /// Only returns a message if there is an error inside the gameplay session.
-    /// If there is no gameplay session or error, then it returns an empty string.
-    #[inline]
-    fn transport_error_message(&self) -> GString {
-        if let Some(session) = &self.game_session {
-            if let Err(error) = &session.transport_error {
-                return GString::from_str(&error.to_string()).unwrap();
-            }
+    /// Makes every application payload on the channel a protobuf message of the given fully qualified type,
+    /// like "game.PlayerState". An empty name goes back to plain payloads.
+    #[func]
+    fn set_channel_protobuf_type(&mut self, channel: Variant, message_name: GString) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "set_channel_protobuf_type") else {
+            return false;
+        };
+
+        if let Err(error) = self
+            .protobuf
+            .set_channel_type(channel as u8, &message_name.to_string())
+        {
+            godot_error!("set_channel_protobuf_type: {error}");
+            return false;
+        }
+        return true;
+    }
+
+    /// Encodes the Dictionary as the channel's protobuf message type and sends it. Returns false if there
+    /// is no connection or the Dictionary doesn't fit the message type.
+    #[func]
+    fn send_protobuf(&mut self, channel: Variant, message: Dictionary) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "send_protobuf") else {
+            return false;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return false;
+        }
+
+        let payload = match self.protobuf.encode(channel as u8, &message) {
+            Ok(payload) => payload,
+            Err(error) => {
+                godot_error!("send_protobuf: {error}");
+                return false;
+            }
+        };
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(channel as u8, &ClientMessage::Application(&payload));
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    // Emitted instead of `message_received` on CBOR channels, with the payload decoded into a Variant. Maps
+    // become Dictionaries, arrays become Arrays and byte strings become PackedByteArrays.
+    #[signal]
+    fn cbor_message_received(channel: i64, value: Variant);
+
+    /// Makes every application payload on the channel CBOR, or plain payloads again if `enabled` is false.
+    #[func]
+    fn set_channel_cbor(&mut self, channel: Variant, enabled: bool) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "set_channel_cbor") else {
+            return false;
+        };
+
+        self.cbor_channels[channel as usize] = enabled;
+        return true;
+    }
+
+    /// Encodes the value as CBOR and sends it. Works with null, bools, numbers, Strings, StringNames, vectors,
+    /// Arrays, packed arrays and Dictionaries of those. Returns false if there is no connection or the value
+    /// holds anything else.
+    #[func]
+    fn send_cbor(&mut self, channel: Variant, value: Variant) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "send_cbor") else {
+            return false;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return false;
+        }
+
+        let mut payload = Vec::new();
+        if let Err(error) = cbor::encode(&value, &mut payload) {
+            godot_error!("send_cbor: {error}");
+            return false;
+        }
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(channel as u8, &ClientMessage::Application(&payload));
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    // Application payload formats, for `preferred_formats` and `get_wire_format`.
+    #[constant]
+    const FORMAT_RAW: i64 = negotiation::FORMAT_RAW as i64;
+    #[constant]
+    const FORMAT_CBOR: i64 = negotiation::FORMAT_CBOR as i64;
+    #[constant]
+    const FORMAT_PROTOBUF: i64 = negotiation::FORMAT_PROTOBUF as i64;
+    #[constant]
+    const FORMAT_FLATBUFFERS: i64 = negotiation::FORMAT_FLATBUFFERS as i64;
+    #[constant]
+    const COMPRESSION_NONE: i64 = negotiation::COMPRESSION_NONE as i64;
+
+    // Emitted once the server has picked a payload format from `preferred_formats`. `fell_back` is true if
+    // the server didn't answer in time or picked something we didn't offer, and raw was used instead.
+    #[signal]
+    fn wire_format_negotiated(format: i64, compression: i64, fell_back: bool);
+
+    /// The payload format picked with the server, raw until negotiation finishes. When it's CBOR, every
+    /// channel without a protobuf type or FlatBuffers handler is treated as a CBOR channel.
+    #[func]
+    fn get_wire_format(&self) -> i64 {
+        return self.wire_format() as i64;
+    }
+
+    #[func]
+    fn get_wire_compression(&self) -> i64 {
+        if let Some(session) = &self.game_session {
+            return session.compression as i64;
+        }
+
+        return negotiation::COMPRESSION_NONE as i64;
+    }
+
+    // Emitted when the server acknowledges a message from `send_durable`, which is then off the outbox.
+    #[signal]
+    fn durable_message_acknowledged(id: i64);
+
+    /// Sends game specific data that must not be lost, like purchases or progress. It stays in an outbox on
+    /// disk until the server acknowledges it, and is sent again on every connection until then, even after
+    /// the game restarts. Always goes over the reliable ordered channel. Returns the message's id, which
+    /// the server uses to ignore copies and `durable_message_acknowledged` reports.
+    #[func]
+    fn send_durable(&mut self, payload: PackedByteArray) -> i64 {
+        let id = self.outbox().push(payload.as_slice());
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Outbox {
+                    id,
+                    payload: payload.as_slice(),
+                };
+                session.send_client_message(channels::RELIABLE_ORDERED, &message);
+            }
+        }
+
+        return id as i64;
+    }
+
+    /// How many messages from `send_durable` are waiting for the server.
+    #[func]
+    fn get_outbox_size(&mut self) -> i64 {
+        return self.outbox().entries().len() as i64;
+    }
+
+    /// Throws away every message waiting in the outbox, like when the player logs out.
+    #[func]
+    fn clear_outbox(&mut self) {
+        self.outbox().clear();
+    }
+
+    // Emitted for messages the server publishes to a topic we subscribed to.
+    #[signal]
+    fn topic_message(topic: GString, payload: PackedByteArray);
+
+    /// Asks the server for messages published to `topic`, which arrive through `topic_message`. Subscriptions
+    /// last until `unsubscribe`, even across reconnects. Returns false if the topic is empty or longer than
+    /// 255 bytes.
+    #[func]
+    fn subscribe(&mut self, topic: GString) -> bool {
+        let topic = topic.to_string();
+        if topic.is_empty() || topic.len() > protocol::MAX_TOPIC_LENGTH {
+            godot_error!("subscribe: topics must be 1 to 255 bytes long");
+            return false;
+        }
+        if !self.subscriptions.insert(topic.clone()) {
+            return true;
+        }
+
+        // Otherwise it is sent when we connect.
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_ORDERED,
+                    &ClientMessage::Subscribe(&topic),
+                );
+            }
+        }
+        return true;
+    }
+
+    #[func]
+    fn unsubscribe(&mut self, topic: GString) {
+        let topic = topic.to_string();
+        if !self.subscriptions.remove(&topic) {
+            return;
+        }
+
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_ORDERED,
+                    &ClientMessage::Unsubscribe(&topic),
+                );
+            }
+        }
+    }
+
+    #[func]
+    fn get_subscriptions(&self) -> PackedStringArray {
+        return self
+            .subscriptions
+            .iter()
+            .map(|topic| GString::from(topic.as_str()))
+            .collect();
+    }
+
+    // Emitted once for every notification the server pushes, like a friend's invite or a maintenance
+    // warning. It also stays in `get_unread_notifications` until marked read or expired.
+    #[signal]
+    fn notification_received(id: i64, category: GString, payload: PackedByteArray);
+
+    /// Unread notifications that haven't expired, oldest first. Each is a Dictionary with id, category,
+    /// payload, and expires_at in unix time or 0 if it doesn't expire.
+    #[func]
+    fn get_unread_notifications(&mut self) -> Array<Dictionary> {
+        let mut unread = Array::new();
+        for notification in self.mailbox.unread() {
+            let mut entry = Dictionary::new();
+            entry.set("id", notification.id as i64);
+            entry.set("category", GString::from(notification.category.as_str()));
+            entry.set("payload", PackedByteArray::from(&notification.payload[..]));
+            entry.set("expires_at", notification.expires_at.unwrap_or(0.0));
+            unread.push(entry);
+        }
+        return unread;
+    }
+
+    #[func]
+    fn get_unread_notification_count(&mut self) -> i64 {
+        return self.mailbox.unread().len() as i64;
+    }
+
+    /// Returns false if there is no unread notification with the id.
+    #[func]
+    fn mark_notification_read(&mut self, id: i64) -> bool {
+        return self.mailbox.mark_read(id as u64);
+    }
+
+    #[func]
+    fn mark_all_notifications_read(&mut self) {
+        self.mailbox.mark_all_read();
+    }
+
+    // Emitted with the save from `download_save`. `data` and `meta` are empty if the server has no save in
+    // the slot. See save_sync.rs for what `meta` holds.
+    #[signal]
+    fn save_downloaded(slot: GString, data: PackedByteArray, meta: Dictionary);
+    // Emitted when the server took the save from `upload_save`.
+    #[signal]
+    fn save_uploaded(slot: GString, meta: Dictionary);
+    // Emitted when the server's save and ours were made without knowing about each other. Show the player
+    // both, like with the `info` and `modified_at` of each, and call `resolve_save_conflict` with their pick.
+    #[signal]
+    fn save_conflict(local_meta: Dictionary, remote_meta: Dictionary);
+
+    /// Uploads a save to the server's `slot`. `info` is game specific and shown in conflict prompts, keep it
+    /// small. The answer is `save_uploaded` or `save_conflict`. Returns false if there is no connection, the
+    /// save doesn't fit in the channel's memory budget, or the slot name isn't 1 to 255 bytes. If the
+    /// connection drops before the answer, upload again.
+    #[func]
+    fn upload_save(&mut self, slot: GString, data: PackedByteArray, info: Dictionary) -> bool {
+        let slot = slot.to_string();
+        let base = self.save_sync().local_version(&slot);
+        return self.send_save(&slot, data, info, &base);
+    }
+
+    /// Asks the server for the save in `slot`. The answer is `save_downloaded` or `save_conflict`.
+    #[func]
+    fn download_save(&mut self, slot: GString) -> bool {
+        let slot = slot.to_string();
+        if slot.is_empty() || slot.len() > protocol::MAX_TOPIC_LENGTH {
+            godot_error!("download_save: slot names must be 1 to 255 bytes long");
+            return false;
+        }
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_UNORDERED,
+                    &ClientMessage::SaveDownload(&slot),
+                );
+                return true;
+            }
+        }
+        return false;
+    }
+
+    /// Settles a `save_conflict`. To keep our save, pass it as `local_data` and it's uploaded over the
+    /// server's. Otherwise the server's save is handed over with `save_downloaded`, downloading it first if
+    /// needed. Returns false if there is no conflict for the slot or sending failed.
+    #[func]
+    fn resolve_save_conflict(
+        &mut self,
+        slot: GString,
+        keep_local: bool,
+        local_data: PackedByteArray,
+    ) -> bool {
+        let slot = slot.to_string();
+        let Some((local, remote, remote_blob)) = self.save_sync().take_conflict(&slot) else {
+            godot_error!("resolve_save_conflict: no conflict for '{slot}'");
+            return false;
+        };
+        let remote_version = save_sync::version_of(&remote);
+        if keep_local {
+            let base = save_sync::merge_versions(&save_sync::version_of(&local), &remote_version);
+            let info = local
+                .get("info")
+                .and_then(|info| info.try_to::<Dictionary>().ok())
+                .unwrap_or_default();
+            return self.send_save(&slot, local_data, info, &base);
+        }
+
+        // Giving up our changes, so whatever the server has from now on is newer.
+        self.save_sync().set_local_version(&slot, remote_version);
+        let Some(remote_blob) = remote_blob else {
+            return self.download_save(slot.into());
+        };
+        let signal = self.signal_names.save_downloaded.clone();
+        self.emit(
+            signal,
+            &[
+                GString::from(slot).to_variant(),
+                remote_blob.to_variant(),
+                remote.to_variant(),
+            ],
+        );
+        return true;
+    }
+
+    // Emitted with the server's reply to `send_request`.
+    #[signal]
+    fn response_received(request_id: i64, payload: PackedByteArray);
+
+    // Emitted instead of `response_received` when the request timed out or the connection was lost first.
+    #[signal]
+    fn request_failed(request_id: i64, error: GString);
+
+    /// Sends a query the server answers, like fetching an inventory. `request_type` is a game specific
+    /// number from 0 to 65535 saying what is being asked. Returns the request id, which is passed to
+    /// `response_received` or `request_failed`, or 0 if there is no connection or `request_type` is out of
+    /// range. A `timeout` of 0 waits forever.
+    #[func]
+    fn send_request(&mut self, request_type: i64, payload: PackedByteArray, timeout: f64) -> i64 {
+        let Ok(request_type) = u16::try_from(request_type) else {
+            godot_error!("send_request: request_type {request_type} isn't from 0 to 65535");
+            return 0;
+        };
+        let Some(session) = &mut self.game_session else {
+            return 0;
+        };
+        if !session.client.is_connected() {
+            return 0;
+        }
+
+        let request_id = session.requests.start(session.session_time, timeout);
+        let message = ClientMessage::Request {
+            request_id,
+            request_type,
+            payload: payload.as_slice(),
+        };
+        session.send_client_message(channels::RELIABLE_ORDERED, &message);
+        return request_id as i64;
+    }
+
+    // Game specific data from the server. The channel tells you how it was delivered, see `send_message`.
+    #[signal]
+    fn message_received(channel: i64, payload: PackedByteArray);
+
+    /// Adds a handler for the application messages on one channel, so chat, state or voice code only gets
+    /// its own traffic without a script sorting `message_received` by channel. It's called with what the
+    /// signal for the channel would carry: the payload, or the decoded value on protobuf and CBOR channels.
+    /// The signals are still emitted. Returns false if the channel is unknown.
+    #[func]
+    fn add_channel_handler(&mut self, channel: Variant, handler: Callable) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "add_channel_handler") else {
+            return false;
+        };
+
+        self.channel_handlers[channel as usize].push(handler);
+        return true;
+    }
+
+    #[func]
+    fn remove_channel_handler(&mut self, channel: Variant, handler: Callable) {
+        if let Some(channel) = self.resolve_channel(&channel, "remove_channel_handler") {
+            self.channel_handlers[channel as usize].retain(|added| *added != handler);
+        }
+    }
+
+    /// Removes every handler of the channel, or of all channels if `channel` is -1.
+    #[func]
+    fn clear_channel_handlers(&mut self, channel: Variant) {
+        if channel.try_to::<i64>().ok() == Some(-1) {
+            self.channel_handlers.iter_mut().for_each(Vec::clear);
+        } else if let Some(channel) = self.resolve_channel(&channel, "clear_channel_handlers") {
+            self.channel_handlers[channel as usize].clear();
+        }
+    }
+
+    /// Call from a handler of a message from the server, like `message_received` or `snapshot_received`, for
+    /// when the message's packet was read off the socket, comparable with `Time.get_ticks_usec()`. That's up
+    /// to a tick before the handler runs, which matters for interpolation and lag graphs. Returns -1 outside
+    /// of a handler.
+    #[func]
+    fn get_message_receive_time_usec(&self) -> i64 {
+        return self
+            .delivering
+            .map_or(-1, |(received_at, _)| received_at as i64);
+    }
+
+    /// Call from a handler of a message from the server for the server tick the message is stamped with.
+    /// Snapshots and checksums have one. Returns -1 for messages without one, and outside of a handler.
+    #[func]
+    fn get_message_server_tick(&self) -> i64 {
+        return self
+            .delivering
+            .and_then(|(_, tick)| tick)
+            .map_or(-1, |tick| tick as i64);
+    }
+
+    /// Returns the id of the channel with the name, from `channel_names` or a built in one like
+    /// "reliable_ordered", or -1 if there is none.
+    #[func]
+    fn get_channel_id(&self, name: GString) -> i64 {
+        return self
+            .channel_by_name(&name.to_string())
+            .map_or(-1, i64::from);
+    }
+
+    /// Returns the channel's name from `channel_names`, or its built in name if it has none there. For signal
+    /// handlers, which get channel ids. Returns an empty string if the channel is unknown.
+    #[func]
+    fn get_channel_name(&self, channel: i64) -> GString {
+        let Some(builtin) = usize::try_from(channel)
+            .ok()
+            .and_then(|channel| CHANNEL_NAMES.get(channel))
+        else {
+            return GString::new();
+        };
+        for (name, id) in self.channel_names.iter_shared() {
+            if id.try_to::<i64>().ok() == Some(channel) {
+                return GString::from(name.to_string());
+            }
+        }
+        return GString::from(*builtin);
+    }
+
+    /// Sends game specific data to the server. Returns false if there is no connection or the channel is
+    /// unknown. Pick the channel by what the data needs:
+    /// 0 reliable ordered: always arrives, in order. For events that must not be lost.
+    /// 1 reliable unordered: always arrives, in any order. For events that don't depend on each other.
+    /// 2 unreliable: may be lost or arrive out of order. For data that is sent again constantly.
+    /// 3 unreliable sequenced: may be lost, but never arrives after newer data. For state updates.
+    /// The channel can also be given by name, one from `channel_names` or a built in one like "unreliable".
+    #[func]
+    fn send_message(&mut self, channel: Variant, payload: PackedByteArray) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "send_message") else {
+            return false;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return false;
+        }
+
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Application(payload.as_slice());
+                session.send_client_message(channel as u8, &message);
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    /// Same as send_message, but never sends more than the channel can take, so a backed up reliable channel
+    /// can't make renet drop the connection. Returns one of the SEND_ constants. After SEND_QUEUED or
+    /// SEND_REJECTED_FULL, `channel_drained` is emitted once the channel is below `congestion_threshold`
+    /// again, for games that hold messages back until then.
+    #[func]
+    fn try_send_message(&mut self, channel: Variant, payload: PackedByteArray) -> i64 {
+        let Some(channel) = self.resolve_channel(&channel, "try_send_message") else {
+            return Self::SEND_FAILED;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return Self::SEND_FAILED;
+        }
+
+        let threshold =
+            self.channel_memory_budget(channel as u8) as f64 * self.congestion_threshold;
+        let Some(session) = &mut self.game_session else {
+            return Self::SEND_FAILED;
+        };
+        if !session.client.is_connected() {
+            return Self::SEND_FAILED;
+        }
+        // Messages waiting to be coalesced aren't in renet's channel yet, but will be.
+        let message = ClientMessage::Application(payload.as_slice());
+        if !session.try_send_client_message(channel as u8, &message) {
+            session.awaiting_drain[channel as usize] = true;
+            return Self::SEND_REJECTED_FULL;
+        }
+        if self.channel_backlog(channel as u8) as f64 > threshold {
+            if let Some(session) = &mut self.game_session {
+                session.awaiting_drain[channel as usize] = true;
+            }
+            return Self::SEND_QUEUED;
+        }
+        return Self::SEND_SENT;
+    }
+
+    // Emitted when a channel `try_send_message` reported as backed up is below `congestion_threshold` again.
+    #[signal]
+    fn channel_drained(channel: i64);
+
+    // Emitted when the server spawns an entity. `scene_index` refers to the NetworkSpawner's scene list.
+    #[signal]
+    fn entity_spawned(entity_id: i64, scene_index: i64, owner_id: i64);
+
+    #[signal]
+    fn entity_despawned(entity_id: i64);
+
+    #[signal]
+    fn authority_changed(entity_id: i64, owner_id: i64);
+
+    /// Same as send_message, but with an idempotency key the server uses to handle the message only once, for
+    /// messages the game sends again after a reconnect. Pass 0 to make a new key, or the key from the first
+    /// send when sending again. Returns the key, or 0 if nothing was sent.
+    #[func]
+    fn send_idempotent(&mut self, channel: Variant, payload: PackedByteArray, key: i64) -> i64 {
+        let Some(channel) = self.resolve_channel(&channel, "send_idempotent") else {
+            return 0;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return 0;
+        }
+
+        let key = match key {
+            0 => {
+                self.last_idempotency_key = dedup::next_unique_id(self.last_idempotency_key);
+                self.last_idempotency_key
+            }
+            key => key as u64,
+        };
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Idempotent {
+                    key,
+                    payload: payload.as_slice(),
+                };
+                session.send_client_message(channel as u8, &message);
+                return key as i64;
+            }
+        }
+
+        return 0;
+    }
+
+    // For `voice_mode`.
+    #[constant]
+    const VOICE_ALWAYS: i64 = voice::VOICE_ALWAYS;
+    #[constant]
+    const VOICE_PUSH_TO_TALK: i64 = voice::VOICE_PUSH_TO_TALK;
+    #[constant]
+    const VOICE_ACTIVATION: i64 = voice::VOICE_ACTIVATION;
+
+    /// Takes a chunk of microphone samples, like from `AudioEffectCapture.get_buffer`, and returns whether it
+    /// should be encoded and sent with `send_voice_frame`, going by `voice_mode`. Call it for every chunk,
+    /// voice activation needs the quiet ones too.
+    #[func]
+    fn process_microphone(&mut self, samples: PackedVector2Array) -> bool {
+        let changed = match self.voice_mode {
+            voice::VOICE_PUSH_TO_TALK => {
+                let pressed =
+                    Input::singleton().is_action_pressed(self.push_to_talk_action.clone());
+                self.voice_gate.set_open(pressed)
+            }
+            voice::VOICE_ACTIVATION => {
+                let duration =
+                    samples.len() as f64 / AudioServer::singleton().get_mix_rate() as f64;
+                self.voice_gate.update_activation(
+                    samples.as_slice(),
+                    duration,
+                    self.voice_activation_threshold,
+                    self.voice_activation_hangover,
+                )
+            }
+            _ => self.voice_gate.set_open(true),
+        };
+
+        let transmitting = self.voice_gate.is_open();
+        if changed {
+            let signal = self.signal_names.voice_transmission_changed.clone();
+            self.emit(signal, &[transmitting.to_variant()]);
+        }
+        return transmitting;
+    }
+
+    /// Whether voice is going out right now, for a talking indicator.
+    #[func]
+    fn is_transmitting_voice(&self) -> bool {
+        return self.voice_mode == voice::VOICE_ALWAYS || self.voice_gate.is_open();
+    }
+
+    // Emitted when `process_microphone` starts or stops letting voice through.
+    #[signal]
+    fn voice_transmission_changed(transmitting: bool);
+
+    /// Sends voice frames, tagged with where the speaker is so listeners can play them in 3D: the entity they
+    /// speak from, or 0, and a position in the world. The frames are whatever the game's codec made, Godot has
+    /// none built in. Voice goes on the unreliable sequenced channel, so late frames are dropped instead of
+    /// played out of order, and it stops while that channel is optional and bandwidth is limited, or while
+    /// `process_microphone` holds voice back. Returns false if nothing was sent.
+    #[func]
+    fn send_voice_frame(
+        &mut self,
+        frames: PackedByteArray,
+        entity_id: i64,
+        position: Vector3,
+    ) -> bool {
+        if !self.is_transmitting_voice()
+            || self.is_channel_suppressed(channels::UNRELIABLE_SEQUENCED)
+        {
+            return false;
+        }
+
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Voice {
+                    entity_id: entity_id as u64,
+                    position: [position.x, position.y, position.z],
+                    frames: frames.as_slice(),
+                };
+                session.send_client_message(channels::UNRELIABLE_SEQUENCED, &message);
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    // Emitted for voice from another player. `speaker` is their client id, `entity_id` the entity they speak
+    // from or 0, and `position` where they are, for positional playback and distance attenuation.
+    #[signal]
+    fn voice_frame_received(
+        speaker: i64,
+        position: Vector3,
+        frames: PackedByteArray,
+        entity_id: i64,
+    );
+
+    /// Sends a chat message for the server to relay to the other players, after the chat filters. Returns false
+    /// without a connection or when a filter dropped it.
+    #[func]
+    fn send_chat(&mut self, text: GString) -> bool {
+        let client_id = match &self.game_session {
+            Some(session) if session.client.is_connected() => session.client_id,
+            _ => return false,
+        };
+        let Some(text) = self.filter_chat(text.to_string(), client_id, true) else {
+            return false;
+        };
+
+        // A filter may have disconnected us.
+        if let Some(session) = &mut self.game_session {
+            session.send_client_message(channels::RELIABLE_ORDERED, &ClientMessage::Chat(&text));
+            return true;
+        }
+        return false;
+    }
+
+    // Emitted for a chat message from another player, unless they are muted or a chat filter dropped it.
+    #[signal]
+    fn chat_received(sender: i64, text: GString);
+
+    /// Sends a quick chat line or emote by its index in the game's own list, 0 to 255. Meant for controllers,
+    /// where typing is a chore, and for games without free text chat. Returns false without a connection or
+    /// for an index out of range.
+    #[func]
+    fn send_quick_chat(&mut self, id: i64) -> bool {
+        let Ok(id) = u8::try_from(id) else {
+            godot_error!("send_quick_chat: the id has to be 0 to 255, got {id}");
+            return false;
+        };
+
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session
+                    .send_client_message(channels::RELIABLE_ORDERED, &ClientMessage::QuickChat(id));
+                return true;
+            }
+        }
+        return false;
+    }
+
+    // Emitted for a quick chat line from another player, unless they are muted. Chat filters don't apply,
+    // the game knows every line already.
+    #[signal]
+    fn quick_chat_received(sender: i64, id: i64);
+
+    /// Counts as input for AFK detection, for activity Godot doesn't see as input, like voice or motion
+    /// controls read from a plugin.
+    #[func]
+    fn mark_active(&mut self) {
+        self.idle_seconds = 0.0;
+        self.set_afk(false);
+    }
+
+    #[func]
+    fn is_afk(&self) -> bool {
+        return self.afk;
+    }
+
+    // Emitted when the player goes AFK after `afk_timeout` seconds without input, and when they are back.
+    #[signal]
+    fn afk_state_changed(is_afk: bool);
+
+    // Flags for `performance_report_fields`.
+    #[constant]
+    const PERFORMANCE_FPS: i64 = performance::PERFORMANCE_FPS as i64;
+    #[constant]
+    const PERFORMANCE_FRAME_SPIKES: i64 = performance::PERFORMANCE_FRAME_SPIKES as i64;
+    #[constant]
+    const PERFORMANCE_RTT: i64 = performance::PERFORMANCE_RTT as i64;
+    #[constant]
+    const PERFORMANCE_PACKET_LOSS: i64 = performance::PERFORMANCE_PACKET_LOSS as i64;
+
+    // Presence states for `set_presence`. Games can use their own from 4 to 255.
+    #[constant]
+    const PRESENCE_ONLINE: i64 = presence::PRESENCE_ONLINE as i64;
+    #[constant]
+    const PRESENCE_TYPING: i64 = presence::PRESENCE_TYPING as i64;
+    #[constant]
+    const PRESENCE_IN_MENU: i64 = presence::PRESENCE_IN_MENU as i64;
+    #[constant]
+    const PRESENCE_IN_MATCH: i64 = presence::PRESENCE_IN_MATCH as i64;
+
+    /// Tells the other players what we are doing, one of the PRESENCE_ constants or a game specific state.
+    /// It's fine to call this every frame, changes are throttled and the state is repeated every few seconds
+    /// so lost pings don't matter. Nothing is sent until this is called once.
+    #[func]
+    fn set_presence(&mut self, state: i64) {
+        let Ok(state) = u8::try_from(state) else {
+            godot_error!("set_presence: the state has to be 0 to 255, got {state}");
+            return;
+        };
+        self.presence_state = Some(state);
+    }
+
+    /// Stops sending presence pings. Other players keep the last state they heard.
+    #[func]
+    fn clear_presence(&mut self) {
+        self.presence_state = None;
+    }
+
+    /// Returns the last presence state heard from a player this session, or -1 if there was none.
+    #[func]
+    fn get_peer_presence(&self, client_id: i64) -> i64 {
+        if let Some(session) = &self.game_session {
+            if let Some(state) = session.presence.peer(client_id as u64) {
+                return state as i64;
+            }
+        }
+        return -1;
+    }
+
+    // Emitted when another player's presence state changes, see `set_presence`.
+    #[signal]
+    fn peer_presence_changed(client_id: i64, state: i64);
+
+    /// Publishes what we are doing to other players and the lobby, like `{"activity": "ranked", "map": "harbor"}`.
+    /// Values can be anything `send_cbor` takes and the whole state has to encode to at most 1 KiB. Each call
+    /// replaces the last state, so `{}` clears it. Updates go out at most once a second, always with the
+    /// newest state, and again in every session joined after.
+    #[func]
+    fn set_rich_presence(&mut self, state: Dictionary) -> bool {
+        let mut encoded = Vec::new();
+        if let Err(error) = cbor::encode(&state.to_variant(), &mut encoded) {
+            godot_error!("set_rich_presence: {error}");
+            return false;
+        }
+        if encoded.len() > MAX_RICH_PRESENCE_SIZE {
+            godot_error!(
+                "set_rich_presence: the state is {} bytes, the limit is {MAX_RICH_PRESENCE_SIZE}",
+                encoded.len()
+            );
+            return false;
+        }
+        self.rich_presence = Some(encoded);
+        return true;
+    }
+
+    /// Returns the last rich presence heard from a player this session, empty if there was none.
+    #[func]
+    fn get_peer_rich_presence(&self, client_id: i64) -> Dictionary {
+        if let Some(session) = &self.game_session {
+            if let Some(state) = session.rich_presence.peer(client_id as u64) {
+                return Self::decode_rich_presence(state).unwrap_or_default();
+            }
+        }
+        return Dictionary::new();
+    }
+
+    // Emitted when another player's rich presence changes, see `set_rich_presence`.
+    #[signal]
+    fn peer_rich_presence_changed(client_id: i64, state: Dictionary);
+
+    /// Adds a filter that every chat message goes through, ours before they are sent and other players' before
+    /// `chat_received`, for things like masking profanity or stripping links. It's called with the text, the
+    /// sender's client id and whether the message is outgoing, and returns the text to use, or null or an
+    /// empty string to drop the message. Filters run in the order they were added, each getting the text the
+    /// one before returned.
+    #[func]
+    fn add_chat_filter(&mut self, filter: Callable) {
+        self.chat_filters.push(filter);
+    }
+
+    #[func]
+    fn remove_chat_filter(&mut self, filter: Callable) {
+        self.chat_filters.retain(|added| *added != filter);
+    }
+
+    #[func]
+    fn clear_chat_filters(&mut self) {
+        self.chat_filters.clear();
+    }
+
+    /// Stops voice and chat from this client id reaching the game, in this session and the ones after.
+    #[func]
+    fn mute_player(&mut self, client_id: i64) {
+        self.set_player_muted(client_id, true);
+    }
+
+    #[func]
+    fn unmute_player(&mut self, client_id: i64) {
+        self.set_player_muted(client_id, false);
+    }
+
+    #[func]
+    fn is_player_muted(&mut self, client_id: i64) -> bool {
+        return self.mute_list().is_muted(client_id as u64);
+    }
+
+    #[func]
+    fn get_muted_players(&mut self) -> PackedInt64Array {
+        let mut players = PackedInt64Array::new();
+        for client_id in self.mute_list().muted() {
+            players.push(client_id as i64);
+        }
+        return players;
+    }
+
+    // Emitted when a channel's queued bytes cross `congestion_threshold`. Games can use this to stop sending
+    // optional traffic before the channel runs out of memory and renet drops the connection.
+    #[signal]
+    fn channel_congested(channel: i64, backlog_bytes: i64);
+
+    #[signal]
+    fn snapshot_received(tick: i64, payload: PackedByteArray);
+
+    // Emitted with the complete game state after `request_full_snapshot`. The game should replace its state
+    // with this rather than apply it as a delta.
+    #[signal]
+    fn resynced(tick: i64, payload: PackedByteArray);
+
+    /// Asks the server for the complete game state, for late joiners and for recovering from a desync
+    /// without reconnecting. The reply arrives through `resynced`. Returns false if there is no connection
+    /// or the channel is too backed up to take the request. Renet splits large messages on reliable channels,
+    /// but the whole snapshot still has to fit in the reliable ordered channel's memory budget. The server
+    /// is told the budget, and a bigger snapshot comes as `message_rejected` for kind "full_snapshot"
+    /// instead, since receiving it would drop the connection.
+    #[func]
+    fn request_full_snapshot(&mut self) -> bool {
+        let budget = self.channel_memory_budget(channels::RELIABLE_ORDERED);
+        let Some(session) = &mut self.game_session else {
+            return false;
+        };
+        if !session.client.is_connected() {
+            return false;
+        }
+        // One request is enough, the reply is on a reliable channel.
+        if session.resync_pending {
+            return true;
+        }
+
+        let request = ClientMessage::RequestFullSnapshot {
+            max_size: (budget - FULL_SNAPSHOT_HEADER_SIZE).clamp(0, u32::MAX as i64) as u32,
+        };
+        if !session.try_send_client_message(channels::RELIABLE_ORDERED, &request) {
+            return false;
+        }
+        session.resync_pending = true;
+        return true;
+    }
+
+    #[func]
+    fn is_resync_pending(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.resync_pending;
+        }
+
+        return false;
+    }
+
+    // Emitted when the server tells us its tick rate, usually right after connecting.
+    #[signal]
+    fn server_tick_rate_changed(tick_rate: f64);
+
+    // Emitted after a config message from the server was applied, with what it had. See `get_server_config`.
+    #[signal]
+    fn server_config_received(config: Dictionary);
+
+    /// Returns everything the server's config messages had this session, or an empty Dictionary. The server
+    /// usually sends one when we connect. These keys are applied to the manager as they arrive, which
+    /// overrides what the game set:
+    ///   tick_rate                    the server's ticks per second, like from the server info message
+    ///   interpolation_delay_ms       see `interpolation_delay_ms`
+    ///   inbound_messages_per_second  see `inbound_messages_per_second`, and inbound_bytes_per_second
+    ///   performance_report_interval  see `performance_report_interval`
+    ///   heartbeat_interval           see `heartbeat_interval`
+    ///   features                     a Dictionary of feature flags, see `is_server_feature_enabled`
+    /// Anything else is game specific.
+    #[func]
+    fn get_server_config(&self) -> Dictionary {
+        if let Some(session) = &self.game_session {
+            return session.server_config.clone();
+        }
+
+        return Dictionary::new();
+    }
+
+    /// Returns true if the server's config turned the feature flag on.
+    #[func]
+    fn is_server_feature_enabled(&self, feature: GString) -> bool {
+        return self
+            .get_server_config()
+            .get("features")
+            .and_then(|features| features.try_to::<Dictionary>().ok())
+            .and_then(|features| features.get(feature))
+            .and_then(|enabled| enabled.try_to::<bool>().ok())
+            .unwrap_or(false);
+    }
+
+    // Emitted when the server sends the match's seed, see `get_match_seed`.
+    #[signal]
+    fn match_seed_received(seed: i64);
+
+    /// Returns the seed the server picked for the match, for procedural content that has to come out the same
+    /// as on the server, like `RandomNumberGenerator.seed`. It's 64 bits, so negative numbers are fine. Returns
+    /// 0 before the server sent one, see `has_match_seed`.
+    #[func]
+    fn get_match_seed(&self) -> i64 {
+        if let Some(session) = &self.game_session {
+            if let Some(seed) = session.match_seed {
+                return seed as i64;
+            }
+        }
+
+        return 0;
+    }
+
+    #[func]
+    fn has_match_seed(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.match_seed.is_some();
+        }
+
+        return false;
+    }
+
+    /// Returns the server's ticks per second. Before the server has told us, this is our own network tick rate.
+    #[func]
+    fn get_server_tick_rate(&self) -> f64 {
+        return self.server_tick_rate();
+    }
+
+    /// Returns our estimate of the server's current tick: the newest tick we heard about, moved forward by
+    /// the time since we heard it. Returns -1 before any tick has arrived.
+    #[func]
+    fn server_tick(&self) -> i64 {
+        return match self.estimated_server_tick() {
+            Some(tick) => tick as i64,
+            None => -1,
+        };
+    }
+
+    /// Returns the server tick being shown right now: the estimated server tick minus the interpolation
+    /// delay, with the fraction of a tick. Returns -1 before any tick has arrived.
+    #[func]
+    fn get_render_tick(&self) -> f64 {
+        return self.render_tick().unwrap_or(-1.0);
+    }
+
+    /// Returns the newest snapshot tick that arrived, or -1 before the first one.
+    #[func]
+    fn get_last_snapshot_tick(&self) -> i64 {
+        if let Some(session) = &self.game_session {
+            if let Some(tick) = session.snapshot_tick {
+                return tick as i64;
+            }
+        }
+
+        return -1;
+    }
+
+    /// Same as send_message, but stamped with `get_render_tick` and `get_last_snapshot_tick`, so the server
+    /// can rewind to what the player saw when it checks hits. Meant for inputs and commands like shots.
+    #[func]
+    fn send_command(&mut self, channel: Variant, payload: PackedByteArray) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "send_command") else {
+            return false;
+        };
+        if self.is_channel_suppressed(channel as u8) {
+            return false;
+        }
+
+        let (render_tick, render_fraction) = match self.render_tick() {
+            Some(tick) => (tick as u32, (tick.fract() * 65536.0) as u16),
+            None => (protocol::NO_TICK, 0),
+        };
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Command {
+                    render_tick,
+                    render_fraction,
+                    snapshot_tick: session.snapshot_tick.unwrap_or(protocol::NO_TICK),
+                    payload: payload.as_slice(),
+                };
+                session.send_client_message(channel as u8, &message);
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    #[func]
+    fn ticks_to_ms(&self, ticks: f64) -> f64 {
+        return ticks * self.server_tick_interval() * 1000.0;
+    }
+
+    #[func]
+    fn ms_to_ticks(&self, ms: f64) -> f64 {
+        return ms / 1000.0 / self.server_tick_interval();
+    }
+
+    #[func]
+    fn set_interpolation_delay_ticks(&mut self, ticks: f64) {
+        self.interpolation_delay_ms = self.ticks_to_ms(ticks);
+    }
+
+    #[func]
+    fn get_interpolation_delay_ticks(&self) -> f64 {
+        return self.ms_to_ticks(self.interpolation_delay_ms);
+    }
+
+    /// Returns the interpolation delay in use right now, which differs from `interpolation_delay_ms`
+    /// in adaptive mode. Meant for HUDs and for the game's interpolation code.
+    #[func]
+    fn get_effective_interpolation_delay_ms(&self) -> f64 {
+        let configured = self.interpolation_delay_ms / 1000.0;
+        let Some(session) = &self.game_session else {
+            return self.interpolation_delay_ms;
+        };
+
+        let delay = session.interpolation.delay(
+            configured,
+            self.adaptive_interpolation_delay,
+            self.min_interpolation_delay_ms / 1000.0,
+            self.max_interpolation_delay_ms / 1000.0,
+        );
+        return delay * 1000.0;
+    }
+
+    /// Returns how much snapshot arrival times vary, in milliseconds.
+    #[func]
+    fn get_snapshot_jitter_ms(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            return session.interpolation.jitter() * 1000.0;
+        }
+
+        return 0.0;
+    }
+
+    // Emitted when `adaptive_send_rate` changes how many times per second packets are sent.
+    #[signal]
+    fn send_rate_changed(rate: f64);
+
+    // Emitted when bandwidth limited mode is turned on or off.
+    #[signal]
+    fn bandwidth_limited(active: bool);
+
+    /// Turns the bandwidth limited mode on or off, for players on mobile data or other metered connections.
+    /// Godot doesn't say whether a connection is metered, so the game decides, from a setting or a platform
+    /// plugin. See `limited_send_rate` and `bandwidth_cap_bytes_per_second`.
+    #[func]
+    fn set_bandwidth_limited(&mut self, active: bool) {
+        if self.bandwidth_limited == active {
+            return;
+        }
+
+        self.bandwidth_limited = active;
+        let cap = self.send_rate_cap();
+        if let Some(session) = &mut self.game_session {
+            session.send_rate.set_cap(cap);
+        }
+        let signal = self.signal_names.bandwidth_limited.clone();
+        self.emit(signal, &[active.to_variant()]);
+    }
+
+    #[func]
+    fn is_bandwidth_limited(&self) -> bool {
+        return self.bandwidth_limited;
+    }
+
+    /// Optional channels carry things the game works without, like voice or cosmetic effects. While
+    /// bandwidth limited, sending on them returns false and nothing is sent.
+    #[func]
+    fn set_channel_optional(&mut self, channel: Variant, optional: bool) {
+        let Some(channel) = self.resolve_channel(&channel, "set_channel_optional") else {
+            return;
+        };
+
+        self.optional_channels[channel as usize] = optional;
+    }
+
+    /// Messages from the server on priority channels are handled the tick they arrive, even over
+    /// `max_messages_per_tick` or `message_time_budget_ms`. For channels with things that can't wait, like
+    /// input acknowledgements or hit confirmations.
+    #[func]
+    fn set_channel_priority(&mut self, channel: Variant, priority: bool) {
+        let Some(channel) = self.resolve_channel(&channel, "set_channel_priority") else {
+            return;
+        };
+
+        self.priority_channels[channel as usize] = priority;
+    }
+
+    /// Returns how many messages from the server are waiting for the next tick, see `max_messages_per_tick`.
+    #[func]
+    fn get_deferred_message_count(&self) -> i64 {
+        return self
+            .game_session
+            .as_ref()
+            .map_or(0, |session| session.received_queue.len() as i64);
+    }
+
+    /// Returns how many times per second packets are currently sent to the server.
+    #[func]
+    fn get_send_rate(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            return session.send_rate.rate();
+        }
+
+        return self.local_tick_rate();
+    }
+
+    // Emitted when `probe_regions` is done. Each result is a Dictionary with `region`, `address` and `rtt_ms`,
+    // fastest first. Regions that didn't answer in time come last with an `rtt_ms` of -1.
+    #[signal]
+    fn regions_probed(results: VariantArray);
+
+    /// Pings every region's gateway at once, to pick the best region before matchmaking. `endpoints` maps
+    /// region names to addresses like "203.0.113.7:7000". Gateways have to echo the probe packets back, see
+    /// probe.rs. Returns the `regions_probed` signal, so GDScript can `await` it. Starting a new probe
+    /// cancels the running one.
+    #[func]
+    fn probe_regions(&mut self, endpoints: Dictionary) -> Signal {
+        let mut regions = Vec::new();
+        let mut addresses = Vec::new();
+        self.unparsed_regions.clear();
+        for (region, address) in endpoints.iter_shared() {
+            let region = GString::from(region.to_string());
+            let address = GString::from(address.to_string());
+            match transport::parse_address(&address.to_string()) {
+                Ok(parsed) => {
+                    regions.push(region);
+                    addresses.push(parsed);
+                }
+                Err(_) => {
+                    godot_warn!("probe_regions: invalid address '{address}' for {region}");
+                    self.unparsed_regions.push((region, address));
+                }
+            }
+        }
+
+        match Prober::new(&addresses) {
+            Ok(prober) => self.region_probe = Some((prober, regions)),
+            Err(error) => {
+                godot_error!("probe_regions: {error}");
+                // Every region is unreachable, reported on the next tick so `await` sees it.
+                self.region_probe = None;
+                for (region, address) in regions.into_iter().zip(addresses) {
+                    self.unparsed_regions
+                        .push((region, GString::from(address.to_string())));
+                }
+                let results = self.region_probe_results().to_variant();
+                let signal = self.signal_names.regions_probed.clone();
+                self.emit_deferred(signal, &[results]);
+            }
+        }
+
+        return Signal::from_object_signal(&self.to_gd(), "regions_probed");
+    }
+
+    // Emitted when a `ping_address` is done, with the address as it was passed in and the round trip, or -1
+    // if the server didn't answer in time.
+    #[signal]
+    fn address_pinged(address: GString, rtt_ms: f64);
+
+    /// Measures the round trip to a single server without joining it, like for the ping column of a server
+    /// browser or to check a server is reachable before joining. Uses the same probe packets as
+    /// `probe_regions`, so the server has to echo them. Several pings can run at once, each ends with
+    /// `address_pinged`. Returns false if the address is invalid or no socket could be opened.
+    #[func]
+    fn ping_address(&mut self, address: GString) -> bool {
+        let parsed = match transport::parse_address(&address.to_string()) {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                godot_error!("ping_address: {error}");
+                return false;
+            }
+        };
+        match Prober::new(&[parsed]) {
+            Ok(prober) => self.address_pings.push((prober, address)),
+            Err(error) => {
+                godot_error!("ping_address: {error}");
+                return false;
+            }
+        }
+        return true;
+    }
+
+    // Emitted when `run_network_diagnostics` is done.
+    #[signal]
+    fn network_diagnostics_completed(results: Dictionary);
+
+    /// Tests the connection, for a "test connection" button in the settings, see diagnostics.rs. Doesn't need
+    /// a session. Returns the `network_diagnostics_completed` signal, so GDScript can `await` it, which gets a
+    /// Dictionary with:
+    ///   ipv4, ipv6          whether a UDP socket of that family could be opened
+    ///   echo_reachable      whether `diagnostics_echo_address` answered, and echo_rtt_ms (-1 if not)
+    ///   max_datagram_size   the largest UDP payload that made it to the echo address and back, -1 if none
+    ///   public_endpoint     what `diagnostics_stun_server` saw, empty if it didn't answer
+    ///   nat                 "open", "behind_nat" or "unknown"
+    ///   errors              what went wrong, for logs
+    /// Names are looked up before this returns. Running it again restarts it.
+    #[func]
+    fn run_network_diagnostics(&mut self) -> Signal {
+        let resolve = |address: &GString| {
+            if address.is_empty() {
+                return None;
+            }
+            let resolved = address
+                .to_string()
+                .to_socket_addrs()
+                .map(|mut addresses| addresses.next());
+            return match resolved {
+                Ok(resolved) => resolved,
+                Err(error) => {
+                    godot_warn!("run_network_diagnostics: {address}: {error}");
+                    None
+                }
+            };
+        };
+        let echo = resolve(&self.diagnostics_echo_address);
+        let stun = resolve(&self.diagnostics_stun_server);
+        self.diagnostics = Some(Diagnostics::start(echo, stun));
+        return Signal::from_object_signal(&self.to_gd(), "network_diagnostics_completed");
+    }
+
+    // Emitted when `measure_download_speed` is done, with the estimate in bytes per second, or -1 if the
+    // download failed before enough arrived to tell.
+    #[signal]
+    fn download_speed_measured(bytes_per_second: f64);
+
+    /// Estimates the download speed by fetching `url` for at most `max_seconds`, see speed_test.rs. It doesn't
+    /// need a session. Returns the `download_speed_measured` signal, so GDScript can `await` it. Starting a
+    /// new measurement cancels the running one.
+    #[func]
+    fn measure_download_speed(&mut self, url: GString, max_seconds: f64) -> Signal {
+        if let Some(mut speed_test) = self.speed_test.take() {
+            speed_test.request.queue_free();
+        }
+
+        let mut request = HttpRequest::new_alloc();
+        request.set_accept_gzip(false);
+        request.set_timeout(max_seconds);
+        self.base_mut().add_child(request.clone().upcast());
+        request.connect(
+            "request_completed".into(),
+            Callable::from_object_method(&self.to_gd(), "on_speed_test_completed"),
+        );
+        let error = request.request(url.clone());
+        if error != Error::OK {
+            godot_error!("measure_download_speed: couldn't request {url}: {error:?}");
+            request.queue_free();
+            // Reported on the next frame so `await` sees it.
+            let signal = self.signal_names.download_speed_measured.clone();
+            self.emit_deferred(signal, &[(-1.0).to_variant()]);
+        } else {
+            self.speed_test = Some(SpeedTest::new(request));
+        }
+        return Signal::from_object_signal(&self.to_gd(), "download_speed_measured");
+    }
+
+    // Timing out at `max_seconds` is the usual way for this to end, what arrived until then still counts.
+    #[func]
+    fn on_speed_test_completed(
+        &mut self,
+        _result: i64,
+        _response_code: i64,
+        _headers: PackedStringArray,
+        _body: PackedByteArray,
+    ) {
+        let Some(mut speed_test) = self.speed_test.take() else {
+            return;
+        };
+        let bytes_per_second = speed_test.bytes_per_second().unwrap_or(-1.0);
+        speed_test.request.queue_free();
+        let signal = self.signal_names.download_speed_measured.clone();
+        self.emit(signal, &[bytes_per_second.to_variant()]);
+    }
+
+    /// Asks a STUN server like "stun.l.google.com:19302" which public address and port our NAT maps a socket
+    /// to, for hosting and NAT traversal. The socket is used by the next session of the same address family,
+    /// so the endpoint is the one the server and other players see. Names are looked up before this returns.
+    /// Returns the `public_endpoint_discovered` signal, so GDScript can `await` it.
+    #[func]
+    fn discover_public_endpoint(&mut self, stun_server: GString) -> Signal {
+        let server = stun_server
+            .to_string()
+            .to_socket_addrs()
+            .and_then(|mut addresses| {
+                addresses.next().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, "the name has no addresses")
+                })
+            });
+        let query = server.and_then(|server| {
+            let options = self.socket_options()?;
+            return StunQuery::new(server, &options);
+        });
+        match query {
+            Ok(query) => self.stun_query = Some(query),
+            Err(error) => {
+                godot_error!("discover_public_endpoint: {stun_server}: {error}");
+                // Reported on the next tick so `await` sees it.
+                self.stun_query = None;
+                let signal = self.signal_names.public_endpoint_discovered.clone();
+                self.emit_deferred(signal, &[GString::new().to_variant()]);
+            }
+        }
+
+        return Signal::from_object_signal(&self.to_gd(), "public_endpoint_discovered");
+    }
+
+    /// Returns the device's network interfaces, each a Dictionary with `name` (the value for `bind_interface`),
+    /// `friendly` (a display name, only on Windows) and `addresses`. Interfaces without any address are left
+    /// out, since they can't be bound.
+    #[func]
+    fn get_network_interfaces(&self) -> VariantArray {
+        let mut interfaces = VariantArray::new();
+        for interface in Ip::singleton().get_local_interfaces().iter_shared() {
+            let addresses = interface
+                .get("addresses")
+                .and_then(|addresses| addresses.try_to::<PackedStringArray>().ok())
+                .unwrap_or_default();
+            if addresses.is_empty() {
+                continue;
+            }
+
+            let mut result = Dictionary::new();
+            result.set("name", interface.get("name").unwrap_or_default());
+            result.set("friendly", interface.get("friendly").unwrap_or_default());
+            result.set("addresses", addresses);
+            interfaces.push(result.to_variant());
+        }
+        return interfaces;
+    }
+
+    /// The public "address:port" from the last `discover_public_endpoint`, or an empty string if none was found.
+    #[func]
+    fn get_public_endpoint(&self) -> GString {
+        if let Some(endpoint) = self.public_endpoint {
+            return GString::from(endpoint.to_string());
+        }
+
+        return GString::new();
+    }
+
+    /// Works out what kind of NAT we are behind by asking two or more STUN servers at different addresses,
+    /// like "stun1.l.google.com:19302" and "stun2.l.google.com:19302", from the same socket. Matchmaking can
+    /// use it to avoid pairing two NAT_STRICT players for peer to peer, and the UI to warn about it. Only
+    /// servers of the first server's address family are asked. Names are looked up before this returns.
+    /// Returns the `nat_type_detected` signal, so GDScript can `await` it.
+    #[func]
+    fn detect_nat_type(&mut self, stun_servers: PackedStringArray) -> Signal {
+        let mut servers: Vec<SocketAddr> = Vec::new();
+        for server in stun_servers.as_slice() {
+            match server.to_string().to_socket_addrs() {
+                Ok(mut addresses) => servers.extend(addresses.next()),
+                Err(error) => godot_warn!("detect_nat_type: {server}: {error}"),
+            }
+        }
+        if let Some(first) = servers.first().copied() {
+            servers.retain(|server| server.is_ipv4() == first.is_ipv4());
+        }
+
+        match NatDetector::new(&servers) {
+            Ok(detector) => self.nat_detector = Some(detector),
+            Err(error) => {
+                godot_error!("detect_nat_type: {error}");
+                // Reported on the next tick so `await` sees it.
+                self.nat_detector = None;
+                let signal = self.signal_names.nat_type_detected.clone();
+                self.emit_deferred(signal, &[Self::NAT_UNKNOWN.to_variant()]);
+            }
+        }
+
+        return Signal::from_object_signal(&self.to_gd(), "nat_type_detected");
+    }
+
+    /// Returns one of the NAT_ constants from the last `detect_nat_type`, NAT_UNKNOWN before it finished.
+    #[func]
+    fn get_nat_type(&self) -> i64 {
+        return self.nat_type as i64;
+    }
+
+    #[signal]
+    fn nat_type_detected(nat_type: i64);
+
+    // Emitted when `discover_public_endpoint` is done, with the public "address:port", or an empty string if
+    // the STUN server couldn't be reached.
+    #[signal]
+    fn public_endpoint_discovered(endpoint: GString);
+
+    // Emitted once no packet has arrived from the server for `unstable_timeout_fraction` of the timeout, so
+    // the game can show a connection problem icon before `lost_connection`.
+    #[signal]
+    fn connection_unstable(seconds_since_last_packet: f64);
+    // Emitted when packets arrive again after `connection_unstable`.
+    #[signal]
+    fn connection_recovered();
+
+    // Emitted when our hash of the game state at `tick` differs from the server's. `diagnostics` has `tick`,
+    // `local_checksum`, `server_checksum`, `server_tick` (our estimate of it now) and `state`, which is
+    // whatever the dump callback returned.
+    #[signal]
+    fn desync_detected(tick: i64, diagnostics: Dictionary);
+
+    /// For games with deterministic simulations. Every `interval_ticks` server ticks, `checksum_callback` is
+    /// called with the tick and returns an int hash of the game state, which is compared with the hash the
+    /// server sends for the same tick. `dump_callback` can be left empty, otherwise it is called without
+    /// arguments on a desync and its result goes into the diagnostics. An interval of 0 turns checking off.
+    #[func]
+    fn set_desync_check(
+        &mut self,
+        interval_ticks: i64,
+        checksum_callback: Callable,
+        dump_callback: Callable,
+    ) {
+        if interval_ticks <= 0 {
+            self.desync = None;
+            return;
+        }
+
+        self.desync = Some(DesyncChecker::new(
+            interval_ticks as u32,
+            checksum_callback,
+            dump_callback,
+        ));
+    }
+
+    /// Returns how many milliseconds the server's clock is ahead of ours, negative if it's behind, measured
+    /// with pings every `heartbeat_interval`. Our unix time in msec plus this is the server's. Returns 0 until
+    /// the server has answered a ping.
+    #[func]
+    fn get_clock_offset_ms(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            if let Some(offset) = session.clock_sync.offset() {
+                return offset * 1000.0;
+            }
+        }
+
+        return 0.0;
+    }
+
+    /// Returns the average milliseconds our packets take to reach the server in x, and the server's take to
+    /// reach us in y. A big difference means one direction is congested, like an upload saturated by a
+    /// stream. It's only as good as the clock offset, so take it as an estimate. Returns (-1, -1) until the
+    /// server has answered a ping.
+    #[func]
+    fn get_one_way_delays_ms(&self) -> Vector2 {
+        if let Some(session) = &self.game_session {
+            if let Some((up, down)) = session.clock_sync.one_way_delays() {
+                return Vector2::new((up * 1000.0) as f32, (down * 1000.0) as f32);
+            }
+        }
+
+        return Vector2::new(-1.0, -1.0);
+    }
+
+    /// Seconds since the last packet from the server, or 0 without a connection.
+    #[func]
+    fn time_since_last_server_packet(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            if session.client.is_connected() {
+                return session
+                    .transport
+                    .time_since_last_received_packet()
+                    .as_secs_f64();
+            }
+        }
+
+        return 0.0;
+    }
+
+    /// Seconds left until netcode gives up on the server if nothing arrives, or -1 without a connection or
+    /// when the connect token turned the timeout off.
+    #[func]
+    fn timeout_remaining(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            if session.client.is_connected() && session.timeout_seconds.is_finite() {
+                let remaining = session.timeout_seconds - self.time_since_last_server_packet();
+                return remaining.max(0.0);
+            }
+        }
+
+        return -1.0;
+    }
+
+    // Emitted when `connection_quality` changes.
+    #[signal]
+    fn quality_changed(rating: i64);
+
+    /// Returns a summary of each of the last `session_history_limit` sessions that connected, newest first.
+    /// Each is a Dictionary with `started_at` (unix time), `duration`, `average_rtt_ms`, `p95_rtt_ms`,
+    /// `average_packet_loss`, `disconnect_code` and `disconnect_reason`. Kept across restarts.
+    #[func]
+    fn get_session_history(&self) -> VariantArray {
+        return quality::load_history();
+    }
+
+    #[func]
+    fn clear_session_history(&mut self) {
+        quality::clear_history();
+    }
+
+    /// Writes a bug report to user://reports and returns its path, or an empty string if it couldn't be
+    /// written. It's JSON with the last messages sent and received (kinds and sizes, no payloads), a stats
+    /// sample for every second of the last two minutes, rejected messages, channel stats, the manager's
+    /// settings and how the session ended. `reason` is saved with it. Also uploaded if `report_upload_url`
+    /// is set.
+    #[func]
+    fn create_report(&mut self, reason: GString) -> GString {
+        return self.write_report(reason, Dictionary::new());
+    }
+
+    /// Returns what we know about how the last session ended, for post-mortem screens and analytics, or an
+    /// empty Dictionary if none has ended yet. Taken when the session is lost, fails to join or is replaced by
+    /// another, and kept until the next one ends. It has:
+    ///   ended_at, session_time   unix time it ended, and how long it lasted in seconds
+    ///   client_id, server_address
+    ///   intended                 true if we ended it, false if it was lost
+    ///   disconnect_code          one of the DISCONNECT_ constants, and disconnect_reason as text
+    ///   error_chain              the error and the errors that caused it, outermost first
+    ///   stats                    rtt_ms, packet_loss, bytes_sent/received_per_second and per channel stats
+    ///                            (see `get_channel_stats`) at the end
+    ///   stats_history            a sample a second for the last two minutes
+    ///   messages                 the last 64 messages sent or received: time, direction, channel, kind, size
+    ///   rejections               the last messages that were rejected, and why
+    #[func]
+    fn get_last_session_report(&self) -> Dictionary {
+        return self.last_session_report.clone();
+    }
+
+    // Emitted for every report written, including the automatic ones.
+    #[signal]
+    fn report_created(path: GString);
+    // Emitted when the upload of a report finishes.
+    #[signal]
+    fn report_uploaded(path: GString, success: bool);
+
+    #[func]
+    fn on_report_uploaded(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        _body: PackedByteArray,
+    ) {
+        let Some((mut request, path)) = self.report_upload.take() else {
+            return;
+        };
+        request.queue_free();
+
+        let success = result == 0 && (200..300).contains(&response_code);
+        if !success {
+            godot_warn!(
+                "Uploading the report {path} failed, result {result}, HTTP {response_code}"
+            );
+        }
+        let signal = self.signal_names.report_uploaded.clone();
+        self.emit(signal, &[path.to_variant(), success.to_variant()]);
+    }
+
+    // Emitted when a channel starts dropping messages for going over its inbound limits. Emitted again if
+    // it happens after the channel has been back under its limits for a while.
+    #[signal]
+    fn inbound_flood_detected(channel: i64);
+
+    // Emitted instead of delivering a message that was malformed or broke a rule, see
+    // `max_application_payload_size`. `kind` is the message kind, like "application" or "snapshot".
+    #[signal]
+    fn message_rejected(channel: i64, kind: GString, reason: GString);
+
+    /// Sets the most messages and bytes per second the channel accepts from the server, 0 for no limit.
+    /// Overrides `inbound_messages_per_second` and `inbound_bytes_per_second` for that channel. Only for
+    /// unreliable channels.
+    #[func]
+    fn set_inbound_limit(&mut self, channel: Variant, messages_per_second: i64, bytes_per_second: i64) {
+        let Some(channel) = self.resolve_channel(&channel, "set_inbound_limit") else {
+            return;
+        };
+        if channels::is_reliable(channel as u8) {
+            godot_error!("set_inbound_limit: channel {channel} is reliable, its messages can't be dropped");
+            return;
+        }
+
+        let limit = InboundLimit {
+            messages_per_second: messages_per_second.max(0) as f64,
+            bytes_per_second: bytes_per_second.max(0) as f64,
+        };
+        self.inbound_limit_overrides[channel as usize] = Some(limit);
+        if let Some(session) = &mut self.game_session {
+            session.inbound_limiters[channel as usize].set_limit(limit);
+        }
+    }
+
+    // Emitted once per tick when messages on the unreliable sequenced channel were skipped, with how many.
+    #[signal]
+    fn sequence_gap(channel: i64, missing: i64);
+
+    // Emitted while `packet_events` is on, once per direction every `packet_event_interval` that had traffic.
+    // `direction` is "in" or "out", `size` is the bytes in that time and `channel_count` how many channels they
+    // were on. The transport owns the socket and builds the datagrams itself, so these are the bytes we gave
+    // renet or got from it: renet's headers and netcode's encryption add a few dozen bytes per packet on top.
+    #[signal]
+    fn packet_event(direction: GString, size: i64, channel_count: i64);
+
+    // Emitted while `player_signals` is on, right after each of the other signals, with our `player_index`,
+    // the name of that signal and its arguments.
+    #[signal]
+    fn player_signal(player_index: i64, signal: StringName, arguments: VariantArray);
+
+    /// Returns a Dictionary with `messages_sent`, `messages_received`, `bytes_sent`, `bytes_received`,
+    /// `messages_dropped`, `messages_rejected`, `duplicates_suppressed`, `queued_bytes` and `encrypted` (see
+    /// `is_encrypted`) for the channel.
+    /// Counts are for the current session and are all 0 without one. The unreliable sequenced channel also
+    /// has `sequence_gaps`, `sequence_missing` and `sequence_reordered`, which are 0 on the others: lots
+    /// missing means packets are lost on the way, while hitches without any mean the server didn't send.
+    /// Returns an empty Dictionary for an unknown channel.
+    #[func]
+    fn get_channel_stats(&self, channel: Variant) -> Dictionary {
+        let Some(channel) = self.resolve_channel(&channel, "get_channel_stats") else {
+            return Dictionary::new();
+        };
+
+        let stats = self
+            .game_session
+            .as_ref()
+            .and_then(|session| session.channel_stats.get(channel as usize).copied())
+            .unwrap_or_default();
+
+        let mut dictionary = Dictionary::new();
+        dictionary.set("messages_sent", stats.messages_sent as i64);
+        dictionary.set("messages_received", stats.messages_received as i64);
+        dictionary.set("bytes_sent", stats.bytes_sent as i64);
+        dictionary.set("bytes_received", stats.bytes_received as i64);
+        dictionary.set("messages_dropped", stats.messages_dropped as i64);
+        dictionary.set("messages_rejected", stats.messages_rejected as i64);
+        dictionary.set("duplicates_suppressed", stats.duplicates_suppressed as i64);
+        let sequence = self
+            .game_session
+            .as_ref()
+            .filter(|_| channel == channels::UNRELIABLE_SEQUENCED as i64)
+            .map(|session| session.sequencer.stats)
+            .unwrap_or_default();
+        dictionary.set("sequence_gaps", sequence.gaps as i64);
+        dictionary.set("sequence_missing", sequence.missing as i64);
+        dictionary.set("sequence_reordered", sequence.reordered as i64);
+        dictionary.set("queued_bytes", self.channel_backlog(channel as u8));
+        dictionary.set("encrypted", self.is_encrypted());
+        return dictionary;
+    }
+
+    /// Returns true if the session's packets are encrypted with keys only we and the server know, which is
+    /// the case for sessions joined with a connect token. Unsecure sessions from `join_session` derive their
+    /// keys from a handshake anyone on the network can read, so their traffic, voice included, can be sniffed.
+    /// Every channel shares the session's encryption.
+    #[func]
+    fn is_encrypted(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.credentials_expire_at.is_some();
+        }
+
+        return false;
+    }
+
+    /// Returns true if a message of `size` bytes fits in the channel right now. Sending when this is false
+    /// makes renet disconnect for exceeding the channel's memory budget, so optional updates should be
+    /// skipped or shrunk instead.
+    #[func]
+    fn can_send(&self, channel: Variant, size: i64) -> bool {
+        let Some(channel) = self.resolve_channel(&channel, "can_send") else {
+            return false;
+        };
+        if let Some(session) = &self.game_session {
+            if session.client.is_connected() {
+                return session
+                    .client
+                    .can_send_message(channel as u8, size.max(0) as usize);
+            }
+        }
+
+        return false;
+    }
+
+    /// Returns how many more bytes the channel can queue, or 0 if there is no session.
+    #[func]
+    fn get_channel_available_bytes(&self, channel: Variant) -> i64 {
+        let Some(channel) = self.resolve_channel(&channel, "get_channel_available_bytes") else {
+            return 0;
+        };
+        if let Some(session) = &self.game_session {
+            return session.client.channel_available_memory(channel as u8) as i64;
+        }
+
+        return 0;
+    }
+
+    /// Returns how many bytes are queued on the channel, or 0 if there is no session or the channel is
+    /// unknown.
+    #[func]
+    fn get_channel_backlog(&self, channel: Variant) -> i64 {
+        let Some(channel) = self.resolve_channel(&channel, "get_channel_backlog") else {
+            return 0;
+        };
+
+        return self.channel_backlog(channel as u8);
+    }
+
+    /// Returns true if this client has authority over the entity.
+    /// Entities the server hasn't told us about are never locally owned.
+    #[func]
+    fn is_local_authority(&self, entity_id: i64) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.owners.get(&(entity_id as u64)) == Some(&session.client_id);
+        }
+
+        return false;
+    }
+
+    /// Returns the client id that has authority over the entity, or -1 if the entity is unknown.
+    #[func]
+    fn get_entity_owner(&self, entity_id: i64) -> i64 {
+        if let Some(session) = &self.game_session {
+            if let Some(owner_id) = session.owners.get(&(entity_id as u64)) {
+                return *owner_id as i64;
+            }
+        }
+
+        return -1;
+    }
+
+    /// Returns false on platforms where join_session can't work, which is web exports. Browsers don't give
+    /// access to UDP sockets, so the netcode transport is left out of wasm builds.
+    #[func]
+    fn is_transport_supported() -> bool {
+        return cfg!(not(target_family = "wasm"));
+    }
+
+    // Emitted once per join_session, when the connection is made or the attempt fails.
+    #[signal]
+    fn join_completed(success: bool, error: GString);
+
+    /// Same as join_session, but returns the `join_completed` signal so GDScript can wait on it:
+    ///     var result = await manager.join_session_async(address, client_id)
+    /// `result` is `[success, error]`.
+    #[func]
+    fn join_session_async(&mut self, address: GString, client_id: i64) -> Signal {
+        self.join_session(address, client_id);
+        return Signal::from_object_signal(&self.to_gd(), "join_completed");
+    }
+
+    // `address` is "ip:port" and goes through `transport::parse_address`: IPv4 like "127.0.0.1:5000", IPv6 in
+    // brackets like "[::1]:5000", and link-local IPv6 with its zone after a %, like "[fe80::1%eth0]:5000".
+    #[func]
+    fn join_session(&mut self, address: GString, client_id: i64) {
+        self.failover = None;
+        // Setup transport layer
+        let server_addr = match transport::parse_address(&address.to_string()) {
+            Ok(server_addr) => server_addr,
+            Err(error) => {
+                self.fail_join(error.into());
+                return;
+            }
+        };
+
+        // This struct is a connection profile. It defines which server to connect to along with other info like
+        // encryption, some basic user data, protocol id, etc...
+        let authentication = ClientAuthentication::Unsecure {
+            server_addr,
+            // The client must get its id from another server/service/api that it will use to connect with this server.
+            // Current id is temporary for testing purposes.
+            client_id: client_id as u64,
+            user_data: None,
+            protocol_id: 0,
+        };
+
+        self.start_session(client_id as u64, server_addr, authentication, None);
+    }
+
+    /// Same as join_session, but tries each route in `routes` in order until one connects. `routes` maps route
+    /// names to addresses, like `{"direct": "203.0.113.7:7000", "relay": "198.51.100.2:7000"}`, where the relay
+    /// forwards packets to the server. The next route is tried when the handshake times out or the network
+    /// can't reach the address, see failover.rs. `join_completed` is emitted once, for the route that worked
+    /// or after the last one failed.
+    #[func]
+    fn join_session_failover(&mut self, routes: Dictionary, client_id: i64) {
+        let mut parsed_routes = Vec::new();
+        for (name, address) in routes.iter_shared() {
+            let name = GString::from(name.to_string());
+            match transport::parse_address(&address.to_string()) {
+                Ok(address) => parsed_routes.push((name, address)),
+                Err(_) => {
+                    godot_warn!("join_session_failover: invalid address '{address}' for {name}")
+                }
+            }
+        }
+        if parsed_routes.is_empty() {
+            self.failover = None;
+            let error = io::Error::new(io::ErrorKind::InvalidInput, "no valid routes to join");
+            self.fail_join(error.into());
+            return;
+        }
+
+        self.failover = Some(Failover::new(client_id as u64, parsed_routes));
+        self.join_failover_route();
+    }
+
+    // Keeps the connection alive while paused with PAUSE_KEEPALIVE: netcode's keepalives and renet's acks go
+    // out, and what arrives waits in renet.
+    #[func]
+    fn keepalive_tick(&mut self) {
+        let paused = self
+            .base()
+            .get_tree()
+            .is_some_and(|tree| tree.is_paused());
+        if !paused || self.transport_has_error() {
+            return;
+        }
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+
+        let delta = Duration::from_secs_f64(KEEPALIVE_INTERVAL);
+        session.client.update(delta);
+        session.transport_error = session.transport.update(delta, &mut session.client);
+        if session.transport_error.is_ok() {
+            session.transport_error = session.transport.send_packets(&mut session.client);
+        }
+        // Reported by the first tick after the pause, like any other error.
+    }
+
+    // Emitted when the handshake timed out and is tried again, see `handshake_attempts`. `attempt` counts
+    // from 2.
+    #[signal]
+    fn join_retrying(attempt: i64);
+
+    // Emitted when a route of `join_session_failover` failed and the next one is tried.
+    #[signal]
+    fn failover_route_failed(route: GString, error: GString);
+    // Emitted right before `join_completed` when a route of `join_session_failover` connected.
+    #[signal]
+    fn failover_route_selected(route: GString);
+
+    /// Joins without an account: POSTs to `api_url` for a temporary identity and a connect token, then joins
+    /// with the token. The body is JSON with the `guest_id` from the last guest login, empty the first time, so
+    /// the backend can hand the same identity back. The response has to be JSON with a `guest_id` and a base64
+    /// `connect_token`. `join_completed` is emitted either way. Returns false if the request couldn't start.
+    #[func]
+    fn join_as_guest(&mut self, api_url: GString) -> bool {
+        if let Some(mut request) = self.guest_login.take() {
+            request.queue_free();
+        }
+
+        let mut request = HttpRequest::new_alloc();
+        self.base_mut().add_child(request.clone().upcast());
+        request.connect(
+            "request_completed".into(),
+            Callable::from_object_method(&self.to_gd(), "on_guest_login_completed"),
+        );
+        let mut body = Dictionary::new();
+        body.set("guest_id", self.guest_id.clone());
+        let mut headers = PackedStringArray::new();
+        headers.push("Content-Type: application/json".into());
+        let error = request
+            .request_ex(api_url.clone())
+            .custom_headers(headers)
+            .method(Method::POST)
+            .request_data(Json::stringify(body.to_variant()))
+            .done();
+        if error != Error::OK {
+            godot_error!("join_as_guest: couldn't request {api_url}: {error:?}");
+            request.queue_free();
+            return false;
+        }
+        self.guest_login = Some(request);
+        return true;
+    }
+
+    /// The id the backend gave us in the last `join_as_guest`, empty if there was none.
+    #[func]
+    fn get_guest_id(&self) -> GString {
+        return self.guest_id.clone();
+    }
+
+    // Emitted when `join_as_guest` couldn't get an identity or token, right before `join_completed`.
+    #[signal]
+    fn guest_login_failed(error: GString);
+
+    #[func]
+    fn on_guest_login_completed(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        body: PackedByteArray,
+    ) {
+        let Some(mut request) = self.guest_login.take() else {
+            return;
+        };
+        request.queue_free();
+
+        let response = match backend::is_success(result, response_code) {
+            true => backend::parse_json_object(&body),
+            false => None,
+        };
+        let granted = response.as_ref().and_then(|response| {
+            let guest_id = backend::get_string(response, "guest_id")?;
+            return Some((guest_id, backend::get_connect_token(response)?));
+        });
+        let Some((guest_id, connect_token)) = granted else {
+            let error = GString::from(format!(
+                "guest login failed, result {result}, HTTP {response_code}"
+            ));
+            godot_warn!("join_as_guest: {error}");
+            let signal = self.signal_names.guest_login_failed.clone();
+            self.emit(signal, &[error.to_variant()]);
+            let signal = self.signal_names.join_completed.clone();
+            self.emit(signal, &[false.to_variant(), error.to_variant()]);
+            return;
+        };
+
+        self.guest_id = guest_id;
+        self.join_session_with_token(connect_token);
+    }
+
+    // Emitted for every link given to `join_from_uri`, with the decoded query. Links with a `lobby` or
+    // `ticket` are for the game to resolve, like looking up the lobby or swapping the ticket for a connect
+    // token with the backend.
+    #[signal]
+    fn join_uri_received(params: Dictionary);
+
+    /// Joins from an invite link like `arcade://join?token=...` or
+    /// `arcade://join?address=203.0.113.7:7000&client_id=42`, see invite.rs for the format. A token is used
+    /// over an address. Returns true if joining started, then `join_completed` follows as usual. Links
+    /// without either only emit `join_uri_received`.
+    #[func]
+    fn join_from_uri(&mut self, uri: GString) -> bool {
+        let join = match JoinUri::parse(&uri.to_string(), &self.join_uri_scheme.to_string()) {
+            Ok(join) => join,
+            Err(error) => {
+                godot_warn!("join_from_uri: {error}");
+                return false;
+            }
+        };
+        let signal = self.signal_names.join_uri_received.clone();
+        self.emit(signal, &[join.params.to_variant()]);
+
+        if let Some(connect_token) = join.connect_token {
+            self.join_session_with_token(connect_token);
+            return true;
+        }
+        if let (Some(address), Some(client_id)) = (join.address, join.client_id) {
+            self.join_session(address.into(), client_id as i64);
+            return true;
+        }
+        return false;
+    }
+
+    /// Same as join_session, but connects with a netcode connect token from the backend, which also sets up
+    /// encryption. The token holds the client id and server address.
+    #[func]
+    fn join_session_with_token(&mut self, connect_token: PackedByteArray) {
+        self.failover = None;
+        let token = match credentials::read_connect_token(connect_token.as_slice()) {
+            Ok(token) => token,
+            Err(error) => {
+                self.fail_join(error);
+                return;
+            }
+        };
+        let server_addr = match credentials::token_server_addr(&token) {
+            Ok(server_addr) => server_addr,
+            Err(error) => {
+                self.fail_join(error);
+                return;
+            }
+        };
+
+        let client_id = token.client_id;
+        let expires_at = token.expire_timestamp;
+        let authentication = ClientAuthentication::Secure {
+            connect_token: token,
+        };
+        self.start_session(client_id, server_addr, authentication, Some(expires_at));
+        if let Some(session) = &mut self.game_session {
+            session.connect_token = Some(connect_token.to_vec());
+        }
+        if self.game_session.is_some() && self.cache_connect_token {
+            let path = self.player_file(credentials::TOKEN_CACHE_PATH);
+            credentials::save_cached_token(path, connect_token.as_slice());
+        }
+    }
+
+    /// Joins with the cached connect token if there is one that hasn't expired. Returns false otherwise,
+    /// and the game has to get a new token from the backend.
+    #[func]
+    fn rejoin_with_cached_token(&mut self) -> bool {
+        let path = self.player_file(credentials::TOKEN_CACHE_PATH);
+        let Some(connect_token) = credentials::load_cached_token(path, CACHED_TOKEN_MIN_LIFETIME)
+        else {
+            return false;
+        };
+
+        self.join_session_with_token(PackedByteArray::from(connect_token.as_slice()));
+        return true;
+    }
+
+    #[func]
+    fn has_cached_connect_token(&self) -> bool {
+        let path = self.player_file(credentials::TOKEN_CACHE_PATH);
+        return credentials::load_cached_token(path, CACHED_TOKEN_MIN_LIFETIME).is_some();
+    }
+
+    /// Call when the player logs out, so the next person on this device can't rejoin as them.
+    #[func]
+    fn clear_cached_connect_token(&mut self) {
+        credentials::clear_cached_token(self.player_file(credentials::TOKEN_CACHE_PATH));
+    }
+
+    // Emitted `credential_refresh_margin` seconds before the connect token expires. Fetch a new token from
+    // the backend and pass it to `rotate_credentials`.
+    #[signal]
+    fn credentials_expiring(seconds_left: f64);
+    // Emitted when the session has moved onto the connection made with the new token.
+    #[signal]
+    fn credentials_rotated();
+    // Emitted when the connection with the new token couldn't be made. The old connection is already gone, so
+    // `lost_connection` follows.
+    #[signal]
+    fn credentials_rotation_failed(error: GString);
+
+    /// Moves the session onto a connection made with a new connect token, without dropping anything
+    /// GDScript sees: entities, subscriptions, pending requests and stats carry over, and there is no
+    /// `join_completed`. The token has to be for the same client id. Returns false if it isn't, or if there
+    /// is no session to rotate.
+    #[func]
+    fn rotate_credentials(&mut self, connect_token: PackedByteArray) -> bool {
+        let client = RenetClient::new(self.connection_config());
+        // The simulated server takes any client id without a token.
+        let simulated = self.simulate_server && Os::singleton().is_debug_build();
+        let options = match self.socket_options() {
+            // The current connection still holds a fixed port.
+            Ok(options) => SocketOptions {
+                bind_port: 0,
+                ..options
+            },
+            Err(error) => {
+                godot_error!("rotate_credentials: {error}");
+                return false;
+            }
+        };
+        let token_cache = self
+            .cache_connect_token
+            .then(|| self.player_file(credentials::TOKEN_CACHE_PATH));
+        let Some(session) = &mut self.game_session else {
+            godot_error!("rotate_credentials: not in a session");
+            return false;
+        };
+
+        let token = match credentials::read_connect_token(connect_token.as_slice()) {
+            Ok(token) => token,
+            Err(error) => {
+                godot_error!("rotate_credentials: {error}");
+                return false;
+            }
+        };
+        if token.client_id != session.client_id {
+            godot_error!(
+                "rotate_credentials: the token is for client {}, the session is client {}",
+                token.client_id,
+                session.client_id
+            );
+            return false;
+        }
+
+        let server_addr = match credentials::token_server_addr(&token) {
+            Ok(server_addr) => server_addr,
+            Err(error) => {
+                godot_error!("rotate_credentials: {error}");
+                return false;
+            }
+        };
+
+        session.credentials_expire_at = Some(token.expire_timestamp);
+        session.timeout_seconds = credentials::timeout_seconds(&token);
+        session.credentials_expiry_warned = false;
+        session.connect_token = Some(connect_token.to_vec());
+        if let Some(path) = token_cache {
+            credentials::save_cached_token(path, connect_token.as_slice());
+        }
+        if simulated {
+            let signal = self.signal_names.credentials_rotated.clone();
+            self.emit(signal, &[]);
+            return true;
+        }
+
+        let authentication = ClientAuthentication::Secure {
+            connect_token: token,
+        };
+        let transport = match transport::create_transport(
+            server_addr,
+            clock::unix_time(),
+            authentication,
+            None,
+            &options,
+        ) {
+            Ok(transport) => transport,
+            Err(error) => {
+                godot_error!("rotate_credentials: {error}");
+                return false;
+            }
+        };
+
+        // Anything still queued is sent before the old connection goes away. Netcode servers take one
+        // connection per client id, so the new one only gets in once the server has let go of the old one.
+        session.flush_coalesced();
+        let _ = session.transport.send_packets(&mut session.client);
+        session.transport.disconnect();
+        session.client = client;
+        session.transport = transport;
+        session.server_addr = server_addr;
+        session.rotating_credentials = true;
+        return true;
+    }
+
+    fn start_session(
+        &mut self,
+        client_id: u64,
+        server_addr: SocketAddr,
+        authentication: ClientAuthentication,
+        credentials_expire_at: Option<u64>,
+    ) {
+        if let Some(other) = &self.duplicate_of {
+            let error = io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "the manager at {other} already has player_index {}",
+                    self.player_index
+                ),
+            );
+            self.fail_join(error.into());
+            return;
+        }
+        let (server_addr, authentication, credentials_expire_at) =
+            match self.simulated_server_address() {
+                Some(Ok(address)) => {
+                    let authentication = ClientAuthentication::Unsecure {
+                        server_addr: address,
+                        client_id,
+                        user_data: None,
+                        protocol_id: 0,
+                    };
+                    (address, authentication, None)
+                }
+                Some(Err(error)) => {
+                    self.fail_join(error.into());
+                    return;
+                }
+                None => (server_addr, authentication, credentials_expire_at),
+            };
+        // A join from the game replaces a retry that was waiting.
+        self.handshake_retry = None;
+        // The session being replaced ends here.
+        self.capture_session_report(true);
+        self.record_session_history();
+        if let Some(desync) = &mut self.desync {
+            desync.reset();
+        }
+
+        let timeout_seconds = match &authentication {
+            ClientAuthentication::Secure { connect_token } => {
+                credentials::timeout_seconds(connect_token)
+            }
+            ClientAuthentication::Unsecure { .. } => credentials::UNSECURE_TIMEOUT_SECONDS,
+        };
+
+        // Creating a client settings profile. This profile controls how the client communicates with the server.
+        let client = RenetClient::new(self.connection_config());
+        let current_time = clock::unix_time();
+
+        // A socket whose public endpoint we know is used if it fits, so the endpoint stays right.
+        let same_family = |socket: &UdpSocket| match socket.local_addr() {
+            Ok(local_addr) => local_addr.is_ipv4() == server_addr.is_ipv4(),
+            Err(_) => false,
+        };
+        let socket = match &self.stun_socket {
+            Some(socket) if same_family(socket) => self.stun_socket.take(),
+            _ => None,
+        };
+        let options = match self.socket_options() {
+            Ok(options) => options,
+            Err(error) => {
+                self.fail_join(error.into());
+                return;
+            }
+        };
+        let transport = match transport::create_transport(
+            server_addr,
+            current_time,
+            authentication,
+            socket,
+            &options,
+        ) {
+            Ok(transport) => transport,
+            Err(error) => {
+                self.fail_join(error);
+                return;
+            }
+        };
+        self.join_error = None;
+
+        self.sessions_started += 1;
+        self.game_session = Some(GameSession {
+            client,
+            transport,
+            transport_error: Result::Ok(()),
+            client_id,
+            server_addr,
+            taken_over: false,
+            reclaim_pending: false,
+            join_pending: true,
+            handshake_attempt: 1,
+            report_captured: false,
+            credentials_expire_at,
+            connect_token: None,
+            timeout_seconds,
+            unstable: false,
+            credentials_expiry_warned: false,
+            rotating_credentials: false,
+            requests: PendingRequests::default(),
+            negotiation: None,
+            wire_format: negotiation::FORMAT_RAW,
+            compression: negotiation::COMPRESSION_NONE,
+            owners: HashMap::new(),
+            congested: Default::default(),
+            awaiting_drain: Default::default(),
+            channel_stats: Default::default(),
+            packet_event_elapsed: 0.0,
+            packet_event_baseline: Default::default(),
+            send_rate: SendRateController::new(
+                self.adaptive_send_rate,
+                self.min_send_rate,
+                self.local_tick_rate(),
+            ),
+            inbound_limiters: std::array::from_fn(|channel_id| {
+                InboundLimiter::new(self.inbound_limit(channel_id))
+            }),
+            session_time: 0.0,
+            render_lag: 0.0,
+            quality: QualitySummary::new(current_time.as_secs_f64()),
+            quality_rating: QualityRating::default(),
+            trace: MessageTrace::default(),
+            stats_history: StatsHistory::default(),
+            interpolation: InterpolationDelay::default(),
+            presence: Presence::default(),
+            rich_presence: RichPresence::default(),
+            frame_stats: FrameStats::default(),
+            clock_sync: ClockSync::default(),
+            server_tick_rate: None,
+            server_tick_reference: None,
+            snapshot_tick: None,
+            match_seed: None,
+            received_at_usec: 0,
+            received_queue: VecDeque::new(),
+            generation: self.sessions_started,
+            server_config: Dictionary::new(),
+            resync_pending: false,
+            sequencer: Sequencer::default(),
+            coalesce_messages: self.coalesce_messages,
+            coalesced: Default::default(),
+            coalesced_size: Default::default(),
+            send_buffer: BytesMut::with_capacity(SEND_BUFFER_CAPACITY),
+            fuzzer: self.create_fuzzer(current_time),
+            corpus: self.create_corpus_recorder(),
+            recorder: self.create_session_recorder(current_time),
+        });
+        let cap = self.send_rate_cap();
+        if let Some(session) = &mut self.game_session {
+            session.send_rate.set_cap(cap);
+        }
+    }
+
+    // Warns before the connect token expires.
+    fn update_credentials(&mut self) {
+        let margin = self.credential_refresh_margin;
+        let mut expiring_in = None;
+        if let Some(session) = &mut self.game_session {
+            if let Some(expire_at) = session.credentials_expire_at {
+                let now = clock::unix_seconds();
+                let seconds_left = expire_at as f64 - now;
+                if !session.credentials_expiry_warned && seconds_left <= margin {
+                    session.credentials_expiry_warned = true;
+                    expiring_in = Some(seconds_left.max(0.0));
+                }
+            }
+        }
+
+        if let Some(seconds_left) = expiring_in {
+            let signal = self.signal_names.credentials_expiring.clone();
+            self.emit(signal, &[seconds_left.to_variant()]);
+        }
+    }
+
+    fn mute_list(&mut self) -> &mut MuteList {
+        let path = self.player_file(mute_list::MUTE_LIST_PATH);
+        return self.mute_list.get_or_insert_with(|| MuteList::load(path));
+    }
+
+    fn save_sync(&mut self) -> &mut SaveSync {
+        let path = self.player_file(save_sync::SAVE_SYNC_PATH);
+        return self.save_sync.get_or_insert_with(|| SaveSync::load(path));
+    }
+
+    fn send_save(
+        &mut self,
+        slot: &str,
+        data: PackedByteArray,
+        info: Dictionary,
+        base: &Dictionary,
+    ) -> bool {
+        if slot.is_empty() || slot.len() > protocol::MAX_TOPIC_LENGTH {
+            godot_error!("upload_save: slot names must be 1 to 255 bytes long");
+            return false;
+        }
+        let meta = self.save_sync().next_meta(slot, base, data.len(), info);
+        let mut encoded = Vec::new();
+        if let Err(error) = cbor::encode(&meta.to_variant(), &mut encoded) {
+            godot_error!("upload_save: {error}");
+            return false;
+        }
+        if encoded.len() > u16::MAX as usize {
+            godot_error!("upload_save: info is too big, keep it under 64 KiB");
+            return false;
+        }
+
+        let Some(session) = &mut self.game_session else {
+            return false;
+        };
+        if !session.client.is_connected() {
+            return false;
+        }
+        // The slot, lengths and message kind come on top.
+        let size = encoded.len() + data.len() + slot.len() + 4;
+        if !session
+            .client
+            .can_send_message(channels::RELIABLE_UNORDERED, size)
+        {
+            godot_error!("upload_save: {size} bytes don't fit in the channel's memory budget");
+            return false;
+        }
+        session.send_client_message(
+            channels::RELIABLE_UNORDERED,
+            &ClientMessage::SaveUpload {
+                slot,
+                meta: &encoded,
+                blob: data.as_slice(),
+            },
+        );
+        self.save_sync().start_upload(slot, meta);
+        return true;
+    }
+
+    fn decode_save_meta(meta: &[u8]) -> Option<Dictionary> {
+        if meta.is_empty() {
+            return Some(Dictionary::new());
+        }
+        return cbor::decode(meta).and_then(|meta| meta.try_to::<Dictionary>().ok());
+    }
+
+    fn channel_by_name(&self, name: &str) -> Option<u8> {
+        if let Some(id) = self.channel_names.get(name) {
+            return id
+                .try_to::<i64>()
+                .ok()
+                .filter(|id| (0..CHANNEL_COUNT as i64).contains(id))
+                .map(|id| id as u8);
+        }
+        return channels::channel_by_name(name);
+    }
+
+    // Channel ids or names, as the channel functions take them. Reports unknown channels with the function's
+    // name.
+    fn resolve_channel(&self, channel: &Variant, function: &str) -> Option<i64> {
+        let channel_id = match channel.get_type() {
+            VariantType::INT => {
+                Some(channel.to::<i64>()).filter(|id| (0..CHANNEL_COUNT as i64).contains(id))
+            }
+            VariantType::STRING | VariantType::STRING_NAME => {
+                self.channel_by_name(&channel.to_string()).map(i64::from)
+            }
+            _ => None,
+        };
+        if channel_id.is_none() {
+            godot_error!("{function}: unknown channel {channel}");
+        }
+        return channel_id;
+    }
+
+    fn call_channel_handlers(&mut self, channel_id: u8, value: Variant) {
+        // Handlers can add and remove handlers, this goes through the ones there were to begin with.
+        let handlers = self.channel_handlers[channel_id as usize].clone();
+        for handler in handlers {
+            if !handler.is_valid() {
+                continue;
+            }
+            let _base = self.base_mut();
+            handler.callv(varray![value.clone()]);
+        }
+    }
+
+    // Runs the chat filters, returns None if one of them dropped the message.
+    fn filter_chat(&mut self, mut text: String, sender: u64, outgoing: bool) -> Option<String> {
+        // Filters can add and remove filters, this goes through the ones there were to begin with.
+        let filters = self.chat_filters.clone();
+        for filter in filters {
+            // Filters on freed objects are skipped rather than dropping every message.
+            if !filter.is_valid() {
+                continue;
+            }
+            let result = {
+                let _base = self.base_mut();
+                filter.callv(varray![text.clone(), sender as i64, outgoing])
+            };
+            if result.is_nil() {
+                return None;
+            }
+            let Ok(filtered) = result.try_to::<GString>() else {
+                godot_error!("Chat filters have to return a String or null, got {result}");
+                return None;
+            };
+            text = filtered.to_string();
+            if text.is_empty() {
+                return None;
+            }
+        }
+        return Some(text);
+    }
+
+    fn set_player_muted(&mut self, client_id: i64, muted: bool) {
+        if !self.mute_list().set_muted(client_id as u64, muted) || !self.share_mute_list {
+            return;
+        }
+        // Otherwise it is sent when we connect.
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                let message = ClientMessage::Mute {
+                    client_id: client_id as u64,
+                    muted,
+                };
+                session.send_client_message(channels::RELIABLE_ORDERED, &message);
+            }
+        }
+    }
+
+    fn send_mute_list(&mut self) {
+        if !self.share_mute_list {
+            return;
+        }
+        let path = self.player_file(mute_list::MUTE_LIST_PATH);
+        let mute_list = self.mute_list.get_or_insert_with(|| MuteList::load(path));
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        for client_id in mute_list.muted() {
+            let message = ClientMessage::Mute {
+                client_id,
+                muted: true,
+            };
+            session.send_client_message(channels::RELIABLE_ORDERED, &message);
+        }
+    }
+
+    fn outbox(&mut self) -> &mut Outbox {
+        let path = self.player_file(outbox::OUTBOX_PATH);
+        return self.outbox.get_or_insert_with(|| Outbox::load(path));
+    }
+
+    fn send_outbox(&mut self) {
+        let path = self.player_file(outbox::OUTBOX_PATH);
+        let outbox = self.outbox.get_or_insert_with(|| Outbox::load(path));
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        for (id, payload) in outbox.entries() {
+            let message = ClientMessage::Outbox { id: *id, payload };
+            session.send_client_message(channels::RELIABLE_ORDERED, &message);
+        }
+    }
+
+    fn update_desync_check(&mut self) {
+        let server_tick = self.server_tick();
+        if server_tick < 0 {
+            return;
+        }
+        let Some(checker) = &mut self.desync else {
+            return;
+        };
+        let Some(tick) = checker.due_tick(server_tick as u32) else {
+            return;
+        };
+
+        let callback = checker.checksum_callback();
+        let checksum = {
+            let _base = self.base_mut();
+            callback.callv(varray![tick as i64])
+        };
+        let Ok(checksum) = checksum.try_to::<i64>() else {
+            godot_error!("The desync checksum callback has to return an int, got {checksum}");
+            return;
+        };
+
+        let desync = match &mut self.desync {
+            Some(checker) => checker.add_local_checksum(tick, checksum),
+            None => None,
+        };
+        if let Some(desync) = desync {
+            self.emit_desync_detected(desync);
+        }
+    }
+
+    fn emit_desync_detected(&mut self, desync: Desync) {
+        let dump_callback = self.desync.as_ref().and_then(DesyncChecker::dump_callback);
+        let state = match dump_callback {
+            Some(callback) => {
+                let _base = self.base_mut();
+                callback.callv(VariantArray::new())
+            }
+            None => Variant::nil(),
+        };
+
+        let mut diagnostics = Dictionary::new();
+        diagnostics.set("tick", desync.tick as i64);
+        diagnostics.set("local_checksum", desync.local_checksum);
+        diagnostics.set("server_checksum", desync.server_checksum);
+        diagnostics.set("server_tick", self.server_tick());
+        diagnostics.set("state", state);
+
+        let signal = self.signal_names.desync_detected.clone();
+        self.emit(
+            signal,
+            &[(desync.tick as i64).to_variant(), diagnostics.to_variant()],
+        );
+
+        if self.auto_report_on_desync {
+            let mut details = Dictionary::new();
+            details.set("desync", diagnostics);
+            self.write_report("desync".into(), details);
+        }
+    }
+
+    fn update_stability(&mut self) {
+        let fraction = self.unstable_timeout_fraction;
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() {
+            return;
+        }
+
+        let silence = session
+            .transport
+            .time_since_last_received_packet()
+            .as_secs_f64();
+        let unstable = silence >= session.timeout_seconds * fraction;
+        if unstable == session.unstable {
+            return;
+        }
+
+        session.unstable = unstable;
+        if unstable {
+            let signal = self.signal_names.connection_unstable.clone();
+            self.emit(signal, &[silence.to_variant()]);
+        } else {
+            let signal = self.signal_names.connection_recovered.clone();
+            self.emit(signal, &[]);
+        }
+    }
+
+    // Everything the network does each tick, driven by `physics_process` or `process` depending on `tick_mode`.
+    fn network_tick(&mut self, delta: f64) {
+        // `delta` is scaled by `Engine.time_scale`, the network runs on the time that really passed.
+        let scaled_delta = delta;
+        let now = Instant::now();
+        let delta = match self.last_network_tick.replace(now) {
+            Some(last) => now.duration_since(last).as_secs_f64(),
+            None => delta / Engine::singleton().get_time_scale().max(f64::EPSILON),
+        };
+        let scale_render_time = self.scale_render_time;
+        if let Some(session) = &mut self.game_session {
+            session.render_lag = match scale_render_time {
+                true => (session.render_lag + delta - scaled_delta).max(0.0),
+                false => 0.0,
+            };
+        }
+
+        // Probes don't need a session, so they run before anything else.
+        self.update_region_probe();
+        self.update_address_pings();
+        self.update_diagnostics();
+        if let Some(speed_test) = &mut self.speed_test {
+            speed_test.update();
+        }
+        self.update_stun_query();
+        self.update_nat_detector();
+        self.update_afk(delta);
+        self.update_handshake_retry(delta);
+        if let Some(local_server) = &mut self.local_server {
+            local_server.update(delta);
+        }
+
+        // If the transport has an error we don't want to do anything.
+        // When the transport has error, it will emit a signal on `lost_connection`. You can see where it
+        // emits the signal below inside this function.
+        if self.transport_has_error() {
+            return;
+        }
+
+        // Update client and transport.
+        let deltadur = Duration::from_secs_f64(delta);
+        let mut connection_quality = 0;
+        if let Some(session) = &mut self.game_session {
+            session.session_time += delta;
+            session.client.update(deltadur);
+            // Capturing any errors the transport might throw.
+            session.transport_error = session.transport.update(deltadur, &mut session.client);
+            // Everything that arrived since the last tick was read just now.
+            session.received_at_usec = Time::singleton().get_ticks_usec();
+            if session.client.is_connected() {
+                let (rtt, packet_loss) = (session.client.rtt(), session.client.packet_loss());
+                session.quality.sample(delta, rtt, packet_loss);
+                session.quality_rating.update(delta, rtt, packet_loss);
+                session
+                    .stats_history
+                    .update(delta, session.session_time, &session.client);
+                connection_quality = session.quality_rating.rating();
+            }
+        }
+        if connection_quality != self.connection_quality {
+            self.connection_quality = connection_quality;
+            let signal = self.signal_names.quality_changed.clone();
+            self.emit(signal, &[connection_quality.to_variant()]);
+        }
+
+        if self.transport_has_error() {
+            self.emit_lost_connection();
+            return;
+        }
+
+        self.update_stability();
+        self.update_desync_check();
+        self.update_presence();
+        self.update_rich_presence();
+        self.update_performance_report(delta);
+        self.update_heartbeat(delta);
+
+        let mut joined = false;
+        let mut rotated = false;
+        if let Some(session) = &mut self.game_session {
+            if session.join_pending && session.client.is_connected() {
+                session.join_pending = false;
+                joined = true;
+            }
+            if session.rotating_credentials && session.client.is_connected() {
+                session.rotating_credentials = false;
+                // The server moves what it kept for our client id onto the new connection.
+                session.reclaim_pending = true;
+                rotated = true;
+            }
+        }
+        if joined {
+            // A new connection means a server that doesn't know our subscriptions yet.
+            let preferred_formats = self.preferred_formats.clone();
+            if let Some(session) = &mut self.game_session {
+                // Sent first, so the server knows the format before anything else arrives.
+                if !preferred_formats.is_empty() {
+                    let negotiation = Negotiation::new(
+                        negotiation::offered_formats(preferred_formats.as_slice()),
+                        session.session_time,
+                    );
+                    session.send_client_message(
+                        channels::RELIABLE_ORDERED,
+                        &ClientMessage::Capabilities {
+                            formats: negotiation.offered_formats(),
+                            compressions: negotiation.offered_compressions(),
+                        },
+                    );
+                    session.negotiation = Some(negotiation);
+                }
+                for topic in &self.subscriptions {
+                    session.send_client_message(
+                        channels::RELIABLE_ORDERED,
+                        &ClientMessage::Subscribe(topic),
+                    );
+                }
+                for store in self.replicas.keys() {
+                    session.send_client_message(
+                        channels::RELIABLE_ORDERED,
+                        &ClientMessage::ReplicaSubscribe(store),
+                    );
+                }
+            }
+            self.send_outbox();
+            self.send_mute_list();
+            // A new connection starts out active on the server.
+            if self.afk {
+                self.send_afk_status();
+            }
+            let route = self.failover.take().and_then(|failover| failover.current());
+            if let Some((name, _)) = route {
+                let signal = self.signal_names.failover_route_selected.clone();
+                self.emit(signal, &[name.to_variant()]);
+            }
+            let signal = self.signal_names.join_completed.clone();
+            self.emit(signal, &[true.to_variant(), GString::new().to_variant()]);
+        }
+        if rotated {
+            // Reliable messages the old connection hadn't delivered are lost with it.
+            self.send_outbox();
+            let signal = self.signal_names.credentials_rotated.clone();
+            self.emit(signal, &[]);
+        }
+
+        let mut negotiation_timed_out = false;
+        if let Some(session) = &mut self.game_session {
+            if let Some(negotiation) = &session.negotiation {
+                if negotiation.is_timed_out(session.session_time) {
+                    session.negotiation = None;
+                    negotiation_timed_out = true;
+                }
+            }
+        }
+        if negotiation_timed_out {
+            self.emit_wire_format_negotiated(true);
+        }
+
+        self.update_credentials();
+
+        let bandwidth_cap = match self.bandwidth_limited {
+            true => self.bandwidth_cap_bytes_per_second as f64,
+            false => 0.0,
+        };
+
+        // Messages are handled after we are done with the session, because handling them emits signals.
+        let mut received = VecDeque::new();
+        let mut generation = None;
+        if let Some(session) = &mut self.game_session {
+            received = std::mem::take(&mut session.received_queue);
+            generation = Some(session.generation);
+        }
+        let limits = self.message_limits();
+        let mut flooded_channels = [false; CHANNEL_COUNT];
+        let mut sequence_missing = 0;
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                // Get messages from the server.
+                for channel_id in 0..CHANNEL_COUNT as u8 {
+                    let limiter = &mut session.inbound_limiters[channel_id as usize];
+                    limiter.refill(delta);
+                    while let Some(message) = session.client.receive_message(channel_id) {
+                        let stats = &mut session.channel_stats[channel_id as usize];
+                        stats.messages_received += 1;
+                        stats.bytes_received += message.len() as u64;
+
+                        // Dropped before decoding, so a flood costs as little as possible.
+                        if !limiter.allow(message.len()) {
+                            stats.messages_dropped += 1;
+                            continue;
+                        }
+
+                        let message = match channel_id {
+                            channels::UNRELIABLE_SEQUENCED => {
+                                match session.sequencer.unwrap(&message) {
+                                    Some(body) => message.slice_ref(body),
+                                    // Older than what we already have.
+                                    None => continue,
+                                }
+                            }
+                            _ => message,
+                        };
+                        session
+                            .trace
+                            .record(session.session_time, false, channel_id, &message);
+                        if let Some(recorder) = &mut session.recorder {
+                            if let Err(error) =
+                                recorder.record(session.session_time, false, channel_id, &message)
+                            {
+                                godot_warn!("Stopped recording the session: {error}");
+                                session.recorder = None;
+                            }
+                        }
+                        let valid = ServerMessage::decode_all(&message, |message| {
+                            let result = validation::validate(&message, &limits).map(|_| message);
+                            received.push_back((channel_id, session.received_at_usec, result));
+                        });
+                        if !valid {
+                            received.push_back((
+                                channel_id,
+                                session.received_at_usec,
+                                Err(Rejection::malformed(&message)),
+                            ));
+                        }
+                    }
+                    flooded_channels[channel_id as usize] = limiter.take_flood_started();
+                }
+                sequence_missing = session.sequencer.take_missing();
+
+                if session.reclaim_pending {
+                    session.reclaim_pending = false;
+                    session.send_client_message(
+                        channels::RELIABLE_ORDERED,
+                        &ClientMessage::ReclaimSession,
+                    );
+                }
+            }
+
+            // Sends all packets to the server based on the client settings. Over the bandwidth cap, packets
+            // wait in renet until the measured rate drops again.
+            let over_cap = bandwidth_cap > 0.0
+                && session.client.network_info().bytes_sent_per_second > bandwidth_cap;
+            if session.send_rate.should_send(delta) && !over_cap {
+                session.flush_coalesced();
+                session.transport_error = session.transport.send_packets(&mut session.client);
+            }
+        }
+
+        self.update_packet_events(delta);
+
+        if sequence_missing > 0 {
+            let signal = self.signal_names.sequence_gap.clone();
+            self.emit(
+                signal,
+                &[
+                    (channels::UNRELIABLE_SEQUENCED as i64).to_variant(),
+                    (sequence_missing as i64).to_variant(),
+                ],
+            );
+        }
+
+        for (channel_id, flooded) in flooded_channels.into_iter().enumerate() {
+            if flooded {
+                let signal = self.signal_names.inbound_flood_detected.clone();
+                self.emit(signal, &[(channel_id as i64).to_variant()]);
+            }
+        }
+
+        // Whatever is still queued after sending is the backlog.
+        let mut congested_channels = [None; CHANNEL_COUNT];
+        let mut drained_channels = [false; CHANNEL_COUNT];
+        let mut fullest_channel: f64 = 0.0;
+        for channel_id in 0..CHANNEL_COUNT as u8 {
+            let backlog = self.channel_backlog(channel_id);
+            let budget = self.channel_memory_budget(channel_id) as f64;
+            let threshold = budget * self.congestion_threshold;
+            fullest_channel = fullest_channel.max(backlog as f64 / budget.max(1.0));
+            if let Some(session) = &mut self.game_session {
+                let was_congested = session.congested[channel_id as usize];
+                session.congested[channel_id as usize] = backlog as f64 > threshold;
+                if !was_congested && session.congested[channel_id as usize] {
+                    congested_channels[channel_id as usize] = Some(backlog);
+                }
+                if session.awaiting_drain[channel_id as usize] && backlog as f64 <= threshold {
+                    session.awaiting_drain[channel_id as usize] = false;
+                    drained_channels[channel_id as usize] = true;
+                }
+            }
+        }
+        for (channel_id, backlog) in congested_channels.into_iter().enumerate() {
+            let Some(backlog) = backlog else {
+                continue;
+            };
+            let signal = self.signal_names.channel_congested.clone();
+            self.emit(
+                signal,
+                &[(channel_id as i64).to_variant(), backlog.to_variant()],
+            );
+        }
+        for (channel_id, drained) in drained_channels.into_iter().enumerate() {
+            if drained {
+                let signal = self.signal_names.channel_drained.clone();
+                self.emit(signal, &[(channel_id as i64).to_variant()]);
+            }
+        }
+
+        let packet_loss_threshold = self.adaptive_packet_loss_threshold;
+        let backlog_threshold = self.congestion_threshold;
+        let mut new_send_rate = None;
+        if let Some(session) = &mut self.game_session {
+            new_send_rate = session.send_rate.evaluate(
+                delta,
+                session.client.packet_loss(),
+                fullest_channel,
+                packet_loss_threshold,
+                backlog_threshold,
+            );
+        }
+        if let Some(rate) = new_send_rate {
+            let signal = self.signal_names.send_rate_changed.clone();
+            self.emit(signal, &[rate.to_variant()]);
+        }
+
+        // Held back messages are at the front, so they go first and stay in order. The oldest are handled
+        // over the budget when too many are waiting, so a server that keeps sending faster than that can't
+        // grow the queue forever.
+        let started = Instant::now();
+        let time_budget = self.message_time_budget_ms / 1000.0;
+        let must_handle = received.len().saturating_sub(MAX_DEFERRED_MESSAGES);
+        let (mut handled, mut over_budget) = (0, false);
+        for index in 0..received.len() {
+            let (channel_id, received_at, result) = received.pop_front().unwrap();
+            let priority = self.priority_channels[channel_id as usize];
+            if over_budget && !priority && index >= must_handle {
+                received.push_back((channel_id, received_at, result));
+                continue;
+            }
+
+            match result {
+                Ok(message) => {
+                    self.delivering = Some((received_at, message.tick()));
+                    self.handle_server_message(channel_id, message);
+                }
+                Err(rejection) => self.reject_server_message(channel_id, rejection),
+            }
+            // A handler that disconnected or joined again ended the session the rest came from.
+            if self.game_session.as_ref().map(|session| session.generation) != generation {
+                received.clear();
+                break;
+            }
+            if !priority {
+                handled += 1;
+                over_budget = (self.max_messages_per_tick > 0
+                    && handled >= self.max_messages_per_tick)
+                    || (time_budget > 0.0 && started.elapsed().as_secs_f64() >= time_budget);
+            }
+        }
+        self.delivering = None;
+        if let Some(session) = &mut self.game_session {
+            if Some(session.generation) == generation {
+                session.received_queue = received;
+            }
+        }
+
+        let mut expired_requests = Vec::new();
+        if let Some(session) = &mut self.game_session {
+            expired_requests = session.requests.take_expired(session.session_time);
+        }
+        for request_id in expired_requests {
+            self.fail_request(request_id, "timed out");
+        }
+
+        if self.transport_has_error() {
+            self.emit_lost_connection();
+            return;
+        }
+    }
+
+    fn update_afk(&mut self, delta: f64) {
+        self.idle_seconds += delta;
+        let afk = self.afk_timeout > 0.0 && self.idle_seconds >= self.afk_timeout;
+        self.set_afk(afk);
+    }
+
+    fn set_afk(&mut self, afk: bool) {
+        if afk == self.afk {
+            return;
+        }
+        self.afk = afk;
+        self.send_afk_status();
+        let signal = self.signal_names.afk_state_changed.clone();
+        self.emit(signal, &[afk.to_variant()]);
+    }
+
+    fn send_afk_status(&mut self) {
+        let afk = self.afk;
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(channels::RELIABLE_ORDERED, &ClientMessage::Afk(afk));
+            }
+        }
+    }
+
+    fn update_performance_report(&mut self, delta: f64) {
+        let interval = self.performance_report_interval;
+        let fields = self.performance_report_fields as u8 & performance::PERFORMANCE_ALL;
+        if self.is_channel_suppressed(channels::UNRELIABLE) {
+            return;
+        }
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() || !session.frame_stats.is_due(delta, interval) {
+            return;
+        }
+
+        let (rtt, packet_loss) = (session.client.rtt(), session.client.packet_loss());
+        let report = session.frame_stats.take_report(fields, rtt, packet_loss);
+        session.send_client_message(channels::UNRELIABLE, &ClientMessage::Performance(&report));
+    }
+
+    fn update_heartbeat(&mut self, delta: f64) {
+        let interval = self.heartbeat_interval;
+        if self.is_channel_suppressed(channels::UNRELIABLE) {
+            return;
+        }
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() {
+            return;
+        }
+        if let Some(ping_id) = session.clock_sync.due(delta, interval) {
+            session.send_client_message(
+                channels::UNRELIABLE,
+                &ClientMessage::Ping {
+                    ping_id,
+                    sent: clock_sync::now_micros(),
+                },
+            );
+        }
+    }
+
+    fn update_packet_events(&mut self, delta: f64) {
+        let (enabled, interval) = (self.packet_events, self.packet_event_interval);
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        // Kept current while off, so turning it on doesn't report everything since the session started.
+        if !enabled {
+            session.packet_event_baseline = session.channel_stats;
+            return;
+        }
+        session.packet_event_elapsed += delta;
+        if session.packet_event_elapsed < interval {
+            return;
+        }
+        session.packet_event_elapsed = 0.0;
+
+        // Direction, bytes and channels.
+        let mut events = [("in", 0, 0), ("out", 0, 0)];
+        for (stats, baseline) in session
+            .channel_stats
+            .iter()
+            .zip(&session.packet_event_baseline)
+        {
+            let received = stats.bytes_received - baseline.bytes_received;
+            let sent = stats.bytes_sent - baseline.bytes_sent;
+            for (event, bytes) in events.iter_mut().zip([received, sent]) {
+                if bytes > 0 {
+                    event.1 += bytes;
+                    event.2 += 1;
+                }
+            }
+        }
+        session.packet_event_baseline = session.channel_stats;
+
+        let signal = self.signal_names.packet_event.clone();
+        for (direction, size, channel_count) in events {
+            if size == 0 {
+                continue;
+            }
+            self.emit(
+                signal.clone(),
+                &[
+                    GString::from(direction).to_variant(),
+                    (size as i64).to_variant(),
+                    (channel_count as i64).to_variant(),
+                ],
+            );
+        }
+    }
+
+    fn update_presence(&mut self) {
+        let Some(state) = self.presence_state else {
+            return;
+        };
+        if self.is_channel_suppressed(channels::UNRELIABLE_SEQUENCED) {
+            return;
+        }
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() {
+            return;
+        }
+        if let Some(state) = session.presence.due(state, session.session_time) {
+            session.send_client_message(
+                channels::UNRELIABLE_SEQUENCED,
+                &ClientMessage::Presence(state),
+            );
+        }
+    }
+
+    fn update_rich_presence(&mut self) {
+        let Some(state) = &self.rich_presence else {
+            return;
+        };
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() {
+            return;
+        }
+        if session.rich_presence.due(state, session.session_time) {
+            session.send_client_message(
+                channels::RELIABLE_ORDERED,
+                &ClientMessage::RichPresence(state),
+            );
+        }
+    }
+
+    fn decode_rich_presence(state: &[u8]) -> Option<Dictionary> {
+        return cbor::decode(state).and_then(|state| state.try_to::<Dictionary>().ok());
+    }
+
+    fn apply_server_config(&mut self, config: Dictionary) {
+        // CBOR keeps integers and floats apart, the server may send either.
+        let number = |key: &str| {
+            let value = config.get(key)?;
+            return value
+                .try_to::<f64>()
+                .ok()
+                .or_else(|| value.try_to::<i64>().ok().map(|value| value as f64))
+                .filter(|value| value.is_finite() && *value >= 0.0);
+        };
+
+        if let Some(delay) = number("interpolation_delay_ms") {
+            self.interpolation_delay_ms = delay;
+        }
+        if let Some(interval) = number("performance_report_interval") {
+            self.performance_report_interval = interval;
+        }
+        if let Some(interval) = number("heartbeat_interval") {
+            self.heartbeat_interval = interval;
+        }
+        let messages_per_second = number("inbound_messages_per_second");
+        let bytes_per_second = number("inbound_bytes_per_second");
+        if let Some(limit) = messages_per_second {
+            self.inbound_messages_per_second = limit as i64;
+        }
+        if let Some(limit) = bytes_per_second {
+            self.inbound_bytes_per_second = limit as i64;
+        }
+        let limits: [InboundLimit; CHANNEL_COUNT] =
+            std::array::from_fn(|channel_id| self.inbound_limit(channel_id));
+        let tick_rate = number("tick_rate").filter(|tick_rate| *tick_rate > 0.0);
+
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if messages_per_second.is_some() || bytes_per_second.is_some() {
+            for (limiter, limit) in session.inbound_limiters.iter_mut().zip(limits) {
+                limiter.set_limit(limit);
+            }
+        }
+        for (key, value) in config.iter_shared() {
+            session.server_config.set(key, value);
+        }
+        let tick_rate_changed = tick_rate.is_some() && session.server_tick_rate != tick_rate;
+        if tick_rate_changed {
+            session.server_tick_rate = tick_rate;
+        }
+
+        if let Some(tick_rate) = tick_rate.filter(|_| tick_rate_changed) {
+            let signal = self.signal_names.server_tick_rate_changed.clone();
+            self.emit(signal, &[tick_rate.to_variant()]);
+        }
+        let signal = self.signal_names.server_config_received.clone();
+        self.emit(signal, &[config.to_variant()]);
+    }
+
+    fn send_rate_cap(&self) -> Option<f64> {
+        return self.bandwidth_limited.then_some(self.limited_send_rate);
+    }
+
+    fn is_channel_suppressed(&self, channel_id: u8) -> bool {
+        return self.bandwidth_limited && self.optional_channels[channel_id as usize];
+    }
+
+    fn update_region_probe(&mut self) {
+        let Some((prober, _)) = &mut self.region_probe else {
+            return;
+        };
+        if !prober.update() {
+            return;
+        }
+
+        let results = self.region_probe_results();
+        self.region_probe = None;
+        let signal = self.signal_names.regions_probed.clone();
+        self.emit(signal, &[results.to_variant()]);
+    }
+
+    // Emitted on the tick after it finished at the earliest, so `await` always sees it.
+    fn update_diagnostics(&mut self) {
+        let Some(diagnostics) = &mut self.diagnostics else {
+            return;
+        };
+        if !diagnostics.update() {
+            return;
+        }
+
+        let results = diagnostics.results();
+        self.diagnostics = None;
+        let signal = self.signal_names.network_diagnostics_completed.clone();
+        self.emit(signal, &[results.to_variant()]);
+    }
+
+    fn update_address_pings(&mut self) {
+        let mut index = 0;
+        while index < self.address_pings.len() {
+            let (prober, address) = &mut self.address_pings[index];
+            if !prober.update() {
+                index += 1;
+                continue;
+            }
+
+            let rtt = prober.results().next().and_then(|(_, rtt)| rtt);
+            let address = address.clone();
+            self.address_pings.remove(index);
+            let signal = self.signal_names.address_pinged.clone();
+            self.emit(
+                signal,
+                &[
+                    address.to_variant(),
+                    rtt.map_or(-1.0, |rtt| rtt * 1000.0).to_variant(),
+                ],
+            );
+        }
+    }
+
+    fn update_nat_detector(&mut self) {
+        let Some(detector) = &mut self.nat_detector else {
+            return;
+        };
+        let Some(nat_type) = detector.update() else {
+            return;
+        };
+
+        self.nat_type = nat_type;
+        self.nat_detector = None;
+        let signal = self.signal_names.nat_type_detected.clone();
+        self.emit(signal, &[(nat_type as i64).to_variant()]);
+    }
+
+    fn update_stun_query(&mut self) {
+        let Some(query) = &mut self.stun_query else {
+            return;
+        };
+        let Some(result) = query.update() else {
+            return;
+        };
+
+        let query = self.stun_query.take().unwrap();
+        self.public_endpoint = match result {
+            Ok(endpoint) => {
+                self.stun_socket = Some(query.into_socket());
+                Some(endpoint)
+            }
+            Err(error) => {
+                godot_warn!("discover_public_endpoint: {error}");
+                None
+            }
+        };
+        let endpoint = self.get_public_endpoint();
+        let signal = self.signal_names.public_endpoint_discovered.clone();
+        self.emit(signal, &[endpoint.to_variant()]);
+    }
+
+    // Every signal of the manager goes out through here, so `player_signal` can follow it.
+    fn emit(&mut self, signal: StringName, arguments: &[Variant]) {
+        self.base_mut().emit_signal(signal.clone(), arguments);
+        if self.player_signals {
+            let tagged = self.player_signal_arguments(signal, arguments);
+            let player_signal = self.signal_names.player_signal.clone();
+            self.base_mut().emit_signal(player_signal, &tagged);
+        }
+    }
+
+    // For signals that have to wait a frame so `await` sees them. Deferred calls run in order, so
+    // `player_signal` still comes right after.
+    fn emit_deferred(&mut self, signal: StringName, arguments: &[Variant]) {
+        let mut deferred = vec![signal.to_variant()];
+        deferred.extend_from_slice(arguments);
+        self.base_mut()
+            .call_deferred("emit_signal".into(), &deferred);
+        if self.player_signals {
+            let mut tagged = vec![self.signal_names.player_signal.to_variant()];
+            tagged.extend(self.player_signal_arguments(signal, arguments));
+            self.base_mut().call_deferred("emit_signal".into(), &tagged);
+        }
+    }
+
+    fn player_signal_arguments(&self, signal: StringName, arguments: &[Variant]) -> [Variant; 3] {
+        let arguments: VariantArray = arguments.iter().cloned().collect();
+        return [
+            self.player_index.to_variant(),
+            signal.to_variant(),
+            arguments.to_variant(),
+        ];
+    }
+
+    fn region_probe_results(&self) -> VariantArray {
+        let mut results: Vec<(GString, GString, Option<f64>)> = Vec::new();
+        if let Some((prober, regions)) = &self.region_probe {
+            for ((address, rtt), region) in prober.results().zip(regions) {
+                results.push((region.clone(), GString::from(address.to_string()), rtt));
+            }
+        }
+        for (region, address) in &self.unparsed_regions {
+            results.push((region.clone(), address.clone(), None));
+        }
+        // Unreachable regions sort last.
+        results.sort_by(|a, b| {
+            let a = a.2.unwrap_or(f64::INFINITY);
+            let b = b.2.unwrap_or(f64::INFINITY);
+            return a.total_cmp(&b);
+        });
+
+        let mut array = VariantArray::new();
+        for (region, address, rtt) in results {
+            let mut result = Dictionary::new();
+            result.set("region", region);
+            result.set("address", address);
+            result.set("rtt_ms", rtt.map_or(-1.0, |rtt| rtt * 1000.0));
+            array.push(result.to_variant());
+        }
+        return array;
+    }
+
+    // Only once per session, even if it is ended more than once. `replaced` is for sessions ended by starting
+    // another one.
+    fn capture_session_report(&mut self, replaced: bool) {
+        let code = self.get_disconnect_code();
+        let reason = self.transport_error_message();
+        let mut channel_stats = VariantArray::new();
+        for channel_id in 0..CHANNEL_COUNT as i64 {
+            channel_stats.push(self.get_channel_stats(channel_id.to_variant()).to_variant());
+        }
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if session.report_captured {
+            return;
+        }
+        session.report_captured = true;
+
+        let error_chain = match (&session.transport_error, &self.join_error) {
+            (Err(error), _) | (Ok(_), Some(error)) => report::error_chain(error),
+            (Ok(_), None) => PackedStringArray::new(),
+        };
+        let ended_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0.0, |time| time.as_secs_f64());
+
+        let info = session.client.network_info();
+        let mut stats = Dictionary::new();
+        stats.set("rtt_ms", info.rtt * 1000.0);
+        stats.set("packet_loss", info.packet_loss);
+        stats.set("bytes_sent_per_second", info.bytes_sent_per_second);
+        stats.set("bytes_received_per_second", info.bytes_received_per_second);
+        stats.set("channels", channel_stats);
+
+        let mut report = Dictionary::new();
+        report.set("ended_at", ended_at);
+        report.set("session_time", session.session_time);
+        report.set("client_id", session.client_id as i64);
+        report.set(
+            "server_address",
+            GString::from(session.server_addr.to_string()),
+        );
+        report.set("intended", replaced || code == Self::DISCONNECT_BY_CLIENT);
+        report.set("disconnect_code", code);
+        report.set("disconnect_reason", reason);
+        report.set("error_chain", error_chain);
+        report.set("stats", stats);
+        report.set("stats_history", session.stats_history.samples());
+        report.set(
+            "messages",
+            session.trace.recent_messages(SESSION_REPORT_MESSAGES),
+        );
+        report.set("rejections", session.trace.rejections());
+        self.last_session_report = report;
+    }
+
+    // Only once per session, even if it is ended more than once.
+    fn record_session_history(&mut self) {
+        let code = self.get_disconnect_code();
+        let reason = self.transport_error_message();
+        let limit = self.session_history_limit.max(0) as usize;
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if limit == 0 || !session.quality.has_samples() {
+            return;
+        }
+
+        quality::append_history(session.quality.to_dictionary(code, &reason), limit);
+        session.quality = QualitySummary::new(0.0);
+    }
+
+    // `details` is added to the report as is.
+    fn write_report(&mut self, reason: GString, details: Dictionary) -> GString {
+        // Milliseconds since the epoch, 0 if the clock is before it.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_millis() as u64);
+        let mut report = self.build_report(reason);
+        report.set("created_at", now as f64 / 1000.0);
+        report.set("details", details);
+
+        let Some(path) = report::write_report(&report, now) else {
+            godot_error!("Couldn't write the report to {}", report::REPORT_DIRECTORY);
+            return GString::new();
+        };
+        if !self.report_upload_url.is_empty() {
+            self.upload_report(&report, path.clone());
+        }
+
+        let signal = self.signal_names.report_created.clone();
+        self.emit(signal, &[path.to_variant()]);
+        return path;
+    }
+
+    fn build_report(&self, reason: GString) -> Dictionary {
+        let os = Os::singleton();
+        let mut report = Dictionary::new();
+        report.set("reason", reason);
+        report.set("version", env!("CARGO_PKG_VERSION"));
+        report.set("os", os.get_name());
+        report.set("debug_build", os.is_debug_build());
+
+        let mut config = Dictionary::new();
+        config.set("available_bytes_per_tick", self.available_bytes_per_tick);
+        let mut memory_budgets = PackedInt64Array::new();
+        for channel_id in 0..CHANNEL_COUNT as u8 {
+            memory_budgets.push(self.channel_memory_budget(channel_id));
+        }
+        config.set("channel_memory_budgets", memory_budgets);
+        config.set("congestion_threshold", self.congestion_threshold);
+        config.set("adaptive_send_rate", self.adaptive_send_rate);
+        config.set("min_send_rate", self.min_send_rate);
+        config.set("interpolation_delay_ms", self.interpolation_delay_ms);
+        config.set(
+            "adaptive_interpolation_delay",
+            self.adaptive_interpolation_delay,
+        );
+        config.set("coalesce_messages", self.coalesce_messages);
+        config.set(
+            "inbound_messages_per_second",
+            self.inbound_messages_per_second,
+        );
+        config.set("inbound_bytes_per_second", self.inbound_bytes_per_second);
+        config.set(
+            "max_application_payload_size",
+            self.max_application_payload_size,
+        );
+        config.set("max_snapshot_payload_size", self.max_snapshot_payload_size);
+        config.set("preferred_formats", self.preferred_formats.clone());
+        config.set("bandwidth_limited", self.bandwidth_limited);
+        config.set(
+            "physics_ticks_per_second",
+            Engine::singleton().get_physics_ticks_per_second(),
+        );
+        config.set("player_index", self.player_index);
+        config.set("tick_mode", self.tick_mode);
+        config.set("local_tick_rate", self.local_tick_rate());
+        report.set("config", config);
+
+        let mut errors = Dictionary::new();
+        errors.set("disconnect_code", self.get_disconnect_code());
+        errors.set("transport_error", self.transport_error_message());
+        let join_error = self.join_error.as_ref().map(|error| error.to_string());
+        errors.set("join_error", GString::from(join_error.unwrap_or_default()));
+        report.set("errors", errors);
+
+        let mut channel_stats = VariantArray::new();
+        for channel_id in 0..CHANNEL_COUNT as i64 {
+            channel_stats.push(self.get_channel_stats(channel_id.to_variant()).to_variant());
+        }
+        report.set("channel_stats", channel_stats);
+
+        if let Some(session) = &self.game_session {
+            report.set("client_id", session.client_id as i64);
+            report.set(
+                "server_address",
+                GString::from(session.server_addr.to_string()),
+            );
+            report.set("session_time", session.session_time);
+            report.set("connected", session.client.is_connected());
+            report.set("server_tick", self.server_tick());
+            report.set("messages", session.trace.messages());
+            report.set("rejections", session.trace.rejections());
+            report.set("stats_history", session.stats_history.samples());
+        }
+        return report;
+    }
+
+    fn upload_report(&mut self, report: &Dictionary, path: GString) {
+        if self.report_upload.is_some() {
+            godot_warn!("Another report is still uploading, {path} is only saved to disk");
+            return;
+        }
+
+        let mut request = HttpRequest::new_alloc();
+        self.base_mut().add_child(request.clone().upcast());
+        request.connect(
+            "request_completed".into(),
+            Callable::from_object_method(&self.to_gd(), "on_report_uploaded"),
+        );
+        let mut headers = PackedStringArray::new();
+        headers.push("Content-Type: application/json".into());
+        let error = request
+            .request_ex(self.report_upload_url.clone())
+            .custom_headers(headers)
+            .method(Method::POST)
+            .request_data(Json::stringify(report.to_variant()))
+            .done();
+        if error != Error::OK {
+            godot_warn!("Couldn't start uploading the report {path}: {error:?}");
+            request.queue_free();
+            return;
+        }
+        self.report_upload = Some((request, path));
+    }
+
+    fn socket_options(&self) -> io::Result<SocketOptions> {
+        let bind_address = match self.bind_address.is_empty() {
+            true => None,
+            false => match transport::parse_ip(&self.bind_address.to_string()) {
+                Some(address) => Some(address),
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid bind_address '{}'", self.bind_address),
+                    ))
+                }
+            },
+        };
+
+        let mut interface_addresses = Vec::new();
+        let mut interface_index = None;
+        if !self.bind_interface.is_empty() {
+            let interface =
+                Ip::singleton()
+                    .get_local_interfaces()
+                    .iter_shared()
+                    .find(|interface| {
+                        let name = interface.get("name").map(|name| name.to_string());
+                        return name.as_deref() == Some(&self.bind_interface.to_string());
+                    });
+            let Some(interface) = interface else {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    format!("no network interface named '{}'", self.bind_interface),
+                ));
+            };
+            interface_index = interface
+                .get("index")
+                .and_then(|index| index.to_string().parse().ok());
+            let addresses = interface
+                .get("addresses")
+                .and_then(|addresses| addresses.try_to::<PackedStringArray>().ok())
+                .unwrap_or_default();
+            for address in addresses.as_slice() {
+                if let Some(address) = transport::parse_ip(&address.to_string()) {
+                    interface_addresses.push(address);
+                }
+            }
+        }
+
+        return Ok(SocketOptions {
+            bind_address,
+            interface_addresses,
+            interface_index,
+            bind_port: self.bind_port.clamp(0, u16::MAX as i64) as u16,
+            send_buffer_size: self.socket_send_buffer_size.max(0) as usize,
+            receive_buffer_size: self.socket_receive_buffer_size.max(0) as usize,
+            dscp: transport::dscp_setting(),
+        });
+    }
+
+    fn wire_format(&self) -> u8 {
+        if let Some(session) = &self.game_session {
+            return session.wire_format;
+        }
+
+        return negotiation::FORMAT_RAW;
+    }
+
+    fn emit_wire_format_negotiated(&mut self, fell_back: bool) {
+        let (format, compression) = (self.get_wire_format(), self.get_wire_compression());
+        let signal = self.signal_names.wire_format_negotiated.clone();
+        self.emit(
+            signal,
+            &[
+                format.to_variant(),
+                compression.to_variant(),
+                fell_back.to_variant(),
+            ],
+        );
+    }
+
+    fn handle_server_message(&mut self, channel_id: u8, message: ServerMessage) {
+        match message {
+            ServerMessage::Application(mut payload) => {
+                let fuzz_fraction = self.fuzz_inbound_fraction;
+                if let Some(session) = &mut self.game_session {
+                    if let Some(corpus) = &mut session.corpus {
+                        if let Err(error) = corpus.record(channel_id, &payload) {
+                            godot_warn!("Stopped recording the message corpus: {error}");
+                            session.corpus = None;
+                        }
+                    }
+                    if let Some(fuzzer) = &mut session.fuzzer {
+                        payload = fuzzer.maybe_mutate(payload, fuzz_fraction);
+                    }
+                }
+
+                match self.flatbuffer_handlers.handle(channel_id, &payload) {
+                    Some(Ok(())) => return,
+                    Some(Err(error)) => {
+                        let rejection = Rejection {
+                            kind: "application",
+                            reason: format!("invalid flatbuffer: {error}"),
+                        };
+                        self.reject_server_message(channel_id, rejection);
+                        return;
+                    }
+                    None => {}
+                }
+
+                if self.protobuf.has_channel_type(channel_id) {
+                    match self.protobuf.decode(channel_id, &payload) {
+                        Ok(message) => {
+                            let signal = self.signal_names.protobuf_message_received.clone();
+                            self.emit(
+                                signal,
+                                &[(channel_id as i64).to_variant(), message.to_variant()],
+                            );
+                            self.call_channel_handlers(channel_id, message.to_variant());
+                        }
+                        Err(error) => {
+                            let rejection = Rejection {
+                                kind: "application",
+                                reason: format!("invalid protobuf: {error}"),
+                            };
+                            self.reject_server_message(channel_id, rejection);
+                        }
+                    }
+                    return;
+                }
+
+                if self.cbor_channels[channel_id as usize]
+                    || self.wire_format() == negotiation::FORMAT_CBOR
+                {
+                    match cbor::decode(&payload) {
+                        Some(value) => {
+                            let signal = self.signal_names.cbor_message_received.clone();
+                            self.emit(signal, &[(channel_id as i64).to_variant(), value.clone()]);
+                            self.call_channel_handlers(channel_id, value);
+                        }
+                        None => {
+                            let rejection = Rejection {
+                                kind: "application",
+                                reason: String::from("invalid cbor"),
+                            };
+                            self.reject_server_message(channel_id, rejection);
+                        }
+                    }
+                    return;
+                }
+
+                let payload = PackedByteArray::from(&payload[..]).to_variant();
+                let signal = self.signal_names.message_received.clone();
+                self.emit(signal, &[(channel_id as i64).to_variant(), payload.clone()]);
+                self.call_channel_handlers(channel_id, payload);
+            }
+            ServerMessage::Spawn {
+                entity_id,
+                scene_index,
+                owner_id,
+            } => {
+                if let Some(session) = &mut self.game_session {
+                    session.owners.insert(entity_id, owner_id);
+                }
+                let signal = self.signal_names.entity_spawned.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (entity_id as i64).to_variant(),
+                        (scene_index as i64).to_variant(),
+                        (owner_id as i64).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::Despawn { entity_id } => {
+                if let Some(session) = &mut self.game_session {
+                    session.owners.remove(&entity_id);
+                }
+                let signal = self.signal_names.entity_despawned.clone();
+                self.emit(signal, &[(entity_id as i64).to_variant()]);
+            }
+            ServerMessage::Authority {
+                entity_id,
+                owner_id,
+            } => {
+                if let Some(session) = &mut self.game_session {
+                    session.owners.insert(entity_id, owner_id);
+                }
+                let signal = self.signal_names.authority_changed.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (entity_id as i64).to_variant(),
+                        (owner_id as i64).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::Rpc(packet) => {
+                if self.rpc_bridge_attached {
+                    self.rpc_inbox.push_back(packet);
+                }
+            }
+            ServerMessage::Snapshot { tick, payload } => {
+                let tick_interval = self.server_tick_interval();
+                if let Some(session) = &mut self.game_session {
+                    session
+                        .interpolation
+                        .on_snapshot(tick, session.session_time, tick_interval);
+                    session.update_server_tick(tick);
+                    session.update_snapshot_tick(tick);
+                }
+                let signal = self.signal_names.snapshot_received.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (tick as i64).to_variant(),
+                        PackedByteArray::from(&payload[..]).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::FullSnapshot { tick, payload } => {
+                if let Some(session) = &mut self.game_session {
+                    // This is a new baseline, so whatever we knew about the timing of older snapshots is stale.
+                    session.interpolation = InterpolationDelay::default();
+                    session.server_tick_reference = None;
+                    session.update_server_tick(tick);
+                    session.snapshot_tick = Some(tick);
+                    session.resync_pending = false;
+                }
+                let signal = self.signal_names.resynced.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (tick as i64).to_variant(),
+                        PackedByteArray::from(&payload[..]).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::FullSnapshotTooLarge { size } => {
+                if let Some(session) = &mut self.game_session {
+                    session.resync_pending = false;
+                }
+                let rejection = Rejection {
+                    kind: "full_snapshot",
+                    reason: format!(
+                        "the snapshot is {size} bytes, over the channel's memory budget"
+                    ),
+                };
+                self.reject_server_message(channel_id, rejection);
+            }
+            ServerMessage::Response {
+                request_id,
+                payload,
+            } => {
+                let Some(session) = &mut self.game_session else {
+                    return;
+                };
+                // Too late, the request already failed.
+                if !session.requests.resolve(request_id) {
+                    return;
+                }
+
+                let signal = self.signal_names.response_received.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (request_id as i64).to_variant(),
+                        PackedByteArray::from(&payload[..]).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::Topic { topic, payload } => {
+                // Messages that were already on their way when we unsubscribed.
+                if !self.subscriptions.contains(&topic) {
+                    return;
+                }
+
+                let signal = self.signal_names.topic_message.clone();
+                self.emit(
+                    signal,
+                    &[
+                        GString::from(topic).to_variant(),
+                        PackedByteArray::from(&payload[..]).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::ReplicaUpdate { store, op, body } => {
+                // Updates that were already on their way when the node stopped watching are dropped.
+                if let Some(updates) = self.replicas.get_mut(&store) {
+                    updates.push_back((op, body));
+                }
+            }
+            ServerMessage::Pong {
+                ping_id,
+                sent,
+                arrived,
+                answered,
+            } => {
+                let received = clock_sync::now_micros();
+                if let Some(session) = &mut self.game_session {
+                    session
+                        .clock_sync
+                        .pong(ping_id, sent, arrived, answered, received);
+                }
+            }
+            ServerMessage::Notification {
+                id,
+                ttl,
+                category,
+                payload,
+            } => {
+                let notification = Notification {
+                    id,
+                    category: category.clone(),
+                    payload: payload.clone(),
+                    expires_at: notifications::expires_at(ttl),
+                };
+                if !self.mailbox.receive(notification) {
+                    return;
+                }
+                let signal = self.signal_names.notification_received.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (id as i64).to_variant(),
+                        GString::from(category).to_variant(),
+                        PackedByteArray::from(&payload[..]).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::SaveData { slot, meta, blob } => {
+                let Some(remote) = Self::decode_save_meta(&meta) else {
+                    godot_warn!("The metadata of save '{slot}' isn't a CBOR map, ignoring it");
+                    return;
+                };
+                let sync = self.save_sync();
+                let local_version = sync.local_version(&slot);
+                let remote_version = save_sync::version_of(&remote);
+                let blob = PackedByteArray::from(&blob[..]);
+                match save_sync::compare_versions(&remote_version, &local_version) {
+                    VersionOrder::Same | VersionOrder::Newer => {
+                        sync.set_local_version(&slot, remote_version);
+                        let signal = self.signal_names.save_downloaded.clone();
+                        self.emit(
+                            signal,
+                            &[
+                                GString::from(slot).to_variant(),
+                                blob.to_variant(),
+                                remote.to_variant(),
+                            ],
+                        );
+                    }
+                    VersionOrder::Older | VersionOrder::Concurrent => {
+                        let mut local = Dictionary::new();
+                        local.set("slot", slot.as_str());
+                        local.set("version", local_version);
+                        sync.add_conflict(&slot, local.clone(), remote.clone(), Some(blob));
+                        let signal = self.signal_names.save_conflict.clone();
+                        self.emit(signal, &[local.to_variant(), remote.to_variant()]);
+                    }
+                }
+            }
+            ServerMessage::SaveUploadResult {
+                slot,
+                accepted,
+                meta,
+            } => {
+                let Some(remote) = Self::decode_save_meta(&meta) else {
+                    godot_warn!("The metadata of save '{slot}' isn't a CBOR map, ignoring it");
+                    return;
+                };
+                let sync = self.save_sync();
+                let Some(local) = sync.take_upload(&slot) else {
+                    return;
+                };
+                if accepted {
+                    sync.set_local_version(&slot, save_sync::version_of(&local));
+                    let signal = self.signal_names.save_uploaded.clone();
+                    self.emit(
+                        signal,
+                        &[GString::from(slot).to_variant(), local.to_variant()],
+                    );
+                } else {
+                    sync.add_conflict(&slot, local.clone(), remote.clone(), None);
+                    let signal = self.signal_names.save_conflict.clone();
+                    self.emit(signal, &[local.to_variant(), remote.to_variant()]);
+                }
+            }
+            ServerMessage::FormatSelected {
+                format,
+                compression,
+            } => {
+                let Some(session) = &mut self.game_session else {
+                    return;
+                };
+                // Only the first answer counts, and only if we asked.
+                let Some(negotiation) = session.negotiation.take() else {
+                    return;
+                };
+
+                let (chosen, fell_back) = match negotiation.resolve(format, compression) {
+                    Ok(chosen) => (chosen, false),
+                    Err(fallback) => (fallback, true),
+                };
+                (session.wire_format, session.compression) = chosen;
+                if fell_back {
+                    godot_warn!(
+                        "The server picked format {format} with compression {compression}, which we didn't offer"
+                    );
+                }
+                self.emit_wire_format_negotiated(fell_back);
+            }
+            // Handled like any application payload, unless it's a copy.
+            ServerMessage::Idempotent { key, payload } => {
+                if !self.received_keys.insert(key) {
+                    if let Some(session) = &mut self.game_session {
+                        session.channel_stats[channel_id as usize].duplicates_suppressed += 1;
+                    }
+                    return;
+                }
+                self.handle_server_message(channel_id, ServerMessage::Application(payload));
+            }
+            ServerMessage::Voice {
+                speaker,
+                entity_id,
+                position,
+                frames,
+            } => {
+                if self.mute_list().is_muted(speaker) {
+                    return;
+                }
+                let signal = self.signal_names.voice_frame_received.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (speaker as i64).to_variant(),
+                        Vector3::new(position[0], position[1], position[2]).to_variant(),
+                        PackedByteArray::from(frames.as_ref()).to_variant(),
+                        (entity_id as i64).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::Chat { sender, text } => {
+                if self.mute_list().is_muted(sender) {
+                    return;
+                }
+                let Some(text) = self.filter_chat(text, sender, false) else {
+                    return;
+                };
+                let signal = self.signal_names.chat_received.clone();
+                self.emit(
+                    signal,
+                    &[
+                        (sender as i64).to_variant(),
+                        GString::from(text).to_variant(),
+                    ],
+                );
+            }
+            ServerMessage::QuickChat { sender, id } => {
+                if self.mute_list().is_muted(sender) {
+                    return;
+                }
+                let signal = self.signal_names.quick_chat_received.clone();
+                self.emit(
+                    signal,
+                    &[(sender as i64).to_variant(), (id as i64).to_variant()],
+                );
+            }
+            ServerMessage::Presence { client_id, state } => {
+                let changed = match &mut self.game_session {
+                    Some(session) => session.presence.peer_state(client_id, state),
+                    None => false,
+                };
+                if changed {
+                    let signal = self.signal_names.peer_presence_changed.clone();
+                    self.emit(
+                        signal,
+                        &[(client_id as i64).to_variant(), (state as i64).to_variant()],
+                    );
+                }
+            }
+            ServerMessage::RichPresence { client_id, state } => {
+                let Some(decoded) = Self::decode_rich_presence(&state) else {
+                    godot_warn!("Rich presence from {client_id} isn't a CBOR map, ignoring it");
+                    return;
+                };
+                let changed = match &mut self.game_session {
+                    Some(session) => session.rich_presence.peer_state(client_id, &state),
+                    None => false,
+                };
+                if changed {
+                    let signal = self.signal_names.peer_rich_presence_changed.clone();
+                    self.emit(
+                        signal,
+                        &[(client_id as i64).to_variant(), decoded.to_variant()],
+                    );
+                }
+            }
+            ServerMessage::Checksum { tick, checksum } => {
+                let Some(desync) = &mut self.desync else {
+                    return;
+                };
+                if let Some(desync) = desync.add_server_checksum(tick, checksum as i64) {
+                    self.emit_desync_detected(desync);
+                }
+            }
+            ServerMessage::OutboxAck { id } => {
+                if !self.outbox().acknowledge(id) {
+                    return;
+                }
+                let signal = self.signal_names.durable_message_acknowledged.clone();
+                self.emit(signal, &[(id as i64).to_variant()]);
+            }
+            ServerMessage::SessionTakenOver => {
+                if let Some(session) = &mut self.game_session {
+                    session.taken_over = true;
+                }
+                let signal = self.signal_names.session_taken_over.clone();
+                self.emit(signal, &[]);
+            }
+            ServerMessage::ServerInfo { tick_rate, tick } => {
+                let Some(session) = &mut self.game_session else {
+                    return;
+                };
+
+                session.update_server_tick(tick);
+                let tick_rate = tick_rate.max(1) as f64;
+                if session.server_tick_rate == Some(tick_rate) {
+                    return;
+                }
+
+                session.server_tick_rate = Some(tick_rate);
+                let signal = self.signal_names.server_tick_rate_changed.clone();
+                self.emit(signal, &[tick_rate.to_variant()]);
+            }
+            ServerMessage::ServerConfig(body) => {
+                let Some(config) =
+                    cbor::decode(&body).and_then(|config| config.try_to::<Dictionary>().ok())
+                else {
+                    let rejection = Rejection {
+                        kind: "server_config",
+                        reason: String::from("isn't a CBOR map"),
+                    };
+                    self.reject_server_message(channel_id, rejection);
+                    return;
+                };
+                self.apply_server_config(config);
+            }
+            ServerMessage::MatchSeed { seed } => {
+                let Some(session) = &mut self.game_session else {
+                    return;
+                };
+                // Sent reliably, but a copy after a reclaim shouldn't make the game generate everything again.
+                if session.match_seed == Some(seed) {
+                    return;
+                }
+
+                session.match_seed = Some(seed);
+                let signal = self.signal_names.match_seed_received.clone();
+                self.emit(signal, &[(seed as i64).to_variant()]);
+            }
+        }
+    }
+
+    fn create_fuzzer(&self, current_time: Duration) -> Option<PayloadFuzzer> {
+        if self.fuzz_inbound_fraction <= 0.0 || !Os::singleton().is_debug_build() {
+            return None;
+        }
+
+        let seed = match self.fuzz_seed {
+            0 => current_time.as_nanos() as u64,
+            seed => seed as u64,
+        };
+        godot_warn!(
+            "Fuzzing {}% of inbound messages with seed {seed}",
+            self.fuzz_inbound_fraction * 100.0
+        );
+        return Some(PayloadFuzzer::new(seed));
+    }
+
+    fn create_corpus_recorder(&self) -> Option<CorpusRecorder> {
+        if self.corpus_directory.is_empty() || !Os::singleton().is_debug_build() {
+            return None;
+        }
+
+        let directory = ProjectSettings::singleton()
+            .globalize_path(self.corpus_directory.clone())
+            .to_string();
+        return match CorpusRecorder::new(directory.into()) {
+            Ok(recorder) => Some(recorder),
+            Err(error) => {
+                godot_warn!(
+                    "Can't record the message corpus to '{}': {error}",
+                    self.corpus_directory
+                );
+                None
+            }
+        };
+    }
+
+    fn create_session_recorder(&self, current_time: Duration) -> Option<SessionRecorder> {
+        if self.recording_directory.is_empty() || !Os::singleton().is_debug_build() {
+            return None;
+        }
+
+        let directory = ProjectSettings::singleton()
+            .globalize_path(self.recording_directory.clone())
+            .to_string();
+        return match SessionRecorder::new(directory.into(), current_time.as_secs()) {
+            Ok(recorder) => Some(recorder),
+            Err(error) => {
+                godot_warn!(
+                    "Can't record the session to '{}': {error}",
+                    self.recording_directory
+                );
+                None
+            }
+        };
+    }
+
+    fn reject_server_message(&mut self, channel_id: u8, rejection: Rejection) {
+        godot_warn!(
+            "Rejected {} message from the server on channel {channel_id}: {}",
+            rejection.kind,
+            rejection.reason
+        );
+        if let Some(session) = &mut self.game_session {
+            session.channel_stats[channel_id as usize].messages_rejected += 1;
+            session.trace.record_rejection(
+                session.session_time,
+                channel_id,
+                rejection.kind,
+                &rejection.reason,
+            );
+        }
+
+        let signal = self.signal_names.message_rejected.clone();
+        self.emit(
+            signal,
+            &[
+                (channel_id as i64).to_variant(),
+                GString::from(rejection.kind).to_variant(),
+                GString::from(rejection.reason).to_variant(),
+            ],
+        );
+    }
+
+    fn message_limits(&self) -> MessageLimits {
+        return MessageLimits {
+            max_application_size: self.max_application_payload_size.max(0) as usize,
+            max_snapshot_size: self.max_snapshot_payload_size.max(0) as usize,
+        };
+    }
+
+    // The server tick with the fraction of a tick since, see `server_tick`.
+    fn estimated_server_tick(&self) -> Option<f64> {
+        let session = self.game_session.as_ref()?;
+        let (tick, received_at) = session.server_tick_reference?;
+        let elapsed_ticks = (session.session_time - received_at) / self.server_tick_interval();
+        return Some(tick as f64 + elapsed_ticks);
+    }
+
+    // Never before tick 0, early in a session the delay can reach back further than the server has run.
+    fn render_tick(&self) -> Option<f64> {
+        let lag = self
+            .game_session
+            .as_ref()
+            .map_or(0.0, |session| session.render_lag);
+        let delay_ticks =
+            self.ms_to_ticks(self.get_effective_interpolation_delay_ms() + lag * 1000.0);
+        return Some((self.estimated_server_tick()? - delay_ticks).max(0.0));
+    }
+
+    // Seconds per server tick. Until the server tells us its tick rate, we assume it matches ours.
+    fn server_tick_interval(&self) -> f64 {
+        return 1.0 / self.server_tick_rate();
+    }
+
+    fn server_tick_rate(&self) -> f64 {
+        if let Some(session) = &self.game_session {
+            if let Some(tick_rate) = session.server_tick_rate {
+                return tick_rate;
+            }
+        }
+
+        return self.local_tick_rate();
+    }
+
+    // How many network ticks we run per second, see `tick_mode`. Frames have no fixed rate, so that mode goes by
+    // the current FPS, which is 0 until the first second has passed.
+    fn local_tick_rate(&self) -> f64 {
+        let engine = Engine::singleton();
+        let rate = match self.tick_mode {
+            Self::TICK_PROCESS => engine.get_frames_per_second(),
+            Self::TICK_TIMER => self.network_tick_rate,
+            _ => 0.0,
+        };
+        if rate >= 1.0 {
+            return rate;
+        }
+        return engine.get_physics_ticks_per_second().max(1) as f64;
+    }
+
+    // None unless joins go to the simulated server, which is started here the first time.
+    fn simulated_server_address(&mut self) -> Option<io::Result<SocketAddr>> {
+        if !self.simulate_server || !Os::singleton().is_debug_build() {
+            return None;
+        }
+        if self.local_server.is_none() {
+            let tick_rate = self.simulated_tick_rate.clamp(1, u16::MAX as i64) as u16;
+            match LocalServer::start(self.connection_config(), tick_rate) {
+                Ok(local_server) => self.local_server = Some(local_server),
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        return self
+            .local_server
+            .as_ref()
+            .map(|server| Ok(server.address()));
+    }
+
+    fn connection_config(&self) -> ConnectionConfig {
+        let mut memory_budgets = [0; CHANNEL_COUNT];
+        for (channel_id, budget) in memory_budgets.iter_mut().enumerate() {
+            *budget = self.channel_memory_budget(channel_id as u8).max(0) as usize;
+        }
+        let channels = channels::channels_config(memory_budgets);
+
+        return ConnectionConfig {
+            available_bytes_per_tick: self.available_bytes_per_tick as u64,
+            server_channels_config: channels.clone(),
+            client_channels_config: channels,
+        };
+    }
+
+    fn inbound_limit(&self, channel: usize) -> InboundLimit {
+        if channels::is_reliable(channel as u8) {
+            return InboundLimit::default();
+        }
+        return self.inbound_limit_overrides[channel].unwrap_or(InboundLimit {
+            messages_per_second: self.inbound_messages_per_second.max(0) as f64,
+            bytes_per_second: self.inbound_bytes_per_second.max(0) as f64,
+        });
+    }
+
+    fn channel_memory_budget(&self, channel_id: u8) -> i64 {
+        return match channel_id {
+            channels::RELIABLE_ORDERED => self.reliable_ordered_memory_budget,
+            channels::RELIABLE_UNORDERED => self.reliable_unordered_memory_budget,
+            channels::UNRELIABLE => self.unreliable_memory_budget,
+            _ => self.unreliable_sequenced_memory_budget,
+        };
+    }
+
+    // Bytes queued on a channel we send on, which is its budget minus what renet says is still available.
+    fn channel_backlog(&self, channel_id: u8) -> i64 {
+        if let Some(session) = &self.game_session {
+            if (channel_id as usize) < CHANNEL_COUNT {
+                let available = session.client.channel_available_memory(channel_id) as i64;
+                return (self.channel_memory_budget(channel_id) - available).max(0);
+            }
+        }
+
+        return 0;
+    }
+
+    fn emit_lost_connection(&mut self) {
+        let mut join_failed = false;
+        let mut rotation_failed = false;
+        let mut unanswered_requests = Vec::new();
+        if let Some(session) = &mut self.game_session {
+            join_failed = std::mem::take(&mut session.join_pending);
+            rotation_failed = std::mem::take(&mut session.rotating_credentials);
+            unanswered_requests = session.requests.take_all();
+        }
+        for request_id in unanswered_requests {
+            self.fail_request(request_id, "disconnected");
+        }
+        if join_failed && self.schedule_handshake_retry() {
+            return;
+        }
+        if join_failed && self.fail_over(self.get_disconnect_code(), self.transport_error_message())
+        {
+            return;
+        }
+        if rotation_failed {
+            let message = self.transport_error_message().to_variant();
+            let signal = self.signal_names.credentials_rotation_failed.clone();
+            self.emit(signal, &[message]);
+        }
+        if join_failed {
+            let message = self.transport_error_message().to_variant();
+            let signal = self.signal_names.join_completed.clone();
+            self.emit(signal, &[false.to_variant(), message]);
+        }
+
+        self.capture_session_report(false);
+        self.record_session_history();
+
+        // The server won't take the cached token anymore.
+        let code = self.get_disconnect_code();
+        if code == Self::DISCONNECT_DENIED || code == Self::DISCONNECT_TOKEN_EXPIRED {
+            credentials::clear_cached_token(self.player_file(credentials::TOKEN_CACHE_PATH));
+        }
+
+        let detail_signal = match code {
+            Self::DISCONNECT_DENIED => Some(self.signal_names.connection_denied.clone()),
+            Self::DISCONNECT_TOKEN_EXPIRED => Some(self.signal_names.connect_token_expired.clone()),
+            _ => None,
+        };
+        if let Some(signal) = detail_signal {
+            self.emit(signal, &[]);
+        }
+
+        let message = self.transport_error_message().to_variant();
+        let signal = self.signal_names.lost_connection.clone();
+        self.emit(signal, &[message]);
+
+        if self.auto_report_on_disconnect && code != Self::DISCONNECT_BY_CLIENT {
+            self.write_report("disconnect".into(), Dictionary::new());
+        }
+    }
+
+    fn fail_request(&mut self, request_id: u32, error: &str) {
+        let signal = self.signal_names.request_failed.clone();
+        self.emit(
+            signal,
+            &[
+                (request_id as i64).to_variant(),
+                GString::from(error).to_variant(),
+            ],
+        );
+    }
+
+    // For join_session failing before there is a session.
+    // Returns true if the failed handshake will be tried again, in which case the join hasn't failed yet.
+    fn schedule_handshake_retry(&mut self) -> bool {
+        if self.get_disconnect_code() != Self::DISCONNECT_TIMED_OUT {
+            return false;
+        }
+        let Some(session) = &self.game_session else {
+            return false;
+        };
+        if session.handshake_attempt as i64 >= self.handshake_attempts {
+            return false;
+        }
+        let delay = self.handshake_retry_delay.max(0.0);
+        if let Some(expire_at) = session.credentials_expire_at {
+            if expire_at as f64 <= clock::unix_seconds() + delay {
+                return false;
+            }
+        }
+
+        let attempt = session.handshake_attempt + 1;
+        godot_warn!(
+            "join_session: the handshake with {} timed out, trying again ({attempt} of {})",
+            session.server_addr,
+            self.handshake_attempts
+        );
+        self.handshake_retry = Some(HandshakeRetry {
+            delay,
+            attempt,
+            client_id: session.client_id,
+            server_addr: session.server_addr,
+            connect_token: session.connect_token.clone(),
+            credentials_expire_at: session.credentials_expire_at,
+        });
+        let signal = self.signal_names.join_retrying.clone();
+        self.emit(signal, &[(attempt as i64).to_variant()]);
+        return true;
+    }
+
+    // With PAUSE_FULL nothing ran during the pause, not even netcode's clock. The server gave up on us if it
+    // lasted longer than the timeout, so we join again instead of waiting out a second timeout.
+    fn resume_after_pause(&mut self) {
+        let Some(paused_at) = self.paused_at.take() else {
+            return;
+        };
+        if self.pause_policy != Self::PAUSE_FULL {
+            return;
+        }
+        let Some(session) = &self.game_session else {
+            return;
+        };
+        let paused_for = paused_at.elapsed().as_secs_f64();
+        if !session.client.is_connected() || paused_for < session.timeout_seconds {
+            return;
+        }
+
+        godot_warn!("Paused for {paused_for:.0} seconds, longer than the timeout, joining again");
+        self.handshake_retry = Some(HandshakeRetry {
+            delay: 0.0,
+            attempt: 1,
+            client_id: session.client_id,
+            server_addr: session.server_addr,
+            connect_token: session.connect_token.clone(),
+            credentials_expire_at: session.credentials_expire_at,
+        });
+    }
+
+    fn update_handshake_retry(&mut self, delta: f64) {
+        let Some(retry) = &mut self.handshake_retry else {
+            return;
+        };
+        retry.delay -= delta;
+        if retry.delay > 0.0 {
+            return;
+        }
+
+        let retry = self.handshake_retry.take().unwrap();
+        let authentication = match &retry.connect_token {
+            Some(connect_token) => match credentials::read_connect_token(connect_token) {
+                Ok(token) => ClientAuthentication::Secure {
+                    connect_token: token,
+                },
+                Err(error) => {
+                    self.fail_join(error);
+                    return;
+                }
+            },
+            None => ClientAuthentication::Unsecure {
+                server_addr: retry.server_addr,
+                client_id: retry.client_id,
+                user_data: None,
+                protocol_id: 0,
+            },
+        };
+        self.start_session(
+            retry.client_id,
+            retry.server_addr,
+            authentication,
+            retry.credentials_expire_at,
+        );
+        if let Some(session) = &mut self.game_session {
+            session.handshake_attempt = retry.attempt;
+            session.connect_token = retry.connect_token;
+        }
+    }
+
+    fn join_failover_route(&mut self) {
+        let Some(failover) = &self.failover else {
+            return;
+        };
+        let Some((_, server_addr)) = failover.current() else {
+            return;
+        };
+
+        let client_id = failover.client_id;
+        let authentication = ClientAuthentication::Unsecure {
+            server_addr,
+            client_id,
+            user_data: None,
+            protocol_id: 0,
+        };
+        self.start_session(client_id, server_addr, authentication, None);
+    }
+
+    // Moves a failed `join_session_failover` on to its next route. Returns false if there is none, or the
+    // failure is one no other route would fix, and the join fails as usual.
+    fn fail_over(&mut self, code: i64, error: GString) -> bool {
+        let Some(failover) = &mut self.failover else {
+            return false;
+        };
+        let route_problem = matches!(
+            code,
+            Self::DISCONNECT_TIMED_OUT
+                | Self::DISCONNECT_SOCKET_ERROR
+                | Self::DISCONNECT_NETWORK_UNREACHABLE
+                | Self::DISCONNECT_ADDRESS_CHANGED
+        );
+        let Some((failed_name, _)) = failover.current() else {
+            return false;
+        };
+        failover.advance();
+        if !route_problem || failover.current().is_none() {
+            self.failover = None;
+            return false;
+        }
+
+        godot_warn!("join_session_failover: route {failed_name} failed: {error}");
+        let signal = self.signal_names.failover_route_failed.clone();
+        self.emit(signal, &[failed_name.to_variant(), error.to_variant()]);
+        self.join_failover_route();
+        return true;
+    }
+
+    fn fail_join(&mut self, error: NetcodeTransportError) {
+        if self.fail_over(
+            Self::disconnect_code(&error),
+            GString::from(error.to_string()),
+        ) {
+            return;
+        }
+
+        godot_error!("join_session: {error}");
+        let message = GString::from(error.to_string());
+        self.join_error = Some(error);
+        self.capture_session_report(false);
+        self.game_session = None;
+
+        // Deferred, so `await join_session_async(...)` is already waiting when it is emitted.
+        let signal = self.signal_names.join_completed.clone();
+        self.emit_deferred(signal, &[false.to_variant(), message.to_variant()]);
+    }
+
+    #[inline]
+    fn transport_has_error(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.transport_error.is_err();
+        }
+
+        return false;
+    }
+
+    // Files in user:// that belong to one player get `player_index` in their name, so split-screen players don't
+    // share an outbox, cached token or mute list. Player 0 keeps the plain name, where they always were.
+    fn player_file(&self, path: &str) -> GString {
+        if self.player_index == 0 {
+            return path.into();
+        }
+        let (stem, extension) = path.rsplit_once('.').unwrap_or((path, ""));
+        return format!("{stem}_{}.{extension}", self.player_index).into();
+    }
+
+    /// Only returns a message if there is an error inside the gameplay session.
+    /// If there is no gameplay session or error, then it returns an empty string.
+    #[inline]
+    fn transport_error_message(&self) -> GString {
+        if let Some(session) = &self.game_session {
+            if let Err(error) = &session.transport_error {
+                return GString::from_str(&error.to_string()).unwrap();
+            }
         }
 
         return GString::new();
     }
 }
+
+impl Drop for GameplaySessionManager {
+    fn drop(&mut self) {
+        let Some(session) = &mut self.game_session else {
+            return;
+        };
+        if !session.client.is_connected() {
+            return;
+        }
+
+        // Frees our client id on the server right away, instead of it waiting for the timeout.
+        session.transport.disconnect();
+
+        // A node that is freed, like when its scene is closed, is done with the session.
+        if !self.predeleted && Engine::singleton().is_editor_hint() {
+            let mut subscriptions = PackedStringArray::new();
+            for topic in &self.subscriptions {
+                subscriptions.push(topic.as_str().into());
+            }
+            let handoff = reload::Handoff {
+                address: GString::from(session.server_addr.to_string()),
+                client_id: session.client_id as i64,
+                connect_token: session
+                    .connect_token
+                    .as_deref()
+                    .map(PackedByteArray::from)
+                    .unwrap_or_default(),
+                subscriptions,
+            };
+            reload::save_handoff(self.base().instance_id(), &handoff);
+        }
+    }
+}
+
+// Crate internal API used by the other networking nodes.
+impl GameplaySessionManager {
+    pub(crate) fn session_client_id(&self) -> Option<u64> {
+        return self.game_session.as_ref().map(|session| session.client_id);
+    }
+
+    pub(crate) fn is_session_connected(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.client.is_connected();
+        }
+
+        return false;
+    }
+
+    pub(crate) fn is_session_connecting(&self) -> bool {
+        if let Some(session) = &self.game_session {
+            return session.client.is_connecting() && session.transport_error.is_ok();
+        }
+
+        return false;
+    }
+
+    /// Application payloads on the channel go to the handler instead of `message_received`, see flatbuffer.rs.
+    #[cfg(feature = "flatbuffers")]
+    pub(crate) fn register_flatbuffer_handler(
+        &mut self,
+        channel_id: u8,
+        handler: flatbuffer::FlatBufferHandler,
+    ) {
+        self.flatbuffer_handlers.register(channel_id, handler);
+    }
+
+    pub(crate) fn attach_rpc_bridge(&mut self) {
+        self.rpc_bridge_attached = true;
+    }
+
+    pub(crate) fn detach_rpc_bridge(&mut self) {
+        self.rpc_bridge_attached = false;
+        self.rpc_inbox.clear();
+    }
+
+    pub(crate) fn take_rpc_packets(&mut self) -> VecDeque<RpcPacket> {
+        return std::mem::take(&mut self.rpc_inbox);
+    }
+
+    /// Starts watching a replicated store. Returns false if the name isn't 1 to 255 bytes or the store is
+    /// already watched.
+    pub(crate) fn attach_replica(&mut self, store: &str) -> bool {
+        if store.is_empty() || store.len() > protocol::MAX_TOPIC_LENGTH {
+            return false;
+        }
+        if self.replicas.contains_key(store) {
+            return false;
+        }
+        self.replicas.insert(store.to_owned(), VecDeque::new());
+
+        // Otherwise it is sent when we connect.
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_ORDERED,
+                    &ClientMessage::ReplicaSubscribe(store),
+                );
+            }
+        }
+        return true;
+    }
+
+    pub(crate) fn detach_replica(&mut self, store: &str) {
+        if self.replicas.remove(store).is_none() {
+            return;
+        }
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_ORDERED,
+                    &ClientMessage::ReplicaUnsubscribe(store),
+                );
+            }
+        }
+    }
+
+    /// Returns false if the key is longer than 255 bytes or there is no connected session.
+    pub(crate) fn send_replica_write(
+        &mut self,
+        store: &str,
+        write_id: u32,
+        key: &str,
+        value: &[u8],
+    ) -> bool {
+        if key.len() > protocol::MAX_TOPIC_LENGTH {
+            return false;
+        }
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                session.send_client_message(
+                    channels::RELIABLE_ORDERED,
+                    &ClientMessage::ReplicaWrite {
+                        store,
+                        write_id,
+                        key,
+                        value,
+                    },
+                );
+                return true;
+            }
+        }
+        return false;
+    }
+
+    pub(crate) fn take_replica_updates(&mut self, store: &str) -> VecDeque<(u8, Bytes)> {
+        return match self.replicas.get_mut(store) {
+            Some(updates) => std::mem::take(updates),
+            None => VecDeque::new(),
+        };
+    }
+
+    /// Queues the packet on the given channel. Returns false if there is no connected session.
+    pub(crate) fn send_rpc_packet(&mut self, channel_id: u8, packet: &RpcPacket) -> bool {
+        if let Some(session) = &mut self.game_session {
+            if session.client.is_connected() {
+                packet.encode(&mut session.send_buffer);
+                let message = session.take_send_buffer();
+                session.send(channel_id, message);
+                return true;
+            }
+        }
+
+        return false;
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use godot::prelude::*;
+use renet::{
+    transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
+    ClientId, ConnectionConfig, RenetServer, ServerEvent,
+};
+
+use crate::{
+    cbor,
+    channels::{self, Sequencer, CHANNEL_COUNT},
+    clock, clock_sync, negotiation,
+    protocol::{self, ClientMessage, RpcPacket, ServerMessage},
+    replica, rpc,
+    save_sync::{self, VersionOrder},
+};
+
+// Start - Simulated server for solo testing
+// With `simulate_server` on, GameplaySessionManager runs this next to the client and every join goes to it, so
+// multiplayer scenes can be played solo in the editor with the real transport and message flow. It's a renet
+// server on 127.0.0.1 with unsecure connections, and it answers the way a simple relay server would:
+//   application, idempotent and outbox payloads  echoed to every client, the sender too
+//   RPC packets                                  relayed to their target peer, 0 for every client
+//   chat, quick chat, voice and presence         relayed to every other client
+//   requests                                     answered with their own payload
+//   pings, capabilities, full snapshot requests  answered, with raw payloads and an empty snapshot
+//   replicated stores                            kept in memory, every write with valid CBOR is accepted
+//   saves                                        kept in memory, with the same version check as a real server
+// Clients get the server info and a match seed when they connect. Topics, spawns and authority need game
+// logic on the server, so they aren't simulated. Nothing outlives the manager.
+
+// A renet server on 127.0.0.1 with unsecure connections, and the address it got. MockServer uses it too.
+pub fn bind_server(
+    config: ConnectionConfig,
+) -> io::Result<(RenetServer, NetcodeServerTransport, SocketAddr)> {
+    let socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+    let address = socket.local_addr()?;
+    let current_time = clock::unix_time();
+    let transport = NetcodeServerTransport::new(
+        ServerConfig {
+            current_time,
+            max_clients: 64,
+            protocol_id: 0,
+            public_addresses: vec![address],
+            authentication: ServerAuthentication::Unsecure,
+        },
+        socket,
+    )?;
+    return Ok((RenetServer::new(config), transport, address));
+}
+
+// Seconds between server info messages after the first, so the client's estimate of the tick stays fresh.
+const SERVER_INFO_INTERVAL: f64 = 1.0;
+
+#[derive(Default)]
+struct LocalClient {
+    // For the unreliable sequenced channel, both ways.
+    sequencer: Sequencer,
+    // The replicated stores the client watches.
+    stores: HashSet<String>,
+}
+
+pub struct LocalServer {
+    server: RenetServer,
+    transport: NetcodeServerTransport,
+    address: SocketAddr,
+    clients: HashMap<u64, LocalClient>,
+
+    tick_rate: u16,
+    tick: u32,
+    since_tick: f64,
+    since_server_info: f64,
+
+    // Replicated stores by name, with their keys and values.
+    stores: HashMap<String, Dictionary>,
+    // Saves by slot, with their CBOR metadata and blob.
+    saves: HashMap<String, (Bytes, Bytes)>,
+    send_buffer: BytesMut,
+}
+
+impl LocalServer {
+    pub fn start(config: ConnectionConfig, tick_rate: u16) -> io::Result<Self> {
+        let (server, transport, address) = bind_server(config)?;
+        return Ok(Self {
+            server,
+            transport,
+            address,
+            clients: HashMap::new(),
+            tick_rate: tick_rate.max(1),
+            tick: 0,
+            since_tick: 0.0,
+            since_server_info: 0.0,
+            stores: HashMap::new(),
+            saves: HashMap::new(),
+            send_buffer: BytesMut::new(),
+        });
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        return self.address;
+    }
+
+    pub fn update(&mut self, delta: f64) {
+        let duration = Duration::from_secs_f64(delta);
+        self.server.update(duration);
+        if let Err(error) = self.transport.update(duration, &mut self.server) {
+            godot_warn!("Simulated server: {error}");
+        }
+
+        self.since_tick += delta;
+        let tick_length = 1.0 / self.tick_rate as f64;
+        while self.since_tick >= tick_length {
+            self.since_tick -= tick_length;
+            self.tick = self.tick.wrapping_add(1);
+        }
+
+        while let Some(event) = self.server.get_event() {
+            match event {
+                ServerEvent::ClientConnected { client_id } => {
+                    self.clients.insert(client_id.raw(), LocalClient::default());
+                    let seed = RandomState::new().build_hasher().finish();
+                    self.send_server_info(Some(client_id.raw()));
+                    self.send(
+                        client_id.raw(),
+                        channels::RELIABLE_ORDERED,
+                        &ServerMessage::MatchSeed { seed },
+                    );
+                }
+                ServerEvent::ClientDisconnected { client_id, .. } => {
+                    self.clients.remove(&client_id.raw());
+                }
+            }
+        }
+
+        self.since_server_info += delta;
+        if self.since_server_info >= SERVER_INFO_INTERVAL {
+            self.since_server_info = 0.0;
+            self.send_server_info(None);
+        }
+
+        for client_id in self.server.clients_id() {
+            for channel_id in 0..CHANNEL_COUNT as u8 {
+                while let Some(message) = self.server.receive_message(client_id, channel_id) {
+                    let message = match channel_id {
+                        channels::UNRELIABLE_SEQUENCED => {
+                            let Some(client) = self.clients.get_mut(&client_id.raw()) else {
+                                continue;
+                            };
+                            match client.sequencer.unwrap(&message) {
+                                Some(body) => message.slice_ref(body),
+                                None => continue,
+                            }
+                        }
+                        _ => message,
+                    };
+                    let Some(parts) = protocol::split_batch(&message) else {
+                        continue;
+                    };
+                    for part in parts {
+                        self.handle(client_id.raw(), channel_id, message.slice_ref(part));
+                    }
+                }
+            }
+        }
+
+        self.transport.send_packets(&mut self.server);
+    }
+
+    fn handle(&mut self, sender: u64, channel_id: u8, message: Bytes) {
+        if message.first() == Some(&protocol::MESSAGE_RPC) {
+            if let Some(packet) = RpcPacket::decode(&message) {
+                self.relay_rpc(sender, channel_id, packet);
+            }
+            return;
+        }
+        let Some(decoded) = ClientMessage::decode(&message) else {
+            godot_warn!("Simulated server: client {sender} sent a message it doesn't understand");
+            return;
+        };
+
+        match decoded {
+            ClientMessage::Application(payload) => {
+                let payload = message.slice_ref(payload);
+                self.broadcast(None, channel_id, &ServerMessage::Application(payload));
+            }
+            ClientMessage::Idempotent { key, payload } => {
+                let payload = message.slice_ref(payload);
+                self.broadcast(
+                    None,
+                    channel_id,
+                    &ServerMessage::Idempotent { key, payload },
+                );
+            }
+            ClientMessage::Outbox { id, payload } => {
+                let payload = message.slice_ref(payload);
+                self.send(sender, channel_id, &ServerMessage::OutboxAck { id });
+                self.broadcast(None, channel_id, &ServerMessage::Application(payload));
+            }
+            ClientMessage::Request {
+                request_id,
+                payload,
+                ..
+            } => {
+                let payload = message.slice_ref(payload);
+                let response = ServerMessage::Response {
+                    request_id,
+                    payload,
+                };
+                self.send(sender, channel_id, &response);
+            }
+            ClientMessage::RequestFullSnapshot { max_size } => {
+                // There's no game state here, but the size check is what a real server has to do.
+                let payload = Bytes::new();
+                let snapshot = match payload.len() > max_size as usize {
+                    true => ServerMessage::FullSnapshotTooLarge {
+                        size: payload.len() as u32,
+                    },
+                    false => ServerMessage::FullSnapshot {
+                        tick: self.tick,
+                        payload,
+                    },
+                };
+                self.send(sender, channels::RELIABLE_ORDERED, &snapshot);
+            }
+            ClientMessage::Capabilities { .. } => {
+                let selected = ServerMessage::FormatSelected {
+                    format: negotiation::FORMAT_RAW,
+                    compression: negotiation::COMPRESSION_NONE,
+                };
+                self.send(sender, channels::RELIABLE_ORDERED, &selected);
+            }
+            ClientMessage::Ping { ping_id, sent } => {
+                let now = clock_sync::now_micros();
+                let pong = ServerMessage::Pong {
+                    ping_id,
+                    sent,
+                    arrived: now,
+                    answered: now,
+                };
+                self.send(sender, channel_id, &pong);
+            }
+            ClientMessage::Chat(text) => {
+                let chat = ServerMessage::Chat {
+                    sender,
+                    text: text.to_owned(),
+                };
+                self.broadcast(Some(sender), channel_id, &chat);
+            }
+            ClientMessage::QuickChat(id) => {
+                self.broadcast(
+                    Some(sender),
+                    channel_id,
+                    &ServerMessage::QuickChat { sender, id },
+                );
+            }
+            ClientMessage::Voice {
+                entity_id,
+                position,
+                frames,
+            } => {
+                let voice = ServerMessage::Voice {
+                    speaker: sender,
+                    entity_id,
+                    position,
+                    frames: message.slice_ref(frames),
+                };
+                self.broadcast(Some(sender), channel_id, &voice);
+            }
+            ClientMessage::Presence(state) => {
+                let presence = ServerMessage::Presence {
+                    client_id: sender,
+                    state,
+                };
+                self.broadcast(Some(sender), channel_id, &presence);
+            }
+            ClientMessage::RichPresence(state) => {
+                let presence = ServerMessage::RichPresence {
+                    client_id: sender,
+                    state: message.slice_ref(state),
+                };
+                self.broadcast(Some(sender), channel_id, &presence);
+            }
+            ClientMessage::ReplicaSubscribe(store) => {
+                if let Some(client) = self.clients.get_mut(&sender) {
+                    client.stores.insert(store.to_owned());
+                }
+                let data = self.stores.get(store).cloned().unwrap_or_default();
+                let mut body = Vec::new();
+                if cbor::encode(&data.to_variant(), &mut body).is_ok() {
+                    let update = ServerMessage::ReplicaUpdate {
+                        store: store.to_owned(),
+                        op: replica::OP_DICTIONARY_FULL,
+                        body: Bytes::from(body),
+                    };
+                    self.send(sender, channels::RELIABLE_ORDERED, &update);
+                }
+            }
+            ClientMessage::ReplicaUnsubscribe(store) => {
+                if let Some(client) = self.clients.get_mut(&sender) {
+                    client.stores.remove(store);
+                }
+            }
+            ClientMessage::ReplicaWrite {
+                store,
+                write_id,
+                key,
+                value,
+            } => self.write_replica(sender, store, write_id, key, value),
+            ClientMessage::SaveDownload(slot) => {
+                let (meta, blob) = self.saves.get(slot).cloned().unwrap_or_default();
+                let data = ServerMessage::SaveData {
+                    slot: slot.to_owned(),
+                    meta,
+                    blob,
+                };
+                self.send(sender, channels::RELIABLE_UNORDERED, &data);
+            }
+            ClientMessage::SaveUpload { slot, meta, blob } => {
+                let meta = message.slice_ref(meta);
+                let blob = message.slice_ref(blob);
+                self.upload_save(sender, slot, meta, blob);
+            }
+            // Only matter to a server with game logic.
+            ClientMessage::ReclaimSession
+            | ClientMessage::Subscribe(_)
+            | ClientMessage::Unsubscribe(_)
+            | ClientMessage::Mute { .. }
+            | ClientMessage::Afk(_)
+            | ClientMessage::Command { .. }
+            | ClientMessage::Performance(_) => {}
+        }
+    }
+
+    fn relay_rpc(&mut self, sender: u64, channel_id: u8, mut packet: RpcPacket) {
+        let target = packet.peer_id;
+        packet.peer_id = rpc::peer_id_for_client(sender);
+        let mut buffer = BytesMut::new();
+        packet.encode(&mut buffer);
+        let message = buffer.freeze();
+
+        let clients: Vec<u64> = self.clients.keys().copied().collect();
+        for client_id in clients {
+            let peer_id = rpc::peer_id_for_client(client_id);
+            let wanted = match target {
+                0 => client_id != sender,
+                // Everyone but the negated peer.
+                target if target < 0 => peer_id != -target && client_id != sender,
+                target => peer_id == target,
+            };
+            if wanted {
+                self.send_bytes(client_id, channel_id, message.clone());
+            }
+        }
+    }
+
+    fn write_replica(&mut self, sender: u64, store: &str, write_id: u32, key: &str, value: &[u8]) {
+        let accepted = match cbor::decode(value) {
+            Some(decoded) => {
+                self.stores
+                    .entry(store.to_owned())
+                    .or_default()
+                    .set(key, decoded);
+                true
+            }
+            None => false,
+        };
+
+        // The set goes out before the result, see replica.rs.
+        if accepted {
+            let mut body = vec![key.len() as u8];
+            body.extend_from_slice(key.as_bytes());
+            body.extend_from_slice(value);
+            let update = ServerMessage::ReplicaUpdate {
+                store: store.to_owned(),
+                op: replica::OP_DICTIONARY_SET,
+                body: Bytes::from(body),
+            };
+            let watchers: Vec<u64> = self
+                .clients
+                .iter()
+                .filter(|(_, client)| client.stores.contains(store))
+                .map(|(client_id, _)| *client_id)
+                .collect();
+            for client_id in watchers {
+                self.send(client_id, channels::RELIABLE_ORDERED, &update);
+            }
+        }
+
+        let mut body = write_id.to_le_bytes().to_vec();
+        body.push(accepted as u8);
+        let result = ServerMessage::ReplicaUpdate {
+            store: store.to_owned(),
+            op: replica::OP_WRITE_RESULT,
+            body: Bytes::from(body),
+        };
+        self.send(sender, channels::RELIABLE_ORDERED, &result);
+    }
+
+    fn upload_save(&mut self, sender: u64, slot: &str, meta: Bytes, blob: Bytes) {
+        let as_dictionary = |meta: &[u8]| {
+            return cbor::decode(meta)
+                .and_then(|meta| meta.try_to::<Dictionary>().ok())
+                .unwrap_or_default();
+        };
+        let uploaded = save_sync::version_of(&as_dictionary(&meta));
+        let accepted = match self.saves.get(slot) {
+            Some((stored, _)) => {
+                let stored = save_sync::version_of(&as_dictionary(stored));
+                matches!(
+                    save_sync::compare_versions(&uploaded, &stored),
+                    VersionOrder::Newer | VersionOrder::Same
+                )
+            }
+            None => true,
+        };
+        if accepted {
+            self.saves.insert(slot.to_owned(), (meta, blob));
+        }
+
+        let result = ServerMessage::SaveUploadResult {
+            slot: slot.to_owned(),
+            accepted,
+            meta: self.saves[slot].0.clone(),
+        };
+        self.send(sender, channels::RELIABLE_UNORDERED, &result);
+    }
+
+    // To one client, or every client when None.
+    fn send_server_info(&mut self, client_id: Option<u64>) {
+        let info = ServerMessage::ServerInfo {
+            tick_rate: self.tick_rate,
+            tick: self.tick,
+        };
+        match client_id {
+            Some(client_id) => self.send(client_id, channels::RELIABLE_ORDERED, &info),
+            None => self.broadcast(None, channels::UNRELIABLE, &info),
+        }
+    }
+
+    fn broadcast(&mut self, except: Option<u64>, channel_id: u8, message: &ServerMessage) {
+        let clients: Vec<u64> = self
+            .clients
+            .keys()
+            .copied()
+            .filter(|client_id| Some(*client_id) != except)
+            .collect();
+        for client_id in clients {
+            self.send(client_id, channel_id, message);
+        }
+    }
+
+    fn send(&mut self, client_id: u64, channel_id: u8, message: &ServerMessage) {
+        message.encode(&mut self.send_buffer);
+        let message = self.send_buffer.split().freeze();
+        self.send_bytes(client_id, channel_id, message);
+    }
+
+    fn send_bytes(&mut self, client_id: u64, channel_id: u8, message: Bytes) {
+        let message = match channel_id {
+            channels::UNRELIABLE_SEQUENCED => {
+                let Some(client) = self.clients.get_mut(&client_id) else {
+                    return;
+                };
+                client.sequencer.wrap(&message, &mut self.send_buffer);
+                self.send_buffer.split().freeze()
+            }
+            _ => message,
+        };
+        self.server
+            .send_message(ClientId::from_raw(client_id), channel_id, message);
+    }
+}
+// End - Simulated server for solo testing
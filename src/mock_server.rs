@@ -0,0 +1,436 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use godot::prelude::*;
+use renet::{
+    transport::NetcodeServerTransport, ClientId, ConnectionConfig, RenetServer, ServerEvent,
+};
+
+use crate::{
+    channels::{self, Sequencer, CHANNEL_COUNT},
+    local_server,
+    protocol::{self, ClientMessage, ServerMessage},
+    replay,
+};
+
+// Renet's default, which is also what GameplaySessionManager uses unless it is changed in the inspector.
+const MEMORY_BUDGET: usize = 5 * 1024 * 1024;
+
+// Start - Scriptable server for tests
+// A server the test scripts of a game control, for testing networking code with GUT, GdUnit or plain scripts
+// without a real server. Add a MockServer to the test scene, `start` it, and join it with the manager's
+// `join_session(mock.get_address(), client_id)`. Then:
+//   accept_connections  false drops clients right after their handshake, which they see as being disconnected
+//   latency_ms          holds every message back this long, both ways
+//   send_message        sends an application payload, `send_raw` sends any bytes, even malformed ones
+//   set_connect_script  messages every client gets after connecting, each a while after the one before
+// Everything clients send comes back out as signals to assert on. It runs in `physics_process`, so awaiting
+// `get_tree().physics_frame` a few times lets messages go back and forth.
+#[derive(GodotClass)]
+#[class(init, base=Node)]
+struct MockServer {
+    base: Base<Node>,
+
+    #[export]
+    #[init(default = true)]
+    accept_connections: bool,
+    #[export]
+    latency_ms: f64,
+
+    running: Option<(RenetServer, NetcodeServerTransport, SocketAddr)>,
+    // Connected clients, with the sequencer of their unreliable sequenced channel.
+    clients: HashMap<u64, Sequencer>,
+    connect_script: Vec<ScriptStep>,
+    elapsed: f64,
+    // Messages waiting for their time, from a script or `latency_ms`: when they're due, the client, the
+    // channel and the message.
+    outgoing: Vec<(f64, u64, u8, Bytes)>,
+    incoming: VecDeque<(f64, u64, u8, Bytes)>,
+    send_buffer: BytesMut,
+}
+
+#[derive(Clone)]
+struct ScriptStep {
+    after: f64,
+    channel: u8,
+    message: Bytes,
+}
+
+#[godot_api]
+impl INode for MockServer {
+    fn physics_process(&mut self, delta: f64) {
+        self.elapsed += delta;
+        let latency = self.latency_ms.max(0.0) / 1000.0;
+        let mut connected = Vec::new();
+        let mut disconnected = Vec::new();
+        let Some((server, transport, _)) = &mut self.running else {
+            return;
+        };
+
+        let deltadur = Duration::from_secs_f64(delta);
+        server.update(deltadur);
+        if let Err(error) = transport.update(deltadur, server) {
+            godot_warn!("MockServer: {error}");
+        }
+        while let Some(event) = server.get_event() {
+            match event {
+                ServerEvent::ClientConnected { client_id } if !self.accept_connections => {
+                    server.disconnect(client_id);
+                }
+                ServerEvent::ClientConnected { client_id } => {
+                    self.clients.insert(client_id.raw(), Sequencer::default());
+                    connected.push(client_id.raw());
+                }
+                ServerEvent::ClientDisconnected { client_id, .. } => {
+                    if self.clients.remove(&client_id.raw()).is_some() {
+                        disconnected.push(client_id.raw());
+                    }
+                }
+            }
+        }
+        for client_id in server.clients_id() {
+            for channel_id in 0..CHANNEL_COUNT as u8 {
+                while let Some(message) = server.receive_message(client_id, channel_id) {
+                    self.incoming.push_back((
+                        self.elapsed + latency,
+                        client_id.raw(),
+                        channel_id,
+                        message,
+                    ));
+                }
+            }
+        }
+
+        for client_id in connected {
+            let mut due = self.elapsed;
+            for step in self.connect_script.clone() {
+                due += step.after;
+                self.outgoing
+                    .push((due, client_id, step.channel, step.message));
+            }
+            self.base_mut()
+                .emit_signal("client_connected".into(), &[client_id.to_variant()]);
+        }
+        for client_id in disconnected {
+            self.base_mut()
+                .emit_signal("client_disconnected".into(), &[client_id.to_variant()]);
+        }
+
+        while self
+            .incoming
+            .front()
+            .is_some_and(|(due, ..)| *due <= self.elapsed)
+        {
+            let (_, client_id, channel_id, message) = self.incoming.pop_front().unwrap();
+            self.receive(client_id, channel_id, message);
+        }
+
+        // Kept in the order they were queued, for messages that are due in the same tick.
+        let elapsed = self.elapsed;
+        let (due, later) = std::mem::take(&mut self.outgoing)
+            .into_iter()
+            .partition(|(due, ..)| *due <= elapsed);
+        self.outgoing = later;
+        for (_, client_id, channel_id, message) in due {
+            self.send_now(client_id, channel_id, message);
+        }
+
+        if let Some((server, transport, _)) = &mut self.running {
+            transport.send_packets(server);
+        }
+    }
+}
+
+#[godot_api]
+impl MockServer {
+    #[signal]
+    fn client_connected(client_id: i64);
+    #[signal]
+    fn client_disconnected(client_id: i64);
+    // Every message a client sent, as it arrived, including those that also get one of the signals below.
+    #[signal]
+    fn raw_message_received(client_id: i64, message: PackedByteArray, channel: i64);
+    // An application payload a client sent with `send_message`.
+    #[signal]
+    fn message_received(client_id: i64, payload: PackedByteArray, channel: i64);
+    // A request a client sent with `send_request`, which waits for a `respond`.
+    #[signal]
+    fn request_received(
+        client_id: i64,
+        request_id: i64,
+        request_type: i64,
+        payload: PackedByteArray,
+    );
+
+    /// Starts listening on a free port on 127.0.0.1, see `get_address`. Restarts it if it was running, which
+    /// drops every client. Returns false if no socket could be bound.
+    #[func]
+    fn start(&mut self) -> bool {
+        self.stop();
+        let channels = channels::channels_config([MEMORY_BUDGET; CHANNEL_COUNT]);
+        let config = ConnectionConfig {
+            server_channels_config: channels.clone(),
+            client_channels_config: channels,
+            ..Default::default()
+        };
+        match local_server::bind_server(config) {
+            Ok(running) => self.running = Some(running),
+            Err(error) => {
+                godot_error!("MockServer: couldn't start, {error}");
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Disconnects every client and stops listening. Queued and scripted messages are dropped.
+    #[func]
+    fn stop(&mut self) {
+        if let Some((mut server, mut transport, _)) = self.running.take() {
+            server.disconnect_all();
+            transport.send_packets(&mut server);
+        }
+        self.clients.clear();
+        self.outgoing.clear();
+        self.incoming.clear();
+    }
+
+    #[func]
+    fn is_running(&self) -> bool {
+        return self.running.is_some();
+    }
+
+    /// Returns the address to join, like "127.0.0.1:53124", or an empty string if it isn't running.
+    #[func]
+    fn get_address(&self) -> GString {
+        return match &self.running {
+            Some((_, _, address)) => GString::from(address.to_string()),
+            None => GString::new(),
+        };
+    }
+
+    #[func]
+    fn get_client_ids(&self) -> PackedInt64Array {
+        let mut client_ids = PackedInt64Array::new();
+        for client_id in self.clients.keys() {
+            client_ids.push(*client_id as i64);
+        }
+        return client_ids;
+    }
+
+    /// Disconnects one client, like a server kicking them.
+    #[func]
+    fn disconnect_client(&mut self, client_id: i64) {
+        if let Some((server, _, _)) = &mut self.running {
+            server.disconnect(ClientId::from_raw(client_id as u64));
+        }
+    }
+
+    /// Sends an application payload to a client, which gets it in `message_received`. Held back for
+    /// `latency_ms` like everything else.
+    #[func]
+    fn send_message(&mut self, client_id: i64, payload: PackedByteArray, channel: i64) {
+        let message = ServerMessage::Application(Bytes::copy_from_slice(payload.as_slice()));
+        self.queue(client_id as u64, channel, &message);
+    }
+
+    /// Sends an application payload to every connected client.
+    #[func]
+    fn broadcast_message(&mut self, payload: PackedByteArray, channel: i64) {
+        let clients: Vec<u64> = self.clients.keys().copied().collect();
+        for client_id in clients {
+            self.send_message(client_id as i64, payload.clone(), channel);
+        }
+    }
+
+    /// Sends `message` as it is, for any message kind of the protocol, or for malformed data.
+    #[func]
+    fn send_raw(&mut self, client_id: i64, message: PackedByteArray, channel: i64) {
+        let Some(channel) = Self::channel(channel) else {
+            return;
+        };
+        let message = Bytes::copy_from_slice(message.as_slice());
+        let due = self.elapsed + self.latency_ms.max(0.0) / 1000.0;
+        self.outgoing
+            .push((due, client_id as u64, channel, message));
+    }
+
+    /// Sends the messages of a session recording to a client at the times they originally arrived, counted
+    /// from now, see replay.rs. What the client sent in the recording is left out. Returns false if the
+    /// recording can't be loaded.
+    #[func]
+    fn play_recording(&mut self, client_id: i64, path: GString) -> bool {
+        let messages = match replay::load_recording(&path.to_string()) {
+            Ok(messages) => messages,
+            Err(error) => {
+                godot_error!("MockServer: can't load {path}: {error}");
+                return false;
+            }
+        };
+        let start = messages.first().map_or(0.0, |message| message.time);
+        let delay = self.elapsed + self.latency_ms.max(0.0) / 1000.0;
+        for message in messages.into_iter().filter(|message| !message.sent) {
+            let due = delay + message.time - start;
+            self.outgoing
+                .push((due, client_id as u64, message.channel, message.message));
+        }
+        return true;
+    }
+
+    /// Answers a request from `request_received` on the reliable ordered channel.
+    #[func]
+    fn respond(&mut self, client_id: i64, request_id: i64, payload: PackedByteArray) {
+        let response = ServerMessage::Response {
+            request_id: request_id as u32,
+            payload: Bytes::copy_from_slice(payload.as_slice()),
+        };
+        self.queue(
+            client_id as u64,
+            channels::RELIABLE_ORDERED as i64,
+            &response,
+        );
+    }
+
+    /// Sets the messages every client gets after connecting, in order. Each step is a Dictionary with
+    /// `payload`, a PackedByteArray sent as an application payload, `channel` (reliable ordered if left out),
+    /// `after`, the seconds since the step before, and `raw`, true to send `payload` as it is like `send_raw`.
+    #[func]
+    fn set_connect_script(&mut self, steps: Array<Dictionary>) {
+        self.connect_script.clear();
+        for step in steps.iter_shared() {
+            let payload = step
+                .get("payload")
+                .and_then(|payload| payload.try_to::<PackedByteArray>().ok())
+                .unwrap_or_default();
+            let channel = step
+                .get("channel")
+                .and_then(|channel| channel.try_to::<i64>().ok())
+                .unwrap_or(channels::RELIABLE_ORDERED as i64);
+            let after = step
+                .get("after")
+                .and_then(|after| after.try_to::<f64>().ok())
+                .unwrap_or(0.0);
+            let raw = step
+                .get("raw")
+                .and_then(|raw| raw.try_to::<bool>().ok())
+                .unwrap_or(false);
+
+            let Some(channel) = Self::channel(channel) else {
+                continue;
+            };
+            let payload = Bytes::copy_from_slice(payload.as_slice());
+            let message = match raw {
+                true => payload,
+                false => {
+                    ServerMessage::Application(payload).encode(&mut self.send_buffer);
+                    self.send_buffer.split().freeze()
+                }
+            };
+            self.connect_script.push(ScriptStep {
+                after: after.max(0.0),
+                channel,
+                message,
+            });
+        }
+    }
+}
+
+impl MockServer {
+    fn channel(channel: i64) -> Option<u8> {
+        if !(0..CHANNEL_COUNT as i64).contains(&channel) {
+            godot_error!("MockServer: there is no channel {channel}");
+            return None;
+        }
+        return Some(channel as u8);
+    }
+
+    fn queue(&mut self, client_id: u64, channel: i64, message: &ServerMessage) {
+        let Some(channel) = Self::channel(channel) else {
+            return;
+        };
+        message.encode(&mut self.send_buffer);
+        let message = self.send_buffer.split().freeze();
+        let due = self.elapsed + self.latency_ms.max(0.0) / 1000.0;
+        self.outgoing.push((due, client_id, channel, message));
+    }
+
+    fn send_now(&mut self, client_id: u64, channel_id: u8, message: Bytes) {
+        let Some(sequencer) = self.clients.get_mut(&client_id) else {
+            return;
+        };
+        let message = match channel_id {
+            channels::UNRELIABLE_SEQUENCED => {
+                sequencer.wrap(&message, &mut self.send_buffer);
+                self.send_buffer.split().freeze()
+            }
+            _ => message,
+        };
+        if let Some((server, _, _)) = &mut self.running {
+            server.send_message(ClientId::from_raw(client_id), channel_id, message);
+        }
+    }
+
+    fn receive(&mut self, client_id: u64, channel_id: u8, message: Bytes) {
+        let message = match channel_id {
+            channels::UNRELIABLE_SEQUENCED => {
+                let Some(sequencer) = self.clients.get_mut(&client_id) else {
+                    return;
+                };
+                match sequencer.unwrap(&message) {
+                    Some(body) => message.slice_ref(body),
+                    None => return,
+                }
+            }
+            _ => message,
+        };
+        let Some(parts) = protocol::split_batch(&message) else {
+            godot_warn!("MockServer: client {client_id} sent a malformed batch");
+            return;
+        };
+
+        let (client_id, channel) = (
+            (client_id as i64).to_variant(),
+            (channel_id as i64).to_variant(),
+        );
+        for part in parts {
+            let bytes = PackedByteArray::from(part);
+            self.base_mut().emit_signal(
+                "raw_message_received".into(),
+                &[client_id.clone(), bytes.to_variant(), channel.clone()],
+            );
+            match ClientMessage::decode(part) {
+                Some(ClientMessage::Application(payload)) => {
+                    self.base_mut().emit_signal(
+                        "message_received".into(),
+                        &[
+                            client_id.clone(),
+                            PackedByteArray::from(payload).to_variant(),
+                            channel.clone(),
+                        ],
+                    );
+                }
+                Some(ClientMessage::Request {
+                    request_id,
+                    request_type,
+                    payload,
+                }) => {
+                    self.base_mut().emit_signal(
+                        "request_received".into(),
+                        &[
+                            client_id.clone(),
+                            (request_id as i64).to_variant(),
+                            (request_type as i64).to_variant(),
+                            PackedByteArray::from(payload).to_variant(),
+                        ],
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+}
+// End - Scriptable server for tests
@@ -0,0 +1,81 @@
+use std::collections::BTreeSet;
+
+use godot::{
+    engine::{file_access::ModeFlags, FileAccess},
+    prelude::*,
+};
+
+use crate::protocol::Reader;
+
+// Start - Players the local player muted
+// Voice and chat from muted client ids are dropped before any signal is emitted, so the game never sees them.
+// The list is the player's own choice, so it's saved to user:// and applies to every session after. Client
+// ids are only stable if the backend hands out the same one to the same player, otherwise a mute ends with
+// their session.
+//
+// The file is the muted client ids as u64s, one after another.
+
+pub const MUTE_LIST_PATH: &str = "user://mute_list.bin";
+
+#[derive(Default)]
+pub struct MuteList {
+    muted: BTreeSet<u64>,
+    path: GString,
+}
+
+impl MuteList {
+    pub fn load(path: GString) -> Self {
+        let mut list = Self {
+            path: path.clone(),
+            ..Self::default()
+        };
+        if !FileAccess::file_exists(path.clone()) {
+            return list;
+        }
+
+        let bytes = FileAccess::get_file_as_bytes(path.clone());
+        let mut reader = Reader::new(bytes.as_slice());
+        while let Some(client_id) = reader.read_u64() {
+            list.muted.insert(client_id);
+        }
+        if !reader.is_empty() {
+            godot_warn!("The mute list at {path} is cut short, the rest is lost");
+        }
+        return list;
+    }
+
+    pub fn is_muted(&self, client_id: u64) -> bool {
+        return self.muted.contains(&client_id);
+    }
+
+    /// Returns false if that didn't change anything.
+    pub fn set_muted(&mut self, client_id: u64, muted: bool) -> bool {
+        let changed = match muted {
+            true => self.muted.insert(client_id),
+            false => self.muted.remove(&client_id),
+        };
+        if changed {
+            self.save();
+        }
+        return changed;
+    }
+
+    pub fn muted(&self) -> impl Iterator<Item = u64> + '_ {
+        return self.muted.iter().copied();
+    }
+
+    fn save(&self) {
+        let mut bytes = Vec::with_capacity(self.muted.len() * 8);
+        for client_id in &self.muted {
+            bytes.extend_from_slice(&client_id.to_le_bytes());
+        }
+
+        let Some(mut file) = FileAccess::open(self.path.clone(), ModeFlags::WRITE) else {
+            godot_warn!("Couldn't save the mute list to {}", self.path);
+            return;
+        };
+        file.store_buffer(PackedByteArray::from(bytes.as_slice()));
+        file.close();
+    }
+}
+// End - Players the local player muted
@@ -0,0 +1,84 @@
+// Start - Picks the payload format with the server when connecting
+// Right after connecting we send the formats we can use for application payloads, most preferred first, and
+// the server answers with the one it picked. The rules are the same on both sides so there are no surprises:
+//
+//   - The server picks the first format in our list that it supports.
+//   - Raw is always last in the list, so there is always something both sides understand.
+//   - A server that doesn't answer in time, or answers with something we didn't offer, gets raw.
+//
+// Older servers that don't know about negotiation never see it unless the game asks for it, see
+// `preferred_formats`. Compression is negotiated the same way, but no compression is built in yet so we
+// only ever offer none.
+
+// Plain bytes, handed to GDScript as is.
+pub const FORMAT_RAW: u8 = 0;
+pub const FORMAT_CBOR: u8 = 1;
+pub const FORMAT_PROTOBUF: u8 = 2;
+pub const FORMAT_FLATBUFFERS: u8 = 3;
+
+pub const COMPRESSION_NONE: u8 = 0;
+
+// How long the server has to answer before we fall back to raw.
+pub const NEGOTIATION_TIMEOUT: f64 = 3.0;
+
+pub fn is_format_built(format: u8) -> bool {
+    return format == FORMAT_RAW
+        || format == FORMAT_CBOR
+        || (format == FORMAT_PROTOBUF && cfg!(feature = "protobuf"))
+        || (format == FORMAT_FLATBUFFERS && cfg!(feature = "flatbuffers"));
+}
+
+/// The formats to offer, in the game's order of preference. Formats that weren't built in and duplicates are
+/// left out, and raw is always last.
+pub fn offered_formats(preferred: &[i32]) -> Vec<u8> {
+    let mut formats = Vec::new();
+    for &format in preferred {
+        let Ok(format) = u8::try_from(format) else {
+            continue;
+        };
+        if format != FORMAT_RAW && is_format_built(format) && !formats.contains(&format) {
+            formats.push(format);
+        }
+    }
+    formats.push(FORMAT_RAW);
+    return formats;
+}
+
+pub struct Negotiation {
+    offered_formats: Vec<u8>,
+    // Session time the server has to answer by.
+    deadline: f64,
+}
+
+impl Negotiation {
+    pub fn new(offered_formats: Vec<u8>, now: f64) -> Self {
+        return Self {
+            offered_formats,
+            deadline: now + NEGOTIATION_TIMEOUT,
+        };
+    }
+
+    pub fn offered_formats(&self) -> &[u8] {
+        return &self.offered_formats;
+    }
+
+    pub fn offered_compressions(&self) -> &[u8] {
+        return &[COMPRESSION_NONE];
+    }
+
+    pub fn is_timed_out(&self, now: f64) -> bool {
+        return now >= self.deadline;
+    }
+
+    /// Returns the format and compression to use for the server's choice, falling back to raw and no
+    /// compression if it picked something we didn't offer.
+    pub fn resolve(&self, format: u8, compression: u8) -> Result<(u8, u8), (u8, u8)> {
+        if self.offered_formats.contains(&format)
+            && self.offered_compressions().contains(&compression)
+        {
+            return Ok((format, compression));
+        }
+        return Err((FORMAT_RAW, COMPRESSION_NONE));
+    }
+}
+// End - Picks the payload format with the server when connecting
@@ -0,0 +1,76 @@
+use bytes::Bytes;
+
+use crate::{clock, dedup::DedupWindow};
+
+// Start - Notifications pushed by the server
+// Things the player should hear about outside of gameplay, like "a friend invited you" or "maintenance in 10
+// minutes". Each has an id, a category the game picks the UI by, a game specific payload and how long it stays
+// relevant. Servers send them again after a reconnect when they aren't sure they arrived, so ids are deduped
+// like idempotent messages. Received notifications stay unread until the game marks them read or they expire.
+
+pub struct Notification {
+    pub id: u64,
+    pub category: String,
+    pub payload: Bytes,
+    // Unix time in seconds, None if it doesn't expire.
+    pub expires_at: Option<f64>,
+}
+
+#[derive(Default)]
+pub struct Mailbox {
+    seen: DedupWindow,
+    // Oldest first.
+    unread: Vec<Notification>,
+}
+
+impl Mailbox {
+    /// Returns false if the notification was seen before or already expired.
+    pub fn receive(&mut self, notification: Notification) -> bool {
+        if !self.seen.insert(notification.id) {
+            return false;
+        }
+        if notification
+            .expires_at
+            .is_some_and(|expires_at| expires_at <= now())
+        {
+            return false;
+        }
+        self.unread.push(notification);
+        return true;
+    }
+
+    /// Unread notifications that haven't expired, oldest first.
+    pub fn unread(&mut self) -> &[Notification] {
+        let now = now();
+        self.unread.retain(|notification| {
+            notification
+                .expires_at
+                .is_none_or(|expires_at| expires_at > now)
+        });
+        return &self.unread;
+    }
+
+    /// Returns false if there is no unread notification with the id.
+    pub fn mark_read(&mut self, id: u64) -> bool {
+        let count = self.unread.len();
+        self.unread.retain(|notification| notification.id != id);
+        return self.unread.len() != count;
+    }
+
+    pub fn mark_all_read(&mut self) {
+        self.unread.clear();
+    }
+}
+
+/// The expiry time for a time to live in seconds, where 0 means it doesn't expire.
+pub fn expires_at(ttl: u32) -> Option<f64> {
+    if ttl == 0 {
+        return None;
+    }
+    return Some(now() + ttl as f64);
+}
+
+fn now() -> f64 {
+    return clock::unix_seconds();
+}
+// End - Notifications pushed by the server
@@ -0,0 +1,101 @@
+use godot::{
+    engine::{file_access::ModeFlags, FileAccess},
+    prelude::*,
+};
+
+use crate::{dedup, protocol::Reader};
+
+// Start - Messages that have to reach the server
+// Purchases, progress and other one-shot events can't be lost to a dropped connection. They are kept in the
+// outbox, which is saved to user:// so it also survives the game closing, until the server acknowledges
+// them. Everything still waiting is sent again on every new connection, and the id lets the server drop the
+// copies it already handled.
+//
+// The file is each message's u64 id, u32 length and payload, one after another.
+
+pub const OUTBOX_PATH: &str = "user://outbox.bin";
+
+#[derive(Default)]
+pub struct Outbox {
+    // Oldest first, which is the order they are sent in.
+    entries: Vec<(u64, Vec<u8>)>,
+    last_id: u64,
+    path: GString,
+}
+
+impl Outbox {
+    pub fn load(path: GString) -> Self {
+        let mut outbox = Self {
+            path: path.clone(),
+            ..Self::default()
+        };
+        if !FileAccess::file_exists(path.clone()) {
+            return outbox;
+        }
+
+        let bytes = FileAccess::get_file_as_bytes(path.clone());
+        let mut reader = Reader::new(bytes.as_slice());
+        while !reader.is_empty() {
+            let entry = reader.read_u64().and_then(|id| {
+                let length = reader.read_u32()?;
+                Some((id, reader.read_bytes(length as usize)?.to_vec()))
+            });
+            let Some(entry) = entry else {
+                godot_warn!("The outbox at {path} is cut short, the rest is lost");
+                break;
+            };
+            outbox.last_id = outbox.last_id.max(entry.0);
+            outbox.entries.push(entry);
+        }
+        return outbox;
+    }
+
+    /// Adds a message and returns its id.
+    pub fn push(&mut self, payload: &[u8]) -> u64 {
+        // Unique across restarts, even if the file is deleted.
+        self.last_id = dedup::next_unique_id(self.last_id);
+        self.entries.push((self.last_id, payload.to_vec()));
+        self.save();
+        return self.last_id;
+    }
+
+    /// Returns false if the id isn't waiting, like when an acknowledgement arrives twice.
+    pub fn acknowledge(&mut self, id: u64) -> bool {
+        let Some(index) = self
+            .entries
+            .iter()
+            .position(|(entry_id, _)| *entry_id == id)
+        else {
+            return false;
+        };
+        self.entries.remove(index);
+        self.save();
+        return true;
+    }
+
+    pub fn entries(&self) -> &[(u64, Vec<u8>)] {
+        return &self.entries;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.save();
+    }
+
+    fn save(&self) {
+        let mut bytes = Vec::new();
+        for (id, payload) in &self.entries {
+            bytes.extend_from_slice(&id.to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(payload);
+        }
+
+        let Some(mut file) = FileAccess::open(self.path.clone(), ModeFlags::WRITE) else {
+            godot_warn!("Couldn't save the outbox to {}", self.path);
+            return;
+        };
+        file.store_buffer(PackedByteArray::from(bytes.as_slice()));
+        file.close();
+    }
+}
+// End - Messages that have to reach the server
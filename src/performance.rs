@@ -0,0 +1,68 @@
+// Start - Client performance reports
+// "The server is lagging" is often the player's machine dropping frames. Every few seconds the client can send
+// the server a small summary of how it's doing, so server operators can tell the two apart. Which fields are
+// included is up to the game, from the PERFORMANCE_ flags, and only those are on the wire.
+
+pub const PERFORMANCE_FPS: u8 = 1;
+// The slowest frame and how many frames were slower than `SPIKE_SECONDS`.
+pub const PERFORMANCE_FRAME_SPIKES: u8 = 2;
+pub const PERFORMANCE_RTT: u8 = 4;
+pub const PERFORMANCE_PACKET_LOSS: u8 = 8;
+pub const PERFORMANCE_ALL: u8 =
+    PERFORMANCE_FPS | PERFORMANCE_FRAME_SPIKES | PERFORMANCE_RTT | PERFORMANCE_PACKET_LOSS;
+
+// Frames slower than this count as spikes, which is below 20 FPS.
+const SPIKE_SECONDS: f64 = 0.05;
+
+// Frame times since the last report.
+#[derive(Default)]
+pub struct FrameStats {
+    frames: u32,
+    total: f64,
+    worst: f64,
+    spikes: u32,
+    // Seconds since the last report went out.
+    since_report: f64,
+}
+
+impl FrameStats {
+    pub fn record_frame(&mut self, delta: f64) {
+        self.frames += 1;
+        self.total += delta;
+        self.worst = self.worst.max(delta);
+        if delta > SPIKE_SECONDS {
+            self.spikes += 1;
+        }
+    }
+
+    /// Advances the report timer, returns true when a report is due.
+    pub fn is_due(&mut self, delta: f64, interval: f64) -> bool {
+        self.since_report += delta;
+        return interval > 0.0 && self.since_report >= interval && self.frames > 0;
+    }
+
+    /// Encodes a report with the given fields and starts over. `rtt` is in seconds and `packet_loss` from 0
+    /// to 1. All values are u16 and saturate: FPS, then the slowest frame in ms and the spike count, then the
+    /// RTT in ms, then the packet loss in 1/10000ths.
+    pub fn take_report(&mut self, fields: u8, rtt: f64, packet_loss: f64) -> Vec<u8> {
+        let to_u16 = |value: f64| value.round().clamp(0.0, u16::MAX as f64) as u16;
+        let mut report = vec![fields];
+        if fields & PERFORMANCE_FPS != 0 {
+            let fps = self.frames as f64 / self.total.max(f64::EPSILON);
+            report.extend_from_slice(&to_u16(fps).to_le_bytes());
+        }
+        if fields & PERFORMANCE_FRAME_SPIKES != 0 {
+            report.extend_from_slice(&to_u16(self.worst * 1000.0).to_le_bytes());
+            report.extend_from_slice(&(self.spikes.min(u16::MAX as u32) as u16).to_le_bytes());
+        }
+        if fields & PERFORMANCE_RTT != 0 {
+            report.extend_from_slice(&to_u16(rtt * 1000.0).to_le_bytes());
+        }
+        if fields & PERFORMANCE_PACKET_LOSS != 0 {
+            report.extend_from_slice(&to_u16(packet_loss * 10000.0).to_le_bytes());
+        }
+        *self = Self::default();
+        return report;
+    }
+}
+// End - Client performance reports
@@ -0,0 +1,115 @@
+use godot::{engine::Upnp, prelude::*};
+
+// Start - Opens a port on the router for player hosting
+// A player hosting a session is behind their router's NAT, so nobody can reach them unless the router forwards
+// a port. Most home routers take requests for that over UPnP IGD, which Godot's UPNP module speaks. Mappings
+// are removed when the PortMapper is freed, so a crashed host doesn't leave ports open past the lease.
+//
+// Finding the router takes up to `discover_timeout_ms`, during which `map_port` blocks. Call it from a Thread
+// or a loading screen:
+//
+//     var mapper = PortMapper.new()
+//     var endpoint = mapper.map_port(7000)   # "203.0.113.7:7000", or empty if no router helped
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+struct PortMapper {
+    base: Base<RefCounted>,
+
+    // How long to wait for routers to answer the discovery.
+    #[var]
+    #[init(default = 2000)]
+    discover_timeout_ms: i64,
+
+    // Set once a router was found.
+    upnp: Option<Gd<Upnp>>,
+    // External ports we mapped, removed again when this is freed.
+    mapped_ports: Vec<i64>,
+}
+
+// Godot's UPNP_RESULT_SUCCESS.
+const UPNP_SUCCESS: i32 = 0;
+
+#[godot_api]
+impl PortMapper {
+    /// Asks the router to forward UDP `port` to the same port on this device, for `lease_seconds` or 0 for as
+    /// long as the router keeps it. Returns the external "address:port", or an empty string if no router
+    /// answered or it refused.
+    #[func]
+    fn map_port(&mut self, port: i64, lease_seconds: i64) -> GString {
+        let Some(upnp) = self.upnp() else {
+            return GString::new();
+        };
+
+        let result = upnp
+            .clone()
+            .add_port_mapping_ex(port as i32)
+            .desc("arcade-client".into())
+            .proto("UDP".into())
+            .duration(lease_seconds as i32)
+            .done();
+        if result != UPNP_SUCCESS {
+            godot_warn!("PortMapper: the router refused to map port {port}, error {result}");
+            return GString::new();
+        }
+        if !self.mapped_ports.contains(&port) {
+            self.mapped_ports.push(port);
+        }
+
+        let address = upnp.query_external_address();
+        if address.is_empty() {
+            return GString::new();
+        }
+        return GString::from(format!("{address}:{port}"));
+    }
+
+    /// Removes a mapping made by `map_port`. Returns false if the router wouldn't.
+    #[func]
+    fn unmap_port(&mut self, port: i64) -> bool {
+        self.mapped_ports.retain(|mapped| *mapped != port);
+        let Some(upnp) = &self.upnp else {
+            return false;
+        };
+
+        let result = upnp
+            .clone()
+            .delete_port_mapping_ex(port as i32)
+            .proto("UDP".into())
+            .done();
+        return result == UPNP_SUCCESS;
+    }
+
+    #[func]
+    fn unmap_all(&mut self) {
+        for port in std::mem::take(&mut self.mapped_ports) {
+            self.unmap_port(port);
+        }
+    }
+
+    // Discovers the router the first time it's needed.
+    fn upnp(&mut self) -> Option<Gd<Upnp>> {
+        if self.upnp.is_none() {
+            let mut upnp = Upnp::new_gd();
+            let result = upnp
+                .discover_ex()
+                .timeout(self.discover_timeout_ms as i32)
+                .done();
+            let has_gateway = upnp
+                .get_gateway()
+                .is_some_and(|gateway| gateway.is_valid_gateway());
+            if result != UPNP_SUCCESS || !has_gateway {
+                godot_warn!("PortMapper: no router with UPnP found, error {result}");
+                return None;
+            }
+            self.upnp = Some(upnp);
+        }
+
+        return self.upnp.clone();
+    }
+}
+
+impl Drop for PortMapper {
+    fn drop(&mut self) {
+        self.unmap_all();
+    }
+}
+// End - Opens a port on the router for player hosting
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+
+// Start - Presence
+// Small hints for lobby and chat UIs, like who is typing or still in the menu. They go over the unreliable
+// sequenced channel, so a lost ping is simply replaced by the next one. To make up for the losses our state is
+// sent again every few seconds, and changes are throttled so someone tapping keys doesn't send a packet for
+// every keystroke: the newest state goes out once the interval has passed.
+
+pub const PRESENCE_ONLINE: u8 = 0;
+pub const PRESENCE_TYPING: u8 = 1;
+pub const PRESENCE_IN_MENU: u8 = 2;
+pub const PRESENCE_IN_MATCH: u8 = 3;
+
+// Seconds between two pings, however often the state changes.
+const MIN_INTERVAL: f64 = 0.25;
+// Seconds after which the state is sent again without a change.
+const REFRESH_INTERVAL: f64 = 2.0;
+
+#[derive(Default)]
+pub struct Presence {
+    // What was sent last, None before the first ping.
+    sent: Option<u8>,
+    sent_at: f64,
+    // Other players' states as last heard from them.
+    peers: HashMap<u64, u8>,
+}
+
+impl Presence {
+    /// Returns the state to send now, if any.
+    pub fn due(&mut self, state: u8, time: f64) -> Option<u8> {
+        let elapsed = time - self.sent_at;
+        let due = match self.sent {
+            None => true,
+            Some(sent) if sent != state => elapsed >= MIN_INTERVAL,
+            Some(_) => elapsed >= REFRESH_INTERVAL,
+        };
+        if !due {
+            return None;
+        }
+        self.sent = Some(state);
+        self.sent_at = time;
+        return Some(state);
+    }
+
+    /// Records a peer's state, returns true if it changed.
+    pub fn peer_state(&mut self, client_id: u64, state: u8) -> bool {
+        return self.peers.insert(client_id, state) != Some(state);
+    }
+
+    pub fn peer(&self, client_id: u64) -> Option<u8> {
+        return self.peers.get(&client_id).copied();
+    }
+}
+// End - Presence
+
+// Start - Rich presence
+// What the player is doing in more detail, for friends lists and lobby UIs, like {"activity": "ranked",
+// "map": "harbor"}. It's game specific and sent as CBOR over the reliable ordered channel, so unlike the small
+// presence states above it isn't repeated. Only the newest state matters: setting it again before the last
+// one went out replaces it, and it goes out at most once every `RICH_MIN_INTERVAL` seconds.
+
+// Seconds between two updates, however often the state changes.
+const RICH_MIN_INTERVAL: f64 = 1.0;
+// Bigger states are refused, they are meant to be a few short fields.
+pub const MAX_RICH_PRESENCE_SIZE: usize = 1024;
+
+#[derive(Default)]
+pub struct RichPresence {
+    // What was sent last, None before the first update.
+    sent: Option<Vec<u8>>,
+    sent_at: f64,
+    // Other players' states as last heard from them, still encoded.
+    peers: HashMap<u64, Vec<u8>>,
+}
+
+impl RichPresence {
+    /// Returns true if `state` should be sent now, and remembers it as sent.
+    pub fn due(&mut self, state: &[u8], time: f64) -> bool {
+        match &self.sent {
+            Some(sent) if sent == state => return false,
+            Some(_) if time - self.sent_at < RICH_MIN_INTERVAL => return false,
+            _ => {}
+        }
+        self.sent = Some(state.to_vec());
+        self.sent_at = time;
+        return true;
+    }
+
+    /// Records a peer's state, returns true if it changed.
+    pub fn peer_state(&mut self, client_id: u64, state: &[u8]) -> bool {
+        if self
+            .peers
+            .get(&client_id)
+            .is_some_and(|known| known == state)
+        {
+            return false;
+        }
+        self.peers.insert(client_id, state.to_vec());
+        return true;
+    }
+
+    pub fn peer(&self, client_id: u64) -> Option<&[u8]> {
+        return self.peers.get(&client_id).map(Vec::as_slice);
+    }
+}
+// End - Rich presence
@@ -0,0 +1,153 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::Instant,
+};
+
+#[cfg(not(target_family = "wasm"))]
+use crate::transport::{self, SocketOptions};
+
+// Start - Pinging servers without a session
+// A probe is a small UDP packet outside of netcode: 4 magic bytes and a u64 nonce. Gateways and game servers
+// send it back unchanged, which is all it takes to measure the round trip. Each address is pinged a few times
+// and the fastest reply counts, so one slow packet doesn't make a good server look bad.
+
+pub const PROBE_MAGIC: [u8; 4] = *b"ARCP";
+const PROBE_SIZE: usize = PROBE_MAGIC.len() + 8;
+
+const PROBES_PER_ADDRESS: u32 = 3;
+// Seconds between rounds of probes.
+const PROBE_INTERVAL: f64 = 0.1;
+// Addresses that haven't answered by now are unreachable.
+pub const PROBE_TIMEOUT: f64 = 2.0;
+
+struct Target {
+    address: SocketAddr,
+    // Seconds.
+    best_rtt: Option<f64>,
+}
+
+pub struct Prober {
+    // One socket per address family, see `transport::bind_socket`.
+    ipv4_socket: Option<UdpSocket>,
+    ipv6_socket: Option<UdpSocket>,
+    targets: Vec<Target>,
+    started_at: Instant,
+    rounds_sent: u32,
+    receive_buffer: [u8; PROBE_SIZE],
+}
+
+impl Prober {
+    #[cfg(target_family = "wasm")]
+    pub fn new(_addresses: &[SocketAddr]) -> io::Result<Self> {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "UDP sockets aren't available in web builds",
+        ));
+    }
+
+    #[cfg(not(target_family = "wasm"))]
+    pub fn new(addresses: &[SocketAddr]) -> io::Result<Self> {
+        let mut prober = Self {
+            ipv4_socket: None,
+            ipv6_socket: None,
+            targets: Vec::new(),
+            started_at: Instant::now(),
+            rounds_sent: 0,
+            receive_buffer: [0; PROBE_SIZE],
+        };
+
+        for &address in addresses {
+            let socket = match address {
+                SocketAddr::V4(_) => &mut prober.ipv4_socket,
+                SocketAddr::V6(_) => &mut prober.ipv6_socket,
+            };
+            if socket.is_none() {
+                let new_socket = transport::bind_socket(address, &SocketOptions::default())?;
+                new_socket.set_nonblocking(true)?;
+                *socket = Some(new_socket);
+            }
+            prober.targets.push(Target {
+                address,
+                best_rtt: None,
+            });
+        }
+
+        return Ok(prober);
+    }
+
+    /// Sends and receives probes. Returns true once every address has answered every probe, or the timeout
+    /// has passed.
+    pub fn update(&mut self) -> bool {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        if self.rounds_sent < PROBES_PER_ADDRESS
+            && elapsed >= self.rounds_sent as f64 * PROBE_INTERVAL
+        {
+            self.send_round(elapsed);
+        }
+
+        for socket in [&self.ipv4_socket, &self.ipv6_socket].into_iter().flatten() {
+            // Nonblocking, so this stops as soon as nothing is waiting.
+            while let Ok((size, from)) = socket.recv_from(&mut self.receive_buffer) {
+                let Some(nonce) = parse_probe(&self.receive_buffer[..size]) else {
+                    continue;
+                };
+                let index = (nonce >> 48) as usize;
+                let sent_at = (nonce & 0xFFFF_FFFF_FFFF) as f64 / 1_000_000.0;
+                let Some(target) = self.targets.get_mut(index) else {
+                    continue;
+                };
+                // Anyone can send us a probe, only replies from the address we pinged count.
+                if target.address != from {
+                    continue;
+                }
+
+                let rtt = (elapsed - sent_at).max(0.0);
+                target.best_rtt = Some(target.best_rtt.map_or(rtt, |best| best.min(rtt)));
+            }
+        }
+
+        let all_answered = self.rounds_sent == PROBES_PER_ADDRESS
+            && elapsed >= PROBES_PER_ADDRESS as f64 * PROBE_INTERVAL
+            && self.targets.iter().all(|target| target.best_rtt.is_some());
+        return all_answered || elapsed >= PROBE_TIMEOUT;
+    }
+
+    // Every probe's nonce is the time it was sent, in microseconds since `started_at`, so replies need no
+    // bookkeeping. The target index is in the top 16 bits.
+    fn send_round(&mut self, elapsed: f64) {
+        self.rounds_sent += 1;
+        let sent_at = (elapsed * 1_000_000.0) as u64 & 0xFFFF_FFFF_FFFF;
+        for (index, target) in self.targets.iter().enumerate() {
+            let socket = match target.address {
+                SocketAddr::V4(_) => &self.ipv4_socket,
+                SocketAddr::V6(_) => &self.ipv6_socket,
+            };
+            let nonce = (index as u64) << 48 | sent_at;
+            let mut packet = [0; PROBE_SIZE];
+            packet[..4].copy_from_slice(&PROBE_MAGIC);
+            packet[4..].copy_from_slice(&nonce.to_le_bytes());
+            if let Some(socket) = socket {
+                // A lost probe is the same as a lost reply, the timeout covers both.
+                let _ = socket.send_to(&packet, target.address);
+            }
+        }
+    }
+
+    /// Each address with its fastest round trip in seconds, or `None` if it never answered. In the order
+    /// the addresses were given.
+    pub fn results(&self) -> impl Iterator<Item = (SocketAddr, Option<f64>)> + '_ {
+        return self
+            .targets
+            .iter()
+            .map(|target| (target.address, target.best_rtt));
+    }
+}
+
+fn parse_probe(packet: &[u8]) -> Option<u64> {
+    if packet.len() != PROBE_SIZE || packet[..4] != PROBE_MAGIC {
+        return None;
+    }
+    return Some(u64::from_le_bytes(packet[4..].try_into().ok()?));
+}
+// End - Pinging servers without a session
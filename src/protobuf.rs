@@ -0,0 +1,241 @@
+use godot::prelude::*;
+
+#[cfg(feature = "protobuf")]
+use prost::Message;
+#[cfg(feature = "protobuf")]
+use prost_reflect::{
+    DescriptorPool, DynamicMessage, FieldDescriptor, Kind, MapKey, MessageDescriptor, Value,
+};
+
+#[cfg(feature = "protobuf")]
+use crate::channels::CHANNEL_COUNT;
+
+// Start - Protobuf payloads as Dictionaries
+// Only does anything with the `protobuf` feature, for backends that already describe their messages with
+// protobuf. The message types are loaded at runtime from a descriptor set, made with
+// `protoc --include_imports --descriptor_set_out=messages.pb messages.proto`, so GDScript can use any message
+// without generated code. A channel that has a message type carries only that type: received payloads are
+// decoded into Dictionaries keyed by field name, and Dictionaries are encoded when sending.
+
+#[cfg(not(feature = "protobuf"))]
+const NOT_BUILT: &str = "the extension was built without the protobuf feature";
+
+#[derive(Default)]
+pub struct ProtobufCodec {
+    #[cfg(feature = "protobuf")]
+    pool: Option<DescriptorPool>,
+    // Indexed by channel id.
+    #[cfg(feature = "protobuf")]
+    channel_types: [Option<MessageDescriptor>; CHANNEL_COUNT],
+}
+
+#[cfg(feature = "protobuf")]
+impl ProtobufCodec {
+    pub fn load_descriptors(&mut self, descriptor_set: &[u8]) -> Result<(), String> {
+        let pool = DescriptorPool::decode(descriptor_set).map_err(|error| error.to_string())?;
+        self.pool = Some(pool);
+        // Types from the old descriptors might not exist anymore.
+        self.channel_types = Default::default();
+        return Ok(());
+    }
+
+    /// An empty name takes the message type off the channel.
+    pub fn set_channel_type(&mut self, channel_id: u8, message_name: &str) -> Result<(), String> {
+        if message_name.is_empty() {
+            self.channel_types[channel_id as usize] = None;
+            return Ok(());
+        }
+
+        let Some(pool) = &self.pool else {
+            return Err(String::from("no descriptors have been loaded"));
+        };
+        let Some(descriptor) = pool.get_message_by_name(message_name) else {
+            return Err(format!("unknown message type '{message_name}'"));
+        };
+        self.channel_types[channel_id as usize] = Some(descriptor);
+        return Ok(());
+    }
+
+    pub fn has_channel_type(&self, channel_id: u8) -> bool {
+        return self.channel_types[channel_id as usize].is_some();
+    }
+
+    pub fn decode(&self, channel_id: u8, payload: &[u8]) -> Result<Dictionary, String> {
+        let Some(descriptor) = &self.channel_types[channel_id as usize] else {
+            return Err(format!("channel {channel_id} has no message type"));
+        };
+
+        let message = DynamicMessage::decode(descriptor.clone(), payload)
+            .map_err(|error| error.to_string())?;
+        return Ok(message_to_dictionary(&message));
+    }
+
+    pub fn encode(&self, channel_id: u8, message: &Dictionary) -> Result<Vec<u8>, String> {
+        let Some(descriptor) = &self.channel_types[channel_id as usize] else {
+            return Err(format!("channel {channel_id} has no message type"));
+        };
+
+        return Ok(dictionary_to_message(descriptor, message)?.encode_to_vec());
+    }
+}
+
+#[cfg(not(feature = "protobuf"))]
+impl ProtobufCodec {
+    pub fn load_descriptors(&mut self, _descriptor_set: &[u8]) -> Result<(), String> {
+        return Err(String::from(NOT_BUILT));
+    }
+
+    pub fn set_channel_type(&mut self, _channel_id: u8, _message_name: &str) -> Result<(), String> {
+        return Err(String::from(NOT_BUILT));
+    }
+
+    pub fn has_channel_type(&self, _channel_id: u8) -> bool {
+        return false;
+    }
+
+    pub fn decode(&self, _channel_id: u8, _payload: &[u8]) -> Result<Dictionary, String> {
+        return Err(String::from(NOT_BUILT));
+    }
+
+    pub fn encode(&self, _channel_id: u8, _message: &Dictionary) -> Result<Vec<u8>, String> {
+        return Err(String::from(NOT_BUILT));
+    }
+}
+
+// Fields that aren't set are left out, like protobuf does on the wire.
+#[cfg(feature = "protobuf")]
+fn message_to_dictionary(message: &DynamicMessage) -> Dictionary {
+    let mut dictionary = Dictionary::new();
+    for (field, value) in message.fields() {
+        dictionary.set(field.name(), value_to_variant(value));
+    }
+    return dictionary;
+}
+
+#[cfg(feature = "protobuf")]
+fn value_to_variant(value: &Value) -> Variant {
+    return match value {
+        Value::Bool(value) => value.to_variant(),
+        Value::I32(value) => (*value as i64).to_variant(),
+        Value::I64(value) => value.to_variant(),
+        Value::U32(value) => (*value as i64).to_variant(),
+        // GDScript has no unsigned integers, so large values wrap around.
+        Value::U64(value) => (*value as i64).to_variant(),
+        Value::F32(value) => (*value as f64).to_variant(),
+        Value::F64(value) => value.to_variant(),
+        Value::String(value) => GString::from(value.as_str()).to_variant(),
+        Value::Bytes(value) => PackedByteArray::from(&value[..]).to_variant(),
+        Value::EnumNumber(value) => (*value as i64).to_variant(),
+        Value::Message(message) => message_to_dictionary(message).to_variant(),
+        Value::List(values) => values
+            .iter()
+            .map(value_to_variant)
+            .collect::<VariantArray>()
+            .to_variant(),
+        Value::Map(entries) => {
+            let mut dictionary = Dictionary::new();
+            for (key, value) in entries {
+                dictionary.set(map_key_to_variant(key), value_to_variant(value));
+            }
+            dictionary.to_variant()
+        }
+    };
+}
+
+#[cfg(feature = "protobuf")]
+fn map_key_to_variant(key: &MapKey) -> Variant {
+    return match key {
+        MapKey::Bool(key) => key.to_variant(),
+        MapKey::I32(key) => (*key as i64).to_variant(),
+        MapKey::I64(key) => key.to_variant(),
+        MapKey::U32(key) => (*key as i64).to_variant(),
+        MapKey::U64(key) => (*key as i64).to_variant(),
+        MapKey::String(key) => GString::from(key.as_str()).to_variant(),
+    };
+}
+
+#[cfg(feature = "protobuf")]
+fn dictionary_to_message(
+    descriptor: &MessageDescriptor,
+    dictionary: &Dictionary,
+) -> Result<DynamicMessage, String> {
+    let mut message = DynamicMessage::new(descriptor.clone());
+    for (key, variant) in dictionary.iter_shared() {
+        // `{name = value}` Dictionaries in GDScript have StringName keys.
+        let name = key
+            .try_to::<GString>()
+            .or_else(|_| key.try_to::<StringName>().map(|name| GString::from(&name)));
+        let Ok(name) = name else {
+            return Err(format!("keys must be field names, not {key}"));
+        };
+        let name = name.to_string();
+        let Some(field) = descriptor.get_field_by_name(&name) else {
+            return Err(format!("{} has no field '{name}'", descriptor.full_name()));
+        };
+
+        let value = field_value(&field, &variant).ok_or(format!(
+            "{}.{name} can't hold {variant}",
+            descriptor.full_name()
+        ))?;
+        message.set_field(&field, value);
+    }
+    return Ok(message);
+}
+
+#[cfg(feature = "protobuf")]
+fn field_value(field: &FieldDescriptor, variant: &Variant) -> Option<Value> {
+    if field.is_map() {
+        let Kind::Message(entry) = field.kind() else {
+            return None;
+        };
+        let key_kind = entry.map_entry_key_field().kind();
+        let value_field = entry.map_entry_value_field();
+
+        let mut entries = std::collections::HashMap::new();
+        for (key, value) in variant.try_to::<Dictionary>().ok()?.iter_shared() {
+            let key = variant_to_value(&key_kind, &key)?.into_map_key()?;
+            entries.insert(key, variant_to_value(&value_field.kind(), &value)?);
+        }
+        return Some(Value::Map(entries));
+    }
+
+    if field.is_list() {
+        let values = variant
+            .try_to::<VariantArray>()
+            .ok()?
+            .iter_shared()
+            .map(|element| variant_to_value(&field.kind(), &element))
+            .collect::<Option<Vec<Value>>>()?;
+        return Some(Value::List(values));
+    }
+
+    return variant_to_value(&field.kind(), variant);
+}
+
+#[cfg(feature = "protobuf")]
+fn variant_to_value(kind: &Kind, variant: &Variant) -> Option<Value> {
+    let integer = || variant.try_to::<i64>().ok();
+    let float = || {
+        variant
+            .try_to::<f64>()
+            .ok()
+            .or_else(|| integer().map(|value| value as f64))
+    };
+
+    return Some(match kind {
+        Kind::Double => Value::F64(float()?),
+        Kind::Float => Value::F32(float()? as f32),
+        Kind::Int32 | Kind::Sint32 | Kind::Sfixed32 => Value::I32(integer()? as i32),
+        Kind::Int64 | Kind::Sint64 | Kind::Sfixed64 => Value::I64(integer()?),
+        Kind::Uint32 | Kind::Fixed32 => Value::U32(integer()? as u32),
+        Kind::Uint64 | Kind::Fixed64 => Value::U64(integer()? as u64),
+        Kind::Bool => Value::Bool(variant.try_to::<bool>().ok()?),
+        Kind::String => Value::String(variant.try_to::<GString>().ok()?.to_string()),
+        Kind::Bytes => Value::Bytes(variant.try_to::<PackedByteArray>().ok()?.to_vec().into()),
+        Kind::Enum(_) => Value::EnumNumber(integer()? as i32),
+        Kind::Message(descriptor) => Value::Message(
+            dictionary_to_message(descriptor, &variant.try_to::<Dictionary>().ok()?).ok()?,
+        ),
+    });
+}
+// End - Protobuf payloads as Dictionaries
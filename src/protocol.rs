@@ -0,0 +1,1346 @@
+use bytes::{Bytes, BytesMut};
+
+// Wire format for the messages exchanged with the server.
+// Every message starts with a single byte saying what kind of message it is, followed by the body
+// for that kind. All integers are little endian.
+
+pub const MESSAGE_APPLICATION: u8 = 0;
+pub const MESSAGE_SPAWN: u8 = 1;
+pub const MESSAGE_DESPAWN: u8 = 2;
+pub const MESSAGE_AUTHORITY: u8 = 3;
+pub const MESSAGE_RPC: u8 = 4;
+pub const MESSAGE_SNAPSHOT: u8 = 5;
+pub const MESSAGE_SERVER_INFO: u8 = 6;
+pub const MESSAGE_REQUEST_FULL_SNAPSHOT: u8 = 7;
+pub const MESSAGE_FULL_SNAPSHOT: u8 = 8;
+pub const MESSAGE_BATCH: u8 = 9;
+pub const MESSAGE_SESSION_TAKEN_OVER: u8 = 10;
+pub const MESSAGE_RECLAIM_SESSION: u8 = 11;
+pub const MESSAGE_REQUEST: u8 = 12;
+pub const MESSAGE_RESPONSE: u8 = 13;
+pub const MESSAGE_SUBSCRIBE: u8 = 14;
+pub const MESSAGE_UNSUBSCRIBE: u8 = 15;
+pub const MESSAGE_TOPIC: u8 = 16;
+pub const MESSAGE_CAPABILITIES: u8 = 17;
+pub const MESSAGE_FORMAT_SELECTED: u8 = 18;
+pub const MESSAGE_OUTBOX: u8 = 19;
+pub const MESSAGE_OUTBOX_ACK: u8 = 20;
+pub const MESSAGE_IDEMPOTENT: u8 = 21;
+pub const MESSAGE_CHECKSUM: u8 = 22;
+pub const MESSAGE_VOICE: u8 = 23;
+pub const MESSAGE_CHAT: u8 = 24;
+pub const MESSAGE_MUTE: u8 = 25;
+pub const MESSAGE_QUICK_CHAT: u8 = 26;
+pub const MESSAGE_PRESENCE: u8 = 27;
+pub const MESSAGE_AFK: u8 = 28;
+pub const MESSAGE_COMMAND: u8 = 29;
+pub const MESSAGE_PERFORMANCE: u8 = 30;
+pub const MESSAGE_RICH_PRESENCE: u8 = 31;
+pub const MESSAGE_REPLICA_SUBSCRIBE: u8 = 32;
+pub const MESSAGE_REPLICA_UNSUBSCRIBE: u8 = 33;
+pub const MESSAGE_REPLICA_UPDATE: u8 = 34;
+pub const MESSAGE_REPLICA_WRITE: u8 = 35;
+pub const MESSAGE_SAVE_DOWNLOAD: u8 = 36;
+pub const MESSAGE_SAVE_UPLOAD: u8 = 37;
+pub const MESSAGE_SAVE_DATA: u8 = 38;
+pub const MESSAGE_SAVE_UPLOAD_RESULT: u8 = 39;
+pub const MESSAGE_NOTIFICATION: u8 = 40;
+pub const MESSAGE_PING: u8 = 41;
+pub const MESSAGE_PONG: u8 = 42;
+pub const MESSAGE_MATCH_SEED: u8 = 43;
+pub const MESSAGE_SERVER_CONFIG: u8 = 44;
+pub const MESSAGE_FULL_SNAPSHOT_TOO_LARGE: u8 = 45;
+
+// Topics are sent as a u8 length followed by UTF-8.
+pub const MAX_TOPIC_LENGTH: usize = u8::MAX as usize;
+
+// Batches are kept under this so an unreliable batch still fits in a single packet.
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+pub enum ServerMessage {
+    // Game specific data, handed to GDScript as-is.
+    Application(Bytes),
+    // Instantiate the scene at `scene_index` in the spawner's scene list, owned by `owner_id`.
+    Spawn {
+        entity_id: u64,
+        scene_index: u16,
+        owner_id: u64,
+    },
+    // Free the node that was spawned for `entity_id`.
+    Despawn {
+        entity_id: u64,
+    },
+    // Authority over `entity_id` moved to `owner_id`.
+    Authority {
+        entity_id: u64,
+        owner_id: u64,
+    },
+    // A SceneMultiplayer packet relayed by the server, see `RenetMultiplayerPeer`.
+    Rpc(RpcPacket),
+    // Game state for server tick `tick`. The payload is game specific and handed to GDScript as-is.
+    Snapshot {
+        tick: u32,
+        payload: Bytes,
+    },
+    // The server's tick rate in ticks per second, and its current tick.
+    ServerInfo {
+        tick_rate: u16,
+        tick: u32,
+    },
+    // The complete game state at `tick`, in reply to `ClientMessage::RequestFullSnapshot`.
+    FullSnapshot {
+        tick: u32,
+        payload: Bytes,
+    },
+    // The answer to `ClientMessage::RequestFullSnapshot` instead of a snapshot bigger than we asked for.
+    FullSnapshotTooLarge {
+        size: u32,
+    },
+    // Our client id connected from somewhere else, and the server is about to drop this connection.
+    SessionTakenOver,
+    // The reply to `ClientMessage::Request` with the same id.
+    Response {
+        request_id: u32,
+        payload: Bytes,
+    },
+    // Published to a topic we subscribed to.
+    Topic {
+        topic: String,
+        payload: Bytes,
+    },
+    // The server's answer to `ClientMessage::Capabilities`.
+    FormatSelected {
+        format: u8,
+        compression: u8,
+    },
+    // The server has handled the `ClientMessage::Outbox` message with this id.
+    OutboxAck {
+        id: u64,
+    },
+    // An application payload that may arrive more than once, see dedup.rs.
+    Idempotent {
+        key: u64,
+        payload: Bytes,
+    },
+    // The server's hash of the game state at `tick`, see desync.rs.
+    Checksum {
+        tick: u32,
+        checksum: u64,
+    },
+    // Voice from another player, relayed with the client id of who is speaking. See `ClientMessage::Voice`.
+    Voice {
+        speaker: u64,
+        entity_id: u64,
+        position: [f32; 3],
+        frames: Bytes,
+    },
+    // A chat message from another player, UTF-8.
+    Chat {
+        sender: u64,
+        text: String,
+    },
+    // A quick chat line or emote from another player, by its index in the game's list.
+    QuickChat {
+        sender: u64,
+        id: u8,
+    },
+    // Another player's presence state, see presence.rs.
+    Presence {
+        client_id: u64,
+        state: u8,
+    },
+    // Another player's rich presence, CBOR, see presence.rs.
+    RichPresence {
+        client_id: u64,
+        state: Bytes,
+    },
+    // A change to a replicated store we watch, see replica.rs. The store name is encoded like a topic.
+    ReplicaUpdate {
+        store: String,
+        op: u8,
+        body: Bytes,
+    },
+    // The answer to `ClientMessage::SaveDownload`. The metadata is CBOR with a u16 length, both are empty if
+    // the server has no save in the slot. See save_sync.rs.
+    SaveData {
+        slot: String,
+        meta: Bytes,
+        blob: Bytes,
+    },
+    // The answer to `ClientMessage::SaveUpload`, with the metadata of what the server has now.
+    SaveUploadResult {
+        slot: String,
+        accepted: bool,
+        meta: Bytes,
+    },
+    // A notification for the player, see notifications.rs. `ttl` is in seconds, 0 if it doesn't expire. The
+    // category is encoded like a topic.
+    Notification {
+        id: u64,
+        ttl: u32,
+        category: String,
+        payload: Bytes,
+    },
+    // The answer to `ClientMessage::Ping`: its id and time, and when the ping arrived and the answer left
+    // by the server's clock. Times are unix time in microseconds, see clock_sync.rs.
+    Pong {
+        ping_id: u32,
+        sent: u64,
+        arrived: u64,
+        answered: u64,
+    },
+    // The seed for the match's procedural content, so ours matches the server's. Sent when the match starts,
+    // and again if a new one starts in the same session.
+    MatchSeed {
+        seed: u64,
+    },
+    // Settings the server wants us to use, a CBOR map. See `apply_server_config` in lib.rs for the keys.
+    ServerConfig(Bytes),
+}
+
+impl ServerMessage {
+    /// The server tick the message is stamped with, for kinds that have one.
+    pub fn tick(&self) -> Option<u32> {
+        return match self {
+            ServerMessage::Snapshot { tick, .. }
+            | ServerMessage::FullSnapshot { tick, .. }
+            | ServerMessage::Checksum { tick, .. } => Some(*tick),
+            _ => None,
+        };
+    }
+
+    /// Decodes a message, or every message inside a batch. Returns false if anything was malformed,
+    /// in which case the messages before the malformed one have still been passed on.
+    /// Payloads share `bytes`' buffer instead of being copied out of it.
+    pub fn decode_all(bytes: &Bytes, mut on_message: impl FnMut(ServerMessage)) -> bool {
+        if bytes.first() != Some(&MESSAGE_BATCH) {
+            return match ServerMessage::decode(bytes) {
+                Some(message) => {
+                    on_message(message);
+                    true
+                }
+                None => false,
+            };
+        }
+
+        let mut reader = Reader::new(&bytes[1..]);
+        while !reader.is_empty() {
+            let Some(message) = reader
+                .read_u16()
+                .and_then(|length| reader.read_bytes(length as usize))
+                .and_then(|message| ServerMessage::decode(&bytes.slice_ref(message)))
+            else {
+                return false;
+            };
+            on_message(message);
+        }
+
+        return true;
+    }
+
+    /// Returns `None` if the message kind is unknown or the body is too short for its kind.
+    pub fn decode(bytes: &Bytes) -> Option<ServerMessage> {
+        let (&kind, body) = bytes.split_first()?;
+        let mut reader = Reader::new(body);
+
+        let message = match kind {
+            MESSAGE_APPLICATION => {
+                ServerMessage::Application(bytes.slice_ref(reader.read_remaining()))
+            }
+            MESSAGE_SPAWN => ServerMessage::Spawn {
+                entity_id: reader.read_u64()?,
+                scene_index: reader.read_u16()?,
+                owner_id: reader.read_u64()?,
+            },
+            MESSAGE_DESPAWN => ServerMessage::Despawn {
+                entity_id: reader.read_u64()?,
+            },
+            MESSAGE_AUTHORITY => ServerMessage::Authority {
+                entity_id: reader.read_u64()?,
+                owner_id: reader.read_u64()?,
+            },
+            MESSAGE_RPC => ServerMessage::Rpc(RpcPacket {
+                transfer_mode: reader.read_u8()?,
+                channel: reader.read_u8()?,
+                peer_id: reader.read_i32()?,
+                sequence: reader.read_u16()?,
+                payload: bytes.slice_ref(reader.read_remaining()),
+            }),
+            MESSAGE_SNAPSHOT => ServerMessage::Snapshot {
+                tick: reader.read_u32()?,
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_SERVER_INFO => ServerMessage::ServerInfo {
+                tick_rate: reader.read_u16()?,
+                tick: reader.read_u32()?,
+            },
+            MESSAGE_FULL_SNAPSHOT => ServerMessage::FullSnapshot {
+                tick: reader.read_u32()?,
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_FULL_SNAPSHOT_TOO_LARGE => ServerMessage::FullSnapshotTooLarge {
+                size: reader.read_u32()?,
+            },
+            MESSAGE_SESSION_TAKEN_OVER => ServerMessage::SessionTakenOver,
+            MESSAGE_RESPONSE => ServerMessage::Response {
+                request_id: reader.read_u32()?,
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_TOPIC => ServerMessage::Topic {
+                topic: reader.read_topic()?.to_owned(),
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_FORMAT_SELECTED => ServerMessage::FormatSelected {
+                format: reader.read_u8()?,
+                compression: reader.read_u8()?,
+            },
+            MESSAGE_OUTBOX_ACK => ServerMessage::OutboxAck {
+                id: reader.read_u64()?,
+            },
+            MESSAGE_IDEMPOTENT => ServerMessage::Idempotent {
+                key: reader.read_u64()?,
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_CHECKSUM => ServerMessage::Checksum {
+                tick: reader.read_u32()?,
+                checksum: reader.read_u64()?,
+            },
+            MESSAGE_VOICE => ServerMessage::Voice {
+                speaker: reader.read_u64()?,
+                entity_id: reader.read_u64()?,
+                position: [reader.read_f32()?, reader.read_f32()?, reader.read_f32()?],
+                frames: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_CHAT => ServerMessage::Chat {
+                sender: reader.read_u64()?,
+                text: std::str::from_utf8(reader.read_remaining())
+                    .ok()?
+                    .to_owned(),
+            },
+            MESSAGE_QUICK_CHAT => ServerMessage::QuickChat {
+                sender: reader.read_u64()?,
+                id: reader.read_u8()?,
+            },
+            MESSAGE_PRESENCE => ServerMessage::Presence {
+                client_id: reader.read_u64()?,
+                state: reader.read_u8()?,
+            },
+            MESSAGE_RICH_PRESENCE => ServerMessage::RichPresence {
+                client_id: reader.read_u64()?,
+                state: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_REPLICA_UPDATE => ServerMessage::ReplicaUpdate {
+                store: reader.read_topic()?.to_owned(),
+                op: reader.read_u8()?,
+                body: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_SAVE_DATA => ServerMessage::SaveData {
+                slot: reader.read_topic()?.to_owned(),
+                meta: reader
+                    .read_u16()
+                    .and_then(|length| reader.read_bytes(length as usize))
+                    .map(|meta| bytes.slice_ref(meta))?,
+                blob: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_SAVE_UPLOAD_RESULT => ServerMessage::SaveUploadResult {
+                slot: reader.read_topic()?.to_owned(),
+                accepted: reader.read_u8()? != 0,
+                meta: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_NOTIFICATION => ServerMessage::Notification {
+                id: reader.read_u64()?,
+                ttl: reader.read_u32()?,
+                category: reader.read_topic()?.to_owned(),
+                payload: bytes.slice_ref(reader.read_remaining()),
+            },
+            MESSAGE_PONG => ServerMessage::Pong {
+                ping_id: reader.read_u32()?,
+                sent: reader.read_u64()?,
+                arrived: reader.read_u64()?,
+                answered: reader.read_u64()?,
+            },
+            MESSAGE_MATCH_SEED => ServerMessage::MatchSeed {
+                seed: reader.read_u64()?,
+            },
+            MESSAGE_SERVER_CONFIG => {
+                ServerMessage::ServerConfig(bytes.slice_ref(reader.read_remaining()))
+            }
+            _ => return None,
+        };
+
+        return Some(message);
+    }
+
+    /// The server side of `decode`, for servers running inside the game, see local_server.rs.
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        match self {
+            ServerMessage::Application(payload) => {
+                buffer.extend_from_slice(&[MESSAGE_APPLICATION]);
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::Spawn {
+                entity_id,
+                scene_index,
+                owner_id,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_SPAWN]);
+                buffer.extend_from_slice(&entity_id.to_le_bytes());
+                buffer.extend_from_slice(&scene_index.to_le_bytes());
+                buffer.extend_from_slice(&owner_id.to_le_bytes());
+            }
+            ServerMessage::Despawn { entity_id } => {
+                buffer.extend_from_slice(&[MESSAGE_DESPAWN]);
+                buffer.extend_from_slice(&entity_id.to_le_bytes());
+            }
+            ServerMessage::Authority {
+                entity_id,
+                owner_id,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_AUTHORITY]);
+                buffer.extend_from_slice(&entity_id.to_le_bytes());
+                buffer.extend_from_slice(&owner_id.to_le_bytes());
+            }
+            ServerMessage::Rpc(packet) => packet.encode(buffer),
+            ServerMessage::Snapshot { tick, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_SNAPSHOT]);
+                buffer.extend_from_slice(&tick.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::ServerInfo { tick_rate, tick } => {
+                buffer.extend_from_slice(&[MESSAGE_SERVER_INFO]);
+                buffer.extend_from_slice(&tick_rate.to_le_bytes());
+                buffer.extend_from_slice(&tick.to_le_bytes());
+            }
+            ServerMessage::FullSnapshot { tick, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_FULL_SNAPSHOT]);
+                buffer.extend_from_slice(&tick.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::FullSnapshotTooLarge { size } => {
+                buffer.extend_from_slice(&[MESSAGE_FULL_SNAPSHOT_TOO_LARGE]);
+                buffer.extend_from_slice(&size.to_le_bytes());
+            }
+            ServerMessage::SessionTakenOver => {
+                buffer.extend_from_slice(&[MESSAGE_SESSION_TAKEN_OVER]);
+            }
+            ServerMessage::Response {
+                request_id,
+                payload,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_RESPONSE]);
+                buffer.extend_from_slice(&request_id.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::Topic { topic, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_TOPIC]);
+                encode_topic(topic, buffer);
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::FormatSelected {
+                format,
+                compression,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_FORMAT_SELECTED, *format, *compression]);
+            }
+            ServerMessage::OutboxAck { id } => {
+                buffer.extend_from_slice(&[MESSAGE_OUTBOX_ACK]);
+                buffer.extend_from_slice(&id.to_le_bytes());
+            }
+            ServerMessage::Idempotent { key, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_IDEMPOTENT]);
+                buffer.extend_from_slice(&key.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::Checksum { tick, checksum } => {
+                buffer.extend_from_slice(&[MESSAGE_CHECKSUM]);
+                buffer.extend_from_slice(&tick.to_le_bytes());
+                buffer.extend_from_slice(&checksum.to_le_bytes());
+            }
+            ServerMessage::Voice {
+                speaker,
+                entity_id,
+                position,
+                frames,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_VOICE]);
+                buffer.extend_from_slice(&speaker.to_le_bytes());
+                buffer.extend_from_slice(&entity_id.to_le_bytes());
+                for component in position {
+                    buffer.extend_from_slice(&component.to_le_bytes());
+                }
+                buffer.extend_from_slice(frames);
+            }
+            ServerMessage::Chat { sender, text } => {
+                buffer.extend_from_slice(&[MESSAGE_CHAT]);
+                buffer.extend_from_slice(&sender.to_le_bytes());
+                buffer.extend_from_slice(text.as_bytes());
+            }
+            ServerMessage::QuickChat { sender, id } => {
+                buffer.extend_from_slice(&[MESSAGE_QUICK_CHAT]);
+                buffer.extend_from_slice(&sender.to_le_bytes());
+                buffer.extend_from_slice(&[*id]);
+            }
+            ServerMessage::Presence { client_id, state } => {
+                buffer.extend_from_slice(&[MESSAGE_PRESENCE]);
+                buffer.extend_from_slice(&client_id.to_le_bytes());
+                buffer.extend_from_slice(&[*state]);
+            }
+            ServerMessage::RichPresence { client_id, state } => {
+                buffer.extend_from_slice(&[MESSAGE_RICH_PRESENCE]);
+                buffer.extend_from_slice(&client_id.to_le_bytes());
+                buffer.extend_from_slice(state);
+            }
+            ServerMessage::ReplicaUpdate { store, op, body } => {
+                buffer.extend_from_slice(&[MESSAGE_REPLICA_UPDATE]);
+                encode_topic(store, buffer);
+                buffer.extend_from_slice(&[*op]);
+                buffer.extend_from_slice(body);
+            }
+            // Callers make sure the metadata fits in a u16.
+            ServerMessage::SaveData { slot, meta, blob } => {
+                buffer.extend_from_slice(&[MESSAGE_SAVE_DATA]);
+                encode_topic(slot, buffer);
+                buffer.extend_from_slice(&(meta.len() as u16).to_le_bytes());
+                buffer.extend_from_slice(meta);
+                buffer.extend_from_slice(blob);
+            }
+            ServerMessage::SaveUploadResult {
+                slot,
+                accepted,
+                meta,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_SAVE_UPLOAD_RESULT]);
+                encode_topic(slot, buffer);
+                buffer.extend_from_slice(&[*accepted as u8]);
+                buffer.extend_from_slice(meta);
+            }
+            ServerMessage::Notification {
+                id,
+                ttl,
+                category,
+                payload,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_NOTIFICATION]);
+                buffer.extend_from_slice(&id.to_le_bytes());
+                buffer.extend_from_slice(&ttl.to_le_bytes());
+                encode_topic(category, buffer);
+                buffer.extend_from_slice(payload);
+            }
+            ServerMessage::Pong {
+                ping_id,
+                sent,
+                arrived,
+                answered,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_PONG]);
+                buffer.extend_from_slice(&ping_id.to_le_bytes());
+                buffer.extend_from_slice(&sent.to_le_bytes());
+                buffer.extend_from_slice(&arrived.to_le_bytes());
+                buffer.extend_from_slice(&answered.to_le_bytes());
+            }
+            ServerMessage::MatchSeed { seed } => {
+                buffer.extend_from_slice(&[MESSAGE_MATCH_SEED]);
+                buffer.extend_from_slice(&seed.to_le_bytes());
+            }
+            ServerMessage::ServerConfig(config) => {
+                buffer.extend_from_slice(&[MESSAGE_SERVER_CONFIG]);
+                buffer.extend_from_slice(config);
+            }
+        }
+    }
+}
+
+// Messages we send to the server. RPC packets have their own encoding, see `RpcPacket`.
+// Encoding appends to a buffer the caller owns, so the send path can reuse one allocation for every message.
+pub enum ClientMessage<'a> {
+    // Game specific data from GDScript.
+    Application(&'a [u8]),
+    // Ask the server for a `ServerMessage::FullSnapshot` with a payload of at most `max_size` bytes. A bigger
+    // one would overrun the reliable channel's memory budget, and renet drops the connection for that.
+    RequestFullSnapshot {
+        max_size: u32,
+    },
+    // Tell the server to drop any other connection with our client id and keep this one.
+    ReclaimSession,
+    // A query the server answers with a `ServerMessage::Response` carrying the same id. The type says
+    // what is being asked for and is game specific, like the payload.
+    Request {
+        request_id: u32,
+        request_type: u16,
+        payload: &'a [u8],
+    },
+    // Start or stop getting `ServerMessage::Topic` messages for a topic.
+    Subscribe(&'a str),
+    Unsubscribe(&'a str),
+    // The payload formats and compressions we can use, most preferred first. Each list is a u8 count
+    // followed by one byte per entry, see negotiation.rs.
+    Capabilities {
+        formats: &'a [u8],
+        compressions: &'a [u8],
+    },
+    // Game specific data that has to arrive exactly once, see outbox.rs. It may be sent more than once, and
+    // the server acknowledges the id every time but only handles it once.
+    Outbox {
+        id: u64,
+        payload: &'a [u8],
+    },
+    // Game specific data with a key the server uses to ignore copies, see dedup.rs.
+    Idempotent {
+        key: u64,
+        payload: &'a [u8],
+    },
+    // Encoded voice frames, and where the speaker is: the entity they speak from, 0 for none, and a position
+    // in the game's world. The frames are game specific.
+    Voice {
+        entity_id: u64,
+        position: [f32; 3],
+        frames: &'a [u8],
+    },
+    // A chat message for the server to relay, UTF-8.
+    Chat(&'a str),
+    // Tell the server we muted or unmuted a player, so it can stop relaying their voice and chat to us.
+    Mute {
+        client_id: u64,
+        muted: bool,
+    },
+    // A quick chat line or emote by its index in the game's list. Two bytes on the wire.
+    QuickChat(u8),
+    // Our presence state, see presence.rs.
+    Presence(u8),
+    // Whether the player stopped touching their input, so the server can apply its idle kick policy.
+    Afk(bool),
+    // Game specific input or command, stamped with what we were seeing when it was made, so the server can
+    // rewind to it for hit registration: the server tick we were rendering, with the fraction of a tick in
+    // 1/65536ths, and the newest snapshot tick we had. Ticks are `NO_TICK` before we know any.
+    Command {
+        render_tick: u32,
+        render_fraction: u16,
+        snapshot_tick: u32,
+        payload: &'a [u8],
+    },
+    // How the client is running, already encoded, see performance.rs.
+    Performance(&'a [u8]),
+    // Our rich presence, CBOR, see presence.rs.
+    RichPresence(&'a [u8]),
+    // Start or stop getting `ServerMessage::ReplicaUpdate` for a store. The server answers a subscribe with
+    // the whole store.
+    ReplicaSubscribe(&'a str),
+    ReplicaUnsubscribe(&'a str),
+    // Asks the server to set a key of a replicated store, which answers with the write id. The store and key
+    // are encoded like topics, the value is CBOR.
+    ReplicaWrite {
+        store: &'a str,
+        write_id: u32,
+        key: &'a str,
+        value: &'a [u8],
+    },
+    // Ask for the save in a slot, see save_sync.rs. Slots are encoded like topics.
+    SaveDownload(&'a str),
+    // A save for the server to keep, if it has seen everything the server's has. The metadata is CBOR with a
+    // u16 length, the blob is game specific.
+    SaveUpload {
+        slot: &'a str,
+        meta: &'a [u8],
+        blob: &'a [u8],
+    },
+    // Asks the server for a `ServerMessage::Pong`, `sent` is our unix time in microseconds.
+    Ping {
+        ping_id: u32,
+        sent: u64,
+    },
+}
+
+pub const NO_TICK: u32 = u32::MAX;
+
+impl ClientMessage<'_> {
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        match self {
+            ClientMessage::Application(payload) => {
+                buffer.extend_from_slice(&[MESSAGE_APPLICATION]);
+                buffer.extend_from_slice(payload);
+            }
+            ClientMessage::RequestFullSnapshot { max_size } => {
+                buffer.extend_from_slice(&[MESSAGE_REQUEST_FULL_SNAPSHOT]);
+                buffer.extend_from_slice(&max_size.to_le_bytes());
+            }
+            ClientMessage::ReclaimSession => {
+                buffer.extend_from_slice(&[MESSAGE_RECLAIM_SESSION]);
+            }
+            ClientMessage::Request {
+                request_id,
+                request_type,
+                payload,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_REQUEST]);
+                buffer.extend_from_slice(&request_id.to_le_bytes());
+                buffer.extend_from_slice(&request_type.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ClientMessage::Subscribe(topic) => {
+                buffer.extend_from_slice(&[MESSAGE_SUBSCRIBE]);
+                encode_topic(topic, buffer);
+            }
+            ClientMessage::Unsubscribe(topic) => {
+                buffer.extend_from_slice(&[MESSAGE_UNSUBSCRIBE]);
+                encode_topic(topic, buffer);
+            }
+            ClientMessage::Capabilities {
+                formats,
+                compressions,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_CAPABILITIES, formats.len() as u8]);
+                buffer.extend_from_slice(formats);
+                buffer.extend_from_slice(&[compressions.len() as u8]);
+                buffer.extend_from_slice(compressions);
+            }
+            ClientMessage::Outbox { id, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_OUTBOX]);
+                buffer.extend_from_slice(&id.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ClientMessage::Idempotent { key, payload } => {
+                buffer.extend_from_slice(&[MESSAGE_IDEMPOTENT]);
+                buffer.extend_from_slice(&key.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ClientMessage::Voice {
+                entity_id,
+                position,
+                frames,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_VOICE]);
+                buffer.extend_from_slice(&entity_id.to_le_bytes());
+                for component in position {
+                    buffer.extend_from_slice(&component.to_le_bytes());
+                }
+                buffer.extend_from_slice(frames);
+            }
+            ClientMessage::Chat(text) => {
+                buffer.extend_from_slice(&[MESSAGE_CHAT]);
+                buffer.extend_from_slice(text.as_bytes());
+            }
+            ClientMessage::Mute { client_id, muted } => {
+                buffer.extend_from_slice(&[MESSAGE_MUTE]);
+                buffer.extend_from_slice(&client_id.to_le_bytes());
+                buffer.extend_from_slice(&[*muted as u8]);
+            }
+            ClientMessage::QuickChat(id) => {
+                buffer.extend_from_slice(&[MESSAGE_QUICK_CHAT, *id]);
+            }
+            ClientMessage::Presence(state) => {
+                buffer.extend_from_slice(&[MESSAGE_PRESENCE, *state]);
+            }
+            ClientMessage::Afk(afk) => {
+                buffer.extend_from_slice(&[MESSAGE_AFK, *afk as u8]);
+            }
+            ClientMessage::Command {
+                render_tick,
+                render_fraction,
+                snapshot_tick,
+                payload,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_COMMAND]);
+                buffer.extend_from_slice(&render_tick.to_le_bytes());
+                buffer.extend_from_slice(&render_fraction.to_le_bytes());
+                buffer.extend_from_slice(&snapshot_tick.to_le_bytes());
+                buffer.extend_from_slice(payload);
+            }
+            ClientMessage::Performance(report) => {
+                buffer.extend_from_slice(&[MESSAGE_PERFORMANCE]);
+                buffer.extend_from_slice(report);
+            }
+            ClientMessage::RichPresence(state) => {
+                buffer.extend_from_slice(&[MESSAGE_RICH_PRESENCE]);
+                buffer.extend_from_slice(state);
+            }
+            ClientMessage::ReplicaSubscribe(store) => {
+                buffer.extend_from_slice(&[MESSAGE_REPLICA_SUBSCRIBE]);
+                encode_topic(store, buffer);
+            }
+            ClientMessage::ReplicaUnsubscribe(store) => {
+                buffer.extend_from_slice(&[MESSAGE_REPLICA_UNSUBSCRIBE]);
+                encode_topic(store, buffer);
+            }
+            ClientMessage::ReplicaWrite {
+                store,
+                write_id,
+                key,
+                value,
+            } => {
+                buffer.extend_from_slice(&[MESSAGE_REPLICA_WRITE]);
+                encode_topic(store, buffer);
+                buffer.extend_from_slice(&write_id.to_le_bytes());
+                encode_topic(key, buffer);
+                buffer.extend_from_slice(value);
+            }
+            ClientMessage::SaveDownload(slot) => {
+                buffer.extend_from_slice(&[MESSAGE_SAVE_DOWNLOAD]);
+                encode_topic(slot, buffer);
+            }
+            // Callers make sure the metadata fits in a u16.
+            ClientMessage::SaveUpload { slot, meta, blob } => {
+                buffer.extend_from_slice(&[MESSAGE_SAVE_UPLOAD]);
+                encode_topic(slot, buffer);
+                buffer.extend_from_slice(&(meta.len() as u16).to_le_bytes());
+                buffer.extend_from_slice(meta);
+                buffer.extend_from_slice(blob);
+            }
+            ClientMessage::Ping { ping_id, sent } => {
+                buffer.extend_from_slice(&[MESSAGE_PING]);
+                buffer.extend_from_slice(&ping_id.to_le_bytes());
+                buffer.extend_from_slice(&sent.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl<'a> ClientMessage<'a> {
+    /// The server side of `encode`, for servers running inside the game, see local_server.rs. Returns
+    /// `None` like `ServerMessage::decode`, also for RPC packets, which aren't a `ClientMessage`.
+    pub fn decode(bytes: &'a [u8]) -> Option<ClientMessage<'a>> {
+        let (&kind, body) = bytes.split_first()?;
+        let mut reader = Reader::new(body);
+
+        let message = match kind {
+            MESSAGE_APPLICATION => ClientMessage::Application(reader.read_remaining()),
+            MESSAGE_REQUEST_FULL_SNAPSHOT => ClientMessage::RequestFullSnapshot {
+                max_size: reader.read_u32()?,
+            },
+            MESSAGE_RECLAIM_SESSION => ClientMessage::ReclaimSession,
+            MESSAGE_REQUEST => ClientMessage::Request {
+                request_id: reader.read_u32()?,
+                request_type: reader.read_u16()?,
+                payload: reader.read_remaining(),
+            },
+            MESSAGE_SUBSCRIBE => ClientMessage::Subscribe(reader.read_topic()?),
+            MESSAGE_UNSUBSCRIBE => ClientMessage::Unsubscribe(reader.read_topic()?),
+            MESSAGE_CAPABILITIES => {
+                let count = reader.read_u8()?;
+                let formats = reader.read_bytes(count as usize)?;
+                let count = reader.read_u8()?;
+                ClientMessage::Capabilities {
+                    formats,
+                    compressions: reader.read_bytes(count as usize)?,
+                }
+            }
+            MESSAGE_OUTBOX => ClientMessage::Outbox {
+                id: reader.read_u64()?,
+                payload: reader.read_remaining(),
+            },
+            MESSAGE_IDEMPOTENT => ClientMessage::Idempotent {
+                key: reader.read_u64()?,
+                payload: reader.read_remaining(),
+            },
+            MESSAGE_VOICE => ClientMessage::Voice {
+                entity_id: reader.read_u64()?,
+                position: [reader.read_f32()?, reader.read_f32()?, reader.read_f32()?],
+                frames: reader.read_remaining(),
+            },
+            MESSAGE_CHAT => ClientMessage::Chat(std::str::from_utf8(reader.read_remaining()).ok()?),
+            MESSAGE_MUTE => ClientMessage::Mute {
+                client_id: reader.read_u64()?,
+                muted: reader.read_u8()? != 0,
+            },
+            MESSAGE_QUICK_CHAT => ClientMessage::QuickChat(reader.read_u8()?),
+            MESSAGE_PRESENCE => ClientMessage::Presence(reader.read_u8()?),
+            MESSAGE_AFK => ClientMessage::Afk(reader.read_u8()? != 0),
+            MESSAGE_COMMAND => ClientMessage::Command {
+                render_tick: reader.read_u32()?,
+                render_fraction: reader.read_u16()?,
+                snapshot_tick: reader.read_u32()?,
+                payload: reader.read_remaining(),
+            },
+            MESSAGE_PERFORMANCE => ClientMessage::Performance(reader.read_remaining()),
+            MESSAGE_RICH_PRESENCE => ClientMessage::RichPresence(reader.read_remaining()),
+            MESSAGE_REPLICA_SUBSCRIBE => ClientMessage::ReplicaSubscribe(reader.read_topic()?),
+            MESSAGE_REPLICA_UNSUBSCRIBE => ClientMessage::ReplicaUnsubscribe(reader.read_topic()?),
+            MESSAGE_REPLICA_WRITE => ClientMessage::ReplicaWrite {
+                store: reader.read_topic()?,
+                write_id: reader.read_u32()?,
+                key: reader.read_topic()?,
+                value: reader.read_remaining(),
+            },
+            MESSAGE_SAVE_DOWNLOAD => ClientMessage::SaveDownload(reader.read_topic()?),
+            MESSAGE_SAVE_UPLOAD => ClientMessage::SaveUpload {
+                slot: reader.read_topic()?,
+                meta: reader
+                    .read_u16()
+                    .and_then(|length| reader.read_bytes(length as usize))?,
+                blob: reader.read_remaining(),
+            },
+            MESSAGE_PING => ClientMessage::Ping {
+                ping_id: reader.read_u32()?,
+                sent: reader.read_u64()?,
+            },
+            _ => return None,
+        };
+
+        return Some(message);
+    }
+}
+
+// The messages in a message or batch, for servers running inside the game. Returns `None` if the batch is
+// malformed.
+pub fn split_batch(bytes: &[u8]) -> Option<Vec<&[u8]>> {
+    if bytes.first() != Some(&MESSAGE_BATCH) {
+        return Some(vec![bytes]);
+    }
+
+    let mut reader = Reader::new(&bytes[1..]);
+    let mut messages = Vec::new();
+    while !reader.is_empty() {
+        let length = reader.read_u16()?;
+        messages.push(reader.read_bytes(length as usize)?);
+    }
+    return Some(messages);
+}
+
+impl RpcPacket {
+    /// The server side of the `MESSAGE_RPC` encoding, for servers running inside the game.
+    pub fn decode(bytes: &Bytes) -> Option<RpcPacket> {
+        return match ServerMessage::decode(bytes)? {
+            ServerMessage::Rpc(packet) => Some(packet),
+            _ => None,
+        };
+    }
+}
+
+// Callers make sure the topic is at most `MAX_TOPIC_LENGTH` bytes.
+fn encode_topic(topic: &str, buffer: &mut BytesMut) {
+    buffer.extend_from_slice(&[topic.len() as u8]);
+    buffer.extend_from_slice(topic.as_bytes());
+}
+
+// Several small messages sent as one, each prefixed with its length. Saves renet's per-message overhead
+// when a lot of small messages are sent in the same tick.
+pub fn encode_batch(messages: &[Bytes], buffer: &mut BytesMut) {
+    buffer.extend_from_slice(&[MESSAGE_BATCH]);
+    for message in messages {
+        buffer.extend_from_slice(&(message.len() as u16).to_le_bytes());
+        buffer.extend_from_slice(message);
+    }
+}
+
+// SceneMultiplayer packets are wrapped in this so the server can relay them between peers.
+// The transfer mode and channel are the ones from the `@rpc` annotation.
+pub struct RpcPacket {
+    pub transfer_mode: u8,
+    pub channel: u8,
+    // The sender for packets we receive, the target for packets we send.
+    pub peer_id: i32,
+    // Only used by unreliable ordered packets, to drop ones that arrive after a newer packet.
+    pub sequence: u16,
+    pub payload: Bytes,
+}
+
+impl RpcPacket {
+    pub fn encode(&self, buffer: &mut BytesMut) {
+        buffer.extend_from_slice(&[MESSAGE_RPC, self.transfer_mode, self.channel]);
+        buffer.extend_from_slice(&self.peer_id.to_le_bytes());
+        buffer.extend_from_slice(&self.sequence.to_le_bytes());
+        buffer.extend_from_slice(&self.payload);
+    }
+}
+
+// Small cursor over a message body. Every read returns `None` once the body runs out, so a truncated
+// message is rejected instead of panicking.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn read_u8(&mut self) -> Option<u8> {
+        let [value] = self.take()?;
+        return Some(value);
+    }
+
+    pub fn peek_u8(&self) -> Option<u8> {
+        return self.bytes.first().copied();
+    }
+
+    pub fn read_u16(&mut self) -> Option<u16> {
+        return self.take().map(u16::from_le_bytes);
+    }
+
+    pub fn read_u32(&mut self) -> Option<u32> {
+        return self.take().map(u32::from_le_bytes);
+    }
+
+    pub fn read_i32(&mut self) -> Option<i32> {
+        return self.take().map(i32::from_le_bytes);
+    }
+
+    pub fn read_u64(&mut self) -> Option<u64> {
+        return self.take().map(u64::from_le_bytes);
+    }
+
+    pub fn read_f32(&mut self) -> Option<f32> {
+        return self.take().map(f32::from_le_bytes);
+    }
+
+    pub fn read_bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < length {
+            return None;
+        }
+
+        let (head, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        return Some(head);
+    }
+
+    pub fn read_topic(&mut self) -> Option<&'a str> {
+        let length = self.read_u8()?;
+        return std::str::from_utf8(self.read_bytes(length as usize)?).ok();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.bytes.is_empty();
+    }
+
+    // How many bytes haven't been read yet.
+    pub fn len(&self) -> usize {
+        return self.bytes.len();
+    }
+
+    // Everything that hasn't been read yet. Used for payloads that run to the end of the message.
+    pub fn read_remaining(&mut self) -> &'a [u8] {
+        return std::mem::take(&mut self.bytes);
+    }
+
+    fn take<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.bytes.len() < N {
+            return None;
+        }
+
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        return head.try_into().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One message of every kind. Whatever runs to the end of the message is `payload`, or `text` for chat, so
+    // with both empty every byte of the encoding is needed to decode it.
+    fn server_messages(payload: &[u8], text: &str) -> Vec<ServerMessage> {
+        let payload = Bytes::copy_from_slice(payload);
+        return vec![
+            ServerMessage::Application(payload.clone()),
+            ServerMessage::Spawn {
+                entity_id: 7,
+                scene_index: 2,
+                owner_id: 9,
+            },
+            ServerMessage::Despawn { entity_id: 7 },
+            ServerMessage::Authority {
+                entity_id: 7,
+                owner_id: 9,
+            },
+            ServerMessage::Rpc(RpcPacket {
+                transfer_mode: 1,
+                channel: 2,
+                peer_id: -3,
+                sequence: 4,
+                payload: payload.clone(),
+            }),
+            ServerMessage::Snapshot {
+                tick: 100,
+                payload: payload.clone(),
+            },
+            ServerMessage::ServerInfo {
+                tick_rate: 60,
+                tick: 100,
+            },
+            ServerMessage::FullSnapshot {
+                tick: 100,
+                payload: payload.clone(),
+            },
+            ServerMessage::FullSnapshotTooLarge { size: 1 << 20 },
+            ServerMessage::SessionTakenOver,
+            ServerMessage::Response {
+                request_id: 5,
+                payload: payload.clone(),
+            },
+            ServerMessage::Topic {
+                topic: "lobby".to_owned(),
+                payload: payload.clone(),
+            },
+            ServerMessage::FormatSelected {
+                format: 1,
+                compression: 2,
+            },
+            ServerMessage::OutboxAck { id: u64::MAX },
+            ServerMessage::Idempotent {
+                key: 11,
+                payload: payload.clone(),
+            },
+            ServerMessage::Checksum {
+                tick: 100,
+                checksum: 0xDEAD_BEEF,
+            },
+            ServerMessage::Voice {
+                speaker: 3,
+                entity_id: 7,
+                position: [1.0, -2.5, 3.0],
+                frames: payload.clone(),
+            },
+            ServerMessage::Chat {
+                sender: 3,
+                text: text.to_owned(),
+            },
+            ServerMessage::QuickChat { sender: 3, id: 4 },
+            ServerMessage::Presence {
+                client_id: 3,
+                state: 1,
+            },
+            ServerMessage::RichPresence {
+                client_id: 3,
+                state: payload.clone(),
+            },
+            ServerMessage::ReplicaUpdate {
+                store: "scores".to_owned(),
+                op: 2,
+                body: payload.clone(),
+            },
+            ServerMessage::SaveData {
+                slot: "main".to_owned(),
+                meta: Bytes::from_static(b"meta"),
+                blob: payload.clone(),
+            },
+            ServerMessage::SaveUploadResult {
+                slot: "main".to_owned(),
+                accepted: true,
+                meta: payload.clone(),
+            },
+            ServerMessage::Notification {
+                id: 8,
+                ttl: 60,
+                category: "friends".to_owned(),
+                payload: payload.clone(),
+            },
+            ServerMessage::Pong {
+                ping_id: 1,
+                sent: 2,
+                arrived: 3,
+                answered: 4,
+            },
+            ServerMessage::MatchSeed { seed: 42 },
+            ServerMessage::ServerConfig(payload),
+        ];
+    }
+
+    fn client_messages<'a>(payload: &'a [u8], text: &'a str) -> Vec<ClientMessage<'a>> {
+        return vec![
+            ClientMessage::Application(payload),
+            ClientMessage::RequestFullSnapshot { max_size: 4096 },
+            ClientMessage::ReclaimSession,
+            ClientMessage::Request {
+                request_id: 5,
+                request_type: 6,
+                payload,
+            },
+            ClientMessage::Subscribe("lobby"),
+            ClientMessage::Unsubscribe("lobby"),
+            ClientMessage::Capabilities {
+                formats: &[0, 1],
+                compressions: &[0],
+            },
+            ClientMessage::Outbox { id: 12, payload },
+            ClientMessage::Idempotent { key: 11, payload },
+            ClientMessage::Voice {
+                entity_id: 7,
+                position: [1.0, -2.5, 3.0],
+                frames: payload,
+            },
+            ClientMessage::Chat(text),
+            ClientMessage::Mute {
+                client_id: 3,
+                muted: true,
+            },
+            ClientMessage::QuickChat(4),
+            ClientMessage::Presence(1),
+            ClientMessage::Afk(true),
+            ClientMessage::Command {
+                render_tick: 100,
+                render_fraction: 32768,
+                snapshot_tick: NO_TICK,
+                payload,
+            },
+            ClientMessage::Performance(payload),
+            ClientMessage::RichPresence(payload),
+            ClientMessage::ReplicaSubscribe("scores"),
+            ClientMessage::ReplicaUnsubscribe("scores"),
+            ClientMessage::ReplicaWrite {
+                store: "scores",
+                write_id: 3,
+                key: "alice",
+                value: payload,
+            },
+            ClientMessage::SaveDownload("main"),
+            ClientMessage::SaveUpload {
+                slot: "main",
+                meta: b"meta",
+                blob: payload,
+            },
+            ClientMessage::Ping {
+                ping_id: 1,
+                sent: 2,
+            },
+        ];
+    }
+
+    fn encode_server(message: &ServerMessage) -> Bytes {
+        let mut buffer = BytesMut::new();
+        message.encode(&mut buffer);
+        return buffer.freeze();
+    }
+
+    fn encode_client(message: &ClientMessage) -> Vec<u8> {
+        let mut buffer = BytesMut::new();
+        message.encode(&mut buffer);
+        return buffer.to_vec();
+    }
+
+    #[test]
+    fn server_messages_round_trip() {
+        for message in server_messages(b"payload", "hello") {
+            let bytes = encode_server(&message);
+            let decoded = ServerMessage::decode(&bytes).expect("every kind decodes");
+            assert_eq!(encode_server(&decoded), bytes, "kind {}", bytes[0]);
+        }
+    }
+
+    #[test]
+    fn client_messages_round_trip() {
+        for message in client_messages(b"payload", "hello") {
+            let bytes = encode_client(&message);
+            let decoded = ClientMessage::decode(&bytes).expect("every kind decodes");
+            assert_eq!(encode_client(&decoded), bytes, "kind {}", bytes[0]);
+        }
+    }
+
+    #[test]
+    fn decoded_fields_match() {
+        let bytes = encode_server(&ServerMessage::SaveData {
+            slot: "main".to_owned(),
+            meta: Bytes::from_static(b"meta"),
+            blob: Bytes::from_static(b"blob"),
+        });
+        let Some(ServerMessage::SaveData { slot, meta, blob }) = ServerMessage::decode(&bytes)
+        else {
+            panic!("not decoded as SaveData");
+        };
+        assert_eq!(slot, "main");
+        assert_eq!(&meta[..], b"meta");
+        assert_eq!(&blob[..], b"blob");
+
+        let bytes = encode_client(&ClientMessage::Command {
+            render_tick: 100,
+            render_fraction: 32768,
+            snapshot_tick: NO_TICK,
+            payload: b"jump",
+        });
+        let Some(ClientMessage::Command {
+            render_tick,
+            render_fraction,
+            snapshot_tick,
+            payload,
+        }) = ClientMessage::decode(&bytes)
+        else {
+            panic!("not decoded as Command");
+        };
+        assert_eq!(
+            (render_tick, render_fraction, snapshot_tick),
+            (100, 32768, NO_TICK)
+        );
+        assert_eq!(payload, b"jump");
+    }
+
+    #[test]
+    fn truncated_messages_are_rejected() {
+        for message in server_messages(b"", "") {
+            let bytes = encode_server(&message);
+            for length in 0..bytes.len() {
+                let truncated = bytes.slice(..length);
+                assert!(
+                    ServerMessage::decode(&truncated).is_none(),
+                    "kind {} decoded from {length} of {} bytes",
+                    bytes[0],
+                    bytes.len()
+                );
+            }
+        }
+        for message in client_messages(b"", "") {
+            let bytes = encode_client(&message);
+            for length in 0..bytes.len() {
+                assert!(
+                    ClientMessage::decode(&bytes[..length]).is_none(),
+                    "kind {} decoded from {length} of {} bytes",
+                    bytes[0],
+                    bytes.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_kinds_are_rejected() {
+        assert!(ServerMessage::decode(&Bytes::from_static(&[200, 0, 0])).is_none());
+        assert!(ClientMessage::decode(&[200, 0, 0]).is_none());
+    }
+
+    #[test]
+    fn batches_split_into_their_messages() {
+        let messages: Vec<Bytes> = server_messages(b"payload", "hello")
+            .iter()
+            .map(encode_server)
+            .collect();
+        let mut buffer = BytesMut::new();
+        encode_batch(&messages, &mut buffer);
+        let batch = buffer.freeze();
+
+        assert_eq!(
+            split_batch(&batch),
+            Some(messages.iter().map(|message| &message[..]).collect())
+        );
+        let mut decoded = Vec::new();
+        assert!(ServerMessage::decode_all(&batch, |message| {
+            decoded.push(encode_server(&message));
+        }));
+        assert_eq!(decoded, messages);
+    }
+
+    #[test]
+    fn truncated_batches_are_rejected() {
+        let messages = vec![Bytes::from_static(&[
+            MESSAGE_DESPAWN,
+            1,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ])];
+        let mut buffer = BytesMut::new();
+        encode_batch(&messages, &mut buffer);
+        let batch = buffer.freeze();
+
+        // Cutting into the length or the message makes the batch malformed. Only the empty batch is whole.
+        for length in 2..batch.len() {
+            let truncated = batch.slice(..length);
+            assert_eq!(split_batch(&truncated), None, "{length} bytes");
+            assert!(
+                !ServerMessage::decode_all(&truncated, |_| {}),
+                "{length} bytes"
+            );
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use godot::{
+    engine::{file_access::ModeFlags, DirAccess, FileAccess, Json},
+    prelude::*,
+};
+
+// Start - Connection quality history
+// Every connected session keeps a running summary of its round trip time and packet loss. When it ends the
+// summary is added to a small file in user://, so settings and support screens can show how the player's
+// last matches went without a backend.
+
+const HISTORY_PATH: &str = "user://session_history.json";
+
+// Round trip times are counted in 1 ms buckets up to this, anything slower lands in the last one.
+const RTT_BUCKETS: usize = 1000;
+
+pub struct QualitySummary {
+    // Unix time in seconds.
+    started_at: f64,
+    duration: f64,
+    samples: u64,
+    rtt_total_ms: f64,
+    rtt_histogram: Box<[u32; RTT_BUCKETS]>,
+    packet_loss_total: f64,
+}
+
+impl QualitySummary {
+    pub fn new(started_at: f64) -> Self {
+        return Self {
+            started_at,
+            duration: 0.0,
+            samples: 0,
+            rtt_total_ms: 0.0,
+            rtt_histogram: Box::new([0; RTT_BUCKETS]),
+            packet_loss_total: 0.0,
+        };
+    }
+
+    /// Called every tick while connected. `rtt` is in seconds, `packet_loss` from 0 to 1.
+    pub fn sample(&mut self, delta: f64, rtt: f64, packet_loss: f64) {
+        let rtt_ms = rtt * 1000.0;
+        self.duration += delta;
+        self.samples += 1;
+        self.rtt_total_ms += rtt_ms;
+        self.rtt_histogram[(rtt_ms as usize).min(RTT_BUCKETS - 1)] += 1;
+        self.packet_loss_total += packet_loss;
+    }
+
+    pub fn has_samples(&self) -> bool {
+        return self.samples > 0;
+    }
+
+    fn percentile_rtt_ms(&self, percentile: f64) -> f64 {
+        let target = (self.samples as f64 * percentile).ceil() as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.rtt_histogram.iter().enumerate() {
+            seen += *count as u64;
+            if seen >= target {
+                return bucket as f64;
+            }
+        }
+        return (RTT_BUCKETS - 1) as f64;
+    }
+
+    pub fn to_dictionary(&self, disconnect_code: i64, disconnect_reason: &GString) -> Dictionary {
+        let samples = self.samples.max(1) as f64;
+        let mut dictionary = Dictionary::new();
+        dictionary.set("started_at", self.started_at);
+        dictionary.set("duration", self.duration);
+        dictionary.set("average_rtt_ms", self.rtt_total_ms / samples);
+        dictionary.set("p95_rtt_ms", self.percentile_rtt_ms(0.95));
+        dictionary.set("average_packet_loss", self.packet_loss_total / samples);
+        dictionary.set("disconnect_code", disconnect_code);
+        dictionary.set("disconnect_reason", disconnect_reason.clone());
+        return dictionary;
+    }
+}
+
+/// Newest first. Empty if nothing was recorded yet or the file can't be read.
+pub fn load_history() -> VariantArray {
+    if !FileAccess::file_exists(HISTORY_PATH.into()) {
+        return VariantArray::new();
+    }
+
+    let contents = FileAccess::get_file_as_string(HISTORY_PATH.into());
+    return Json::parse_string(contents)
+        .try_to::<VariantArray>()
+        .unwrap_or_default();
+}
+
+pub fn append_history(entry: Dictionary, limit: usize) {
+    let mut history = load_history();
+    history.insert(0, entry.to_variant());
+    while history.len() > limit {
+        history.pop();
+    }
+
+    let Some(mut file) = FileAccess::open(HISTORY_PATH.into(), ModeFlags::WRITE) else {
+        godot_warn!("Couldn't save the session history to {HISTORY_PATH}");
+        return;
+    };
+    file.store_string(Json::stringify(history.to_variant()));
+    file.close();
+}
+
+pub fn clear_history() {
+    if FileAccess::file_exists(HISTORY_PATH.into()) {
+        DirAccess::remove_absolute(HISTORY_PATH.into());
+    }
+}
+// End - Connection quality history
+
+// Start - Connection quality rating
+// Turns RTT, jitter and packet loss into 0 to 4 bars for a connection icon. Each measure is rated on its own
+// and the worst one wins, since any of them alone can ruin a match. The rating only changes once the new value
+// has held for a while, so the icon doesn't flicker on a single spike.
+
+// The most bars each threshold still allows. Values at or over the last threshold get 0 bars.
+const RTT_THRESHOLDS_MS: [f64; 4] = [60.0, 120.0, 200.0, 350.0];
+const JITTER_THRESHOLDS_MS: [f64; 4] = [10.0, 25.0, 50.0, 100.0];
+const PACKET_LOSS_THRESHOLDS: [f64; 4] = [0.01, 0.03, 0.07, 0.15];
+
+// How fast the smoothed RTT and jitter follow new samples.
+const SMOOTHING: f64 = 0.1;
+// How long a new rating has to hold. Getting worse shows up faster than getting better.
+const DOWNGRADE_HOLD: f64 = 1.0;
+const UPGRADE_HOLD: f64 = 3.0;
+
+pub const MAX_BARS: i64 = 4;
+
+pub struct QualityRating {
+    rating: i64,
+    smoothed_rtt_ms: Option<f64>,
+    jitter_ms: f64,
+    // The rating the measures point at, and for how long they have.
+    candidate: i64,
+    candidate_held: f64,
+}
+
+impl Default for QualityRating {
+    fn default() -> Self {
+        return Self {
+            rating: MAX_BARS,
+            smoothed_rtt_ms: None,
+            jitter_ms: 0.0,
+            candidate: MAX_BARS,
+            candidate_held: 0.0,
+        };
+    }
+}
+
+impl QualityRating {
+    pub fn rating(&self) -> i64 {
+        return self.rating;
+    }
+
+    /// Called every tick while connected. Returns the new rating when it changes.
+    pub fn update(&mut self, delta: f64, rtt: f64, packet_loss: f64) -> Option<i64> {
+        let rtt_ms = rtt * 1000.0;
+        let smoothed = self.smoothed_rtt_ms.unwrap_or(rtt_ms);
+        self.jitter_ms += ((rtt_ms - smoothed).abs() - self.jitter_ms) * SMOOTHING;
+        let smoothed = smoothed + (rtt_ms - smoothed) * SMOOTHING;
+        self.smoothed_rtt_ms = Some(smoothed);
+
+        let bars = bars(smoothed, &RTT_THRESHOLDS_MS)
+            .min(bars(self.jitter_ms, &JITTER_THRESHOLDS_MS))
+            .min(bars(packet_loss, &PACKET_LOSS_THRESHOLDS));
+
+        if bars != self.candidate {
+            self.candidate = bars;
+            self.candidate_held = 0.0;
+        }
+        self.candidate_held += delta;
+
+        let hold = if self.candidate < self.rating {
+            DOWNGRADE_HOLD
+        } else {
+            UPGRADE_HOLD
+        };
+        if self.candidate == self.rating || self.candidate_held < hold {
+            return None;
+        }
+
+        self.rating = self.candidate;
+        return Some(self.rating);
+    }
+}
+
+fn bars(value: f64, thresholds: &[f64; 4]) -> i64 {
+    let over = thresholds
+        .iter()
+        .filter(|threshold| value >= **threshold)
+        .count();
+    return MAX_BARS - over as i64;
+}
+// End - Connection quality rating
@@ -0,0 +1,178 @@
+// Start - Protects the client from a server that sends too much
+// A misbehaving or malicious server could send more messages than the game can handle in a frame. Each
+// channel gets a budget of messages and bytes per second, and whatever is over it is dropped before decoding.
+// Budgets are token buckets holding up to one second's worth, so short bursts are fine. Only the unreliable
+// channels are limited: a dropped reliable message is gone for good, since renet has already acknowledged it.
+
+#[derive(Default, Clone, Copy)]
+pub struct InboundLimit {
+    // 0 means unlimited.
+    pub messages_per_second: f64,
+    pub bytes_per_second: f64,
+}
+
+#[derive(Default)]
+pub struct InboundLimiter {
+    limit: InboundLimit,
+    messages: f64,
+    bytes: f64,
+    // Set from the first dropped message until the buckets fill up again.
+    flooding: bool,
+    flood_started: bool,
+}
+
+impl InboundLimiter {
+    pub fn new(limit: InboundLimit) -> Self {
+        return Self {
+            limit,
+            messages: limit.messages_per_second,
+            bytes: limit.bytes_per_second,
+            flooding: false,
+            flood_started: false,
+        };
+    }
+
+    pub fn set_limit(&mut self, limit: InboundLimit) {
+        *self = Self::new(limit);
+    }
+
+    /// Call once per tick before receiving.
+    pub fn refill(&mut self, delta: f64) {
+        let limit = self.limit;
+        self.messages =
+            (self.messages + limit.messages_per_second * delta).min(limit.messages_per_second);
+        self.bytes = (self.bytes + limit.bytes_per_second * delta).min(limit.bytes_per_second);
+
+        if self.messages >= limit.messages_per_second && self.bytes >= limit.bytes_per_second {
+            self.flooding = false;
+        }
+    }
+
+    /// Returns false if the message is over the budget and should be dropped.
+    pub fn allow(&mut self, size: usize) -> bool {
+        let limit = self.limit;
+        // A bucket only has to be positive, so a message larger than a whole second's budget can still get
+        // through. It just leaves the bucket in debt.
+        let messages_ok = limit.messages_per_second <= 0.0 || self.messages > 0.0;
+        let bytes_ok = limit.bytes_per_second <= 0.0 || self.bytes > 0.0;
+        if !messages_ok || !bytes_ok {
+            if !self.flooding {
+                self.flooding = true;
+                self.flood_started = true;
+            }
+            return false;
+        }
+
+        if limit.messages_per_second > 0.0 {
+            self.messages -= 1.0;
+        }
+        if limit.bytes_per_second > 0.0 {
+            self.bytes -= size as f64;
+        }
+        return true;
+    }
+
+    /// Returns true once when messages start being dropped.
+    pub fn take_flood_started(&mut self) -> bool {
+        return std::mem::take(&mut self.flood_started);
+    }
+}
+// End - Protects the client from a server that sends too much
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(messages_per_second: f64, bytes_per_second: f64) -> InboundLimiter {
+        return InboundLimiter::new(InboundLimit {
+            messages_per_second,
+            bytes_per_second,
+        });
+    }
+
+    #[test]
+    fn no_limit_allows_everything() {
+        let mut limiter = InboundLimiter::default();
+        for _ in 0..10_000 {
+            assert!(limiter.allow(64 * 1024));
+        }
+        assert!(!limiter.take_flood_started());
+    }
+
+    #[test]
+    fn messages_over_the_budget_are_dropped() {
+        let mut limiter = limiter(3.0, 0.0);
+        for _ in 0..3 {
+            assert!(limiter.allow(10));
+        }
+        assert!(!limiter.allow(10));
+
+        // A third of a second refills one message.
+        limiter.refill(1.0 / 3.0);
+        assert!(limiter.allow(10));
+        assert!(!limiter.allow(10));
+    }
+
+    #[test]
+    fn bytes_over_the_budget_are_dropped() {
+        let mut limiter = limiter(0.0, 100.0);
+        assert!(limiter.allow(60));
+        // Positive is enough, so this one gets through and leaves the bucket in debt.
+        assert!(limiter.allow(60));
+        assert!(!limiter.allow(1));
+
+        // 20 bytes of debt to pay off before anything gets through again.
+        limiter.refill(0.1);
+        assert!(!limiter.allow(1));
+        limiter.refill(0.11);
+        assert!(limiter.allow(1));
+    }
+
+    #[test]
+    fn refills_stop_at_one_second() {
+        let mut limiter = limiter(2.0, 0.0);
+        limiter.refill(10.0);
+        assert!(limiter.allow(1));
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+    }
+
+    #[test]
+    fn a_flood_is_reported_once_until_the_buckets_are_full() {
+        let mut limiter = limiter(1.0, 0.0);
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+        assert!(!limiter.allow(1));
+        assert!(limiter.take_flood_started());
+        assert!(!limiter.take_flood_started());
+
+        // Back under the limit, but the bucket isn't full yet, so it's still the same flood.
+        limiter.refill(0.5);
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+        assert!(!limiter.take_flood_started());
+
+        // A full bucket ends it, and the next drop is a new flood.
+        limiter.refill(1.0);
+        limiter.refill(1.0);
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+        assert!(limiter.take_flood_started());
+    }
+
+    #[test]
+    fn set_limit_starts_with_full_buckets() {
+        let mut limiter = limiter(1.0, 0.0);
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+
+        limiter.set_limit(InboundLimit {
+            messages_per_second: 2.0,
+            bytes_per_second: 0.0,
+        });
+        assert!(limiter.allow(1));
+        assert!(limiter.allow(1));
+        assert!(!limiter.allow(1));
+        assert!(limiter.take_flood_started());
+    }
+}
@@ -0,0 +1,73 @@
+use godot::{engine::Engine, prelude::*};
+
+// Start - Surviving hot reloads
+// When the editor reloads the extension, every manager's Rust half is dropped and made again from its exported
+// properties, so the connection goes with it. Netcode's keys can't be carried over to a new transport, so
+// instead the old connection says goodbye to the server, which frees our client id right away, and what it
+// takes to join again is left on the Engine singleton, which outlives the library. Once Godot tells the new
+// instance the reload is done it picks that up and joins again.
+//
+// Freeing a node sends it NOTIFICATION_PREDELETE before its Rust half is dropped, while a reload drops the
+// Rust half of a node that lives on. Only a drop without that notification leaves a handoff, so managers that
+// are freed in the editor, like when their scene is closed, just disconnect. Reloads only happen in the
+// editor, so nothing is left behind in exported games.
+
+const HANDOFF_META: &str = "arcade_client_reload_handoff";
+
+pub struct Handoff {
+    pub address: GString,
+    pub client_id: i64,
+    // Empty for sessions joined without a connect token.
+    pub connect_token: PackedByteArray,
+    pub subscriptions: PackedStringArray,
+}
+
+impl Handoff {
+    fn to_dictionary(&self) -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        dictionary.set("address", self.address.clone());
+        dictionary.set("client_id", self.client_id);
+        dictionary.set("connect_token", self.connect_token.clone());
+        dictionary.set("subscriptions", self.subscriptions.clone());
+        return dictionary;
+    }
+
+    fn from_dictionary(dictionary: &Dictionary) -> Option<Self> {
+        return Some(Self {
+            address: dictionary.get("address")?.try_to().ok()?,
+            client_id: dictionary.get("client_id")?.try_to().ok()?,
+            connect_token: dictionary.get("connect_token")?.try_to().ok()?,
+            subscriptions: dictionary.get("subscriptions")?.try_to().ok()?,
+        });
+    }
+}
+
+// Keyed by the manager's instance id, which stays the same through a reload.
+fn handoffs() -> Dictionary {
+    let engine = Engine::singleton();
+    if !engine.has_meta(HANDOFF_META.into()) {
+        return Dictionary::new();
+    }
+    return engine
+        .get_meta(HANDOFF_META.into())
+        .try_to()
+        .unwrap_or_default();
+}
+
+pub fn save_handoff(instance_id: InstanceId, handoff: &Handoff) {
+    let mut handoffs = handoffs();
+    handoffs.set(instance_id.to_i64(), handoff.to_dictionary());
+    Engine::singleton().set_meta(HANDOFF_META.into(), handoffs.to_variant());
+}
+
+pub fn take_handoff(instance_id: InstanceId) -> Option<Handoff> {
+    let mut handoffs = handoffs();
+    let handoff = handoffs.remove(instance_id.to_i64())?;
+    if handoffs.is_empty() {
+        Engine::singleton().remove_meta(HANDOFF_META.into());
+    } else {
+        Engine::singleton().set_meta(HANDOFF_META.into(), handoffs.to_variant());
+    }
+    return Handoff::from_dictionary(&handoff.try_to().ok()?);
+}
+// End - Surviving hot reloads
@@ -0,0 +1,468 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use bytes::Bytes;
+use godot::{engine::ProjectSettings, prelude::*};
+
+use crate::{
+    protocol::{self, ClientMessage, Reader, ServerMessage},
+    validation::{self, Rejection},
+};
+
+// Start - Session recordings
+// With `recording_directory` set, debug builds write everything the server sends in a session to a recording,
+// along with everything we send. Games that predict can feed the inputs they sent to their resimulation
+// again when a session is played back, and get the same result, and a session from a real server can be kept
+// and checked again after protocol changes. A recording is "ARRC" and a version byte, followed by one entry
+// per message as it arrived or left: session time as an f64, a u8 that is 1 for messages we sent, the channel
+// as a u8, and a u32 length before the message. Messages on the unreliable sequenced channel are kept without
+// their sequence number.
+//
+// SessionReplay loads a recording, hands its inputs to the game's rollback code, decodes the server's
+// messages for assertions, and collects the signals of a node while the recording is played. Playing it is
+// MockServer's `play_recording`, which sends the server's messages to a manager over a real connection at the
+// times they originally arrived:
+//
+//     var replay := SessionReplay.new()
+//     replay.load_recording("res://tests/recordings/match.arcrec")
+//     replay.watch(session_manager)
+//     mock_server.play_recording(client_id, "res://tests/recordings/match.arcrec")
+//     ...
+//     assert(replay.expect_signals(["join_completed", "match_seed_received", "message_received"]).is_empty())
+//     for input in replay.get_inputs():
+//         rollback.add_input(input.tick, input.payload)
+
+const MAGIC: &[u8; 4] = b"ARRC";
+const VERSION: u8 = 1;
+
+// Signals with more arguments than this can't be watched, see `on_signal_0`.
+const MAX_SIGNAL_ARGS: usize = 6;
+
+pub struct RecordedMessage {
+    pub time: f64,
+    // False for messages the server sent, true for ours.
+    pub sent: bool,
+    pub channel: u8,
+    pub message: Bytes,
+}
+
+pub struct SessionRecorder {
+    file: BufWriter<File>,
+}
+
+impl SessionRecorder {
+    /// Starts a recording in `directory`, named after the unix time it started at.
+    pub fn new(directory: PathBuf, started_at: u64) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        let path = directory.join(format!("session_{started_at}.arcrec"));
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        return Ok(Self { file });
+    }
+
+    pub fn record(&mut self, time: f64, sent: bool, channel: u8, message: &[u8]) -> io::Result<()> {
+        self.file.write_all(&time.to_le_bytes())?;
+        self.file.write_all(&[sent as u8, channel])?;
+        self.file.write_all(&(message.len() as u32).to_le_bytes())?;
+        return self.file.write_all(message);
+    }
+}
+
+/// Reads a recording, from res://, user:// or an absolute path.
+pub fn load_recording(path: &str) -> io::Result<Vec<RecordedMessage>> {
+    let path = ProjectSettings::singleton()
+        .globalize_path(path.into())
+        .to_string();
+    let bytes = fs::read(path)?;
+    return parse_recording(&bytes)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a session recording"));
+}
+
+fn parse_recording(bytes: &[u8]) -> Option<Vec<RecordedMessage>> {
+    let mut reader = Reader::new(bytes);
+    if reader.read_bytes(MAGIC.len())? != MAGIC || reader.read_u8()? != VERSION {
+        return None;
+    }
+
+    let mut messages = Vec::new();
+    while !reader.is_empty() {
+        let time = f64::from_bits(reader.read_u64()?);
+        let sent = reader.read_u8()? != 0;
+        let channel = reader.read_u8()?;
+        let length = reader.read_u32()?;
+        let message = Bytes::copy_from_slice(reader.read_bytes(length as usize)?);
+        messages.push(RecordedMessage {
+            time,
+            sent,
+            channel,
+            message,
+        });
+    }
+    return Some(messages);
+}
+
+/// A decoded message as a Dictionary, with its `kind` name and the fields of that kind. Payloads are
+/// PackedByteArrays.
+pub fn describe(message: &ServerMessage) -> Dictionary {
+    let bytes = |bytes: &Bytes| PackedByteArray::from(bytes.as_ref());
+    let mut fields = Dictionary::new();
+    fields.set("kind", validation::message_kind(message));
+    match message {
+        ServerMessage::Application(payload) => fields.set("payload", bytes(payload)),
+        ServerMessage::Spawn {
+            entity_id,
+            scene_index,
+            owner_id,
+        } => {
+            fields.set("entity_id", *entity_id as i64);
+            fields.set("scene_index", *scene_index as i64);
+            fields.set("owner_id", *owner_id as i64);
+        }
+        ServerMessage::Despawn { entity_id } => fields.set("entity_id", *entity_id as i64),
+        ServerMessage::Authority {
+            entity_id,
+            owner_id,
+        } => {
+            fields.set("entity_id", *entity_id as i64);
+            fields.set("owner_id", *owner_id as i64);
+        }
+        ServerMessage::Rpc(packet) => {
+            fields.set("peer_id", packet.peer_id as i64);
+            fields.set("payload", bytes(&packet.payload));
+        }
+        ServerMessage::Snapshot { tick, payload }
+        | ServerMessage::FullSnapshot { tick, payload } => {
+            fields.set("tick", *tick as i64);
+            fields.set("payload", bytes(payload));
+        }
+        ServerMessage::ServerInfo { tick_rate, tick } => {
+            fields.set("tick_rate", *tick_rate as i64);
+            fields.set("tick", *tick as i64);
+        }
+        ServerMessage::FullSnapshotTooLarge { size } => fields.set("size", *size as i64),
+        ServerMessage::SessionTakenOver => {}
+        ServerMessage::Response {
+            request_id,
+            payload,
+        } => {
+            fields.set("request_id", *request_id as i64);
+            fields.set("payload", bytes(payload));
+        }
+        ServerMessage::Topic { topic, payload } => {
+            fields.set("topic", GString::from(topic.as_str()));
+            fields.set("payload", bytes(payload));
+        }
+        ServerMessage::FormatSelected {
+            format,
+            compression,
+        } => {
+            fields.set("format", *format as i64);
+            fields.set("compression", *compression as i64);
+        }
+        ServerMessage::OutboxAck { id } => fields.set("id", *id as i64),
+        ServerMessage::Idempotent { key, payload } => {
+            fields.set("key", *key as i64);
+            fields.set("payload", bytes(payload));
+        }
+        ServerMessage::Checksum { tick, checksum } => {
+            fields.set("tick", *tick as i64);
+            fields.set("checksum", *checksum as i64);
+        }
+        ServerMessage::Voice {
+            speaker, entity_id, ..
+        } => {
+            fields.set("speaker", *speaker as i64);
+            fields.set("entity_id", *entity_id as i64);
+        }
+        ServerMessage::Chat { sender, text } => {
+            fields.set("sender", *sender as i64);
+            fields.set("text", GString::from(text.as_str()));
+        }
+        ServerMessage::QuickChat { sender, id } => {
+            fields.set("sender", *sender as i64);
+            fields.set("id", *id as i64);
+        }
+        ServerMessage::Presence { client_id, state } => {
+            fields.set("client_id", *client_id as i64);
+            fields.set("state", *state as i64);
+        }
+        ServerMessage::RichPresence { client_id, state } => {
+            fields.set("client_id", *client_id as i64);
+            fields.set("state", bytes(state));
+        }
+        ServerMessage::ReplicaUpdate { store, op, body } => {
+            fields.set("store", GString::from(store.as_str()));
+            fields.set("op", *op as i64);
+            fields.set("body", bytes(body));
+        }
+        ServerMessage::SaveData { slot, .. } => fields.set("slot", GString::from(slot.as_str())),
+        ServerMessage::SaveUploadResult { slot, accepted, .. } => {
+            fields.set("slot", GString::from(slot.as_str()));
+            fields.set("accepted", *accepted);
+        }
+        ServerMessage::Notification {
+            id,
+            category,
+            payload,
+            ..
+        } => {
+            fields.set("id", *id as i64);
+            fields.set("category", GString::from(category.as_str()));
+            fields.set("payload", bytes(payload));
+        }
+        ServerMessage::Pong { ping_id, .. } => fields.set("ping_id", *ping_id as i64),
+        ServerMessage::MatchSeed { seed } => fields.set("seed", *seed as i64),
+        ServerMessage::ServerConfig(config) => fields.set("config", bytes(config)),
+    }
+    return fields;
+}
+
+// The differences between what happened and what a test expected, in order. Names that don't appear in
+// `expected` are skipped, so a test only lists what it cares about.
+fn compare_sequence(actual: &[String], expected: &PackedStringArray) -> PackedStringArray {
+    let expected: Vec<String> = expected
+        .as_slice()
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let actual: Vec<&String> = actual
+        .iter()
+        .filter(|name| expected.contains(*name))
+        .collect();
+
+    let mut differences = PackedStringArray::new();
+    for (index, name) in expected.iter().enumerate() {
+        match actual.get(index) {
+            Some(&got) if got == name => {}
+            Some(got) => differences.push(format!("#{index}: expected {name}, got {got}").into()),
+            None => differences.push(format!("#{index}: expected {name}, got nothing").into()),
+        }
+    }
+    for (index, got) in actual.iter().enumerate().skip(expected.len()) {
+        differences.push(format!("#{index}: got {got}, expected nothing").into());
+    }
+    return differences;
+}
+
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct SessionReplay {
+    base: Base<RefCounted>,
+
+    messages: Vec<RecordedMessage>,
+    // Signals of the watched nodes, in the order they were emitted, with their arguments.
+    emitted: Vec<(GString, VariantArray)>,
+}
+
+#[godot_api]
+impl SessionReplay {
+    /// Loads a recording made with the manager's `recording_directory`. Returns false if it's missing or
+    /// isn't a recording.
+    #[func]
+    fn load_recording(&mut self, path: GString) -> bool {
+        match load_recording(&path.to_string()) {
+            Ok(messages) => self.messages = messages,
+            Err(error) => {
+                godot_error!("SessionReplay: can't load {path}: {error}");
+                return false;
+            }
+        }
+        return true;
+    }
+
+    /// Returns the recorded messages from the server, decoded and oldest first, see `describe` in replay.rs
+    /// for the fields. Every one also has `time` and `channel`. Batches are split into their messages, and a
+    /// message that can't be decoded has only its `kind` and `malformed` set to true.
+    #[func]
+    fn get_messages(&self) -> Array<Dictionary> {
+        let mut messages = Array::new();
+        for recorded in self.messages.iter().filter(|recorded| !recorded.sent) {
+            let mut decoded = Vec::new();
+            let valid = ServerMessage::decode_all(&recorded.message, |message| {
+                decoded.push(describe(&message));
+            });
+            if !valid {
+                let mut fields = Dictionary::new();
+                fields.set("kind", Rejection::malformed(&recorded.message).kind);
+                fields.set("malformed", true);
+                decoded.push(fields);
+            }
+            for mut fields in decoded {
+                fields.set("time", recorded.time);
+                fields.set("channel", recorded.channel as i64);
+                messages.push(fields);
+            }
+        }
+        return messages;
+    }
+
+    /// Returns the payloads sent with `send_message` and `send_command` during the recording, oldest first,
+    /// as Dictionaries with `time`, the session time it was sent at, `channel`, `tick`, the render tick with
+    /// its fraction, `snapshot_tick`, and `payload`. Ticks are -1 for `send_message` and where the session
+    /// didn't know one yet.
+    #[func]
+    fn get_inputs(&self) -> Array<Dictionary> {
+        let mut inputs = Array::new();
+        for recorded in self.messages.iter().filter(|recorded| recorded.sent) {
+            let (tick, snapshot_tick, payload) = match ClientMessage::decode(&recorded.message) {
+                Some(ClientMessage::Application(payload)) => (-1.0, -1, payload),
+                Some(ClientMessage::Command {
+                    render_tick,
+                    render_fraction,
+                    snapshot_tick,
+                    payload,
+                }) => {
+                    let tick = match render_tick {
+                        protocol::NO_TICK => -1.0,
+                        tick => tick as f64 + render_fraction as f64 / 65536.0,
+                    };
+                    let snapshot_tick = match snapshot_tick {
+                        protocol::NO_TICK => -1,
+                        tick => tick as i64,
+                    };
+                    (tick, snapshot_tick, payload)
+                }
+                _ => continue,
+            };
+
+            let mut input = Dictionary::new();
+            input.set("time", recorded.time);
+            input.set("channel", recorded.channel as i64);
+            input.set("tick", tick);
+            input.set("snapshot_tick", snapshot_tick);
+            input.set("payload", PackedByteArray::from(payload));
+            inputs.push(input);
+        }
+        return inputs;
+    }
+
+    /// Compares the kinds of the recorded messages with `expected`, like `expect_signals`.
+    #[func]
+    fn expect_messages(&self, expected: PackedStringArray) -> PackedStringArray {
+        let kinds: Vec<String> = self
+            .get_messages()
+            .iter_shared()
+            .map(|message| {
+                return message
+                    .get("kind")
+                    .map_or_else(String::new, |kind| kind.to_string());
+            })
+            .collect();
+        return compare_sequence(&kinds, &expected);
+    }
+
+    /// Starts collecting every signal `node` emits, usually a GameplaySessionManager. Signals with more than
+    /// six arguments are skipped.
+    #[func]
+    fn watch(&mut self, mut node: Gd<Object>) {
+        for signal in node.get_signal_list().iter_shared() {
+            let name = signal
+                .get("name")
+                .map_or_else(GString::new, |name| name.to::<GString>());
+            let arg_count = signal
+                .get("args")
+                .and_then(|args| args.try_to::<VariantArray>().ok())
+                .map_or(0, |args| args.len());
+            if arg_count > MAX_SIGNAL_ARGS {
+                godot_warn!("SessionReplay: can't watch {name}, it has {arg_count} arguments");
+                continue;
+            }
+
+            let mut bound = VariantArray::new();
+            bound.push(name.to_variant());
+            let method = format!("on_signal_{arg_count}");
+            let callable =
+                Callable::from_object_method(&self.to_gd(), method.as_str()).bindv(bound);
+            node.connect(StringName::from(name.to_string().as_str()), callable);
+        }
+    }
+
+    /// Returns the collected signals in the order they were emitted, as Dictionaries with `name` and `args`.
+    #[func]
+    fn get_emitted_signals(&self) -> Array<Dictionary> {
+        let mut signals = Array::new();
+        for (name, args) in &self.emitted {
+            let mut signal = Dictionary::new();
+            signal.set("name", name.clone());
+            signal.set("args", args.clone());
+            signals.push(signal);
+        }
+        return signals;
+    }
+
+    #[func]
+    fn clear_emitted_signals(&mut self) {
+        self.emitted.clear();
+    }
+
+    /// Compares the names of the collected signals with `expected`, in order. Signals that aren't in
+    /// `expected` at all are ignored. Returns a line for every difference, so it's empty if they match.
+    #[func]
+    fn expect_signals(&self, expected: PackedStringArray) -> PackedStringArray {
+        let names: Vec<String> = self
+            .emitted
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        return compare_sequence(&names, &expected);
+    }
+
+    // Signals pass their own arguments before the bound name, so there is one of these per argument count.
+    #[func]
+    fn on_signal_0(&mut self, name: GString) {
+        self.emitted.push((name, VariantArray::new()));
+    }
+
+    #[func]
+    fn on_signal_1(&mut self, a: Variant, name: GString) {
+        self.emitted.push((name, varray![a]));
+    }
+
+    #[func]
+    fn on_signal_2(&mut self, a: Variant, b: Variant, name: GString) {
+        self.emitted.push((name, varray![a, b]));
+    }
+
+    #[func]
+    fn on_signal_3(&mut self, a: Variant, b: Variant, c: Variant, name: GString) {
+        self.emitted.push((name, varray![a, b, c]));
+    }
+
+    #[func]
+    fn on_signal_4(&mut self, a: Variant, b: Variant, c: Variant, d: Variant, name: GString) {
+        self.emitted.push((name, varray![a, b, c, d]));
+    }
+
+    #[func]
+    fn on_signal_5(
+        &mut self,
+        a: Variant,
+        b: Variant,
+        c: Variant,
+        d: Variant,
+        e: Variant,
+        name: GString,
+    ) {
+        self.emitted.push((name, varray![a, b, c, d, e]));
+    }
+
+    #[func]
+    #[allow(clippy::too_many_arguments)]
+    fn on_signal_6(
+        &mut self,
+        a: Variant,
+        b: Variant,
+        c: Variant,
+        d: Variant,
+        e: Variant,
+        f: Variant,
+        name: GString,
+    ) {
+        self.emitted.push((name, varray![a, b, c, d, e, f]));
+    }
+}
+// End - Session recordings
@@ -0,0 +1,850 @@
+use std::collections::HashMap;
+
+use godot::{engine::Engine, prelude::*};
+
+use crate::{cbor, protocol::Reader, GameplaySessionManager};
+
+// Start - State replicated from the server
+// Small pieces of shared state like match settings, scores or world flags, kept by the server and mirrored
+// on every client that watches them. Each store has a name. Watching one sends a subscribe for it, again on
+// every join, and the server answers with the whole store and then streams changes to it over the reliable
+// ordered channel. The manager queues the updates of watched stores and the nodes here take them
+// from it every frame, the same way RenetMultiplayerPeer takes its packets.
+//
+// Values are CBOR, see cbor.rs. Keys are UTF-8 like topics, a u8 length and then the bytes. An update is an
+// op byte followed by its body:
+//   OP_DICTIONARY_FULL   CBOR map with every key
+//   OP_DICTIONARY_SET    key, CBOR value
+//   OP_DICTIONARY_ERASE  key
+//   OP_ARRAY_FULL        CBOR array with every item
+//   OP_ARRAY_INSERT      u32 index, CBOR value
+//   OP_ARRAY_REMOVE      u32 index
+//   OP_ARRAY_UPDATE      u32 index, CBOR value
+//   OP_WRITE_RESULT      u32 write id, u8 1 if accepted
+// Array indices are the ones before the op is applied, with insert also allowing the length to append.
+//
+// NetworkDictionary can also write to the keys listed in its `writable_keys`. The write shows locally right
+// away and goes to the server as `ClientMessage::ReplicaWrite`, which answers with OP_WRITE_RESULT. An
+// accepted write has to be sent back as OP_DICTIONARY_SET before its result, like to every other client, so
+// the value we settle on is the server's. A rejected one is rolled back to the server's value.
+//
+// A NetworkDictionary can belong to a spawned entity through its `entity_id`, like a player's loadout. While
+// this client has authority over the entity, see `is_local_authority`, the store changes through our writes
+// only, and the server's changes to it are dropped. Write results still count, so rejected writes roll back.
+
+pub const OP_DICTIONARY_FULL: u8 = 0;
+pub const OP_DICTIONARY_SET: u8 = 1;
+pub const OP_DICTIONARY_ERASE: u8 = 2;
+pub const OP_ARRAY_FULL: u8 = 3;
+pub const OP_ARRAY_INSERT: u8 = 4;
+pub const OP_ARRAY_REMOVE: u8 = 5;
+pub const OP_ARRAY_UPDATE: u8 = 6;
+pub const OP_WRITE_RESULT: u8 = 7;
+
+// Signal names are made once, like the manager's SignalNames, since stores can change every frame.
+struct ReplicaSignalNames {
+    synced: StringName,
+    value_changed: StringName,
+    value_removed: StringName,
+    write_rejected: StringName,
+    item_inserted: StringName,
+    item_removed: StringName,
+    item_updated: StringName,
+}
+
+impl Default for ReplicaSignalNames {
+    fn default() -> Self {
+        Self {
+            synced: StringName::from("synced"),
+            value_changed: StringName::from("value_changed"),
+            value_removed: StringName::from("value_removed"),
+            write_rejected: StringName::from("write_rejected"),
+            item_inserted: StringName::from("item_inserted"),
+            item_removed: StringName::from("item_removed"),
+            item_updated: StringName::from("item_updated"),
+        }
+    }
+}
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+struct NetworkDictionary {
+    base: Base<Node>,
+
+    // The GameplaySessionManager whose session the store comes from.
+    #[export]
+    session_manager: NodePath,
+    // The store's name on the server. Two nodes can't watch the same store through one manager.
+    #[export]
+    store: GString,
+    // The keys `write_value` may change. The server decides whether a write goes through.
+    #[export]
+    writable_keys: PackedStringArray,
+    // The spawned entity the store belongs to, or -1 for shared state.
+    #[export]
+    #[init(default = -1)]
+    entity_id: i64,
+
+    manager: Option<Gd<GameplaySessionManager>>,
+    data: Dictionary,
+    synced: bool,
+    // Writes the server hasn't answered yet, by write id, with their key.
+    pending_writes: HashMap<u32, String>,
+    // The server's value for keys with pending writes, None where it doesn't have the key. While a key has
+    // writes pending, `data` holds our newest write and updates from the server only land here.
+    confirmed: HashMap<String, Option<Variant>>,
+    next_write_id: u32,
+    signal_names: ReplicaSignalNames,
+}
+
+#[godot_api]
+impl INode for NetworkDictionary {
+    fn ready(&mut self) {
+        if Engine::singleton().is_editor_hint() {
+            return;
+        }
+
+        self.manager = attach(
+            "NetworkDictionary",
+            &self.base(),
+            &self.session_manager,
+            &self.store,
+        );
+    }
+
+    fn exit_tree(&mut self) {
+        if let Some(mut manager) = self.manager.take() {
+            manager.bind_mut().detach_replica(&self.store.to_string());
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let Some(manager) = &mut self.manager else {
+            return;
+        };
+        let updates = manager
+            .bind_mut()
+            .take_replica_updates(&self.store.to_string());
+        let locally_owned =
+            self.entity_id >= 0 && manager.bind().is_local_authority(self.entity_id);
+        for (op, body) in updates {
+            if locally_owned && op != OP_WRITE_RESULT {
+                // The whole store still counts as arrived, it's ours.
+                if op == OP_DICTIONARY_FULL && !self.synced {
+                    self.synced = true;
+                    let signal = self.signal_names.synced.clone();
+                    self.base_mut().emit_signal(signal, &[]);
+                }
+                continue;
+            }
+            if !self.apply(op, &body) {
+                godot_warn!(
+                    "NetworkDictionary: malformed update {op} for '{}', ignoring it",
+                    self.store
+                );
+            }
+        }
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        return configuration_warnings(&self.base(), &self.session_manager, &self.store);
+    }
+}
+
+#[godot_api]
+impl NetworkDictionary {
+    // Emitted when a key is added or its value changes, including for the keys a resync changed.
+    #[signal]
+    fn value_changed(key: GString, value: Variant);
+    #[signal]
+    fn value_removed(key: GString);
+    // Emitted after the whole store arrived, when joining and after every reconnect.
+    #[signal]
+    fn synced();
+    // Emitted when the server turned down a `write_value`. The key shows the server's value again once no
+    // other writes to it are pending. Writes still pending when the connection drops count as rejected.
+    #[signal]
+    fn write_rejected(key: GString);
+
+    /// Sets a key listed in `writable_keys` right away, and asks the server to set it for everyone. Returns
+    /// false if the key isn't writable, the value can't be sent or there is no connection.
+    #[func]
+    fn write_value(&mut self, key: GString, value: Variant) -> bool {
+        if !self.writable_keys.as_slice().contains(&key) {
+            godot_error!("NetworkDictionary: '{key}' isn't in writable_keys");
+            return false;
+        }
+        let mut encoded = Vec::new();
+        if let Err(error) = cbor::encode(&value, &mut encoded) {
+            godot_error!("NetworkDictionary: can't write '{key}': {error}");
+            return false;
+        }
+        let Some(manager) = &mut self.manager else {
+            return false;
+        };
+
+        let write_id = self.next_write_id;
+        let (store, key_string) = (self.store.to_string(), key.to_string());
+        if !manager
+            .bind_mut()
+            .send_replica_write(&store, write_id, &key_string, &encoded)
+        {
+            return false;
+        }
+        self.next_write_id = self.next_write_id.wrapping_add(1);
+        self.pending_writes.insert(write_id, key_string.clone());
+        if !self.confirmed.contains_key(&key_string) {
+            self.confirmed
+                .insert(key_string, self.data.get(key.clone()));
+        }
+        self.set_local(key, Some(value));
+        return true;
+    }
+
+    /// Whether a `write_value` to the key is still waiting for the server.
+    #[func]
+    fn has_pending_write(&self, key: GString) -> bool {
+        return self.confirmed.contains_key(&key.to_string());
+    }
+
+    /// Returns the value for `key`, or `default` if the store doesn't have it.
+    #[func]
+    fn get_value(&self, key: GString, default: Variant) -> Variant {
+        return self.data.get(key).unwrap_or(default);
+    }
+
+    #[func]
+    fn has_key(&self, key: GString) -> bool {
+        return self.data.contains_key(key);
+    }
+
+    #[func]
+    fn get_keys(&self) -> PackedStringArray {
+        let mut keys = PackedStringArray::new();
+        for key in self.data.keys_array().iter_shared() {
+            keys.push(key.to::<GString>());
+        }
+        return keys;
+    }
+
+    /// A copy of the whole store.
+    #[func]
+    fn get_data(&self) -> Dictionary {
+        return self.data.duplicate_deep();
+    }
+
+    /// Whether the whole store has arrived at least once. Before that the values are missing or, after a
+    /// reconnect, from the last session.
+    #[func]
+    fn is_synced(&self) -> bool {
+        return self.synced;
+    }
+}
+
+impl NetworkDictionary {
+    /// Returns false if the update was malformed.
+    fn apply(&mut self, op: u8, body: &[u8]) -> bool {
+        let Some(update) = parse_dictionary_update(op, body) else {
+            return false;
+        };
+        match update {
+            DictionaryUpdate::Full(data) => {
+                let Some(data) =
+                    cbor::decode(data).and_then(|data| data.try_to::<Dictionary>().ok())
+                else {
+                    return false;
+                };
+                self.replace(data);
+            }
+            DictionaryUpdate::Set(key, value) => {
+                let Some(value) = cbor::decode(value) else {
+                    return false;
+                };
+                self.set_from_server(key.into(), Some(value));
+            }
+            DictionaryUpdate::Erase(key) => self.set_from_server(key.into(), None),
+            DictionaryUpdate::WriteResult(write_id, accepted) => {
+                self.resolve_write(write_id, accepted)
+            }
+        }
+        return true;
+    }
+
+    // Only what differs from what we had is signaled, so a resync after a reconnect is quiet when nothing
+    // changed in the meantime.
+    fn replace(&mut self, data: Dictionary) {
+        // The full store only comes after subscribing again, from a server that never saw our pending
+        // writes or won't answer them anymore.
+        let rejected: Vec<String> = self.confirmed.drain().map(|(key, _)| key).collect();
+        self.pending_writes.clear();
+
+        let old = std::mem::replace(&mut self.data, Dictionary::new());
+        for (key, value) in data.iter_shared() {
+            let key = GString::from(key.to_string());
+            self.data.set(key.clone(), value.clone());
+            if old.get(key.clone()).as_ref() != Some(&value) {
+                let signal = self.signal_names.value_changed.clone();
+                self.base_mut()
+                    .emit_signal(signal, &[key.to_variant(), value]);
+            }
+        }
+        for key in old.keys_array().iter_shared() {
+            let key = key.to::<GString>();
+            if !self.data.contains_key(key.clone()) {
+                let signal = self.signal_names.value_removed.clone();
+                self.base_mut().emit_signal(signal, &[key.to_variant()]);
+            }
+        }
+        for key in rejected {
+            let signal = self.signal_names.write_rejected.clone();
+            self.base_mut()
+                .emit_signal(signal, &[GString::from(key.as_str()).to_variant()]);
+        }
+        self.synced = true;
+        let signal = self.signal_names.synced.clone();
+        self.base_mut().emit_signal(signal, &[]);
+    }
+
+    fn set_from_server(&mut self, key: GString, value: Option<Variant>) {
+        if let Some(confirmed) = self.confirmed.get_mut(&key.to_string()) {
+            *confirmed = value;
+            return;
+        }
+        self.set_local(key, value);
+    }
+
+    fn resolve_write(&mut self, write_id: u32, accepted: bool) {
+        let Some(key) = self.pending_writes.remove(&write_id) else {
+            return;
+        };
+        if !accepted {
+            let signal = self.signal_names.write_rejected.clone();
+            self.base_mut()
+                .emit_signal(signal, &[GString::from(key.as_str()).to_variant()]);
+        }
+        if self.pending_writes.values().any(|pending| *pending == key) {
+            return;
+        }
+        if let Some(confirmed) = self.confirmed.remove(&key) {
+            self.set_local(key.into(), confirmed);
+        }
+    }
+
+    // Sets or, for None, removes the key, with a signal if that changed anything.
+    fn set_local(&mut self, key: GString, value: Option<Variant>) {
+        match value {
+            Some(value) => {
+                if self.data.get(key.clone()).as_ref() == Some(&value) {
+                    return;
+                }
+                self.data.set(key.clone(), value.clone());
+                let signal = self.signal_names.value_changed.clone();
+                self.base_mut()
+                    .emit_signal(signal, &[key.to_variant(), value]);
+            }
+            None => {
+                if self.data.remove(key.clone()).is_some() {
+                    let signal = self.signal_names.value_removed.clone();
+                    self.base_mut().emit_signal(signal, &[key.to_variant()]);
+                }
+            }
+        }
+    }
+}
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+struct NetworkArray {
+    base: Base<Node>,
+
+    // The GameplaySessionManager whose session the store comes from.
+    #[export]
+    session_manager: NodePath,
+    // The store's name on the server. Two nodes can't watch the same store through one manager.
+    #[export]
+    store: GString,
+
+    manager: Option<Gd<GameplaySessionManager>>,
+    items: VariantArray,
+    synced: bool,
+    signal_names: ReplicaSignalNames,
+}
+
+#[godot_api]
+impl INode for NetworkArray {
+    fn ready(&mut self) {
+        if Engine::singleton().is_editor_hint() {
+            return;
+        }
+        self.manager = attach(
+            "NetworkArray",
+            &self.base(),
+            &self.session_manager,
+            &self.store,
+        );
+    }
+
+    fn exit_tree(&mut self) {
+        if let Some(mut manager) = self.manager.take() {
+            manager.bind_mut().detach_replica(&self.store.to_string());
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        let Some(manager) = &mut self.manager else {
+            return;
+        };
+        let updates = manager
+            .bind_mut()
+            .take_replica_updates(&self.store.to_string());
+        for (op, body) in updates {
+            if !self.apply(op, &body) {
+                godot_warn!(
+                    "NetworkArray: malformed update {op} for '{}', ignoring it",
+                    self.store
+                );
+            }
+        }
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        return configuration_warnings(&self.base(), &self.session_manager, &self.store);
+    }
+}
+
+#[godot_api]
+impl NetworkArray {
+    // Every change comes with the index it happened at, at the time it happened. Mirroring the signals onto a
+    // list of UI rows one by one keeps the rows lined up with the items.
+    #[signal]
+    fn item_inserted(index: i64, value: Variant);
+    #[signal]
+    fn item_removed(index: i64, value: Variant);
+    #[signal]
+    fn item_updated(index: i64, value: Variant);
+    // Emitted after the whole store arrived, when joining and after every reconnect. The differences to what
+    // we had were signaled as inserts, removes and updates before.
+    #[signal]
+    fn synced();
+
+    /// Returns the item at `index`, or null if there is none.
+    #[func]
+    fn get_item(&self, index: i64) -> Variant {
+        let Ok(index) = usize::try_from(index) else {
+            return Variant::nil();
+        };
+        return self.items.iter_shared().nth(index).unwrap_or_default();
+    }
+
+    #[func]
+    fn size(&self) -> i64 {
+        return self.items.len() as i64;
+    }
+
+    /// A copy of the whole store.
+    #[func]
+    fn get_items(&self) -> VariantArray {
+        return self.items.duplicate_deep();
+    }
+
+    /// Whether the whole store has arrived at least once. Before that the items are missing or, after a
+    /// reconnect, from the last session.
+    #[func]
+    fn is_synced(&self) -> bool {
+        return self.synced;
+    }
+}
+
+impl NetworkArray {
+    /// Returns false if the update was malformed or its index out of range.
+    fn apply(&mut self, op: u8, body: &[u8]) -> bool {
+        let Some(update) = parse_array_update(op, body, self.items.len()) else {
+            return false;
+        };
+        match update {
+            ArrayUpdate::Full(items) => {
+                let Some(items) =
+                    cbor::decode(items).and_then(|items| items.try_to::<VariantArray>().ok())
+                else {
+                    return false;
+                };
+                self.replace(items);
+            }
+            ArrayUpdate::Insert(index, value) => {
+                let Some(value) = cbor::decode(value) else {
+                    return false;
+                };
+                self.insert(index, value);
+            }
+            ArrayUpdate::Remove(index) => self.remove(index),
+            ArrayUpdate::Update(index, value) => {
+                let Some(value) = cbor::decode(value) else {
+                    return false;
+                };
+                self.update(index, value);
+            }
+        }
+        return true;
+    }
+
+    fn insert(&mut self, index: usize, value: Variant) {
+        self.items.insert(index, value.clone());
+        let signal = self.signal_names.item_inserted.clone();
+        self.base_mut()
+            .emit_signal(signal, &[(index as i64).to_variant(), value]);
+    }
+
+    fn remove(&mut self, index: usize) {
+        let value = self.items.remove(index);
+        let signal = self.signal_names.item_removed.clone();
+        self.base_mut()
+            .emit_signal(signal, &[(index as i64).to_variant(), value]);
+    }
+
+    fn update(&mut self, index: usize, value: Variant) {
+        if self.items.iter_shared().nth(index).as_ref() == Some(&value) {
+            return;
+        }
+        self.items.set(index, value.clone());
+        let signal = self.signal_names.item_updated.clone();
+        self.base_mut()
+            .emit_signal(signal, &[(index as i64).to_variant(), value]);
+    }
+
+    // Turns what we had into the new items with the changes from `diff_items`.
+    fn replace(&mut self, items: VariantArray) {
+        // Arrays index differently across API versions, so we compare plain copies.
+        let old_items: Vec<Variant> = self.items.iter_shared().collect();
+        let items: Vec<Variant> = items.iter_shared().collect();
+        for change in diff_items(&old_items, &items) {
+            match change {
+                ArrayChange::Update(index) => self.update(index, items[index].clone()),
+                ArrayChange::Remove(index) => self.remove(index),
+                ArrayChange::Insert(index) => self.insert(index, items[index].clone()),
+            }
+        }
+
+        self.synced = true;
+        let signal = self.signal_names.synced.clone();
+        self.base_mut().emit_signal(signal, &[]);
+    }
+}
+
+// Updates split into their parts, with array indices checked against the length, before any value is decoded.
+// Kept apart from the nodes so it works without the engine.
+#[derive(Debug, PartialEq)]
+enum DictionaryUpdate<'a> {
+    Full(&'a [u8]),
+    Set(&'a str, &'a [u8]),
+    Erase(&'a str),
+    WriteResult(u32, bool),
+}
+
+#[derive(Debug, PartialEq)]
+enum ArrayUpdate<'a> {
+    Full(&'a [u8]),
+    Insert(usize, &'a [u8]),
+    Remove(usize),
+    Update(usize, &'a [u8]),
+}
+
+fn parse_dictionary_update(op: u8, body: &[u8]) -> Option<DictionaryUpdate<'_>> {
+    let mut reader = Reader::new(body);
+    let update = match op {
+        OP_DICTIONARY_FULL => DictionaryUpdate::Full(body),
+        OP_DICTIONARY_SET => DictionaryUpdate::Set(reader.read_topic()?, reader.read_remaining()),
+        OP_DICTIONARY_ERASE => DictionaryUpdate::Erase(reader.read_topic()?),
+        OP_WRITE_RESULT => {
+            DictionaryUpdate::WriteResult(reader.read_u32()?, reader.read_u8()? != 0)
+        }
+        _ => return None,
+    };
+    return Some(update);
+}
+
+// `len` is how many items the array has before the update.
+fn parse_array_update(op: u8, body: &[u8], len: usize) -> Option<ArrayUpdate<'_>> {
+    if op == OP_ARRAY_FULL {
+        return Some(ArrayUpdate::Full(body));
+    }
+    let mut reader = Reader::new(body);
+    let index = reader.read_u32()? as usize;
+    let update = match op {
+        OP_ARRAY_INSERT if index <= len => ArrayUpdate::Insert(index, reader.read_remaining()),
+        OP_ARRAY_REMOVE if index < len => ArrayUpdate::Remove(index),
+        OP_ARRAY_UPDATE if index < len => ArrayUpdate::Update(index, reader.read_remaining()),
+        _ => return None,
+    };
+    return Some(update);
+}
+
+// The changes that turn `old` into `new`, in the order they're made, each with the index at that time.
+// Inserts and updates take the item at their index in `new`.
+#[derive(Debug, PartialEq)]
+enum ArrayChange {
+    Update(usize),
+    Remove(usize),
+    Insert(usize),
+}
+
+// As few changes as is cheap to work out: the items both start and end with are kept, the ones in between are
+// updated in place where they differ, and the rest is removed or inserted.
+fn diff_items<T: PartialEq>(old: &[T], new: &[T]) -> Vec<ArrayChange> {
+    let (old_len, new_len) = (old.len(), new.len());
+    let mut prefix = 0;
+    while prefix < old_len.min(new_len) && old[prefix] == new[prefix] {
+        prefix += 1;
+    }
+    let mut suffix = 0;
+    while suffix < old_len.min(new_len) - prefix
+        && old[old_len - 1 - suffix] == new[new_len - 1 - suffix]
+    {
+        suffix += 1;
+    }
+
+    let (old_middle, new_middle) = (old_len - prefix - suffix, new_len - prefix - suffix);
+    let mut changes = Vec::new();
+    for index in prefix..prefix + old_middle.min(new_middle) {
+        if old[index] != new[index] {
+            changes.push(ArrayChange::Update(index));
+        }
+    }
+    for index in (prefix + new_middle..prefix + old_middle).rev() {
+        changes.push(ArrayChange::Remove(index));
+    }
+    for index in prefix + old_middle..prefix + new_middle {
+        changes.push(ArrayChange::Insert(index));
+    }
+    return changes;
+}
+
+// Finds the manager and starts watching the store through it. `class` is for the errors.
+fn attach(
+    class: &str,
+    base: &Node,
+    session_manager: &NodePath,
+    store: &GString,
+) -> Option<Gd<GameplaySessionManager>> {
+    let Some(mut manager) = base
+        .get_node_or_null(session_manager.clone())
+        .and_then(|node| node.try_cast::<GameplaySessionManager>().ok())
+    else {
+        godot_error!("{class}: no session manager found at '{session_manager}'");
+        return None;
+    };
+    if !manager.bind_mut().attach_replica(&store.to_string()) {
+        godot_error!("{class}: the store '{store}' is already watched by another node");
+        return None;
+    }
+    return Some(manager);
+}
+
+fn configuration_warnings(
+    base: &Node,
+    session_manager: &NodePath,
+    store: &GString,
+) -> PackedStringArray {
+    let mut warnings = PackedStringArray::new();
+    if session_manager.is_empty() {
+        warnings
+            .push("Set session_manager to the GameplaySessionManager to replicate from.".into());
+    } else if let Some(node) = base.get_node_or_null(session_manager.clone()) {
+        // Autoloads don't resolve in the editor, so only a node that is there and wrong is flagged.
+        if !node.is_class("GameplaySessionManager".into()) {
+            warnings.push("session_manager doesn't point at a GameplaySessionManager.".into());
+        }
+    }
+    if store.is_empty() {
+        warnings.push("Set store to the name of the store on the server.".into());
+    }
+    return warnings;
+}
+// End - State replicated from the server
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Variants need the engine, so these stick to the parts that don't make any.
+
+    fn indexed(index: u32, value: &[u8]) -> Vec<u8> {
+        let mut body = index.to_le_bytes().to_vec();
+        body.extend_from_slice(value);
+        return body;
+    }
+
+    // Applies the changes like `NetworkArray::replace` does.
+    fn replaced(old: &[i32], new: &[i32]) -> (Vec<i32>, Vec<ArrayChange>) {
+        let changes = diff_items(old, new);
+        let mut items = old.to_vec();
+        for change in &changes {
+            match *change {
+                ArrayChange::Update(index) => items[index] = new[index],
+                ArrayChange::Remove(index) => _ = items.remove(index),
+                ArrayChange::Insert(index) => items.insert(index, new[index]),
+            }
+        }
+        return (items, changes);
+    }
+
+    #[test]
+    fn array_indices_are_checked() {
+        // CBOR 1.
+        let value = [0x01];
+        let insert = indexed(2, &value);
+        assert_eq!(
+            parse_array_update(OP_ARRAY_INSERT, &insert, 2),
+            Some(ArrayUpdate::Insert(2, &value[..]))
+        );
+        assert_eq!(parse_array_update(OP_ARRAY_INSERT, &insert, 1), None);
+
+        let remove = indexed(1, &[]);
+        assert_eq!(
+            parse_array_update(OP_ARRAY_REMOVE, &remove, 2),
+            Some(ArrayUpdate::Remove(1))
+        );
+        assert_eq!(parse_array_update(OP_ARRAY_REMOVE, &remove, 1), None);
+        assert_eq!(
+            parse_array_update(OP_ARRAY_REMOVE, &indexed(0, &[]), 0),
+            None
+        );
+
+        let update = indexed(1, &value);
+        assert_eq!(
+            parse_array_update(OP_ARRAY_UPDATE, &update, 2),
+            Some(ArrayUpdate::Update(1, &value[..]))
+        );
+        assert_eq!(parse_array_update(OP_ARRAY_UPDATE, &update, 1), None);
+
+        let far = indexed(u32::MAX, &value);
+        for op in [OP_ARRAY_INSERT, OP_ARRAY_REMOVE, OP_ARRAY_UPDATE] {
+            assert_eq!(parse_array_update(op, &far, 10), None, "op {op}");
+        }
+    }
+
+    #[test]
+    fn malformed_bodies_are_rejected() {
+        // Indices are 4 bytes.
+        for op in [OP_ARRAY_INSERT, OP_ARRAY_REMOVE, OP_ARRAY_UPDATE] {
+            assert_eq!(parse_array_update(op, &[0, 0, 0], 10), None, "op {op}");
+        }
+        assert_eq!(parse_array_update(OP_DICTIONARY_SET, &[], 10), None);
+        assert_eq!(
+            parse_dictionary_update(OP_ARRAY_INSERT, &indexed(0, &[0x01])),
+            None
+        );
+
+        // A key that is longer than the body, and one that isn't UTF-8.
+        assert_eq!(
+            parse_dictionary_update(OP_DICTIONARY_SET, &[5, b'a', b'b']),
+            None
+        );
+        assert_eq!(
+            parse_dictionary_update(OP_DICTIONARY_ERASE, &[1, 0xFF]),
+            None
+        );
+        assert_eq!(
+            parse_dictionary_update(OP_WRITE_RESULT, &[1, 0, 0, 0]),
+            None
+        );
+
+        // The values are split off as they are, then fail to decode: an array promising 4 items with 1, and a
+        // reserved head.
+        let bad_values: [&[u8]; 3] = [&[0x84, 0x01], &[0x1C], &[]];
+        for value in bad_values {
+            let insert = indexed(0, value);
+            let Some(ArrayUpdate::Insert(0, split)) =
+                parse_array_update(OP_ARRAY_INSERT, &insert, 0)
+            else {
+                panic!("{value:?} wasn't split off");
+            };
+            assert!(cbor::decode(split).is_none(), "{value:?}");
+
+            let mut body = vec![3, b'k', b'e', b'y'];
+            body.extend_from_slice(value);
+            let Some(DictionaryUpdate::Set("key", split)) =
+                parse_dictionary_update(OP_DICTIONARY_SET, &body)
+            else {
+                panic!("{value:?} wasn't split off");
+            };
+            assert!(cbor::decode(split).is_none(), "{value:?}");
+        }
+    }
+
+    #[test]
+    fn dictionary_updates_split_into_their_parts() {
+        assert_eq!(
+            parse_dictionary_update(OP_DICTIONARY_ERASE, &[3, b'k', b'e', b'y']),
+            Some(DictionaryUpdate::Erase("key"))
+        );
+        assert_eq!(
+            parse_dictionary_update(OP_WRITE_RESULT, &[7, 0, 0, 0, 1]),
+            Some(DictionaryUpdate::WriteResult(7, true))
+        );
+        assert_eq!(
+            parse_dictionary_update(OP_WRITE_RESULT, &[7, 0, 0, 0, 0]),
+            Some(DictionaryUpdate::WriteResult(7, false))
+        );
+    }
+
+    #[test]
+    fn unchanged_items_are_quiet() {
+        assert_eq!(diff_items::<i32>(&[], &[]), []);
+        assert_eq!(diff_items(&[1, 2, 3], &[1, 2, 3]), []);
+    }
+
+    #[test]
+    fn replace_changes_only_what_differs() {
+        assert_eq!(diff_items(&[1, 2, 3], &[1, 4, 3]), [ArrayChange::Update(1)]);
+        assert_eq!(diff_items(&[1, 2, 3], &[1, 3]), [ArrayChange::Remove(1)]);
+        assert_eq!(diff_items(&[1, 3], &[1, 2, 3]), [ArrayChange::Insert(1)]);
+        assert_eq!(
+            diff_items(&[1, 2, 3], &[0, 1, 2, 3]),
+            [ArrayChange::Insert(0)]
+        );
+        assert_eq!(
+            diff_items(&[1, 2, 3], &[1, 2, 3, 4]),
+            [ArrayChange::Insert(3)]
+        );
+        // Items in the middle that stayed the same aren't updated.
+        assert_eq!(
+            diff_items(&[1, 2, 5, 3, 9], &[1, 4, 5, 6, 9]),
+            [ArrayChange::Update(1), ArrayChange::Update(3)]
+        );
+        // Removes go from the back, so earlier indices still hold.
+        assert_eq!(
+            diff_items(&[1, 2, 3, 4], &[]),
+            [
+                ArrayChange::Remove(3),
+                ArrayChange::Remove(2),
+                ArrayChange::Remove(1),
+                ArrayChange::Remove(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn replace_ends_with_the_new_items() {
+        let cases: [(&[i32], &[i32]); 8] = [
+            (&[], &[1, 2]),
+            (&[1, 2], &[]),
+            (&[1, 2, 3], &[4, 5]),
+            (&[4, 5], &[1, 2, 3]),
+            (&[1, 2, 3, 4, 5], &[1, 5]),
+            (&[1, 5], &[1, 2, 3, 4, 5]),
+            (&[1, 1, 1], &[1, 1]),
+            (&[1, 2, 1, 2], &[2, 1, 2, 1]),
+        ];
+        for (old, new) in cases {
+            let (items, changes) = replaced(old, new);
+            assert_eq!(items, new, "{old:?} to {new:?}");
+            // Never more signals than items on the longer side.
+            assert!(
+                changes.len() <= old.len().max(new.len()),
+                "{old:?} to {new:?}"
+            );
+        }
+    }
+}
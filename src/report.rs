@@ -0,0 +1,167 @@
+use std::{collections::VecDeque, error::Error};
+
+use godot::{
+    engine::{file_access::ModeFlags, DirAccess, FileAccess, Json},
+    prelude::*,
+};
+
+use renet::RenetClient;
+
+// Start - Bug report bundles
+// Players describe network problems as "it lagged". A report bundle is what we'd want to see instead: the
+// last messages in both directions, a minute or two of connection stats, why messages were rejected, how the
+// manager was configured and how the session ended. It's written as JSON to user://reports, and can be
+// uploaded from there.
+
+pub const REPORT_DIRECTORY: &str = "user://reports";
+
+const MAX_TRACE_ENTRIES: usize = 256;
+// How many of the last messages go into `last_session_report`.
+pub const SESSION_REPORT_MESSAGES: usize = 64;
+const MAX_REJECTIONS: usize = 32;
+// One sample a second.
+const MAX_STATS_SAMPLES: usize = 120;
+
+struct TraceEntry {
+    // Session time.
+    time: f64,
+    outbound: bool,
+    channel: u8,
+    // The message kind byte, see protocol.rs. None for empty messages.
+    kind: Option<u8>,
+    size: usize,
+}
+
+struct RejectionEntry {
+    time: f64,
+    channel: u8,
+    kind: &'static str,
+    reason: String,
+}
+
+// Only sizes and kinds are kept, never payloads, so reports don't leak chat or account data.
+#[derive(Default)]
+pub struct MessageTrace {
+    entries: VecDeque<TraceEntry>,
+    rejections: VecDeque<RejectionEntry>,
+}
+
+impl MessageTrace {
+    pub fn record(&mut self, time: f64, outbound: bool, channel: u8, message: &[u8]) {
+        if self.entries.len() == MAX_TRACE_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(TraceEntry {
+            time,
+            outbound,
+            channel,
+            kind: message.first().copied(),
+            size: message.len(),
+        });
+    }
+
+    pub fn record_rejection(&mut self, time: f64, channel: u8, kind: &'static str, reason: &str) {
+        if self.rejections.len() == MAX_REJECTIONS {
+            self.rejections.pop_front();
+        }
+        self.rejections.push_back(RejectionEntry {
+            time,
+            channel,
+            kind,
+            reason: reason.to_string(),
+        });
+    }
+
+    pub fn messages(&self) -> VariantArray {
+        return self.recent_messages(MAX_TRACE_ENTRIES);
+    }
+
+    // The newest `count` messages, oldest first.
+    pub fn recent_messages(&self, count: usize) -> VariantArray {
+        let mut array = VariantArray::new();
+        let skip = self.entries.len().saturating_sub(count);
+        for entry in self.entries.iter().skip(skip) {
+            let mut dictionary = Dictionary::new();
+            dictionary.set("time", entry.time);
+            dictionary.set("direction", if entry.outbound { "out" } else { "in" });
+            dictionary.set("channel", entry.channel as i64);
+            dictionary.set("kind", entry.kind.map_or(-1, i64::from));
+            dictionary.set("size", entry.size as i64);
+            array.push(dictionary.to_variant());
+        }
+        return array;
+    }
+
+    pub fn rejections(&self) -> VariantArray {
+        let mut array = VariantArray::new();
+        for rejection in &self.rejections {
+            let mut dictionary = Dictionary::new();
+            dictionary.set("time", rejection.time);
+            dictionary.set("channel", rejection.channel as i64);
+            dictionary.set("kind", rejection.kind);
+            dictionary.set("reason", GString::from(rejection.reason.as_str()));
+            array.push(dictionary.to_variant());
+        }
+        return array;
+    }
+}
+
+#[derive(Default)]
+pub struct StatsHistory {
+    samples: VecDeque<Dictionary>,
+    since_last_sample: f64,
+}
+
+impl StatsHistory {
+    /// Called every tick while connected, takes a sample once a second.
+    pub fn update(&mut self, delta: f64, time: f64, client: &RenetClient) {
+        self.since_last_sample += delta;
+        if self.since_last_sample < 1.0 {
+            return;
+        }
+        self.since_last_sample = 0.0;
+
+        let info = client.network_info();
+        let mut sample = Dictionary::new();
+        sample.set("time", time);
+        sample.set("rtt_ms", info.rtt * 1000.0);
+        sample.set("packet_loss", info.packet_loss);
+        sample.set("bytes_sent_per_second", info.bytes_sent_per_second);
+        sample.set("bytes_received_per_second", info.bytes_received_per_second);
+        if self.samples.len() == MAX_STATS_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    pub fn samples(&self) -> VariantArray {
+        return self
+            .samples
+            .iter()
+            .map(|sample| sample.to_variant())
+            .collect();
+    }
+}
+
+// The error and every error that caused it, outermost first. Transport errors often only say what failed
+// at the top, the IO error underneath says why.
+pub fn error_chain(error: &dyn Error) -> PackedStringArray {
+    let mut chain = PackedStringArray::new();
+    let mut next = Some(error);
+    while let Some(error) = next {
+        chain.push(GString::from(error.to_string()));
+        next = error.source();
+    }
+    return chain;
+}
+
+/// Writes the report and returns its path, or `None` if it couldn't be written.
+pub fn write_report(report: &Dictionary, created_at: u64) -> Option<GString> {
+    DirAccess::make_dir_recursive_absolute(REPORT_DIRECTORY.into());
+    let path = GString::from(format!("{REPORT_DIRECTORY}/report_{created_at}.json"));
+    let mut file = FileAccess::open(path.clone(), ModeFlags::WRITE)?;
+    file.store_string(Json::stringify(report.to_variant()));
+    file.close();
+    return Some(path);
+}
+// End - Bug report bundles
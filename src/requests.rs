@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+// Start - Keeps track of requests waiting for a response
+// Every request gets an id that the server copies into its response, so responses can arrive in any order.
+// Requests that don't get a response in time are failed, and a late response for them is ignored.
+
+#[derive(Default)]
+pub struct PendingRequests {
+    last_id: u32,
+    // Request id to the session time it times out at.
+    deadlines: HashMap<u32, f64>,
+}
+
+impl PendingRequests {
+    /// Returns the id for a new request. A `timeout` of 0 or less never times out.
+    pub fn start(&mut self, now: f64, timeout: f64) -> u32 {
+        // 0 is skipped so GDScript can use it for "no request".
+        self.last_id = self.last_id.wrapping_add(1).max(1);
+        let deadline = if timeout > 0.0 {
+            now + timeout
+        } else {
+            f64::INFINITY
+        };
+        self.deadlines.insert(self.last_id, deadline);
+        return self.last_id;
+    }
+
+    /// Returns false if the request is unknown, which means it already timed out or was answered.
+    pub fn resolve(&mut self, id: u32) -> bool {
+        return self.deadlines.remove(&id).is_some();
+    }
+
+    /// Removes and returns the requests that have timed out. Doesn't allocate when none have.
+    pub fn take_expired(&mut self, now: f64) -> Vec<u32> {
+        let mut expired = Vec::new();
+        self.deadlines.retain(|id, deadline| {
+            if *deadline > now {
+                return true;
+            }
+            expired.push(*id);
+            return false;
+        });
+        return expired;
+    }
+
+    pub fn take_all(&mut self) -> Vec<u32> {
+        return self.deadlines.drain().map(|(id, _)| id).collect();
+    }
+}
+// End - Keeps track of requests waiting for a response
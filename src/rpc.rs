@@ -0,0 +1,256 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{channels, protocol::RpcPacket, GameplaySessionManager};
+use bytes::Bytes;
+use godot::{
+    engine::{
+        global::Error,
+        multiplayer_peer::{ConnectionStatus, TransferMode},
+        IMultiplayerPeerExtension, MultiplayerPeerExtension,
+    },
+    prelude::*,
+};
+
+// Godot always gives the server peer id 1.
+const SERVER_PEER_ID: i32 = 1;
+
+// Start - Lets SceneMultiplayer run on top of the renet session
+// Assign this to `multiplayer.multiplayer_peer` and `@rpc` methods in GDScript are sent through the
+// GameplaySessionManager's connection instead of ENet. This keeps existing high-level multiplayer code
+// working while the rest of the game moves to the low-level API.
+//
+//     var peer := RenetMultiplayerPeer.new()
+//     peer.attach($GameplaySessionManager)
+//     multiplayer.multiplayer_peer = peer
+//
+// The client only talks to the server, so every packet goes to the server together with the target peer id
+// and the server relays it.
+#[derive(GodotClass)]
+#[class(init, base=MultiplayerPeerExtension)]
+struct RenetMultiplayerPeer {
+    base: Base<MultiplayerPeerExtension>,
+    session_manager: Option<Gd<GameplaySessionManager>>,
+
+    inbox: VecDeque<RpcPacket>,
+    // Newest sequence seen per (sender, channel) for unreliable ordered packets.
+    inbound_sequences: HashMap<(i32, u8), u16>,
+    outbound_sequence: u16,
+
+    // Set by SceneMultiplayer before each `put_packet`.
+    #[init(default = TransferMode::RELIABLE)]
+    transfer_mode: TransferMode,
+    transfer_channel: i32,
+    target_peer: i32,
+
+    #[init(default = ConnectionStatus::DISCONNECTED)]
+    last_status: ConnectionStatus,
+}
+
+#[godot_api]
+impl IMultiplayerPeerExtension for RenetMultiplayerPeer {
+    fn poll(&mut self) {
+        let status = self.get_connection_status();
+        if status != self.last_status {
+            self.last_status = status;
+            if status == ConnectionStatus::CONNECTED {
+                self.base_mut()
+                    .emit_signal("peer_connected".into(), &[SERVER_PEER_ID.to_variant()]);
+            } else if status == ConnectionStatus::DISCONNECTED {
+                self.inbox.clear();
+                self.inbound_sequences.clear();
+                self.base_mut()
+                    .emit_signal("peer_disconnected".into(), &[SERVER_PEER_ID.to_variant()]);
+            }
+        }
+
+        let Some(manager) = &mut self.session_manager else {
+            return;
+        };
+        let packets = manager.bind_mut().take_rpc_packets();
+        for packet in packets {
+            if self.is_stale(&packet) {
+                continue;
+            }
+            self.inbox.push_back(packet);
+        }
+    }
+
+    fn get_available_packet_count(&self) -> i32 {
+        return self.inbox.len() as i32;
+    }
+
+    // SceneMultiplayer asks about the packet before taking it, so these describe the front of the inbox.
+    fn get_packet_peer(&self) -> i32 {
+        return self.inbox.front().map_or(0, |packet| packet.peer_id);
+    }
+
+    fn get_packet_channel(&self) -> i32 {
+        return self.inbox.front().map_or(0, |packet| packet.channel as i32);
+    }
+
+    fn get_packet_mode(&self) -> TransferMode {
+        return self
+            .inbox
+            .front()
+            .and_then(|packet| TransferMode::try_from_ord(packet.transfer_mode as i32))
+            .unwrap_or(TransferMode::RELIABLE);
+    }
+
+    fn get_packet_script(&mut self) -> PackedByteArray {
+        return self
+            .inbox
+            .pop_front()
+            .map_or_else(PackedByteArray::new, |packet| {
+                PackedByteArray::from(&packet.payload[..])
+            });
+    }
+
+    fn put_packet_script(&mut self, buffer: PackedByteArray) -> Error {
+        let Some(manager) = &mut self.session_manager else {
+            return Error::ERR_UNCONFIGURED;
+        };
+
+        if self.transfer_mode == TransferMode::UNRELIABLE_ORDERED {
+            self.outbound_sequence = self.outbound_sequence.wrapping_add(1);
+        }
+
+        let packet = RpcPacket {
+            transfer_mode: self.transfer_mode.ord() as u8,
+            channel: self.transfer_channel as u8,
+            peer_id: self.target_peer,
+            sequence: self.outbound_sequence,
+            payload: Bytes::from(buffer.to_vec()),
+        };
+
+        if manager
+            .bind_mut()
+            .send_rpc_packet(renet_channel(self.transfer_mode), &packet)
+        {
+            return Error::OK;
+        }
+
+        return Error::ERR_CONNECTION_ERROR;
+    }
+
+    fn get_max_packet_size(&self) -> i32 {
+        // Renet splits large messages into slices for us, so this is only a sanity limit.
+        return 1 << 24;
+    }
+
+    fn set_transfer_channel(&mut self, channel: i32) {
+        self.transfer_channel = channel;
+    }
+
+    fn get_transfer_channel(&self) -> i32 {
+        return self.transfer_channel;
+    }
+
+    fn set_transfer_mode(&mut self, mode: TransferMode) {
+        self.transfer_mode = mode;
+    }
+
+    fn get_transfer_mode(&self) -> TransferMode {
+        return self.transfer_mode;
+    }
+
+    fn set_target_peer(&mut self, peer: i32) {
+        self.target_peer = peer;
+    }
+
+    fn get_unique_id(&self) -> i32 {
+        let Some(manager) = &self.session_manager else {
+            return 0;
+        };
+
+        return match manager.bind().session_client_id() {
+            Some(client_id) => peer_id_for_client(client_id),
+            None => 0,
+        };
+    }
+
+    fn is_server(&self) -> bool {
+        return false;
+    }
+
+    fn is_server_relay_supported(&self) -> bool {
+        return true;
+    }
+
+    fn get_connection_status(&self) -> ConnectionStatus {
+        let Some(manager) = &self.session_manager else {
+            return ConnectionStatus::DISCONNECTED;
+        };
+
+        let manager = manager.bind();
+        if manager.is_session_connected() {
+            return ConnectionStatus::CONNECTED;
+        }
+        if manager.is_session_connecting() {
+            return ConnectionStatus::CONNECTING;
+        }
+
+        return ConnectionStatus::DISCONNECTED;
+    }
+
+    // The session belongs to the GameplaySessionManager, so closing the peer only detaches from it.
+    fn close(&mut self) {
+        if let Some(mut manager) = self.session_manager.take() {
+            manager.bind_mut().detach_rpc_bridge();
+        }
+        self.inbox.clear();
+        self.inbound_sequences.clear();
+    }
+
+    fn disconnect_peer(&mut self, peer: i32, _force: bool) {
+        if peer == SERVER_PEER_ID {
+            self.close();
+        }
+    }
+}
+
+#[godot_api]
+impl RenetMultiplayerPeer {
+    /// Routes this peer's packets through the manager's session. The manager still owns the connection,
+    /// so `join_session` must be called on it as usual.
+    #[func]
+    fn attach(&mut self, mut manager: Gd<GameplaySessionManager>) {
+        self.close();
+        manager.bind_mut().attach_rpc_bridge();
+        self.session_manager = Some(manager);
+    }
+
+    // Unreliable ordered packets that arrive after a newer one from the same sender and channel are dropped,
+    // which is what ENet does for that mode as well.
+    fn is_stale(&mut self, packet: &RpcPacket) -> bool {
+        if packet.transfer_mode as i32 != TransferMode::UNRELIABLE_ORDERED.ord() {
+            return false;
+        }
+
+        let key = (packet.peer_id, packet.channel);
+        if let Some(&newest) = self.inbound_sequences.get(&key) {
+            // Wrapping comparison, so the sequence can roll over.
+            if packet.sequence.wrapping_sub(newest) as i16 <= 0 {
+                return true;
+            }
+        }
+
+        self.inbound_sequences.insert(key, packet.sequence);
+        return false;
+    }
+}
+// End - Lets SceneMultiplayer run on top of the renet session
+
+// Maps the reliability from the `@rpc` annotation onto our channels. Unreliable ordered packets carry their
+// own sequence per RPC channel, so they use the plain unreliable channel.
+fn renet_channel(mode: TransferMode) -> u8 {
+    if mode == TransferMode::RELIABLE {
+        return channels::RELIABLE_ORDERED;
+    }
+
+    return channels::UNRELIABLE;
+}
+
+// Godot peer ids are positive i32s and 1 is taken by the server, so client ids are folded into 2..=i32::MAX.
+pub fn peer_id_for_client(client_id: u64) -> i32 {
+    return (client_id % (i32::MAX as u64 - 1)) as i32 + 2;
+}
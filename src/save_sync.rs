@@ -0,0 +1,217 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    time::SystemTime,
+};
+
+use godot::{
+    engine::{file_access::ModeFlags, FileAccess},
+    prelude::*,
+};
+
+use crate::cbor;
+
+// Start - Save games kept on the server
+// Save blobs are uploaded to and downloaded from the server over the session, on the reliable unordered
+// channel so a big save doesn't hold up gameplay messages. Renet splits them into slices and puts them back
+// together, up to the channel's memory budget.
+//
+// Every save slot carries metadata, a Dictionary:
+//   slot         the slot name
+//   version      version vector, a Dictionary from device id to how many saves that device made
+//   modified_at  unix time of the save
+//   size         bytes in the blob
+//   info         game specific, like the level or play time, for showing in a conflict prompt
+// A device bumps its own counter for every upload. The server takes an upload if the upload's version has
+// seen everything the server's has, and otherwise answers with its own metadata. Two versions where neither
+// has seen the other are a conflict: two devices saved while they didn't know about each other's save, and
+// only the player can say which one to keep.
+//
+// Which version this device last had of each slot is kept in `SAVE_SYNC_PATH`, as a CBOR map with our device
+// id and the slots' versions.
+
+pub const SAVE_SYNC_PATH: &str = "user://save_sync.cbor";
+
+pub enum VersionOrder {
+    Same,
+    // The first version has seen everything in the second and more.
+    Newer,
+    Older,
+    Concurrent,
+}
+
+pub fn compare_versions(a: &Dictionary, b: &Dictionary) -> VersionOrder {
+    let count = |version: &Dictionary, device: &Variant| {
+        return version
+            .get(device.clone())
+            .and_then(|count| count.try_to::<i64>().ok())
+            .unwrap_or(0);
+    };
+    let (mut a_ahead, mut b_ahead) = (false, false);
+    for device in a
+        .keys_array()
+        .iter_shared()
+        .chain(b.keys_array().iter_shared())
+    {
+        let (a_count, b_count) = (count(a, &device), count(b, &device));
+        a_ahead |= a_count > b_count;
+        b_ahead |= b_count > a_count;
+    }
+    return match (a_ahead, b_ahead) {
+        (false, false) => VersionOrder::Same,
+        (true, false) => VersionOrder::Newer,
+        (false, true) => VersionOrder::Older,
+        (true, true) => VersionOrder::Concurrent,
+    };
+}
+
+// The highest count of every device, for a version that has seen both.
+pub fn merge_versions(a: &Dictionary, b: &Dictionary) -> Dictionary {
+    let mut merged = a.duplicate_shallow();
+    for (device, count) in b.iter_shared() {
+        let count = count.try_to::<i64>().unwrap_or(0);
+        let known = merged
+            .get(device.clone())
+            .and_then(|known| known.try_to::<i64>().ok())
+            .unwrap_or(0);
+        merged.set(device, count.max(known));
+    }
+    return merged;
+}
+
+pub fn version_of(meta: &Dictionary) -> Dictionary {
+    return meta
+        .get("version")
+        .and_then(|version| version.try_to::<Dictionary>().ok())
+        .unwrap_or_default();
+}
+
+pub struct SaveSync {
+    path: GString,
+    device_id: GString,
+    // The version we last uploaded or downloaded, by slot.
+    versions: Dictionary,
+    // Saves waiting on the player to pick a side, by slot: our metadata, and the server's metadata with its
+    // blob if we downloaded it.
+    conflicts: HashMap<String, (Dictionary, Dictionary, Option<PackedByteArray>)>,
+    // Uploads the server hasn't answered yet, by slot, with their metadata.
+    uploads: HashMap<String, Dictionary>,
+}
+
+impl SaveSync {
+    pub fn load(path: GString) -> Self {
+        let stored = match FileAccess::file_exists(path.clone()) {
+            true => cbor::decode(FileAccess::get_file_as_bytes(path.clone()).as_slice())
+                .and_then(|stored| stored.try_to::<Dictionary>().ok()),
+            false => None,
+        }
+        .unwrap_or_default();
+
+        let device_id = stored
+            .get("device_id")
+            .and_then(|device_id| device_id.try_to::<GString>().ok())
+            .unwrap_or_else(|| {
+                let random = RandomState::new().build_hasher().finish();
+                return format!("{random:016x}").into();
+            });
+        let versions = stored
+            .get("versions")
+            .and_then(|versions| versions.try_to::<Dictionary>().ok())
+            .unwrap_or_default();
+        let sync = Self {
+            path,
+            device_id,
+            versions,
+            conflicts: HashMap::new(),
+            uploads: HashMap::new(),
+        };
+        sync.save();
+        return sync;
+    }
+
+    pub fn local_version(&self, slot: &str) -> Dictionary {
+        return self
+            .versions
+            .get(slot)
+            .and_then(|version| version.try_to::<Dictionary>().ok())
+            .unwrap_or_default();
+    }
+
+    /// Metadata for uploading a new save on top of `base`, the version it replaces.
+    pub fn next_meta(
+        &self,
+        slot: &str,
+        base: &Dictionary,
+        size: usize,
+        info: Dictionary,
+    ) -> Dictionary {
+        let mut version = base.duplicate_shallow();
+        let count = version
+            .get(self.device_id.clone())
+            .and_then(|count| count.try_to::<i64>().ok())
+            .unwrap_or(0);
+        version.set(self.device_id.clone(), count + 1);
+
+        let modified_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs() as i64);
+        let mut meta = Dictionary::new();
+        meta.set("slot", slot);
+        meta.set("version", version);
+        meta.set("modified_at", modified_at);
+        meta.set("size", size as i64);
+        meta.set("info", info);
+        return meta;
+    }
+
+    /// Remembers the version we now have of the slot.
+    pub fn set_local_version(&mut self, slot: &str, version: Dictionary) {
+        self.versions.set(slot, version);
+        self.save();
+    }
+
+    pub fn start_upload(&mut self, slot: &str, meta: Dictionary) {
+        self.uploads.insert(slot.to_owned(), meta);
+    }
+
+    pub fn take_upload(&mut self, slot: &str) -> Option<Dictionary> {
+        return self.uploads.remove(slot);
+    }
+
+    pub fn add_conflict(
+        &mut self,
+        slot: &str,
+        local: Dictionary,
+        remote: Dictionary,
+        remote_blob: Option<PackedByteArray>,
+    ) {
+        self.conflicts
+            .insert(slot.to_owned(), (local, remote, remote_blob));
+    }
+
+    pub fn take_conflict(
+        &mut self,
+        slot: &str,
+    ) -> Option<(Dictionary, Dictionary, Option<PackedByteArray>)> {
+        return self.conflicts.remove(slot);
+    }
+
+    fn save(&self) {
+        let mut stored = Dictionary::new();
+        stored.set("device_id", self.device_id.clone());
+        stored.set("versions", self.versions.clone());
+        let mut bytes = Vec::new();
+        if let Err(error) = cbor::encode(&stored.to_variant(), &mut bytes) {
+            godot_warn!("Couldn't encode the save sync state: {error}");
+            return;
+        }
+
+        let Some(mut file) = FileAccess::open(self.path.clone(), ModeFlags::WRITE) else {
+            godot_warn!("Couldn't save the save sync state to {}", self.path);
+            return;
+        };
+        file.store_buffer(PackedByteArray::from(bytes.as_slice()));
+        file.close();
+    }
+}
+// End - Save games kept on the server
@@ -0,0 +1,58 @@
+use godot::prelude::*;
+
+// The generator build.rs uses, so tests can run it.
+#[cfg(test)]
+#[path = "../codegen.rs"]
+mod codegen;
+
+// Start - Messages generated from the shared schema
+// build.rs turns schema/messages.json into the types below, see schema/messages.example.json for the format.
+// Each message is its u16 id followed by its fields in schema order, little endian. Bools are one byte,
+// strings and bytes are a u16 length followed by the data, so they can't be longer than 65535 bytes. Encoding
+// a longer one fails, and the GDScript `encode_` functions report it and return an empty PackedByteArray.
+// They travel as application payloads:
+//
+//     manager.send_message(0, MessageSchema.encode_player_move(x, y, sprinting))
+//     var message = MessageSchema.decode(payload)   # {"type": "PlayerMove", "x": ..., ...}
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct MessageSchema {
+    base: Base<RefCounted>,
+}
+
+// The generated code is written for every schema, so it doesn't avoid lints that only hit some. The helpers
+// are in here too, since a schema without strings or bytes doesn't use them.
+#[allow(dead_code, unreachable_code, unused_mut, unused_variables)]
+#[allow(
+    clippy::unnecessary_cast,
+    clippy::redundant_field_names,
+    clippy::match_single_binding
+)]
+mod generated {
+    use bytes::BytesMut;
+    use godot::prelude::*;
+
+    use super::MessageSchema;
+    use crate::protocol::Reader;
+
+    fn write_bytes(field: &str, bytes: &[u8], buffer: &mut BytesMut) -> Result<(), String> {
+        let Ok(length) = u16::try_from(bytes.len()) else {
+            return Err(format!(
+                "{field} is {} bytes, the most is {}",
+                bytes.len(),
+                u16::MAX
+            ));
+        };
+        buffer.extend_from_slice(&length.to_le_bytes());
+        buffer.extend_from_slice(bytes);
+        return Ok(());
+    }
+
+    fn read_bytes<'a>(reader: &mut Reader<'a>) -> Option<&'a [u8]> {
+        let length = reader.read_u16()?;
+        return reader.read_bytes(length as usize);
+    }
+
+    include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+}
+// End - Messages generated from the shared schema
@@ -0,0 +1,96 @@
+// Start - Lowers how often we send packets when the connection can't keep up
+// Works like TCP's congestion control: the rate is halved when the link looks congested and slowly
+// climbs back to the tick rate once it recovers. Renet is still updated every tick, only
+// `send_packets` is skipped, so messages sent in between are batched into the next send.
+
+// How often the conditions are looked at. Checking every tick would react to single lost packets.
+const EVALUATION_INTERVAL: f64 = 0.5;
+
+pub struct SendRateController {
+    enabled: bool,
+    rate: f64,
+    min_rate: f64,
+    max_rate: f64,
+    since_last_send: f64,
+    since_last_evaluation: f64,
+    // The most sends per second no matter what, for the bandwidth limited mode. Applies even when the
+    // controller is disabled.
+    cap: Option<f64>,
+}
+
+impl SendRateController {
+    // `max_rate` should be the tick rate, which is what we send at when the controller is disabled.
+    pub fn new(enabled: bool, min_rate: f64, max_rate: f64) -> Self {
+        Self {
+            enabled,
+            rate: max_rate,
+            min_rate: min_rate.clamp(1.0, max_rate),
+            max_rate,
+            since_last_send: 0.0,
+            since_last_evaluation: 0.0,
+            cap: None,
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        return match self.cap {
+            Some(cap) => self.rate.min(cap),
+            None => self.rate,
+        };
+    }
+
+    pub fn set_cap(&mut self, cap: Option<f64>) {
+        self.cap = cap.map(|cap| cap.max(1.0));
+    }
+
+    /// Returns true if packets should be sent this tick.
+    pub fn should_send(&mut self, delta: f64) -> bool {
+        if !self.enabled && self.cap.is_none() {
+            return true;
+        }
+
+        self.since_last_send += delta;
+        if self.since_last_send < 1.0 / self.rate() {
+            return false;
+        }
+
+        self.since_last_send = 0.0;
+        return true;
+    }
+
+    /// Returns the new rate if it changed. `backlog` is the fullest channel's queued bytes as a fraction
+    /// of its budget.
+    pub fn evaluate(
+        &mut self,
+        delta: f64,
+        packet_loss: f64,
+        backlog: f64,
+        packet_loss_threshold: f64,
+        backlog_threshold: f64,
+    ) -> Option<f64> {
+        if !self.enabled {
+            return None;
+        }
+
+        self.since_last_evaluation += delta;
+        if self.since_last_evaluation < EVALUATION_INTERVAL {
+            return None;
+        }
+        self.since_last_evaluation = 0.0;
+
+        let congested = packet_loss > packet_loss_threshold || backlog > backlog_threshold;
+        let rate = if congested {
+            (self.rate / 2.0).max(self.min_rate)
+        } else {
+            (self.rate + self.max_rate / 10.0).min(self.max_rate)
+        };
+
+        if rate == self.rate {
+            return None;
+        }
+
+        self.rate = rate;
+        return Some(rate);
+    }
+}
+// End - Lowers how often we send packets when the connection can't keep up
@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+
+use godot::{
+    engine::{http_client::Method, Engine, HttpRequest},
+    prelude::*,
+};
+
+use crate::backend::{self, BackendClient};
+
+// Start - Friends list
+// FriendsList keeps the player's friends and whether they are online, for friend lists and invite menus. It
+// asks the backend's `friends` endpoint with the BackendClient's login, when logged in and then every
+// `poll_interval` seconds, and compares each answer with the last one, so the game only hears about what
+// changed. The endpoint answers GET with {friends: [{account_id, name, status}]}. The status is up to the
+// backend, the usual ones are "offline", "online" and "in_match".
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+pub struct FriendsList {
+    base: Base<Node>,
+
+    // The BackendClient whose login is used.
+    #[export]
+    backend_client: NodePath,
+    // Seconds between updates while logged in, 0 to only update on `refresh`.
+    #[export]
+    #[init(default = 30.0)]
+    poll_interval: f64,
+
+    // In the backend's order, each a Dictionary with account_id, name and status.
+    friends: Array<Dictionary>,
+    statuses: HashMap<String, GString>,
+    since_poll: f64,
+    request: Option<Gd<HttpRequest>>,
+}
+
+#[godot_api]
+impl INode for FriendsList {
+    fn process(&mut self, delta: f64) {
+        if Engine::singleton().is_editor_hint() || self.poll_interval <= 0.0 {
+            return;
+        }
+        self.since_poll += delta;
+        if self.since_poll >= self.poll_interval {
+            self.refresh();
+        }
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        if self.backend_client.is_empty() {
+            warnings.push("Set backend_client to the BackendClient to log in with.".into());
+        } else if let Some(node) = self.base().get_node_or_null(self.backend_client.clone()) {
+            // Autoloads don't resolve in the editor, so only a node that is there and wrong is flagged.
+            if !node.is_class("BackendClient".into()) {
+                warnings.push("backend_client doesn't point at a BackendClient.".into());
+            }
+        }
+        return warnings;
+    }
+}
+
+#[godot_api]
+impl FriendsList {
+    // Emitted after every update that changed anything, with the whole list from `get_friends`.
+    #[signal]
+    fn friends_updated(friends: Array<Dictionary>);
+    // Emitted for every friend whose status changed, including new friends. Friends that were removed get
+    // an empty status.
+    #[signal]
+    fn friend_status_changed(account_id: GString, status: GString);
+
+    /// Updates the list now. Returns false when not logged in or an update is already running.
+    #[func]
+    fn refresh(&mut self) -> bool {
+        self.since_poll = 0.0;
+        if self.request.is_some() {
+            return false;
+        }
+        let Some(backend) = self.backend() else {
+            return false;
+        };
+        let (url, access_token) = {
+            let backend = backend.bind();
+            (
+                format!("{}/friends", backend.api_url()),
+                backend.access_token().clone(),
+            )
+        };
+        if access_token.is_empty() {
+            return false;
+        }
+
+        let callback = Callable::from_object_method(&self.to_gd(), "on_request_completed");
+        let Some(request) = backend::start_request(
+            &mut self.base_mut(),
+            callback,
+            &url,
+            Method::GET,
+            &access_token,
+            None,
+        ) else {
+            return false;
+        };
+        self.request = Some(request);
+        return true;
+    }
+
+    #[func]
+    fn get_friends(&self) -> Array<Dictionary> {
+        return self.friends.duplicate_deep();
+    }
+
+    /// Returns a friend's status, or an empty string for someone who isn't a friend.
+    #[func]
+    fn get_friend_status(&self, account_id: GString) -> GString {
+        return self
+            .statuses
+            .get(&account_id.to_string())
+            .cloned()
+            .unwrap_or_default();
+    }
+
+    #[func]
+    fn on_request_completed(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        body: PackedByteArray,
+    ) {
+        let Some(mut request) = self.request.take() else {
+            return;
+        };
+        request.queue_free();
+
+        let friends = match backend::is_success(result, response_code) {
+            true => backend::parse_json_object(&body)
+                .and_then(|response| response.get("friends"))
+                .and_then(|friends| friends.try_to::<VariantArray>().ok()),
+            false => None,
+        };
+        let Some(friends) = friends else {
+            godot_warn!("FriendsList: updating failed, result {result}, HTTP {response_code}");
+            return;
+        };
+        self.update(friends);
+    }
+}
+
+impl FriendsList {
+    fn backend(&self) -> Option<Gd<BackendClient>> {
+        let node = self.base().get_node_or_null(self.backend_client.clone())?;
+        return node.try_cast::<BackendClient>().ok();
+    }
+
+    fn update(&mut self, response: VariantArray) {
+        let mut friends = Array::new();
+        let mut statuses = HashMap::new();
+        let mut changes = Vec::new();
+        for entry in response.iter_shared() {
+            let Ok(friend) = entry.try_to::<Dictionary>() else {
+                continue;
+            };
+            let Some(account_id) = backend::get_string(&friend, "account_id") else {
+                continue;
+            };
+            let status = backend::get_string(&friend, "status").unwrap_or_default();
+            let mut entry = Dictionary::new();
+            entry.set("account_id", account_id.clone());
+            entry.set(
+                "name",
+                backend::get_string(&friend, "name").unwrap_or_default(),
+            );
+            entry.set("status", status.clone());
+            friends.push(entry);
+
+            if self.statuses.get(&account_id.to_string()) != Some(&status) {
+                changes.push((account_id.clone(), status.clone()));
+            }
+            statuses.insert(account_id.to_string(), status);
+        }
+        for account_id in self.statuses.keys() {
+            if !statuses.contains_key(account_id) {
+                changes.push((GString::from(account_id.as_str()), GString::new()));
+            }
+        }
+        let changed = friends != self.friends;
+        self.friends = friends;
+        self.statuses = statuses;
+
+        for (account_id, status) in changes {
+            self.base_mut().emit_signal(
+                "friend_status_changed".into(),
+                &[account_id.to_variant(), status.to_variant()],
+            );
+        }
+        if changed {
+            let friends = self.friends.duplicate_deep();
+            self.base_mut()
+                .emit_signal("friends_updated".into(), &[friends.to_variant()]);
+        }
+    }
+}
+// End - Friends list
+
+// Start - Parties
+// A party is a group of players who queue and play together. PartyClient creates, joins and leaves parties
+// through the backend, and keeps the roster up to date by asking for the party every `poll_interval` seconds.
+// When the leader starts matchmaking with `start_match`, the backend puts a connect token for each member into
+// the party, and every member's PartyClient joins with it, so the whole party ends up on the same server.
+//
+// The endpoints are POST party/create, POST party/join {party_id}, POST party/leave {party_id}, GET
+// party/<party_id> and POST party/<party_id>/match {game specific}. All but leave answer with the party:
+// {party_id, leader_id, members: [{account_id, name}], match: {match_id, connect_token} or null}.
+
+#[derive(Clone, Copy, PartialEq)]
+enum PartyAction {
+    Create,
+    Join,
+    Leave,
+    Poll,
+    StartMatch,
+}
+
+impl PartyAction {
+    fn name(self) -> &'static str {
+        return match self {
+            PartyAction::Create => "create",
+            PartyAction::Join => "join",
+            PartyAction::Leave => "leave",
+            PartyAction::Poll => "poll",
+            PartyAction::StartMatch => "start_match",
+        };
+    }
+}
+
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+pub struct PartyClient {
+    base: Base<Node>,
+
+    // The BackendClient whose login is used.
+    #[export]
+    backend_client: NodePath,
+    // The GameplaySessionManager that joins the party's matches. Without one, only `party_match_found` is
+    // emitted and the game joins itself.
+    #[export]
+    session_manager: NodePath,
+    // Seconds between roster updates while in a party.
+    #[export]
+    #[init(default = 3.0)]
+    poll_interval: f64,
+
+    party_id: GString,
+    leader_id: GString,
+    members: Array<Dictionary>,
+    // The last match we joined, so the same match isn't joined again on every update.
+    match_id: GString,
+    since_poll: f64,
+    request: Option<(Gd<HttpRequest>, PartyAction)>,
+}
+
+#[godot_api]
+impl INode for PartyClient {
+    fn process(&mut self, delta: f64) {
+        if Engine::singleton().is_editor_hint() || self.party_id.is_empty() {
+            return;
+        }
+        self.since_poll += delta;
+        if self.since_poll >= self.poll_interval && self.request.is_none() {
+            let path = format!("party/{}", self.party_id);
+            self.send(PartyAction::Poll, &path, Method::GET, None);
+        }
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        if self.backend_client.is_empty() {
+            warnings.push("Set backend_client to the BackendClient to log in with.".into());
+        } else if let Some(node) = self.base().get_node_or_null(self.backend_client.clone()) {
+            // Autoloads don't resolve in the editor, so only a node that is there and wrong is flagged.
+            if !node.is_class("BackendClient".into()) {
+                warnings.push("backend_client doesn't point at a BackendClient.".into());
+            }
+        }
+        return warnings;
+    }
+}
+
+#[godot_api]
+impl PartyClient {
+    #[signal]
+    fn party_joined(party_id: GString);
+    // Emitted after `leave_party`, and when the backend says we are no longer in the party.
+    #[signal]
+    fn party_left();
+    // Emitted when anyone joins or leaves, or the leader changes.
+    #[signal]
+    fn party_members_changed(members: Array<Dictionary>, leader_id: GString);
+    // Emitted when the leader's matchmaking found a match, right before joining it.
+    #[signal]
+    fn party_match_found(match_id: GString, connect_token: PackedByteArray);
+    // `action` is "create", "join", "leave", "poll" or "start_match".
+    #[signal]
+    fn party_request_failed(action: GString, error: GString);
+
+    /// Creates a party with us as the leader. Returns false if a request is already running, or when not
+    /// logged in.
+    #[func]
+    fn create_party(&mut self) -> bool {
+        return self.send(
+            PartyAction::Create,
+            "party/create",
+            Method::POST,
+            Some(Dictionary::new()),
+        );
+    }
+
+    /// Joins a party by its id, like one from an invite.
+    #[func]
+    fn join_party(&mut self, party_id: GString) -> bool {
+        let mut body = Dictionary::new();
+        body.set("party_id", party_id);
+        return self.send(PartyAction::Join, "party/join", Method::POST, Some(body));
+    }
+
+    /// Leaves the party. We are out of it here right away, whether or not the backend hears about it.
+    #[func]
+    fn leave_party(&mut self) {
+        if self.party_id.is_empty() {
+            return;
+        }
+        if let Some((mut request, _)) = self.request.take() {
+            request.queue_free();
+        }
+        let mut body = Dictionary::new();
+        body.set("party_id", self.party_id.clone());
+        self.send(PartyAction::Leave, "party/leave", Method::POST, Some(body));
+        self.clear_party();
+    }
+
+    /// Starts matchmaking for the whole party. Only the leader can. `request` is game specific, like the
+    /// mode or region.
+    #[func]
+    fn start_match(&mut self, request: Dictionary) -> bool {
+        if !self.is_leader() {
+            godot_error!("PartyClient: only the party leader can start a match");
+            return false;
+        }
+        let path = format!("party/{}/match", self.party_id);
+        return self.send(PartyAction::StartMatch, &path, Method::POST, Some(request));
+    }
+
+    #[func]
+    fn get_party_id(&self) -> GString {
+        return self.party_id.clone();
+    }
+
+    #[func]
+    fn get_leader_id(&self) -> GString {
+        return self.leader_id.clone();
+    }
+
+    /// Each member is a Dictionary with account_id and name.
+    #[func]
+    fn get_members(&self) -> Array<Dictionary> {
+        return self.members.duplicate_deep();
+    }
+
+    #[func]
+    fn is_leader(&self) -> bool {
+        let Some(backend) = self.backend() else {
+            return false;
+        };
+        let account_id = backend.bind().account_id().clone();
+        return !self.party_id.is_empty() && !account_id.is_empty() && account_id == self.leader_id;
+    }
+
+    #[func]
+    fn on_request_completed(
+        &mut self,
+        result: i64,
+        response_code: i64,
+        _headers: PackedStringArray,
+        body: PackedByteArray,
+    ) {
+        let Some((mut request, action)) = self.request.take() else {
+            return;
+        };
+        request.queue_free();
+        if action == PartyAction::Leave {
+            return;
+        }
+
+        // The party is gone or we were kicked.
+        if action == PartyAction::Poll && (response_code == 403 || response_code == 404) {
+            self.clear_party();
+            return;
+        }
+        let party = match backend::is_success(result, response_code) {
+            true => backend::parse_json_object(&body),
+            false => None,
+        };
+        let Some(party) = party.filter(|party| backend::get_string(party, "party_id").is_some())
+        else {
+            let error = GString::from(format!("result {result}, HTTP {response_code}"));
+            godot_warn!("PartyClient: {} failed, {error}", action.name());
+            self.base_mut().emit_signal(
+                "party_request_failed".into(),
+                &[
+                    GString::from(action.name()).to_variant(),
+                    error.to_variant(),
+                ],
+            );
+            return;
+        };
+        self.update(&party);
+    }
+}
+
+impl PartyClient {
+    fn backend(&self) -> Option<Gd<BackendClient>> {
+        let node = self.base().get_node_or_null(self.backend_client.clone())?;
+        return node.try_cast::<BackendClient>().ok();
+    }
+
+    fn send(
+        &mut self,
+        action: PartyAction,
+        path: &str,
+        method: Method,
+        body: Option<Dictionary>,
+    ) -> bool {
+        self.since_poll = 0.0;
+        if self.request.is_some() {
+            godot_warn!(
+                "PartyClient: can't {} while another request is running",
+                action.name()
+            );
+            return false;
+        }
+        let Some(backend) = self.backend() else {
+            return false;
+        };
+        let (url, access_token) = {
+            let backend = backend.bind();
+            (
+                format!("{}/{path}", backend.api_url()),
+                backend.access_token().clone(),
+            )
+        };
+        if access_token.is_empty() {
+            return false;
+        }
+
+        let callback = Callable::from_object_method(&self.to_gd(), "on_request_completed");
+        let Some(request) = backend::start_request(
+            &mut self.base_mut(),
+            callback,
+            &url,
+            method,
+            &access_token,
+            body,
+        ) else {
+            return false;
+        };
+        self.request = Some((request, action));
+        return true;
+    }
+
+    fn update(&mut self, party: &Dictionary) {
+        let party_id = backend::get_string(party, "party_id").unwrap_or_default();
+        let leader_id = backend::get_string(party, "leader_id").unwrap_or_default();
+        let mut members = Array::new();
+        let listed = party
+            .get("members")
+            .and_then(|members| members.try_to::<VariantArray>().ok())
+            .unwrap_or_default();
+        for member in listed.iter_shared() {
+            let Ok(member) = member.try_to::<Dictionary>() else {
+                continue;
+            };
+            let Some(account_id) = backend::get_string(&member, "account_id") else {
+                continue;
+            };
+            let mut entry = Dictionary::new();
+            entry.set("account_id", account_id);
+            entry.set(
+                "name",
+                backend::get_string(&member, "name").unwrap_or_default(),
+            );
+            members.push(entry);
+        }
+
+        if party_id != self.party_id {
+            self.party_id = party_id.clone();
+            self.match_id = GString::new();
+            self.base_mut()
+                .emit_signal("party_joined".into(), &[party_id.to_variant()]);
+        }
+        if members != self.members || leader_id != self.leader_id {
+            self.members = members;
+            self.leader_id = leader_id;
+            let (members, leader_id) = (self.members.duplicate_deep(), self.leader_id.clone());
+            self.base_mut().emit_signal(
+                "party_members_changed".into(),
+                &[members.to_variant(), leader_id.to_variant()],
+            );
+        }
+
+        let found = party
+            .get("match")
+            .and_then(|found| found.try_to::<Dictionary>().ok())
+            .and_then(|found| {
+                let match_id = backend::get_string(&found, "match_id")?;
+                return Some((match_id, backend::get_connect_token(&found)?));
+            });
+        let Some((match_id, connect_token)) = found else {
+            return;
+        };
+        if match_id == self.match_id {
+            return;
+        }
+        self.match_id = match_id.clone();
+        self.base_mut().emit_signal(
+            "party_match_found".into(),
+            &[match_id.to_variant(), connect_token.to_variant()],
+        );
+        if self.session_manager.is_empty() {
+            return;
+        }
+        let path = self.session_manager.clone();
+        let Some(mut manager) = self.base().get_node_or_null(path.clone()) else {
+            godot_error!("PartyClient: no session manager found at '{path}'");
+            return;
+        };
+        manager.call(
+            "join_session_with_token".into(),
+            &[connect_token.to_variant()],
+        );
+    }
+
+    fn clear_party(&mut self) {
+        self.party_id = GString::new();
+        self.leader_id = GString::new();
+        self.members = Array::new();
+        self.match_id = GString::new();
+        self.base_mut().emit_signal("party_left".into(), &[]);
+    }
+}
+// End - Parties
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+
+use godot::{engine::Engine, prelude::*};
+
+// Metadata set on every node the spawner creates, so game scripts can tell which entity a node
+// is and who owns it without keeping their own lookup tables.
+pub const META_ENTITY_ID: &str = "network_entity_id";
+pub const META_OWNER_ID: &str = "network_owner_id";
+
+// Start - Spawns scenes when the server tells us to
+// This is the same idea as Godot's MultiplayerSpawner, but driven by the spawn/despawn messages
+// the GameplaySessionManager receives over renet instead of the SceneMultiplayer API.
+//
+// A tool class so the editor shows its configuration warnings.
+#[derive(GodotClass)]
+#[class(init, tool, base=Node)]
+struct NetworkSpawner {
+    base: Base<Node>,
+
+    // The GameplaySessionManager whose spawn/despawn messages this spawner listens to.
+    #[export]
+    session_manager: NodePath,
+
+    // Spawned scenes are added as children of this node. Like MultiplayerSpawner, the path is
+    // relative to the spawner.
+    #[export]
+    spawn_path: NodePath,
+
+    // The server refers to scenes by their index in this list, so the order must match the server's.
+    #[export]
+    spawnable_scenes: Array<Gd<PackedScene>>,
+
+    spawned: HashMap<u64, Gd<Node>>,
+}
+
+#[godot_api]
+impl INode for NetworkSpawner {
+    fn ready(&mut self) {
+        if Engine::singleton().is_editor_hint() {
+            return;
+        }
+
+        let Some(mut manager) = self.base().get_node_or_null(self.session_manager.clone()) else {
+            godot_error!(
+                "NetworkSpawner: no session manager found at '{}'",
+                self.session_manager
+            );
+            return;
+        };
+
+        let spawner = self.to_gd();
+        manager.connect(
+            "entity_spawned".into(),
+            Callable::from_object_method(&spawner, "on_entity_spawned"),
+        );
+        manager.connect(
+            "entity_despawned".into(),
+            Callable::from_object_method(&spawner, "on_entity_despawned"),
+        );
+        manager.connect(
+            "authority_changed".into(),
+            Callable::from_object_method(&spawner, "on_authority_changed"),
+        );
+        // Whatever the server spawned is meaningless once the connection is gone.
+        manager.connect(
+            "lost_connection".into(),
+            Callable::from_object_method(&spawner, "on_lost_connection"),
+        );
+    }
+
+    fn get_configuration_warnings(&self) -> PackedStringArray {
+        let mut warnings = PackedStringArray::new();
+        if self.session_manager.is_empty() {
+            warnings.push("Set session_manager to the GameplaySessionManager to spawn for.".into());
+        } else if let Some(node) = self.base().get_node_or_null(self.session_manager.clone()) {
+            // Autoloads don't resolve in the editor, so only a node that is there and wrong is flagged.
+            if !node.is_class("GameplaySessionManager".into()) {
+                warnings.push("session_manager doesn't point at a GameplaySessionManager.".into());
+            }
+        }
+        if self.spawn_path.is_empty() {
+            warnings.push("Set spawn_path to the node spawned scenes are added to.".into());
+        }
+        if self.spawnable_scenes.is_empty() {
+            warnings.push(
+                "spawnable_scenes is empty, so nothing the server spawns can be shown.".into(),
+            );
+        }
+        return warnings;
+    }
+}
+
+#[godot_api]
+impl NetworkSpawner {
+    #[signal]
+    fn spawned(node: Gd<Node>);
+
+    #[signal]
+    fn despawned(node: Gd<Node>);
+
+    /// Returns the node spawned for the entity, or null if the entity isn't spawned.
+    #[func]
+    fn get_entity_node(&self, entity_id: i64) -> Option<Gd<Node>> {
+        return self.spawned.get(&(entity_id as u64)).cloned();
+    }
+
+    /// Frees every node this spawner created.
+    #[func]
+    fn despawn_all(&mut self) {
+        let entity_ids: Vec<u64> = self.spawned.keys().copied().collect();
+        for entity_id in entity_ids {
+            self.on_entity_despawned(entity_id as i64);
+        }
+    }
+
+    #[func]
+    fn on_lost_connection(&mut self, _reason: GString) {
+        self.despawn_all();
+    }
+
+    #[func]
+    fn on_entity_spawned(&mut self, entity_id: i64, scene_index: i64, owner_id: i64) {
+        // Nodes game code freed itself aren't spawned anymore, so the server can spawn their entities again,
+        // like after a reconnect or a full snapshot.
+        self.spawned
+            .retain(|_, node| node.is_instance_valid() && !node.is_queued_for_deletion());
+        if self.spawned.contains_key(&(entity_id as u64)) {
+            godot_warn!("NetworkSpawner: entity {entity_id} is already spawned");
+            return;
+        }
+
+        let Some(scene) = usize::try_from(scene_index)
+            .ok()
+            .and_then(|index| self.spawnable_scenes.iter_shared().nth(index))
+        else {
+            godot_error!("NetworkSpawner: no spawnable scene at index {scene_index}");
+            return;
+        };
+
+        let Some(mut parent) = self.base().get_node_or_null(self.spawn_path.clone()) else {
+            godot_error!(
+                "NetworkSpawner: no spawn parent found at '{}'",
+                self.spawn_path
+            );
+            return;
+        };
+
+        let Some(mut node) = scene.instantiate() else {
+            godot_error!("NetworkSpawner: failed to instantiate scene at index {scene_index}");
+            return;
+        };
+
+        // Naming the node after the entity keeps node paths identical on every client.
+        node.set_name(entity_id.to_string().into());
+        node.set_meta(META_ENTITY_ID.into(), entity_id.to_variant());
+        node.set_meta(META_OWNER_ID.into(), owner_id.to_variant());
+        parent.add_child(node.clone());
+
+        self.spawned.insert(entity_id as u64, node.clone());
+        self.base_mut()
+            .emit_signal("spawned".into(), &[node.to_variant()]);
+    }
+
+    // Keeps the owner metadata on the spawned node in sync with the server.
+    #[func]
+    fn on_authority_changed(&mut self, entity_id: i64, owner_id: i64) {
+        if let Some(node) = self.spawned.get_mut(&(entity_id as u64)) {
+            if node.is_instance_valid() {
+                node.set_meta(META_OWNER_ID.into(), owner_id.to_variant());
+            }
+        }
+    }
+
+    #[func]
+    fn on_entity_despawned(&mut self, entity_id: i64) {
+        let Some(mut node) = self.spawned.remove(&(entity_id as u64)) else {
+            return;
+        };
+
+        // The node might have been freed by game code already.
+        if !node.is_instance_valid() {
+            return;
+        }
+
+        self.base_mut()
+            .emit_signal("despawned".into(), &[node.to_variant()]);
+        node.queue_free();
+    }
+}
+// End - Spawns scenes when the server tells us to
@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use godot::{engine::HttpRequest, prelude::*};
+
+// Start - Measuring download speed
+// Downloads a file over HTTP and times it, to estimate how much the connection can take before the game picks
+// snapshot rates or warns about modes that stream a lot. Connecting and the server's time to first byte say
+// nothing about throughput, so the clock starts once the first bytes are in. Compression is turned off so the
+// bytes counted are the bytes that came over the wire.
+//
+// A few MB from a CDN near the game servers works well. Too small and the download is over before TCP gets up
+// to speed, too big and players wait, which is what `max_seconds` is for.
+
+pub struct SpeedTest {
+    pub request: Gd<HttpRequest>,
+    // When the first bytes were seen, and how many there were by then.
+    first_bytes: Option<(Instant, i32)>,
+    // The newest count, and when it was seen.
+    last_bytes: Option<(Instant, i32)>,
+}
+
+impl SpeedTest {
+    pub fn new(request: Gd<HttpRequest>) -> Self {
+        return Self {
+            request,
+            first_bytes: None,
+            last_bytes: None,
+        };
+    }
+
+    /// Call every tick while the download runs.
+    pub fn update(&mut self) {
+        let downloaded = self.request.get_downloaded_bytes();
+        if downloaded <= 0 {
+            return;
+        }
+        let now = Instant::now();
+        if self.first_bytes.is_none() {
+            self.first_bytes = Some((now, downloaded));
+        }
+        if self.last_bytes.is_none_or(|(_, last)| downloaded > last) {
+            self.last_bytes = Some((now, downloaded));
+        }
+    }
+
+    /// Bytes per second between the first and last bytes seen, None if too little arrived to tell.
+    pub fn bytes_per_second(&mut self) -> Option<f64> {
+        self.update();
+        let ((start, first), (end, last)) = (self.first_bytes?, self.last_bytes?);
+        let seconds = end.duration_since(start).as_secs_f64();
+        if last <= first || seconds <= 0.0 {
+            return None;
+        }
+        return Some((last - first) as f64 / seconds);
+    }
+}
+// End - Measuring download speed
@@ -0,0 +1,406 @@
+use std::{
+    collections::{hash_map::RandomState, VecDeque},
+    hash::{BuildHasher, Hasher},
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    time::Instant,
+};
+
+use godot::{engine::Ip, prelude::*};
+
+use crate::transport::{self, SocketOptions};
+
+// Start - Finding our public address with STUN
+// A STUN server answers a binding request with the address and port it saw the request come from, which is
+// the mapping our NAT made for the socket. Only the binding request from RFC 5389 is used, without
+// authentication, which every public STUN server answers.
+//
+// The mapping belongs to the socket, so the socket is handed to the next session instead of being closed.
+// That way the public endpoint is also the one the session uses.
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_SIZE: usize = 20;
+
+const ATTRIBUTE_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTRIBUTE_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const FAMILY_IPV4: u8 = 0x01;
+const FAMILY_IPV6: u8 = 0x02;
+
+// The request is sent again this often until an answer arrives, since it's a single UDP packet.
+const RETRANSMIT_INTERVAL: f64 = 0.5;
+pub const STUN_TIMEOUT: f64 = 3.0;
+
+pub struct StunQuery {
+    socket: UdpSocket,
+    server: SocketAddr,
+    transaction_id: [u8; 12],
+    started_at: Instant,
+    requests_sent: u32,
+    receive_buffer: [u8; 512],
+}
+
+impl StunQuery {
+    #[cfg(target_family = "wasm")]
+    pub fn new(_server: SocketAddr, _options: &SocketOptions) -> io::Result<Self> {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "UDP sockets aren't available in web builds",
+        ));
+    }
+
+    // Bound like a session's socket would be, since the next session takes it over.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn new(server: SocketAddr, options: &SocketOptions) -> io::Result<Self> {
+        let socket = transport::bind_socket(server, options)?;
+        socket.set_nonblocking(true)?;
+        return Self::with_socket(socket, server);
+    }
+
+    /// Asks on a socket we already have, to learn its mapping.
+    pub fn with_socket(socket: UdpSocket, server: SocketAddr) -> io::Result<Self> {
+        // Only has to be unpredictable enough that stray packets don't match.
+        let mut transaction_id = [0; 12];
+        let random = RandomState::new().build_hasher().finish();
+        transaction_id[..8].copy_from_slice(&random.to_le_bytes());
+        transaction_id[8..].copy_from_slice(&std::process::id().to_le_bytes());
+        return Ok(Self {
+            socket,
+            server,
+            transaction_id,
+            started_at: Instant::now(),
+            requests_sent: 0,
+            receive_buffer: [0; 512],
+        });
+    }
+
+    /// Sends and receives. Returns the public endpoint once the server answered, or an error once it timed
+    /// out. None while still waiting.
+    pub fn update(&mut self) -> Option<io::Result<SocketAddr>> {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        if elapsed >= self.requests_sent as f64 * RETRANSMIT_INTERVAL {
+            self.requests_sent += 1;
+            let request = binding_request(&self.transaction_id);
+            if let Err(error) = self.socket.send_to(&request, self.server) {
+                return Some(Err(error));
+            }
+        }
+
+        // Nonblocking, so this stops as soon as nothing is waiting.
+        while let Ok((size, from)) = self.socket.recv_from(&mut self.receive_buffer) {
+            if from != self.server {
+                continue;
+            }
+            if let Some(endpoint) =
+                parse_response(&self.receive_buffer[..size], &self.transaction_id)
+            {
+                return Some(Ok(endpoint));
+            }
+        }
+
+        if elapsed >= STUN_TIMEOUT {
+            return Some(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("the STUN server {} didn't answer", self.server),
+            )));
+        }
+        return None;
+    }
+
+    pub fn into_socket(self) -> UdpSocket {
+        return self.socket;
+    }
+
+    pub fn local_port(&self) -> Option<u16> {
+        return self.socket.local_addr().ok().map(|address| address.port());
+    }
+}
+
+fn binding_request(transaction_id: &[u8; 12]) -> [u8; HEADER_SIZE] {
+    let mut request = [0; HEADER_SIZE];
+    request[0..2].copy_from_slice(&BINDING_REQUEST.to_be_bytes());
+    // No attributes, so the length stays 0.
+    request[4..8].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    request[8..].copy_from_slice(transaction_id);
+    return request;
+}
+
+// Prefers XOR-MAPPED-ADDRESS, which NATs that rewrite addresses inside packets can't mangle. Old servers
+// only send MAPPED-ADDRESS.
+fn parse_response(packet: &[u8], transaction_id: &[u8; 12]) -> Option<SocketAddr> {
+    if packet.len() < HEADER_SIZE
+        || u16::from_be_bytes([packet[0], packet[1]]) != BINDING_SUCCESS
+        || packet[4..8] != MAGIC_COOKIE.to_be_bytes()
+        || packet[8..HEADER_SIZE] != transaction_id[..]
+    {
+        return None;
+    }
+
+    let length = u16::from_be_bytes([packet[2], packet[3]]) as usize;
+    let mut attributes = packet.get(HEADER_SIZE..HEADER_SIZE + length)?;
+    let mut mapped = None;
+    while attributes.len() >= 4 {
+        let kind = u16::from_be_bytes([attributes[0], attributes[1]]);
+        let size = u16::from_be_bytes([attributes[2], attributes[3]]) as usize;
+        let value = attributes.get(4..4 + size)?;
+        match kind {
+            ATTRIBUTE_XOR_MAPPED_ADDRESS => return parse_address(value, Some(transaction_id)),
+            ATTRIBUTE_MAPPED_ADDRESS => mapped = parse_address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to 4 bytes.
+        let padded = (4 + size + 3) & !3;
+        attributes = attributes.get(padded..).unwrap_or_default();
+    }
+    return mapped;
+}
+
+// XORed addresses are XORed with the magic cookie, followed by the transaction id for IPv6.
+fn parse_address(value: &[u8], xor_transaction_id: Option<&[u8; 12]>) -> Option<SocketAddr> {
+    if value.len() < 4 {
+        return None;
+    }
+    let mut mask = [0; 16];
+    if let Some(transaction_id) = xor_transaction_id {
+        mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        mask[4..].copy_from_slice(transaction_id);
+    }
+
+    let port = u16::from_be_bytes([value[2] ^ mask[0], value[3] ^ mask[1]]);
+    let ip = match value[1] {
+        FAMILY_IPV4 => {
+            let bytes: [u8; 4] = value.get(4..8)?.try_into().ok()?;
+            let bytes: [u8; 4] = std::array::from_fn(|i| bytes[i] ^ mask[i]);
+            IpAddr::V4(Ipv4Addr::from(bytes))
+        }
+        FAMILY_IPV6 => {
+            let bytes: [u8; 16] = value.get(4..20)?.try_into().ok()?;
+            let bytes: [u8; 16] = std::array::from_fn(|i| bytes[i] ^ mask[i]);
+            IpAddr::V6(Ipv6Addr::from(bytes))
+        }
+        _ => return None,
+    };
+    return Some(SocketAddr::new(ip, port));
+}
+// End - Finding our public address with STUN
+
+// Start - NAT type
+// The same socket asks two STUN servers at different addresses for its public endpoint:
+//   open      the endpoint is our own address and port, nothing rewrites our packets
+//   moderate  both servers saw the same endpoint, so the NAT keeps one mapping per socket and a peer that
+//             learns it can usually reach us with hole punching
+//   strict    each server saw another endpoint, the NAT maps every destination separately (symmetric NAT)
+//             and the endpoint we'd tell a peer is useless to it. Two strict players need a relay.
+// Full cone and restricted NATs both come out as moderate, telling them apart needs a server that can
+// answer from another address, which public STUN servers have stopped doing.
+
+pub const NAT_UNKNOWN: u8 = 0;
+pub const NAT_OPEN: u8 = 1;
+pub const NAT_MODERATE: u8 = 2;
+pub const NAT_STRICT: u8 = 3;
+
+pub struct NatDetector {
+    query: Option<StunQuery>,
+    // Servers still to ask after the running query, in the order they were given.
+    remaining: VecDeque<SocketAddr>,
+    endpoints: Vec<SocketAddr>,
+    local_port: Option<u16>,
+}
+
+impl NatDetector {
+    /// `servers` have to be the same address family, see `StunQuery::new` for the socket.
+    pub fn new(servers: &[SocketAddr]) -> io::Result<Self> {
+        let Some((&first, rest)) = servers.split_first() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no STUN servers to ask",
+            ));
+        };
+        let query = StunQuery::new(first, &SocketOptions::default())?;
+        return Ok(Self {
+            local_port: query.local_port(),
+            query: Some(query),
+            remaining: rest.iter().copied().collect(),
+            endpoints: Vec::new(),
+        });
+    }
+
+    /// Returns one of the NAT_ constants once every server answered or timed out, None while still waiting.
+    pub fn update(&mut self) -> Option<u8> {
+        let query = self.query.as_mut()?;
+        let result = query.update()?;
+        let query = self.query.take().unwrap();
+        match result {
+            Ok(endpoint) => self.endpoints.push(endpoint),
+            Err(error) => godot_warn!("NAT type detection: {error}"),
+        }
+
+        if let Some(next) = self.remaining.pop_front() {
+            match StunQuery::with_socket(query.into_socket(), next) {
+                Ok(query) => {
+                    self.query = Some(query);
+                    return None;
+                }
+                Err(error) => godot_warn!("NAT type detection: {error}"),
+            }
+        }
+        return Some(self.classify());
+    }
+
+    fn classify(&self) -> u8 {
+        let Some(&first) = self.endpoints.first() else {
+            return NAT_UNKNOWN;
+        };
+        if Some(first.port()) == self.local_port && is_own_address(first.ip()) {
+            return NAT_OPEN;
+        }
+        // A single answer can't tell the other two apart.
+        if self.endpoints.len() < 2 {
+            return NAT_UNKNOWN;
+        }
+        if self.endpoints.iter().all(|&endpoint| endpoint == first) {
+            return NAT_MODERATE;
+        }
+        return NAT_STRICT;
+    }
+}
+
+// An endpoint that is one of our own addresses means nothing rewrote it on the way. Compared as parsed
+// addresses, since Godot's text for an IPv6 address can differ from ours, zone index included.
+pub fn is_own_address(ip: IpAddr) -> bool {
+    return Ip::singleton()
+        .get_local_addresses()
+        .as_slice()
+        .iter()
+        .filter_map(|address| transport::parse_ip(&address.to_string()))
+        .any(|address| address == ip);
+}
+// End - NAT type
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Transaction id and answers from the sample responses in RFC 5769, section 2.
+    const TRANSACTION_ID: [u8; 12] = [
+        0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    ];
+
+    #[rustfmt::skip]
+    const IPV4_RESPONSE: [u8; 80] = [
+        0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86,
+        0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63,
+        0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43,
+        0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99, 0xfd, 0x9e, 0x90, 0xc3, 0x8c, 0x74, 0x89, 0xf9,
+        0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7, 0x80, 0x28, 0x00, 0x04, 0xc0, 0x7d, 0x4c, 0x96,
+    ];
+
+    #[rustfmt::skip]
+    const IPV6_RESPONSE: [u8; 92] = [
+        0x01, 0x01, 0x00, 0x48, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86,
+        0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63,
+        0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xa1, 0x47, 0x01, 0x13, 0xa9, 0xfa,
+        0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9, 0x00, 0x08, 0x00, 0x14,
+        0xa3, 0x82, 0x95, 0x4e, 0x4b, 0xe6, 0x7b, 0xf1, 0x17, 0x84, 0xc9, 0x7c, 0x82, 0x92, 0xc2, 0x75,
+        0xbf, 0xe3, 0xed, 0x41, 0x80, 0x28, 0x00, 0x04, 0xc8, 0xfb, 0x0b, 0x4c,
+    ];
+
+    fn response(attributes: &[u8]) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&BINDING_SUCCESS.to_be_bytes());
+        packet.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        packet.extend_from_slice(&TRANSACTION_ID);
+        packet.extend_from_slice(attributes);
+        return packet;
+    }
+
+    // MAPPED-ADDRESS of 198.51.100.7:4000, which isn't XORed.
+    const MAPPED_ATTRIBUTE: [u8; 12] = [
+        0x00, 0x01, 0x00, 0x08, 0x00, 0x01, 0x0f, 0xa0, 198, 51, 100, 7,
+    ];
+
+    #[test]
+    fn xor_mapped_ipv4() {
+        let endpoint = parse_response(&IPV4_RESPONSE, &TRANSACTION_ID);
+        assert_eq!(endpoint, Some("192.0.2.1:32853".parse().unwrap()));
+    }
+
+    #[test]
+    fn xor_mapped_ipv6() {
+        let endpoint = parse_response(&IPV6_RESPONSE, &TRANSACTION_ID);
+        assert_eq!(
+            endpoint,
+            Some(
+                "[2001:db8:1234:5678:11:2233:4455:6677]:32853"
+                    .parse()
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn mapped_address_is_the_fallback() {
+        let packet = response(&MAPPED_ATTRIBUTE);
+        let endpoint = parse_response(&packet, &TRANSACTION_ID);
+        assert_eq!(endpoint, Some("198.51.100.7:4000".parse().unwrap()));
+    }
+
+    #[test]
+    fn xor_mapped_address_wins_over_mapped_address() {
+        let mut attributes = MAPPED_ATTRIBUTE.to_vec();
+        attributes.extend_from_slice(&IPV4_RESPONSE[36..48]);
+        let endpoint = parse_response(&response(&attributes), &TRANSACTION_ID);
+        assert_eq!(endpoint, Some("192.0.2.1:32853".parse().unwrap()));
+    }
+
+    #[test]
+    fn answers_to_other_requests_are_ignored() {
+        let mut transaction_id = TRANSACTION_ID;
+        transaction_id[11] ^= 1;
+        assert_eq!(parse_response(&IPV4_RESPONSE, &transaction_id), None);
+
+        let mut packet = IPV4_RESPONSE;
+        packet[4] ^= 1;
+        assert_eq!(parse_response(&packet, &TRANSACTION_ID), None);
+
+        let mut packet = IPV4_RESPONSE;
+        packet[1] = 0x11;
+        assert_eq!(parse_response(&packet, &TRANSACTION_ID), None);
+    }
+
+    #[test]
+    fn truncated_attributes_are_rejected() {
+        // The attribute says 8 bytes but only has 6.
+        let packet = response(&MAPPED_ATTRIBUTE[..10]);
+        assert_eq!(parse_response(&packet, &TRANSACTION_ID), None);
+
+        // An IPv6 address needs 16 bytes.
+        let attribute = [0x00, 0x01, 0x00, 0x08, 0x00, 0x02, 0x0f, 0xa0, 0, 0, 0, 0];
+        assert_eq!(parse_response(&response(&attribute), &TRANSACTION_ID), None);
+
+        assert_eq!(
+            parse_response(&IPV4_RESPONSE[..HEADER_SIZE - 1], &TRANSACTION_ID),
+            None
+        );
+    }
+
+    #[test]
+    fn a_length_past_the_end_is_rejected() {
+        let mut packet = response(&MAPPED_ATTRIBUTE);
+        packet[3] += 4;
+        assert_eq!(parse_response(&packet, &TRANSACTION_ID), None);
+
+        let packet = &IPV4_RESPONSE[..IPV4_RESPONSE.len() - 4];
+        assert_eq!(parse_response(packet, &TRANSACTION_ID), None);
+    }
+
+    #[test]
+    fn binding_requests_have_no_attributes() {
+        let request = binding_request(&TRANSACTION_ID);
+        assert_eq!(request[..4], [0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(request[4..8], MAGIC_COOKIE.to_be_bytes());
+        assert_eq!(request[8..], TRANSACTION_ID);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket},
+    time::Duration,
+};
+
+#[cfg(not(target_family = "wasm"))]
+use std::net::Ipv4Addr;
+
+#[cfg(not(target_family = "wasm"))]
+use socket2::{Domain, Protocol, Socket, Type};
+
+use godot::{
+    engine::{global::PropertyHint, Ip, ProjectSettings},
+    obj::EngineEnum,
+    prelude::*,
+};
+use renet::transport::{ClientAuthentication, NetcodeClientTransport, NetcodeTransportError};
+
+// Start - Platform specific transport setup
+// Netcode already sends a keep-alive packet several times a second while nothing else is sent, which is far
+// more often than cellular NATs (30 seconds and up) forget a mapping, so the socket doesn't need its own.
+
+// How the socket is bound. The defaults are what the OS picks, which suits almost everyone. Web builds
+// have no socket to bind, so nothing reads these there.
+#[derive(Default, Clone)]
+#[cfg_attr(target_family = "wasm", allow(dead_code))]
+pub struct SocketOptions {
+    // None binds every local address of the server's family.
+    pub bind_address: Option<IpAddr>,
+    // The addresses of the interface to bind, from which the first of the server's family is used. Takes
+    // precedence over `bind_address`.
+    pub interface_addresses: Vec<IpAddr>,
+    // The interface's index, which binding to its link-local IPv6 address needs.
+    pub interface_index: Option<u32>,
+    // 0 lets the OS pick a free port. A fixed port is for firewalls that only allow known ports.
+    pub bind_port: u16,
+    // 0 keeps the OS default. The OS may round or clamp these.
+    pub send_buffer_size: usize,
+    pub receive_buffer_size: usize,
+    // 0 leaves packets unmarked, see `dscp_setting`.
+    pub dscp: u8,
+}
+
+// `socket` is used instead of binding a new one when given, like the one a STUN query learned the public
+// endpoint of. It has to be the same family as the server.
+#[cfg(not(target_family = "wasm"))]
+pub fn create_transport(
+    server_addr: SocketAddr,
+    current_time: Duration,
+    authentication: ClientAuthentication,
+    socket: Option<UdpSocket>,
+    options: &SocketOptions,
+) -> Result<NetcodeClientTransport, NetcodeTransportError> {
+    let socket = match socket {
+        Some(socket) => socket,
+        None => bind_socket(server_addr, options)?,
+    };
+    return Ok(NetcodeClientTransport::new(
+        current_time,
+        authentication,
+        socket,
+    )?);
+}
+
+// There are no UDP sockets in the browser. This keeps the rest of the extension usable in web exports, and
+// is where a WebSocket or WebRTC transport would go.
+#[cfg(target_family = "wasm")]
+pub fn create_transport(
+    _server_addr: SocketAddr,
+    _current_time: Duration,
+    _authentication: ClientAuthentication,
+    _socket: Option<UdpSocket>,
+    _options: &SocketOptions,
+) -> Result<NetcodeClientTransport, NetcodeTransportError> {
+    return Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "UDP sockets aren't available in web builds",
+    )
+    .into());
+}
+
+// The socket has to be the same family as the server. Mobile networks are often IPv6 only, or IPv4 only
+// on older carriers, and a dual stack socket isn't available everywhere (iOS refuses to send to an IPv4
+// address from an IPv6 socket).
+// Netcode needs the socket nonblocking and makes it so itself, which is why there is no option for it.
+#[cfg(not(target_family = "wasm"))]
+pub fn bind_socket(server_addr: SocketAddr, options: &SocketOptions) -> io::Result<UdpSocket> {
+    let interface_ip = options
+        .interface_addresses
+        .iter()
+        .find(|address| address.is_ipv4() == server_addr.is_ipv4())
+        .copied();
+    if !options.interface_addresses.is_empty() && interface_ip.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::AddrNotAvailable,
+            format!("the bind interface has no address that can reach {server_addr}"),
+        ));
+    }
+
+    let local_ip = interface_ip
+        .or(options.bind_address)
+        .unwrap_or(match server_addr {
+            SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        });
+    if local_ip.is_ipv4() != server_addr.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("can't reach {server_addr} from the bind address {local_ip}"),
+        ));
+    }
+
+    let socket = Socket::new(
+        Domain::for_address(server_addr),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if options.send_buffer_size > 0 {
+        socket.set_send_buffer_size(options.send_buffer_size)?;
+    }
+    if options.receive_buffer_size > 0 {
+        socket.set_recv_buffer_size(options.receive_buffer_size)?;
+    }
+    if options.dscp > 0 {
+        set_dscp(&socket, server_addr, options.dscp);
+    }
+    // Link-local addresses exist once per interface, so binding one needs to say which. Without an interface
+    // of our own, the one the server's zone index names is it.
+    let bind_addr = match local_ip {
+        IpAddr::V6(ip) if is_link_local(&ip) => {
+            let scope_id = match server_addr {
+                SocketAddr::V6(server) => options.interface_index.unwrap_or(server.scope_id()),
+                SocketAddr::V4(_) => 0,
+            };
+            SocketAddr::V6(SocketAddrV6::new(ip, options.bind_port, 0, scope_id))
+        }
+        _ => SocketAddr::new(local_ip, options.bind_port),
+    };
+    socket.bind(&bind_addr.into())?;
+    return Ok(socket.into());
+}
+
+/// Parses "address:port" like std does, and also link-local IPv6 addresses with a zone index, like
+/// "[fe80::1%eth0]:7000" or "[fe80::1%2]:7000". Those are how LANs on IPv6 only networks reach each other,
+/// and the zone says which interface the address is on. std doesn't parse zones.
+pub fn parse_address(address: &str) -> io::Result<SocketAddr> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid server address '{address}'"),
+        )
+    };
+    let zoned = address
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once("]:"))
+        .and_then(|(ip, port)| Some((ip.split_once('%')?, port)));
+    let Some(((ip, zone), port)) = zoned else {
+        return address.parse().map_err(|_| invalid());
+    };
+
+    let ip: Ipv6Addr = ip.parse().map_err(|_| invalid())?;
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    let scope_id = match zone.parse::<u32>() {
+        Ok(index) => index,
+        Err(_) => interface_index(zone).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                format!("no network interface named '{zone}' in '{address}'"),
+            )
+        })?,
+    };
+    return Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)));
+}
+
+/// Parses an IP address without a port, dropping any zone index.
+pub fn parse_ip(address: &str) -> Option<IpAddr> {
+    let ip = address.split('%').next()?;
+    return ip.parse().ok();
+}
+
+pub fn interface_index(name: &str) -> Option<u32> {
+    let interface = Ip::singleton()
+        .get_local_interfaces()
+        .iter_shared()
+        .find(|interface| {
+            let interface_name = interface.get("name").map(|name| name.to_string());
+            return interface_name.as_deref() == Some(name);
+        })?;
+    return interface.get("index")?.to_string().parse().ok();
+}
+
+// fe80::/10. Ipv6Addr::is_unicast_link_local isn't stable yet.
+#[cfg(not(target_family = "wasm"))]
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    return ip.segments()[0] & 0xffc0 == 0xfe80;
+}
+
+// DSCP is the top 6 bits of the IPv4 TOS byte and the IPv6 traffic class. Marking is only a hint and some
+// platforms refuse it (Windows wants its QoS API instead), so a failure is a warning and the socket is used
+// unmarked.
+#[cfg(not(target_family = "wasm"))]
+fn set_dscp(socket: &Socket, server_addr: SocketAddr, dscp: u8) {
+    let tos = (dscp as u32) << 2;
+    let result = match server_addr {
+        SocketAddr::V4(_) => socket.set_tos(tos),
+        #[cfg(unix)]
+        SocketAddr::V6(_) => socket.set_tclass_v6(tos),
+        #[cfg(not(unix))]
+        SocketAddr::V6(_) => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no IPv6 traffic class on this platform",
+        )),
+    };
+    if let Err(error) = result {
+        godot_warn!("Couldn't mark packets with DSCP {dscp}, sending them unmarked: {error}");
+    }
+}
+
+// Set in Project Settings, because it's the same for every session and most games never touch it. 46
+// (expedited forwarding) is what routers that prioritize anything usually prioritize.
+pub const DSCP_SETTING: &str = "network/arcade_client/dscp";
+
+// Shows the settings in Project Settings. They only get saved to project.godot when changed.
+pub fn register_project_settings() {
+    let mut settings = ProjectSettings::singleton();
+    if !settings.has_setting(DSCP_SETTING.into()) {
+        settings.set_setting(DSCP_SETTING.into(), 0.to_variant());
+    }
+    settings.set_initial_value(DSCP_SETTING.into(), 0.to_variant());
+
+    let mut info = Dictionary::new();
+    info.set("name", DSCP_SETTING);
+    info.set("type", VariantType::INT.ord());
+    info.set("hint", PropertyHint::RANGE.ord());
+    info.set("hint_string", "0,63");
+    settings.add_property_info(info);
+}
+
+pub fn dscp_setting() -> u8 {
+    let value = ProjectSettings::singleton()
+        .get_setting_ex(DSCP_SETTING.into())
+        .default_value(0.to_variant())
+        .done();
+    return value.try_to::<i64>().unwrap_or(0).clamp(0, 63) as u8;
+}
+
+pub enum SocketErrorKind {
+    // The app isn't allowed to use the network. On Android the INTERNET permission is missing, on iOS the
+    // user turned off network access for the app or local network access was denied.
+    PermissionDenied,
+    // No route to the server. Usually the device is offline, in airplane mode, or switching networks.
+    NetworkUnreachable,
+    // The local address went away, which mobile devices do when they move between wifi and cellular.
+    AddressUnavailable,
+    Other,
+}
+
+pub fn classify_socket_error(error: &io::Error) -> SocketErrorKind {
+    if error.kind() == io::ErrorKind::PermissionDenied {
+        return SocketErrorKind::PermissionDenied;
+    }
+    if error.kind() == io::ErrorKind::AddrNotAvailable {
+        return SocketErrorKind::AddressUnavailable;
+    }
+
+    // ErrorKind has no stable variant for these, so the raw errno values are checked.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const UNREACHABLE: [i32; 2] = [101, 113]; // ENETUNREACH, EHOSTUNREACH
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    const UNREACHABLE: [i32; 2] = [51, 65]; // ENETUNREACH, EHOSTUNREACH
+    #[cfg(windows)]
+    const UNREACHABLE: [i32; 2] = [10051, 10065]; // WSAENETUNREACH, WSAEHOSTUNREACH
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        windows
+    )))]
+    const UNREACHABLE: [i32; 0] = [];
+
+    if let Some(code) = error.raw_os_error() {
+        if UNREACHABLE.contains(&code) {
+            return SocketErrorKind::NetworkUnreachable;
+        }
+    }
+
+    return SocketErrorKind::Other;
+}
+// End - Platform specific transport setup
@@ -0,0 +1,162 @@
+use crate::{
+    presence::MAX_RICH_PRESENCE_SIZE,
+    protocol::{self, ServerMessage},
+};
+
+// Start - Checks messages from the server before GDScript sees them
+// Decoding only proves a message is long enough for its kind. These rules also catch messages that decode
+// fine but make no sense, like oversized payloads or a tick rate of 0, so game code never has to.
+// The game specific contents of application messages and snapshots are still up to the game.
+
+#[derive(Default, Clone, Copy)]
+pub struct MessageLimits {
+    // Largest payload in bytes, 0 for no limit.
+    pub max_application_size: usize,
+    pub max_snapshot_size: usize,
+}
+
+pub struct Rejection {
+    pub kind: &'static str,
+    pub reason: String,
+}
+
+impl Rejection {
+    /// For messages that couldn't be decoded at all. `message` is the raw message as it arrived.
+    pub fn malformed(message: &[u8]) -> Self {
+        return Self {
+            kind: kind_name(message.first().copied()),
+            reason: String::from("malformed"),
+        };
+    }
+}
+
+pub fn validate(message: &ServerMessage, limits: &MessageLimits) -> Result<(), Rejection> {
+    let kind = message_kind(message);
+    let reject = |reason: String| Err(Rejection { kind, reason });
+
+    match message {
+        ServerMessage::Voice { position, .. } if position.iter().any(|c| !c.is_finite()) => {
+            return reject(String::from("position isn't finite"));
+        }
+        ServerMessage::Application(payload)
+        | ServerMessage::Idempotent { payload, .. }
+        | ServerMessage::ReplicaUpdate { body: payload, .. }
+        | ServerMessage::Notification { payload, .. }
+        | ServerMessage::Voice {
+            frames: payload, ..
+        } if exceeds(payload.len(), limits.max_application_size) => {
+            return reject(format!(
+                "payload is {} bytes, the limit is {}",
+                payload.len(),
+                limits.max_application_size
+            ));
+        }
+        ServerMessage::Snapshot { payload, .. }
+            if exceeds(payload.len(), limits.max_snapshot_size) =>
+        {
+            return reject(format!(
+                "payload is {} bytes, the limit is {}",
+                payload.len(),
+                limits.max_snapshot_size
+            ));
+        }
+        // Godot's transfer modes are reliable, unreliable and unreliable ordered.
+        ServerMessage::Rpc(packet) if packet.transfer_mode > 2 => {
+            return reject(format!("unknown transfer mode {}", packet.transfer_mode));
+        }
+        ServerMessage::Chat { text, .. } if exceeds(text.len(), limits.max_application_size) => {
+            return reject(format!(
+                "text is {} bytes, the limit is {}",
+                text.len(),
+                limits.max_application_size
+            ));
+        }
+        ServerMessage::RichPresence { state, .. } if state.len() > MAX_RICH_PRESENCE_SIZE => {
+            return reject(format!(
+                "state is {} bytes, the limit is {MAX_RICH_PRESENCE_SIZE}",
+                state.len()
+            ));
+        }
+        ServerMessage::ServerInfo { tick_rate: 0, .. } => {
+            return reject(String::from("tick rate is 0"));
+        }
+        _ => {}
+    }
+
+    return Ok(());
+}
+
+fn exceeds(size: usize, limit: usize) -> bool {
+    return limit > 0 && size > limit;
+}
+
+pub fn message_kind(message: &ServerMessage) -> &'static str {
+    let kind = match message {
+        ServerMessage::Application(_) => protocol::MESSAGE_APPLICATION,
+        ServerMessage::Spawn { .. } => protocol::MESSAGE_SPAWN,
+        ServerMessage::Despawn { .. } => protocol::MESSAGE_DESPAWN,
+        ServerMessage::Authority { .. } => protocol::MESSAGE_AUTHORITY,
+        ServerMessage::Rpc(_) => protocol::MESSAGE_RPC,
+        ServerMessage::Snapshot { .. } => protocol::MESSAGE_SNAPSHOT,
+        ServerMessage::ServerInfo { .. } => protocol::MESSAGE_SERVER_INFO,
+        ServerMessage::FullSnapshot { .. } => protocol::MESSAGE_FULL_SNAPSHOT,
+        ServerMessage::FullSnapshotTooLarge { .. } => protocol::MESSAGE_FULL_SNAPSHOT_TOO_LARGE,
+        ServerMessage::SessionTakenOver => protocol::MESSAGE_SESSION_TAKEN_OVER,
+        ServerMessage::Response { .. } => protocol::MESSAGE_RESPONSE,
+        ServerMessage::Topic { .. } => protocol::MESSAGE_TOPIC,
+        ServerMessage::FormatSelected { .. } => protocol::MESSAGE_FORMAT_SELECTED,
+        ServerMessage::OutboxAck { .. } => protocol::MESSAGE_OUTBOX_ACK,
+        ServerMessage::Idempotent { .. } => protocol::MESSAGE_IDEMPOTENT,
+        ServerMessage::Checksum { .. } => protocol::MESSAGE_CHECKSUM,
+        ServerMessage::Voice { .. } => protocol::MESSAGE_VOICE,
+        ServerMessage::Chat { .. } => protocol::MESSAGE_CHAT,
+        ServerMessage::QuickChat { .. } => protocol::MESSAGE_QUICK_CHAT,
+        ServerMessage::Presence { .. } => protocol::MESSAGE_PRESENCE,
+        ServerMessage::RichPresence { .. } => protocol::MESSAGE_RICH_PRESENCE,
+        ServerMessage::ReplicaUpdate { .. } => protocol::MESSAGE_REPLICA_UPDATE,
+        ServerMessage::SaveData { .. } => protocol::MESSAGE_SAVE_DATA,
+        ServerMessage::SaveUploadResult { .. } => protocol::MESSAGE_SAVE_UPLOAD_RESULT,
+        ServerMessage::Notification { .. } => protocol::MESSAGE_NOTIFICATION,
+        ServerMessage::Pong { .. } => protocol::MESSAGE_PONG,
+        ServerMessage::MatchSeed { .. } => protocol::MESSAGE_MATCH_SEED,
+        ServerMessage::ServerConfig(_) => protocol::MESSAGE_SERVER_CONFIG,
+    };
+    return kind_name(Some(kind));
+}
+
+fn kind_name(kind: Option<u8>) -> &'static str {
+    return match kind {
+        Some(protocol::MESSAGE_APPLICATION) => "application",
+        Some(protocol::MESSAGE_SPAWN) => "spawn",
+        Some(protocol::MESSAGE_DESPAWN) => "despawn",
+        Some(protocol::MESSAGE_AUTHORITY) => "authority",
+        Some(protocol::MESSAGE_RPC) => "rpc",
+        Some(protocol::MESSAGE_SNAPSHOT) => "snapshot",
+        Some(protocol::MESSAGE_SERVER_INFO) => "server_info",
+        Some(protocol::MESSAGE_FULL_SNAPSHOT) => "full_snapshot",
+        Some(protocol::MESSAGE_BATCH) => "batch",
+        Some(protocol::MESSAGE_SESSION_TAKEN_OVER) => "session_taken_over",
+        Some(protocol::MESSAGE_RESPONSE) => "response",
+        Some(protocol::MESSAGE_TOPIC) => "topic",
+        Some(protocol::MESSAGE_FORMAT_SELECTED) => "format_selected",
+        Some(protocol::MESSAGE_OUTBOX_ACK) => "outbox_ack",
+        Some(protocol::MESSAGE_IDEMPOTENT) => "idempotent",
+        Some(protocol::MESSAGE_CHECKSUM) => "checksum",
+        Some(protocol::MESSAGE_VOICE) => "voice",
+        Some(protocol::MESSAGE_CHAT) => "chat",
+        Some(protocol::MESSAGE_QUICK_CHAT) => "quick_chat",
+        Some(protocol::MESSAGE_PRESENCE) => "presence",
+        Some(protocol::MESSAGE_RICH_PRESENCE) => "rich_presence",
+        Some(protocol::MESSAGE_REPLICA_UPDATE) => "replica_update",
+        Some(protocol::MESSAGE_SAVE_DATA) => "save_data",
+        Some(protocol::MESSAGE_SAVE_UPLOAD_RESULT) => "save_upload_result",
+        Some(protocol::MESSAGE_NOTIFICATION) => "notification",
+        Some(protocol::MESSAGE_PONG) => "pong",
+        Some(protocol::MESSAGE_MATCH_SEED) => "match_seed",
+        Some(protocol::MESSAGE_SERVER_CONFIG) => "server_config",
+        Some(protocol::MESSAGE_FULL_SNAPSHOT_TOO_LARGE) => "full_snapshot_too_large",
+        Some(_) => "unknown",
+        None => "empty",
+    };
+}
+// End - Checks messages from the server before GDScript sees them
@@ -0,0 +1,77 @@
+use godot::prelude::*;
+
+// Start - Deciding when to transmit voice
+// The game captures the microphone, usually with an AudioEffectCapture on a muted bus, and hands every chunk of
+// samples to `process_microphone` before encoding it. The gate decides whether that chunk goes out, so nothing
+// is encoded or sent while the player isn't talking. With push-to-talk that's while the input action is held,
+// with voice activation while the chunk is louder than the threshold, plus a hangover so quiet syllables and
+// the ends of words aren't cut off.
+
+pub const VOICE_ALWAYS: i64 = 0;
+pub const VOICE_PUSH_TO_TALK: i64 = 1;
+pub const VOICE_ACTIVATION: i64 = 2;
+
+#[derive(Default)]
+pub struct VoiceGate {
+    open: bool,
+    // Seconds the gate stays open after the last chunk that was loud enough.
+    hangover_left: f64,
+}
+
+impl VoiceGate {
+    pub fn is_open(&self) -> bool {
+        return self.open;
+    }
+
+    /// Opens or closes the gate. Returns true if that changed it.
+    pub fn set_open(&mut self, open: bool) -> bool {
+        let changed = self.open != open;
+        self.open = open;
+        if !open {
+            self.hangover_left = 0.0;
+        }
+        return changed;
+    }
+
+    /// Voice activation for one chunk of `duration` seconds. Returns true if the gate opened or closed.
+    pub fn update_activation(
+        &mut self,
+        samples: &[Vector2],
+        duration: f64,
+        threshold: f64,
+        hangover: f64,
+    ) -> bool {
+        if rms(samples) >= threshold {
+            self.hangover_left = hangover;
+            let changed = !self.open;
+            self.open = true;
+            return changed;
+        }
+
+        if !self.open {
+            return false;
+        }
+        self.hangover_left -= duration;
+        if self.hangover_left > 0.0 {
+            return false;
+        }
+        return self.set_open(false);
+    }
+}
+
+// Loudness of a stereo chunk, 0 for silence and 1 for a full scale square wave. Both channels count, so a mono
+// microphone on one side isn't half as loud.
+pub fn rms(samples: &[Vector2]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples
+        .iter()
+        .map(|frame| {
+            let (left, right) = (frame.x as f64, frame.y as f64);
+            return (left * left + right * right) / 2.0;
+        })
+        .sum();
+    return (sum / samples.len() as f64).sqrt();
+}
+// End - Deciding when to transmit voice